@@ -69,16 +69,21 @@ double contr_coulomb(int lena, double *aexps, double *acoefs,
   return Jij;
 }
 
-double coulomb_repulsion(double xa, double ya, double za, double norma,
+/* The attenuated operator erf(omega r12)/r12 only changes the Fourier
+   space Gaussian exp(-delta k^2) of the Coulomb operator to
+   exp(-(delta + 1/(4 omega^2)) k^2), so both integrals share this routine,
+   with delta_shift = 0 for the bare Coulomb operator. */
+static double coulomb_repulsion_shifted(double xa, double ya, double za, double norma,
 				int la, int ma, int na, double alphaa,
 				double xb, double yb, double zb, double normb,
 				int lb, int mb, int nb, double alphab,
 				double xc, double yc, double zc, double normc,
 				int lc, int mc, int nc, double alphac,
 				double xd, double yd, double zd, double normd,
-				int ld, int md, int nd, double alphad){
+				int ld, int md, int nd, double alphad,
+				double delta_shift){
 
-  double rab2, rcd2,rpq2,xp,yp,zp,xq,yq,zq,gamma1,gamma2,delta,sum;
+  double rab2, rcd2,rpq2,xp,yp,zp,xq,yq,zq,gamma1,gamma2,delta,delta0,sum;
   double *Bx, *By, *Bz;
   int I,J,K;
 
@@ -93,7 +98,8 @@ double coulomb_repulsion(double xa, double ya, double za, double norma,
   rpq2 = dist2(xp,yp,zp,xq,yq,zq);
   gamma1 = alphaa+alphab;
   gamma2 = alphac+alphad;
-  delta = (1./gamma1+1./gamma2)/4.;
+  delta0 = (1./gamma1+1./gamma2)/4.;
+  delta = delta0+delta_shift;
 
   Bx = B_array(la,lb,lc,ld,xp,xa,xb,xq,xc,xd,gamma1,gamma2,delta);
   By = B_array(ma,mb,mc,md,yp,ya,yb,yq,yc,yd,gamma1,gamma2,delta);
@@ -110,10 +116,41 @@ double coulomb_repulsion(double xa, double ya, double za, double norma,
   free(Bz);
 
   return 2.*pow(M_PI,2.5)/(gamma1*gamma2*sqrt(gamma1+gamma2))
+    *sqrt(delta0/delta)
     *exp(-alphaa*alphab*rab2/gamma1)
     *exp(-alphac*alphad*rcd2/gamma2)*sum*norma*normb*normc*normd;
 }
 
+double coulomb_repulsion(double xa, double ya, double za, double norma,
+				int la, int ma, int na, double alphaa,
+				double xb, double yb, double zb, double normb,
+				int lb, int mb, int nb, double alphab,
+				double xc, double yc, double zc, double normc,
+				int lc, int mc, int nc, double alphac,
+				double xd, double yd, double zd, double normd,
+				int ld, int md, int nd, double alphad){
+  return coulomb_repulsion_shifted(xa,ya,za,norma,la,ma,na,alphaa,
+				   xb,yb,zb,normb,lb,mb,nb,alphab,
+				   xc,yc,zc,normc,lc,mc,nc,alphac,
+				   xd,yd,zd,normd,ld,md,nd,alphad,0.);
+}
+
+double coulomb_repulsion_erf(double xa, double ya, double za, double norma,
+				int la, int ma, int na, double alphaa,
+				double xb, double yb, double zb, double normb,
+				int lb, int mb, int nb, double alphab,
+				double xc, double yc, double zc, double normc,
+				int lc, int mc, int nc, double alphac,
+				double xd, double yd, double zd, double normd,
+				int ld, int md, int nd, double alphad,
+				double omega){
+  return coulomb_repulsion_shifted(xa,ya,za,norma,la,ma,na,alphaa,
+				   xb,yb,zb,normb,lb,mb,nb,alphab,
+				   xc,yc,zc,normc,lc,mc,nc,alphac,
+				   xd,yd,zd,normd,ld,md,nd,alphad,
+				   0.25/(omega*omega));
+}
+
 double *B_array(int l1, int l2, int l3, int l4, double p, double a,
 		double b, double q, double c, double d,
 		double g1, double g2, double delta){
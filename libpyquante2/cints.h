@@ -28,6 +28,15 @@ double coulomb_repulsion(double xa, double ya, double za, double norma,
 				int lc, int mc, int nc, double alphac,
 				double xd, double yd, double zd, double normd,
 				int ld, int md, int nd, double alphad);
+double coulomb_repulsion_erf(double xa, double ya, double za, double norma,
+				int la, int ma, int na, double alphaa,
+				double xb, double yb, double zb, double normb,
+				int lb, int mb, int nb, double alphab,
+				double xc, double yc, double zc, double normc,
+				int lc, int mc, int nc, double alphac,
+				double xd, double yd, double zd, double normd,
+				int ld, int md, int nd, double alphad,
+				double omega);
 
 double *B_array(int l1, int l2, int l3, int l4, double p, double a,
 		double b, double q, double c, double d,
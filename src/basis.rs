@@ -3,13 +3,15 @@
 use std::collections::HashMap as Map;
 use std::collections::HashSet as Set;
 use std::f64::consts::PI;
+use std::io;
 
-use cpython::{PyDict, Python};
-use ndarray::{Array, Axis, Ix2, Ix4};
+use cpython::{PyDict, PyResult, Python};
+use ndarray::{Array, Ix2, Ix3, Ix4};
 use serde::{Deserialize, Deserializer};
 
 use crate::integrals;
 use crate::shell;
+use crate::symmetry::{FunctionSymmetry, Symmetry};
 
 #[derive(Debug, Deserialize)]
 struct BSEResult {
@@ -84,25 +86,18 @@ struct BSEElectronShell {
     region: BSERegion,
 }
 
-fn get_bse_json(py: Python, basis_set_name: &str, elements: &Vec<u64>) -> BSEResult {
+fn get_bse_json(py: Python, basis_set_name: &str, elements: &[u64]) -> PyResult<String> {
     let locals = PyDict::new(py);
-    locals
-        .set_item(py, "bse", py.import("basis_set_exchange").unwrap())
-        .unwrap();
+    locals.set_item(py, "bse", py.import("basis_set_exchange")?)?;
     // Copy over the elements
-    let unique_elements: Set<u64> = elements.into_iter().map(|&x| x).collect();
+    let unique_elements: Set<u64> = elements.iter().copied().collect();
     let unique_elements: Vec<String> = unique_elements.iter().map(|x| x.to_string()).collect();
     let unique_elements = unique_elements.join(", ");
     let call = format!(
         "bse.get_basis(\"{}\", elements=[{}], fmt=\"json\")",
         basis_set_name, unique_elements
     );
-    let jsonstr: String = py
-        .eval(&call, None, Some(&locals))
-        .unwrap()
-        .extract(py)
-        .unwrap();
-    serde_json::from_str(&jsonstr).unwrap()
+    py.eval(&call, None, Some(&locals))?.extract(py)
 }
 
 fn fact2(n: isize) -> isize {
@@ -114,15 +109,15 @@ fn fact2(n: isize) -> isize {
 }
 
 #[derive(Clone, Debug)]
-struct PGTO {
-    origin: [f64; 3],
-    powers: [usize; 3],
-    exponent: f64,
-    norm: f64,
+pub(crate) struct PGTO {
+    pub(crate) origin: [f64; 3],
+    pub(crate) powers: [usize; 3],
+    pub(crate) exponent: f64,
+    pub(crate) norm: f64,
 }
 
 impl PGTO {
-    fn new(origin: [f64; 3], powers: [usize; 3], exponent: f64) -> PGTO {
+    pub(crate) fn new(origin: [f64; 3], powers: [usize; 3], exponent: f64) -> PGTO {
         let mut ret = PGTO {
             origin: origin,
             powers: powers,
@@ -149,16 +144,20 @@ impl PGTO {
     }
 }
 
-#[derive(Debug)]
-struct CGTO {
-    origin: [f64; 3],
-    powers: [usize; 3],
-    coefs: Vec<f64>,
-    primitives: Vec<PGTO>,
+#[derive(Clone, Debug)]
+pub(crate) struct CGTO {
+    pub(crate) origin: [f64; 3],
+    pub(crate) powers: [usize; 3],
+    pub(crate) coefs: Vec<f64>,
+    pub(crate) primitives: Vec<PGTO>,
+    // index of the atom this function is centered on
+    pub(crate) atom: usize,
+    // index of the shell this function belongs to
+    pub(crate) shell: usize,
 }
 
 impl CGTO {
-    fn from_pgtos(pgtos: &Vec<PGTO>, coefs: &Vec<f64>) -> CGTO {
+    fn from_pgtos(pgtos: &Vec<PGTO>, coefs: &Vec<f64>, atom: usize, shell: usize) -> CGTO {
         // TODO Ok and Err?
         assert!(pgtos.len() > 0);
         CGTO {
@@ -168,45 +167,307 @@ impl CGTO {
             // norms: pgtos.iter().map(|pgto| pgto.norm).collect(),
             coefs: coefs.clone(),
             primitives: pgtos.clone(),
+            atom,
+            shell,
+        }
+    }
+
+    /// Derivative of the function with respect to the given Cartesian
+    /// component of its center.
+    ///
+    /// d/dA_x x_A^l exp(-a x_A^2) = 2a x_A^(l+1) exp(-a x_A^2) - l x_A^(l-1) exp(-a x_A^2)
+    ///
+    /// The result is again a contraction, where each primitive keeps the
+    /// normalization of the primitive it was raised or lowered from, so it
+    /// can be passed directly to the existing integral routines.
+    pub(crate) fn differentiate(&self, component: usize) -> CGTO {
+        let mut coefs = Vec::new();
+        let mut primitives = Vec::new();
+        for (p, c) in self.primitives.iter().zip(&self.coefs) {
+            let mut up = p.clone();
+            up.powers[component] += 1;
+            coefs.push(2.0 * p.exponent * c);
+            primitives.push(up);
+            let l = p.powers[component];
+            if l > 0 {
+                let mut down = p.clone();
+                down.powers[component] -= 1;
+                coefs.push(-(l as f64) * c);
+                primitives.push(down);
+            }
+        }
+        CGTO {
+            origin: self.origin,
+            powers: self.powers,
+            coefs,
+            primitives,
+            atom: self.atom,
+            shell: self.shell,
         }
     }
 }
 
-#[derive(Debug)]
+/// A contracted shell of Cartesian functions sharing a center, angular
+/// momentum and contraction, as exchanged with other programs.
+#[derive(Clone, Debug)]
+pub struct Shell {
+    pub atom: usize,
+    pub angular_momentum: usize,
+    pub exponents: Vec<f64>,
+    /// Contraction coefficients multiplying normalized primitives.
+    pub coefficients: Vec<f64>,
+}
+
+impl Shell {
+    pub fn nfunctions(&self) -> usize {
+        (self.angular_momentum + 1) * (self.angular_momentum + 2) / 2
+    }
+
+    /// Norm of the contracted x^l function, which is not exactly one when
+    /// the coefficients are not normalized as a whole.
+    pub(crate) fn contraction_norm(&self) -> f64 {
+        let l = self.angular_momentum as f64;
+        let mut norm2 = 0.0;
+        for (a, ca) in self.exponents.iter().zip(&self.coefficients) {
+            for (b, cb) in self.exponents.iter().zip(&self.coefficients) {
+                norm2 += ca * cb * (2.0 * (a * b).sqrt() / (a + b)).powf(l + 1.5);
+            }
+        }
+        norm2.sqrt()
+    }
+}
+
+/// Ratio between the normalization of the Cartesian component with the
+/// given powers and that of x^l in the same shell. Programs that normalize
+/// every component like x^l need coefficients multiplied by this.
+pub(crate) fn axis_normalization_ratio(powers: [usize; 3]) -> f64 {
+    let l: usize = powers.iter().sum();
+    let denom = powers
+        .iter()
+        .map(|&p| fact2(2 * p as isize - 1))
+        .product::<isize>();
+    (fact2(2 * l as isize - 1) as f64 / denom as f64).sqrt()
+}
+
+#[derive(Clone, Debug)]
 pub struct Basis {
     name: String,
-    cgtos: Vec<CGTO>,
+    pub(crate) cgtos: Vec<CGTO>,
+    natoms: usize,
+    /// When set, the integral builders only compute integrals that are
+    /// unique under the point group.
+    symmetry: Option<FunctionSymmetry>,
 }
 
 impl Basis {
     pub fn new(atomnos: &Vec<u64>, all_atomcoords: &[[f64; 3]], basis_set_name: &str) -> Basis {
-        let mut cgtos: Vec<CGTO> = Vec::new();
+        Basis::try_new(atomnos, all_atomcoords, basis_set_name).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Like `new`, but fails instead of panicking when the Basis Set
+    /// Exchange does not know the basis set for all of the elements.
+    pub fn try_new(
+        atomnos: &[u64],
+        all_atomcoords: &[[f64; 3]],
+        basis_set_name: &str,
+    ) -> io::Result<Basis> {
         let gil = Python::acquire_gil();
-        let bseresult = get_bse_json(gil.python(), basis_set_name, &atomnos);
+        let jsonstr = get_bse_json(gil.python(), basis_set_name, atomnos).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("basis set {}: {:?}", basis_set_name, e),
+            )
+        })?;
+        let bseresult: BSEResult = serde_json::from_str(&jsonstr)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        if let Some(atomno) = atomnos
+            .iter()
+            .find(|&&atomno| !bseresult.elements.contains_key(&(atomno as u8)))
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("basis set {} has no element {}", basis_set_name, atomno),
+            ));
+        }
+        Ok(Basis::from_bse(
+            atomnos,
+            all_atomcoords,
+            basis_set_name,
+            &bseresult,
+        ))
+    }
+
+    /// Construct a basis from a Basis Set Exchange JSON document rather than
+    /// querying the `basis_set_exchange` Python package.
+    pub fn from_json(atomnos: &[u64], all_atomcoords: &[[f64; 3]], jsonstr: &str) -> Basis {
+        let bseresult: BSEResult = serde_json::from_str(jsonstr).unwrap();
+        Basis::from_bse(atomnos, all_atomcoords, &bseresult.name, &bseresult)
+    }
+
+    fn from_bse(
+        atomnos: &[u64],
+        all_atomcoords: &[[f64; 3]],
+        basis_set_name: &str,
+        bseresult: &BSEResult,
+    ) -> Basis {
+        let mut cgtos: Vec<CGTO> = Vec::new();
+        let mut nshells = 0;
         for (i, &atomno) in atomnos.iter().enumerate() {
             let atomcoords = all_atomcoords[i];
             let element = &bseresult.elements[&(atomno as u8)];
             for shell in &element.electron_shells {
-                for angular_momentum in &shell.angular_momentum {
-                    assert_eq!(
-                        shell.exponents.len(),
-                        shell.coefficients[*angular_momentum].len()
-                    );
-                    for powers in shell::get_ijk_list(*angular_momentum) {
+                // Fused shells (such as SP) have one row of coefficients per
+                // angular momentum, while general contractions have several
+                // rows for a single angular momentum.
+                for (k, coefficients) in shell.coefficients.iter().enumerate() {
+                    let angular_momentum = if shell.angular_momentum.len() == 1 {
+                        shell.angular_momentum[0]
+                    } else {
+                        shell.angular_momentum[k]
+                    };
+                    assert_eq!(shell.exponents.len(), coefficients.len());
+                    for powers in shell::get_ijk_list(angular_momentum) {
                         let pgtos: Vec<_> = shell
                             .exponents
                             .iter()
                             .map(|exponent| PGTO::new(atomcoords.clone(), powers, *exponent))
                             .collect();
-                        let cgto = CGTO::from_pgtos(&pgtos, &shell.coefficients[*angular_momentum]);
+                        let cgto = CGTO::from_pgtos(&pgtos, coefficients, i, nshells);
                         cgtos.push(cgto);
                     }
+                    nshells += 1;
                 }
             }
         }
         Basis {
             name: basis_set_name.to_string(),
             cgtos: cgtos,
+            natoms: atomnos.len(),
+            symmetry: None,
+        }
+    }
+
+    /// Construct a basis from explicit shells, such as those read from the
+    /// output of another program. Each shell has its functions in the order
+    /// of `shell::get_ijk_list`.
+    pub fn from_shells(name: &str, all_atomcoords: &[[f64; 3]], shells: &[Shell]) -> Basis {
+        let mut cgtos: Vec<CGTO> = Vec::new();
+        for (index, shell) in shells.iter().enumerate() {
+            assert_eq!(shell.exponents.len(), shell.coefficients.len());
+            let atomcoords = all_atomcoords[shell.atom];
+            for powers in shell::get_ijk_list(shell.angular_momentum) {
+                let pgtos: Vec<_> = shell
+                    .exponents
+                    .iter()
+                    .map(|exponent| PGTO::new(atomcoords, powers, *exponent))
+                    .collect();
+                cgtos.push(CGTO::from_pgtos(
+                    &pgtos,
+                    &shell.coefficients,
+                    shell.atom,
+                    index,
+                ));
+            }
+        }
+        Basis {
+            name: name.to_string(),
+            cgtos,
+            natoms: all_atomcoords.len(),
+            symmetry: None,
+        }
+    }
+
+    /// The shells of the basis, in order. The functions of each shell are
+    /// contiguous and follow `shell::get_ijk_list`.
+    pub fn shells(&self) -> Vec<Shell> {
+        let mut shells: Vec<Shell> = Vec::new();
+        for (mu, cgto) in self.cgtos.iter().enumerate() {
+            if mu == 0 || self.cgtos[mu - 1].shell != cgto.shell {
+                shells.push(Shell {
+                    atom: cgto.atom,
+                    angular_momentum: cgto.powers.iter().sum(),
+                    exponents: cgto.primitives.iter().map(|p| p.exponent).collect(),
+                    coefficients: cgto.coefs.clone(),
+                });
+            }
+        }
+        shells
+    }
+
+    pub fn nbasis(&self) -> usize {
+        self.cgtos.len()
+    }
+
+    pub fn natoms(&self) -> usize {
+        self.natoms
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// The atom each basis function is centered on.
+    pub fn function_atoms(&self) -> Vec<usize> {
+        self.cgtos.iter().map(|cgto| cgto.atom).collect()
+    }
+
+    /// The shell each basis function belongs to, numbered over the whole
+    /// basis with fused shells such as SP counted once per angular momentum.
+    pub fn function_shells(&self) -> Vec<usize> {
+        self.cgtos.iter().map(|cgto| cgto.shell).collect()
+    }
+
+    /// The angular momentum of each basis function.
+    pub fn function_angular_momenta(&self) -> Vec<usize> {
+        self.cgtos
+            .iter()
+            .map(|cgto| cgto.powers.iter().sum())
+            .collect()
+    }
+
+    /// The functions centered on one atom as a basis of their own, together
+    /// with their indices in this basis.
+    pub fn atom_basis(&self, atom: usize) -> (Basis, Vec<usize>) {
+        let indices: Vec<usize> = (0..self.cgtos.len())
+            .filter(|&mu| self.cgtos[mu].atom == atom)
+            .collect();
+        let cgtos = indices
+            .iter()
+            .map(|&mu| CGTO {
+                atom: 0,
+                ..self.cgtos[mu].clone()
+            })
+            .collect();
+        let basis_set = Basis {
+            name: self.name.clone(),
+            cgtos,
+            natoms: 1,
+            symmetry: None,
+        };
+        (basis_set, indices)
+    }
+
+    /// Let the integral builders use the point group of `symmetry`, whose
+    /// geometry the basis must be built on.
+    pub fn set_symmetry(&mut self, symmetry: &Symmetry) {
+        self.symmetry = Some(FunctionSymmetry::new(self, symmetry));
+    }
+
+    pub fn symmetry(&self) -> Option<&FunctionSymmetry> {
+        self.symmetry.as_ref()
+    }
+
+    /// Move every basis function along with the atom it is centered on.
+    /// This drops any symmetry, since the new geometry need not have it.
+    pub fn set_atomcoords(&mut self, all_atomcoords: &[[f64; 3]]) {
+        assert_eq!(all_atomcoords.len(), self.natoms);
+        self.symmetry = None;
+        for cgto in self.cgtos.iter_mut() {
+            let origin = all_atomcoords[cgto.atom];
+            cgto.origin = origin;
+            for pgto in cgto.primitives.iter_mut() {
+                pgto.origin = origin;
+            }
         }
     }
 }
@@ -231,25 +492,92 @@ fn overlap_cgto_left(a: &CGTO, b: &PGTO) -> f64 {
         .sum()
 }
 
-pub fn S(basis_set: &Basis) -> Array<f64, Ix2> {
+/// The overlap between the functions of two different bases, with the rows
+/// belonging to `basis_a`.
+pub fn S_cross(basis_a: &Basis, basis_b: &Basis) -> Array<f64, Ix2> {
+    let mut mat: Array<f64, _> = Array::zeros((basis_a.cgtos.len(), basis_b.cgtos.len()));
+    for (mu, a) in basis_a.cgtos.iter().enumerate() {
+        for (nu, b) in basis_b.cgtos.iter().enumerate() {
+            mat[[mu, nu]] = b
+                .primitives
+                .iter()
+                .zip(&b.coefs)
+                .map(|(pb, cb)| cb * overlap_cgto_left(a, pb))
+                .sum();
+        }
+    }
+    mat
+}
+
+/// Fill a symmetric one-electron matrix from `element`, computing only the
+/// first pair of functions of each set related by `symmetry`.
+fn one_electron<F: Fn(&CGTO, &CGTO) -> f64>(
+    basis_set: &Basis,
+    symmetry: Option<&FunctionSymmetry>,
+    element: F,
+) -> Array<f64, Ix2> {
     let dim = basis_set.cgtos.len();
     let mut mat: Array<f64, _> = Array::zeros((dim, dim));
     for mu in 0..dim {
         let a = &basis_set.cgtos[mu];
         for nu in 0..mu + 1 {
             let b = &basis_set.cgtos[nu];
-            mat[[mu, nu]] = b
-                .primitives
-                .iter()
-                .zip(&b.coefs)
-                .map(|(pb, cb)| cb * overlap_cgto_left(&a, &pb))
-                .sum();
-            mat[[nu, mu]] = mat[[mu, nu]];
+            let images = match symmetry {
+                Some(symmetry) => match symmetry.pair_images(mu, nu) {
+                    Some(images) => images,
+                    None => continue,
+                },
+                None => vec![([mu, nu], 1.0)],
+            };
+            let value = element(a, b);
+            for ([p, q], sign) in images {
+                mat[[p, q]] = sign * value;
+                mat[[q, p]] = sign * value;
+            }
         }
     }
     mat
 }
 
+pub fn S(basis_set: &Basis) -> Array<f64, Ix2> {
+    one_electron(basis_set, basis_set.symmetry(), overlap_cgto)
+}
+
+fn overlap_cgto(a: &CGTO, b: &CGTO) -> f64 {
+    b.primitives
+        .iter()
+        .zip(&b.coefs)
+        .map(|(pb, cb)| cb * overlap_cgto_left(a, pb))
+        .sum()
+}
+
+/// Derivatives of a symmetric one-electron operator matrix with respect to
+/// the basis function centers, with shape `(natoms, 3, nbasis, nbasis)`.
+fn one_electron_deriv<F: Fn(&CGTO, &CGTO) -> f64>(basis_set: &Basis, f: F) -> Array<f64, Ix4> {
+    let dim = basis_set.cgtos.len();
+    let mut mat: Array<f64, _> = Array::zeros((basis_set.natoms, 3, dim, dim));
+    for mu in 0..dim {
+        let a = &basis_set.cgtos[mu];
+        for k in 0..3 {
+            let da = a.differentiate(k);
+            // Only the bra is differentiated; the ket derivative is picked
+            // up when the loop reaches (nu, mu).
+            for nu in 0..dim {
+                let elem = f(&da, &basis_set.cgtos[nu]);
+                mat[[a.atom, k, mu, nu]] += elem;
+                mat[[a.atom, k, nu, mu]] += elem;
+            }
+        }
+    }
+    mat
+}
+
+/// Nuclear derivatives of the overlap matrix, with shape `(natoms, 3,
+/// nbasis, nbasis)`.
+pub fn S_deriv(basis_set: &Basis) -> Array<f64, Ix4> {
+    one_electron_deriv(basis_set, overlap_cgto)
+}
+
 fn kinetic_pgto(a: &PGTO, b: &PGTO) -> f64 {
     let powers = [
         a.powers[0],
@@ -271,22 +599,21 @@ fn kinetic_cgto_left(a: &CGTO, b: &PGTO) -> f64 {
 }
 
 pub fn T(basis_set: &Basis) -> Array<f64, Ix2> {
-    let dim = basis_set.cgtos.len();
-    let mut mat: Array<f64, _> = Array::zeros((dim, dim));
-    for mu in 0..dim {
-        let a = &basis_set.cgtos[mu];
-        for nu in 0..mu + 1 {
-            let b = &basis_set.cgtos[nu];
-            mat[[mu, nu]] = b
-                .primitives
-                .iter()
-                .zip(&b.coefs)
-                .map(|(pb, cb)| cb * kinetic_cgto_left(&a, &pb))
-                .sum();
-            mat[[nu, mu]] = mat[[mu, nu]];
-        }
-    }
-    mat
+    one_electron(basis_set, basis_set.symmetry(), kinetic_cgto)
+}
+
+fn kinetic_cgto(a: &CGTO, b: &CGTO) -> f64 {
+    b.primitives
+        .iter()
+        .zip(&b.coefs)
+        .map(|(pb, cb)| cb * kinetic_cgto_left(a, pb))
+        .sum()
+}
+
+/// Nuclear derivatives of the kinetic energy matrix, with shape `(natoms, 3,
+/// nbasis, nbasis)`.
+pub fn T_deriv(basis_set: &Basis) -> Array<f64, Ix4> {
+    one_electron_deriv(basis_set, kinetic_cgto)
 }
 
 fn nuclear_pgto(a: &PGTO, b: &PGTO, atomcoords: &[f64; 3]) -> f64 {
@@ -313,28 +640,172 @@ fn nuclear_cgto_left(a: &CGTO, b: &PGTO, atomcoords: &[f64; 3]) -> f64 {
         .sum()
 }
 
-pub fn V(basis_set: &Basis, atomcoords: &[[f64; 3]], atomnos: &Vec<u64>) -> Array<f64, Ix2> {
+pub fn V(basis_set: &Basis, atomcoords: &[[f64; 3]], atomnos: &[u64]) -> Array<f64, Ix2> {
+    // Charges that break the symmetry, such as a probe charge for the
+    // electrostatic potential, need every integral.
+    let symmetry = basis_set
+        .symmetry()
+        .filter(|symmetry| symmetry.is_invariant(atomcoords, atomnos));
+    one_electron(basis_set, symmetry, |a, b| {
+        atomcoords
+            .iter()
+            .zip(atomnos)
+            .map(|(single_atomcoords, &atomno)| {
+                atomno as f64 * nuclear_cgto(a, b, single_atomcoords)
+            })
+            .sum()
+    })
+}
+
+fn nuclear_cgto(a: &CGTO, b: &CGTO, atomcoords: &[f64; 3]) -> f64 {
+    b.primitives
+        .iter()
+        .zip(&b.coefs)
+        .map(|(pb, cb)| cb * nuclear_cgto_left(a, pb, atomcoords))
+        .sum()
+}
+
+/// Nuclear derivatives of the nuclear-electron attraction matrix, with shape
+/// `(natoms, 3, nbasis, nbasis)`.
+///
+/// Besides the basis function center derivatives, this includes the
+/// Hellmann-Feynman term from moving the operator center, which by
+/// translational invariance is d/dC (mu|C|nu) = -[(d mu|C|nu) + (mu|C|d nu)].
+pub fn V_deriv(basis_set: &Basis, atomcoords: &[[f64; 3]], atomnos: &[u64]) -> Array<f64, Ix4> {
     let dim = basis_set.cgtos.len();
     let natoms = atomcoords.len();
-    let mut mat: Array<f64, _> = Array::zeros((dim, dim, natoms));
-    for (c, single_atomcoords) in atomcoords.iter().enumerate() {
-        let atomno = atomnos[c] as f64;
-        for mu in 0..dim {
-            let a = &basis_set.cgtos[mu];
-            for nu in 0..mu + 1 {
+    let mut mat: Array<f64, _> = Array::zeros((natoms, 3, dim, dim));
+    for mu in 0..dim {
+        let a = &basis_set.cgtos[mu];
+        for k in 0..3 {
+            let da = a.differentiate(k);
+            for nu in 0..dim {
                 let b = &basis_set.cgtos[nu];
-                let elem: f64 = b
-                    .primitives
-                    .iter()
-                    .zip(&b.coefs)
-                    .map(|(pb, cb)| cb * nuclear_cgto_left(&a, &pb, single_atomcoords))
-                    .sum();
-                mat[[mu, nu, c]] = atomno * elem;
-                mat[[nu, mu, c]] = mat[[mu, nu, c]];
+                for (c, single_atomcoords) in atomcoords.iter().enumerate() {
+                    let elem = atomnos[c] as f64 * nuclear_cgto(&da, b, single_atomcoords);
+                    mat[[a.atom, k, mu, nu]] += elem;
+                    mat[[a.atom, k, nu, mu]] += elem;
+                    mat[[c, k, mu, nu]] -= elem;
+                    mat[[c, k, nu, mu]] -= elem;
+                }
             }
         }
     }
-    mat.sum_axis(Axis(2))
+    mat
+}
+
+fn moment_pgto(a: &PGTO, b: &PGTO, origin: &[f64; 3], order: &[u8; 3]) -> f64 {
+    let powers = [
+        a.powers[0] as u8,
+        a.powers[1] as u8,
+        a.powers[2] as u8,
+        b.powers[0] as u8,
+        b.powers[1] as u8,
+        b.powers[2] as u8,
+    ];
+    a.norm
+        * b.norm
+        * integrals::get_moment(
+            a.exponent, b.exponent, &a.origin, &b.origin, origin, &powers, order,
+        )
+}
+
+/// Cartesian multipole moment integrals <mu|(x-Cx)^i (y-Cy)^j (z-Cz)^k|nu>
+/// about `origin`, where `order` is [i, j, k].
+pub fn M(basis_set: &Basis, origin: &[f64; 3], order: &[u8; 3]) -> Array<f64, Ix2> {
+    let dim = basis_set.cgtos.len();
+    let mut mat: Array<f64, _> = Array::zeros((dim, dim));
+    for mu in 0..dim {
+        let a = &basis_set.cgtos[mu];
+        for nu in 0..mu + 1 {
+            let b = &basis_set.cgtos[nu];
+            let mut elem = 0.0;
+            for (pa, ca) in a.primitives.iter().zip(&a.coefs) {
+                for (pb, cb) in b.primitives.iter().zip(&b.coefs) {
+                    elem += ca * cb * moment_pgto(pa, pb, origin, order);
+                }
+            }
+            mat[[mu, nu]] = elem;
+            mat[[nu, mu]] = elem;
+        }
+    }
+    mat
+}
+
+/// The x, y and z dipole integrals about `origin`.
+pub fn dipole(basis_set: &Basis, origin: &[f64; 3]) -> [Array<f64, Ix2>; 3] {
+    [
+        M(basis_set, origin, &[1, 0, 0]),
+        M(basis_set, origin, &[0, 1, 0]),
+        M(basis_set, origin, &[0, 0, 1]),
+    ]
+}
+
+/// Values of the basis functions at a set of points, with shape
+/// `(npoints, nbasis)`, along with their x, y and z gradients.
+///
+/// d/dx x^l exp(-a r^2) = (l x^(l-1) - 2a x^(l+1)) exp(-a r^2)
+pub fn evaluate(basis_set: &Basis, points: &[[f64; 3]]) -> (Array<f64, Ix2>, [Array<f64, Ix2>; 3]) {
+    let npoints = points.len();
+    let dim = basis_set.cgtos.len();
+    let mut values: Array<f64, _> = Array::zeros((npoints, dim));
+    let mut gradients = [
+        Array::zeros((npoints, dim)),
+        Array::zeros((npoints, dim)),
+        Array::zeros((npoints, dim)),
+    ];
+    for (mu, cgto) in basis_set.cgtos.iter().enumerate() {
+        let [l, m, n] = cgto.powers;
+        for (g, point) in points.iter().enumerate() {
+            let d = [
+                point[0] - cgto.origin[0],
+                point[1] - cgto.origin[1],
+                point[2] - cgto.origin[2],
+            ];
+            let r2 = d[0] * d[0] + d[1] * d[1] + d[2] * d[2];
+            // The contracted radial part and its derivative with respect to
+            // r^2, shared by every component.
+            let mut radial = 0.0;
+            let mut radial_deriv = 0.0;
+            for (p, c) in cgto.primitives.iter().zip(&cgto.coefs) {
+                let e = c * p.norm * (-p.exponent * r2).exp();
+                radial += e;
+                radial_deriv -= 2.0 * p.exponent * e;
+            }
+            let angular = [
+                d[0].powi(l as i32),
+                d[1].powi(m as i32),
+                d[2].powi(n as i32),
+            ];
+            // Derivatives of the angular factors, which vanish for zero
+            // powers.
+            let angular_deriv = [
+                if l > 0 {
+                    l as f64 * d[0].powi(l as i32 - 1)
+                } else {
+                    0.0
+                },
+                if m > 0 {
+                    m as f64 * d[1].powi(m as i32 - 1)
+                } else {
+                    0.0
+                },
+                if n > 0 {
+                    n as f64 * d[2].powi(n as i32 - 1)
+                } else {
+                    0.0
+                },
+            ];
+            let xyz = angular[0] * angular[1] * angular[2];
+            values[[g, mu]] = xyz * radial;
+            for k in 0..3 {
+                let others: f64 = (0..3).filter(|&j| j != k).map(|j| angular[j]).product();
+                gradients[k][[g, mu]] =
+                    angular_deriv[k] * others * radial + xyz * radial_deriv * d[k];
+            }
+        }
+    }
+    (values, gradients)
 }
 
 fn coulomb_pgto(a: &PGTO, b: &PGTO, c: &PGTO, d: &PGTO) -> f64 {
@@ -380,6 +851,59 @@ fn coulomb_pgto(a: &PGTO, b: &PGTO, c: &PGTO, d: &PGTO) -> f64 {
     // )
 }
 
+fn coulomb_pgto_erf(a: &PGTO, b: &PGTO, c: &PGTO, d: &PGTO, omega: f64) -> f64 {
+    let powers = [
+        a.powers[0] as i32,
+        a.powers[1] as i32,
+        a.powers[2] as i32,
+        b.powers[0] as i32,
+        b.powers[1] as i32,
+        b.powers[2] as i32,
+        c.powers[0] as i32,
+        c.powers[1] as i32,
+        c.powers[2] as i32,
+        d.powers[0] as i32,
+        d.powers[1] as i32,
+        d.powers[2] as i32,
+    ];
+    integrals::tho66::pyquante2::pyquante2_coulomb_repulsion_erf(
+        a.exponent, b.exponent, c.exponent, d.exponent, &a.origin, &b.origin, &c.origin, &d.origin,
+        a.norm, b.norm, c.norm, d.norm, &powers, omega,
+    )
+}
+
+/// Add the contributions of one integral to J and K for every index
+/// permutation that leaves it unchanged, counting each distinct one once.
+fn JK_add_quartet(
+    J: &mut Array<f64, Ix2>,
+    K: &mut Array<f64, Ix2>,
+    D: &Array<f64, Ix2>,
+    quartet: [usize; 4],
+    value: f64,
+) {
+    let [i, j, k, l] = quartet;
+    let permutations = [
+        [i, j, k, l],
+        [j, i, k, l],
+        [i, j, l, k],
+        [j, i, l, k],
+        [k, l, i, j],
+        [l, k, i, j],
+        [k, l, j, i],
+        [l, k, j, i],
+    ];
+    for (n, &[mu, nu, lambda, sigma]) in permutations.iter().enumerate() {
+        if permutations[..n].contains(&permutations[n]) {
+            continue;
+        }
+        J[[mu, nu]] += value * D[[lambda, sigma]];
+        K[[mu, lambda]] += value * D[[nu, sigma]];
+    }
+}
+
+/// Build J and K while computing each integral only once, using the
+/// permutational symmetry of the integrals and, if the basis has one, its
+/// point group. No assumption is made about the symmetry of D.
 pub fn JK_direct(
     J: &mut Array<f64, Ix2>,
     K: &mut Array<f64, Ix2>,
@@ -391,36 +915,25 @@ pub fn JK_direct(
     let dim = basis_set.cgtos.len();
     for mu in 0..dim {
         let a = &basis_set.cgtos[mu];
-        for nu in 0..dim {
+        for nu in 0..mu + 1 {
             let b = &basis_set.cgtos[nu];
-            for lambda in 0..dim {
+            for lambda in 0..mu + 1 {
                 let c = &basis_set.cgtos[lambda];
-                for sigma in 0..dim {
+                let sigma_max = if lambda == mu { nu } else { lambda };
+                for sigma in 0..sigma_max + 1 {
                     let d = &basis_set.cgtos[sigma];
-                    let mut j_contr = 0.0;
-                    let mut k_contr = 0.0;
-                    for (pa, ca) in a.primitives.iter().zip(&a.coefs) {
-                        for (pb, cb) in b.primitives.iter().zip(&b.coefs) {
-                            for (pc, cc) in c.primitives.iter().zip(&c.coefs) {
-                                for (pd, cd) in d.primitives.iter().zip(&d.coefs) {
-                                    j_contr += ca
-                                        * cb
-                                        * cc
-                                        * cd
-                                        * coulomb_pgto(&pa, &pb, &pc, &pd)
-                                        * D[[lambda, sigma]];
-                                    k_contr += ca
-                                        * cb
-                                        * cc
-                                        * cd
-                                        * coulomb_pgto(&pa, &pc, &pb, &pd)
-                                        * D[[lambda, sigma]];
-                                }
-                            }
-                        }
+                    let quartet = [mu, nu, lambda, sigma];
+                    let images = match basis_set.symmetry() {
+                        Some(symmetry) => match symmetry.quartet_images(quartet) {
+                            Some(images) => images,
+                            None => continue,
+                        },
+                        None => vec![(quartet, 1.0)],
+                    };
+                    let value = coulomb_cgto(a, b, c, d);
+                    for (image, sign) in images {
+                        JK_add_quartet(J, K, D, image, sign * value);
                     }
-                    J[[mu, nu]] += j_contr;
-                    K[[mu, nu]] += k_contr;
                 }
             }
         }
@@ -428,6 +941,20 @@ pub fn JK_direct(
 }
 
 pub fn build_I(basis_set: &Basis) -> Array<f64, Ix4> {
+    build_I_operator(basis_set, coulomb_pgto)
+}
+
+/// Two-electron integrals over the long-range operator erf(omega r12)/r12,
+/// as needed for range-separated exchange. The short-range integrals over
+/// erfc(omega r12)/r12 are the difference from `build_I`.
+pub fn build_I_erf(basis_set: &Basis, omega: f64) -> Array<f64, Ix4> {
+    build_I_operator(basis_set, |a, b, c, d| coulomb_pgto_erf(a, b, c, d, omega))
+}
+
+fn build_I_operator<F>(basis_set: &Basis, operator: F) -> Array<f64, Ix4>
+where
+    F: Fn(&PGTO, &PGTO, &PGTO, &PGTO) -> f64,
+{
     let dim = basis_set.cgtos.len();
     let mut I: Array<f64, _> = Array::zeros((dim, dim, dim, dim));
     for mu in 0..dim {
@@ -443,7 +970,7 @@ pub fn build_I(basis_set: &Basis) -> Array<f64, Ix4> {
                         for (pb, cb) in b.primitives.iter().zip(&b.coefs) {
                             for (pc, cc) in c.primitives.iter().zip(&c.coefs) {
                                 for (pd, cd) in d.primitives.iter().zip(&d.coefs) {
-                                    val += ca * cb * cc * cd * coulomb_pgto(&pa, &pb, &pc, &pd);
+                                    val += ca * cb * cc * cd * operator(pa, pb, pc, pd);
                                 }
                             }
                         }
@@ -459,6 +986,43 @@ pub fn build_I(basis_set: &Basis) -> Array<f64, Ix4> {
     I
 }
 
+/// All two-electron integrals (mu nu|lambda sigma) for a fixed pair of
+/// functions mu and nu, with shape `(nbasis, nbasis)`, for algorithms that
+/// never hold the full four-index array.
+pub fn I_pair(basis_set: &Basis, mu: usize, nu: usize) -> Array<f64, Ix2> {
+    let dim = basis_set.cgtos.len();
+    let a = &basis_set.cgtos[mu];
+    let b = &basis_set.cgtos[nu];
+    let mut mat: Array<f64, _> = Array::zeros((dim, dim));
+    for lambda in 0..dim {
+        let c = &basis_set.cgtos[lambda];
+        for sigma in 0..lambda + 1 {
+            let d = &basis_set.cgtos[sigma];
+            let val = coulomb_cgto(a, b, c, d);
+            mat[[lambda, sigma]] = val;
+            mat[[sigma, lambda]] = val;
+        }
+    }
+    mat
+}
+
+/// The diagonal two-electron integrals (mu nu|mu nu), which bound every
+/// integral through the Schwarz inequality
+/// |(mu nu|lambda sigma)| <= sqrt((mu nu|mu nu) (lambda sigma|lambda sigma)).
+pub fn schwarz_diagonal(basis_set: &Basis) -> Array<f64, Ix2> {
+    let dim = basis_set.cgtos.len();
+    let mut mat: Array<f64, _> = Array::zeros((dim, dim));
+    for mu in 0..dim {
+        let a = &basis_set.cgtos[mu];
+        for nu in 0..mu + 1 {
+            let b = &basis_set.cgtos[nu];
+            mat[[mu, nu]] = coulomb_cgto(a, b, a, b);
+            mat[[nu, mu]] = mat[[mu, nu]];
+        }
+    }
+    mat
+}
+
 pub fn JK_inmem(I: &Array<f64, Ix4>, D: &Array<f64, Ix2>) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
     let dim = I.shape()[0];
     let mut J: Array<f64, _> = Array::zeros((dim, dim));
@@ -482,3 +1046,164 @@ pub fn JK_inmem(I: &Array<f64, Ix4>, D: &Array<f64, Ix2>) -> (Array<f64, Ix2>, A
     }
     (J, K)
 }
+
+/// J and K for a density matrix that need not be symmetric, such as a
+/// transition density, in which case K is not symmetric either.
+pub fn JK_inmem_general(
+    I: &Array<f64, Ix4>,
+    D: &Array<f64, Ix2>,
+) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+    let dim = I.shape()[0];
+    let mut J: Array<f64, _> = Array::zeros((dim, dim));
+    let mut K: Array<f64, _> = Array::zeros((dim, dim));
+    for mu in 0..dim {
+        for nu in 0..dim {
+            let mut j_contr = 0.0;
+            let mut k_contr = 0.0;
+            for lambda in 0..dim {
+                for sigma in 0..dim {
+                    j_contr += I[[mu, nu, lambda, sigma]] * D[[lambda, sigma]];
+                    k_contr += I[[mu, lambda, nu, sigma]] * D[[lambda, sigma]];
+                }
+            }
+            J[[mu, nu]] = j_contr;
+            K[[mu, nu]] = k_contr;
+        }
+    }
+    (J, K)
+}
+
+fn coulomb_cgto(a: &CGTO, b: &CGTO, c: &CGTO, d: &CGTO) -> f64 {
+    let mut val = 0.0;
+    for (pa, ca) in a.primitives.iter().zip(&a.coefs) {
+        for (pb, cb) in b.primitives.iter().zip(&b.coefs) {
+            for (pc, cc) in c.primitives.iter().zip(&c.coefs) {
+                for (pd, cd) in d.primitives.iter().zip(&d.coefs) {
+                    val += ca * cb * cc * cd * coulomb_pgto(pa, pb, pc, pd);
+                }
+            }
+        }
+    }
+    val
+}
+
+/// The constant function 1, as an s function with a zero exponent and unit
+/// normalization, which turns the four-center integral routines into two-
+/// and three-center ones.
+fn unit_cgto(origin: [f64; 3], atom: usize) -> CGTO {
+    let unit = PGTO {
+        origin,
+        powers: [0, 0, 0],
+        exponent: 0.0,
+        norm: 1.0,
+    };
+    CGTO::from_pgtos(&vec![unit], &vec![1.0], atom, 0)
+}
+
+/// Two-center Coulomb integrals (P|Q) over the functions of an auxiliary
+/// basis, the metric for density fitting.
+pub fn two_center(aux: &Basis) -> Array<f64, Ix2> {
+    let naux = aux.cgtos.len();
+    let units: Vec<CGTO> = aux
+        .cgtos
+        .iter()
+        .map(|p| unit_cgto(p.origin, p.atom))
+        .collect();
+    let mut mat: Array<f64, _> = Array::zeros((naux, naux));
+    for P in 0..naux {
+        for Q in 0..P + 1 {
+            mat[[P, Q]] = coulomb_cgto(&aux.cgtos[P], &units[P], &aux.cgtos[Q], &units[Q]);
+            mat[[Q, P]] = mat[[P, Q]];
+        }
+    }
+    mat
+}
+
+/// Three-center Coulomb integrals (P|mu nu) between the functions of an
+/// auxiliary basis and pairs of orbital basis functions, with shape
+/// `(naux, nbasis, nbasis)`.
+pub fn three_center(basis_set: &Basis, aux: &Basis) -> Array<f64, Ix3> {
+    let dim = basis_set.cgtos.len();
+    let naux = aux.cgtos.len();
+    let mut mat: Array<f64, _> = Array::zeros((naux, dim, dim));
+    for P in 0..naux {
+        let p = &aux.cgtos[P];
+        let unit = unit_cgto(p.origin, p.atom);
+        for mu in 0..dim {
+            let a = &basis_set.cgtos[mu];
+            for nu in 0..mu + 1 {
+                let b = &basis_set.cgtos[nu];
+                mat[[P, mu, nu]] = coulomb_cgto(p, &unit, a, b);
+                mat[[P, nu, mu]] = mat[[P, mu, nu]];
+            }
+        }
+    }
+    mat
+}
+
+/// Contract the nuclear derivatives of the two-electron integrals with a
+/// two-particle density, giving
+///
+/// sum_{mu nu lambda sigma} gamma(mu, nu, lambda, sigma) d(mu nu|lambda sigma)/dX
+///
+/// for every atom and Cartesian direction, with shape `(natoms, 3)`.
+///
+/// Only permutationally unique quartets are differentiated, and the
+/// derivative with respect to the fourth center follows from translational
+/// invariance.
+pub fn I_deriv_contract<F>(basis_set: &Basis, gamma: F) -> Array<f64, Ix2>
+where
+    F: Fn(usize, usize, usize, usize) -> f64,
+{
+    let dim = basis_set.cgtos.len();
+    let mut grad: Array<f64, _> = Array::zeros((basis_set.natoms, 3));
+    let dcgtos: Vec<Vec<CGTO>> = basis_set
+        .cgtos
+        .iter()
+        .map(|cgto| (0..3).map(|k| cgto.differentiate(k)).collect())
+        .collect();
+    for mu in 0..dim {
+        let a = &basis_set.cgtos[mu];
+        for nu in 0..mu + 1 {
+            let b = &basis_set.cgtos[nu];
+            let munu = mu * (mu + 1) / 2 + nu;
+            for lambda in 0..dim {
+                let c = &basis_set.cgtos[lambda];
+                for sigma in 0..lambda + 1 {
+                    let d = &basis_set.cgtos[sigma];
+                    let lambdasigma = lambda * (lambda + 1) / 2 + sigma;
+                    if lambdasigma > munu {
+                        continue;
+                    }
+                    let mut quartets = vec![
+                        [mu, nu, lambda, sigma],
+                        [nu, mu, lambda, sigma],
+                        [mu, nu, sigma, lambda],
+                        [nu, mu, sigma, lambda],
+                        [lambda, sigma, mu, nu],
+                        [sigma, lambda, mu, nu],
+                        [lambda, sigma, nu, mu],
+                        [sigma, lambda, nu, mu],
+                    ];
+                    quartets.sort();
+                    quartets.dedup();
+                    let g: f64 = quartets.iter().map(|q| gamma(q[0], q[1], q[2], q[3])).sum();
+                    if g.abs() < 1.0e-14 {
+                        continue;
+                    }
+                    for k in 0..3 {
+                        let da = coulomb_cgto(&dcgtos[mu][k], b, c, d);
+                        let db = coulomb_cgto(a, &dcgtos[nu][k], c, d);
+                        let dc = coulomb_cgto(a, b, &dcgtos[lambda][k], d);
+                        let dd = -(da + db + dc);
+                        grad[[a.atom, k]] += g * da;
+                        grad[[b.atom, k]] += g * db;
+                        grad[[c.atom, k]] += g * dc;
+                        grad[[d.atom, k]] += g * dd;
+                    }
+                }
+            }
+        }
+    }
+    grad
+}
@@ -1,13 +1,60 @@
-#![allow(non_snake_case, unused_variables)]
+use rchem::basis;
+use rchem::checkpoint;
+use rchem::cube;
+use rchem::davidson;
+use rchem::dft;
+use rchem::dispersion;
+use rchem::elements;
+use rchem::fchk;
+use rchem::gradient;
+use rchem::guess;
+use rchem::molden;
+use rchem::optimize;
+use rchem::population;
+use rchem::qcschema;
+use rchem::scf;
+use rchem::soscf;
+use rchem::stability;
+use rchem::symmetry;
+use rchem::xc;
 
-use std::f64;
+/// Report a command-line error and exit with a non-zero status.
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    std::process::exit(2)
+}
 
-use ndarray::{Array, Axis, Ix1, Ix2, Slice};
-use ndarray_linalg::*;
+/// Report a failure to read or write a file and exit with a non-zero status.
+fn io_error(path: &str, error: std::io::Error) -> ! {
+    eprintln!("error: {}: {}", path, error);
+    std::process::exit(1)
+}
 
-use rchem::basis;
+/// The `n`th value after the flag at `args[i]`.
+fn value(args: &[String], i: usize, n: usize) -> &str {
+    args.get(i + n)
+        .map(String::as_str)
+        .unwrap_or_else(|| usage_error(&format!("{}: missing value {}", args[i], n)))
+}
+
+/// The `n`th value after the flag at `args[i]`, parsed.
+fn parse<T: std::str::FromStr>(args: &[String], i: usize, n: usize) -> T {
+    let text = value(args, i, n);
+    text.parse()
+        .unwrap_or_else(|_| usage_error(&format!("{}: invalid value {}", args[i], text)))
+}
 
 fn main() {
+    // `rchem --qcschema <input.json>` runs a QCSchema AtomicInput and prints
+    // only the AtomicResult, for workflow tools that parse stdout.
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(i) = args.iter().position(|arg| arg == "--qcschema") {
+        let path = value(&args, i, 1);
+        let input = std::fs::read_to_string(path).unwrap_or_else(|e| io_error(path, e));
+        println!("{}", qcschema::run(&input));
+        return;
+    }
+
     // http://www.patorjk.com/software/taag/#p=display&f=3D%20Diagonal&t=rchem
     let logo = r#"
                    ,---,                       ____
@@ -26,111 +73,451 @@ fn main() {
 "#;
     println!("{}", logo);
 
-    let mut trajectory = chemfiles::Trajectory::open("water_crawford.xyz", 'r').unwrap();
+    // `--geometry <file>` gives the molecule, with coordinates in bohr, and
+    // `--basis <name>` the basis set (default STO-3G).
+    let flag = |name: &str| args.iter().position(|arg| arg == name);
+    let path = match flag("--geometry") {
+        Some(i) => value(&args, i, 1),
+        None => usage_error("--geometry <file> is required"),
+    };
+    let invalid =
+        |e: chemfiles::Error| std::io::Error::new(std::io::ErrorKind::InvalidData, e.to_string());
+    let mut trajectory = chemfiles::Trajectory::open(path, 'r')
+        .map_err(invalid)
+        .unwrap_or_else(|e| io_error(path, e));
     let mut frame = chemfiles::Frame::new();
-    trajectory.read(&mut frame).unwrap();
+    trajectory
+        .read(&mut frame)
+        .map_err(invalid)
+        .unwrap_or_else(|e| io_error(path, e));
     let natom = frame.size();
+    if natom == 0 {
+        io_error(
+            path,
+            std::io::Error::new(std::io::ErrorKind::InvalidData, "no atoms"),
+        );
+    }
     let atomcoords = frame.positions();
     let atomnos: Vec<_> = (0..natom).map(|i| frame.atom(i).atomic_number()).collect();
-    let basis_set = basis::Basis::new(&atomnos, &atomcoords, "STO-3G");
-    // println!("{:#?}", basis_set);
-
-    // let I = basis::build_I(&basis_set);
-
-    let S = basis::S(&basis_set);
-    // TODO better way of getting this
-    let nbasis = S.shape()[0];
-
-    let (overlap_eigvals, overlap_eigvecs) = S.eigh(UPLO::Upper).unwrap();
-    let overlap_eigvals_inv_sqrt = vec_to_diag_mat(&overlap_eigvals)
-        .inv()
-        .unwrap()
-        .mapv(f64::sqrt);
-    let symm_orthog = overlap_eigvecs
-        .dot(&overlap_eigvals_inv_sqrt)
-        .dot(&overlap_eigvecs.t());
-
-    let T = basis::T(&basis_set);
-    let V = basis::V(&basis_set, &atomcoords, &atomnos);
-    let H = T + V;
-    let F_prime = symm_orthog.t().dot(&H).dot(&symm_orthog);
-    let (eps_vec, C_prime) = F_prime.eigh(UPLO::Upper).unwrap();
-    let C = symm_orthog.dot(&C_prime);
-    let mut D = build_density(&C, 5);
-
-    let mut e_elec_new = calc_elec_energy(&D, &H, &H);
-
-    let thresh_e = 1.0e-11;
-    // let thresh_d = 1.0e-10;
-    let max_iterations = 1024;
-    let mut iteration = 0;
-
-    let mut J: Array<f64, _> = Array::zeros((nbasis, nbasis));
-    let mut K: Array<f64, _> = Array::zeros((nbasis, nbasis));
-    let mut F: Array<f64, _> = Array::zeros((nbasis, nbasis));
-
-    while iteration < max_iterations {
-        build_fock(&mut F, &mut J, &mut K, &D, &H, &basis_set);
-        let F_prime = symm_orthog.t().dot(&F).dot(&symm_orthog);
-        let (eps_vec, C_prime) = F_prime.eigh(UPLO::Upper).unwrap();
-        let C = symm_orthog.dot(&C_prime);
-        // let D_old = D.clone();
-        D = build_density(&C, 5);
-        let e_elec_old = e_elec_new;
-        e_elec_new = calc_elec_energy(&D, &H, &F);
-        let e_total = e_elec_new;
-        let delta_e = e_elec_new - e_elec_old;
-        println!("{:4} {:20.12} {:20.12}", iteration, e_elec_new, delta_e);
-        if delta_e.abs() < thresh_e {
-            println!("Convergence achieved!");
-            break;
+    // `--symmetry` symmetrizes and reorients the molecule for its point
+    // group (up to D2h), and `--docc n1,n2,...` then fixes the doubly
+    // occupied orbitals in each irrep, in the order of the character table.
+    let symmetry = flag("--symmetry")
+        .map(|_| symmetry::detect(atomcoords, &atomnos, &symmetry::SymmetryOptions::default()));
+    let atomcoords: &[[f64; 3]] = match &symmetry {
+        Some(symmetry) => {
+            println!("Point group: {}", symmetry.point_group.name());
+            &symmetry.atomcoords
         }
-        iteration += 1;
+        None => atomcoords,
+    };
+    let basis_name = flag("--basis").map_or("STO-3G", |i| value(&args, i, 1));
+    let mut basis_set = basis::Basis::try_new(&atomnos, atomcoords, basis_name)
+        .unwrap_or_else(|e| usage_error(&format!("--basis {}: {}", basis_name, e)));
+    if let Some(symmetry) = &symmetry {
+        basis_set.set_symmetry(symmetry);
     }
-}
+    let basis_set = basis_set;
+    let adaptation = symmetry
+        .as_ref()
+        .map(|symmetry| symmetry::SymmetryAdaptation::new(&basis_set, symmetry));
+    let nocc = scf::nelectrons(&atomnos, 0) / 2;
+    let docc = flag("--docc").map(|i| {
+        let adaptation = adaptation
+            .as_ref()
+            .unwrap_or_else(|| usage_error("--docc needs --symmetry"));
+        let docc = value(&args, i, 1)
+            .split(',')
+            .map(|n| {
+                n.parse::<usize>()
+                    .unwrap_or_else(|_| usage_error(&format!("--docc: invalid value {}", n)))
+            })
+            .collect::<Vec<_>>();
+        let sizes = adaptation.irrep_sizes();
+        if docc.len() != sizes.len()
+            || docc.iter().sum::<usize>() != nocc
+            || docc.iter().zip(&sizes).any(|(n, size)| n > size)
+        {
+            usage_error(&format!(
+                "--docc needs {} occupations, one per irrep of {}, adding up to {}",
+                sizes.len(),
+                adaptation.point_group.name(),
+                nocc
+            ));
+        }
+        docc
+    });
 
-fn vec_to_diag_mat(vec: &Array<f64, Ix1>) -> Array<f64, Ix2> {
-    let dim = vec.shape()[0];
-    let mut mat = Array::zeros((dim, dim));
-    for i in 0..dim {
-        mat[[i, i]] = vec[i];
+    // `--guess core|sad|huckel|gwh`, `--guess read <file>` for orbitals
+    // written by `--write-orbitals <file>`, or `--guess fchk <file>`, the
+    // orbitals possibly being in another basis. Read orbitals are not
+    // rotated into the orientation `--symmetry` picks, so the two don't mix.
+    if let Some(i) = flag("--guess") {
+        if symmetry.is_some() && ["read", "fchk"].contains(&value(&args, i, 1)) {
+            usage_error(&format!(
+                "--guess {} cannot be combined with --symmetry",
+                value(&args, i, 1)
+            ));
+        }
+    }
+    let guess = match flag("--guess") {
+        Some(i) if value(&args, i, 1) == "fchk" => {
+            let path = value(&args, i, 2);
+            let checkpoint = fchk::read_fchk(path).unwrap_or_else(|e| io_error(path, e));
+            guess::Guess::Read {
+                basis: checkpoint.basis,
+                C: checkpoint.C_alpha,
+            }
+        }
+        Some(i) if value(&args, i, 1) == "read" => {
+            let path = value(&args, i, 2);
+            let (name, coefficients) =
+                guess::read_orbitals(path).unwrap_or_else(|e| io_error(path, e));
+            guess::Guess::Read {
+                basis: basis::Basis::try_new(&atomnos, atomcoords, &name)
+                    .unwrap_or_else(|e| io_error(path, e)),
+                C: coefficients,
+            }
+        }
+        Some(i) => guess::Guess::from_name(value(&args, i, 1))
+            .unwrap_or_else(|| usage_error(&format!("unknown guess {}", value(&args, i, 1)))),
+        None => guess::Guess::SAD,
+    };
+    if let Err(e) = guess.check(nocc) {
+        usage_error(&format!("--guess: {}", e));
+    }
+    // Aids for hard cases: `--level-shift <hartree>`, `--damping <fraction>
+    // <iterations>`, `--smearing fermi|gaussian <width>`, and
+    // `--second-order <iterations>` to switch to the second-order optimizer
+    // once the orbital gradient stalls for that many iterations.
+    let number = |i: usize| parse::<f64>(&args, i, 1);
+    let (damping, damping_iterations) = match flag("--damping") {
+        Some(i) => (number(i), parse(&args, i, 2)),
+        None => (0.0, 0),
+    };
+    let smearing = flag("--smearing").map(|i| match value(&args, i, 1) {
+        "fermi" => scf::Smearing::FermiDirac(parse(&args, i, 2)),
+        "gaussian" => scf::Smearing::Gaussian(parse(&args, i, 2)),
+        other => usage_error(&format!("unknown smearing {}", other)),
+    });
+    // `--checkpoint <file>` saves the SCF every `--checkpoint-interval <n>`
+    // iterations (default 1), and `--restart` picks up from that file.
+    let checkpoint_options = flag("--checkpoint").map(|i| checkpoint::CheckpointOptions {
+        path: value(&args, i, 1).into(),
+        interval: flag("--checkpoint-interval").map_or(1, |j| parse(&args, j, 1)),
+    });
+    let restart = if args.iter().any(|arg| arg == "--restart") {
+        let path = &checkpoint_options
+            .as_ref()
+            .unwrap_or_else(|| usage_error("--restart needs --checkpoint <file>"))
+            .path;
+        let restart = checkpoint::read_restart(
+            path,
+            match flag("--functional") {
+                Some(_) => checkpoint::SCFKind::RKS,
+                None => checkpoint::SCFKind::RHF,
+            },
+            &basis_set,
+            atomcoords,
+            &atomnos,
+        );
+        Some(restart.unwrap_or_else(|e| io_error(&path.to_string_lossy(), e)))
+    } else {
+        None
+    };
+    let options = scf::SCFOptions {
+        guess,
+        level_shift: flag("--level-shift").map_or(0.0, number),
+        damping,
+        damping_iterations,
+        smearing,
+        second_order: flag("--second-order").map(|i| soscf::SOSCFOptions {
+            stall_iterations: parse(&args, i, 1),
+            ..Default::default()
+        }),
+        verbose: true,
+        checkpoint: checkpoint_options.clone(),
+        restart,
+        symmetry: adaptation.clone(),
+        docc,
+        ..Default::default()
+    };
+    // `--functional <name>` runs restricted Kohn-Sham instead of RHF. The
+    // stability analysis and the optimizer are for RHF only.
+    let functional = flag("--functional").map(|i| {
+        let name = value(&args, i, 1);
+        xc::Functional::from_name(name)
+            .unwrap_or_else(|| usage_error(&format!("unknown functional {}", name)))
+    });
+    if functional.is_some() {
+        for rhf_only in ["--second-order", "--stability", "--opt"] {
+            if flag(rhf_only).is_some() {
+                usage_error(&format!(
+                    "{} cannot be combined with --functional",
+                    rhf_only
+                ));
+            }
+        }
+    }
+    let method = functional.as_ref().map_or("hf".to_string(), |functional| {
+        functional.name.to_lowercase()
+    });
+    let result = match &functional {
+        Some(functional) => {
+            let ks_options = dft::KSOptions {
+                scf: options.clone(),
+                functional: functional.clone(),
+                ..Default::default()
+            };
+            dft::rks(&basis_set, atomcoords, &atomnos, nocc, &ks_options)
+        }
+        None => scf::rhf(&basis_set, atomcoords, &atomnos, nocc, &options),
+    };
+    if let Some(error) = &result.checkpoint_error {
+        eprintln!("warning: could not write checkpoint {}", error);
+    }
+    println!("SCF energy: {:20.12}", result.energy);
+    if let Some(adaptation) = &adaptation {
+        let names = adaptation.point_group.irreps();
+        println!("Orbital energies:");
+        for (p, irrep) in adaptation.orbital_irreps(&result.C).into_iter().enumerate() {
+            let occupied = if p < nocc { "occ" } else { "vir" };
+            println!(
+                "{:4} {:4} {} {:16.10}",
+                p + 1,
+                names[irrep],
+                occupied,
+                result.eps[p]
+            );
+        }
+    }
+    let mulliken = population::mulliken(&basis_set, &atomnos, &result.D, &result.D);
+    let lowdin = population::lowdin(&basis_set, &atomnos, &result.D, &result.D);
+    let natural = population::natural(&basis_set, &atomnos, &result.D, &result.D);
+    println!("Atomic charges:   Mulliken     Lowdin    Natural");
+    for (i, &atomno) in atomnos.iter().enumerate() {
+        println!(
+            "{:4} {:2} {:14.6} {:10.6} {:10.6}",
+            i,
+            elements::symbol(atomno),
+            mulliken.charges[i],
+            lowdin.charges[i],
+            natural.charges[i]
+        );
+    }
+    let mayer = population::mayer_bond_orders(&basis_set, &result.D, &result.D);
+    println!("Mayer bond orders:");
+    for i in 0..natom {
+        for j in 0..i {
+            if mayer[[i, j]] > 0.1 {
+                println!(
+                    "{:2}{} - {:2}{} {:10.6}",
+                    elements::symbol(atomnos[i]),
+                    i,
+                    elements::symbol(atomnos[j]),
+                    j,
+                    mayer[[i, j]]
+                );
+            }
+        }
+    }
+    if args.iter().any(|arg| arg == "--stability") {
+        let stability_options = stability::StabilityOptions::default();
+        let analysis =
+            stability::rhf_stability(&basis_set, &result, &options.jk, &stability_options);
+        let lowest = |result: &davidson::DavidsonResult| result.eigenvalues[0];
+        println!(
+            "Lowest RHF -> RHF eigenvalue: {:14.8}",
+            lowest(&analysis.internal)
+        );
+        if let Some(rhf_to_uhf) = &analysis.rhf_to_uhf {
+            println!("Lowest RHF -> UHF eigenvalue: {:14.8}", lowest(rhf_to_uhf));
+        }
+        println!(
+            "Lowest real -> complex eigenvalue: {:14.8}",
+            lowest(&analysis.real_to_complex)
+        );
+        let followed = stability::follow_rhf_instability(
+            &basis_set,
+            atomcoords,
+            &atomnos,
+            &result,
+            &analysis,
+            &options,
+            &stability_options,
+        );
+        if let Some(followed) = followed {
+            println!(
+                "SCF energy after following the instability: {:20.12}",
+                followed.energy
+            );
+        }
+        let followed = stability::follow_rhf_to_uhf(
+            &basis_set,
+            atomcoords,
+            &atomnos,
+            &result,
+            &analysis,
+            &options,
+            &stability_options,
+        );
+        if let Some(followed) = followed {
+            println!(
+                "UHF energy after following the instability: {:20.12}",
+                followed.energy
+            );
+        }
+    }
+    // Cube files: `--cube-orbital <n> <file>` (numbered from 1),
+    // `--cube-density <file>` and `--cube-esp <file>`, on a grid set by
+    // `--cube-spacing` and `--cube-padding` (bohr).
+    let cube_options = cube::CubeOptions {
+        spacing: flag("--cube-spacing").map_or(0.2, number),
+        padding: flag("--cube-padding").map_or(4.0, number),
+    };
+    let cube_grid = cube::CubeGrid::new(atomcoords, &cube_options);
+    let total_density = 2.0 * &result.D;
+    if let Some(i) = flag("--cube-orbital") {
+        let nmo = result.C.ncols();
+        let index = match parse::<usize>(&args, i, 1) {
+            n if (1..=nmo).contains(&n) => n - 1,
+            n => usage_error(&format!("--cube-orbital: no orbital {} of {}", n, nmo)),
+        };
+        let values = cube::orbitals(&basis_set, &cube_grid, &result.C, &[index]);
+        let title = format!("Orbital {}", index + 1);
+        let path = value(&args, i, 2);
+        cube::write_cube(
+            path,
+            &title,
+            &cube_grid,
+            atomcoords,
+            &atomnos,
+            &values,
+            Some(&[index]),
+        )
+        .unwrap_or_else(|e| io_error(path, e));
+    }
+    if let Some(i) = flag("--cube-density") {
+        let values =
+            cube::density(&basis_set, &cube_grid, &total_density).insert_axis(ndarray::Axis(1));
+        let path = value(&args, i, 1);
+        cube::write_cube(
+            path,
+            "Total density",
+            &cube_grid,
+            atomcoords,
+            &atomnos,
+            &values,
+            None,
+        )
+        .unwrap_or_else(|e| io_error(path, e));
+    }
+    if let Some(i) = flag("--cube-esp") {
+        let values = cube::electrostatic_potential(
+            &basis_set,
+            atomcoords,
+            &atomnos,
+            &total_density,
+            &cube_grid.points(),
+        )
+        .insert_axis(ndarray::Axis(1));
+        let path = value(&args, i, 1);
+        cube::write_cube(
+            path,
+            "Electrostatic potential",
+            &cube_grid,
+            atomcoords,
+            &atomnos,
+            &values,
+            None,
+        )
+        .unwrap_or_else(|e| io_error(path, e));
+    }
+    if let Some(i) = flag("--fchk") {
+        let checkpoint = fchk::Fchk::from_rhf(&basis_set, atomcoords, &atomnos, &result);
+        let path = value(&args, i, 1);
+        fchk::write_fchk(path, &checkpoint).unwrap_or_else(|e| io_error(path, e));
+    }
+    if let Some(i) = flag("--molden") {
+        let path = value(&args, i, 1);
+        molden::write_rhf(path, &basis_set, atomcoords, &atomnos, &result)
+            .unwrap_or_else(|e| io_error(path, e));
+    }
+    if let Some(i) = flag("--write-orbitals") {
+        let path = value(&args, i, 1);
+        guess::write_orbitals(path, basis_set.name(), &result.C)
+            .unwrap_or_else(|e| io_error(path, e));
     }
-    mat
-}
-
-fn build_density(C: &Array<f64, Ix2>, nocc: usize) -> Array<f64, Ix2> {
-    C.slice_axis(Axis(1), Slice::from(..nocc))
-        .dot(&C.slice_axis(Axis(1), Slice::from(..nocc)).t())
-}
 
-fn calc_elec_energy(D: &Array<f64, Ix2>, H: &Array<f64, Ix2>, F: &Array<f64, Ix2>) -> f64 {
-    ((H + F) * D).sum()
-}
+    // D3 dispersion, either `--d3bj <pars>` or `--d3 <pars> <r0ab>`, with
+    // the reference data files distributed with dftd3.
+    let dispersion = args
+        .iter()
+        .position(|arg| arg == "--d3" || arg == "--d3bj")
+        .map(|i| {
+            let read = |n: usize| {
+                let path = value(&args, i, n);
+                std::fs::read_to_string(path).unwrap_or_else(|e| io_error(path, e))
+            };
+            let mut reference = dispersion::D3Reference::from_pars(&read(1))
+                .unwrap_or_else(|e| io_error(&args[i + 1], e));
+            let damping = if args[i] == "--d3" {
+                reference
+                    .set_r0ab(&read(2))
+                    .unwrap_or_else(|e| io_error(&args[i + 2], e));
+                dispersion::Damping::zero(&method)
+            } else {
+                dispersion::Damping::becke_johnson(&method)
+            }
+            .unwrap_or_else(|| usage_error(&format!("{}: no parameters for {}", args[i], method)));
+            reference
+                .check(&atomnos, &damping)
+                .unwrap_or_else(|e| usage_error(&format!("{}: {}", args[i], e)));
+            (damping, reference)
+        });
+    if let Some((damping, reference)) = &dispersion {
+        let d3 = dispersion::d3(atomcoords, &atomnos, damping, reference);
+        println!("D3 energy: {:20.12}", d3.energy);
+        println!("Total energy: {:20.12}", result.energy + d3.energy);
+    }
 
-fn build_fock(
-    F: &mut Array<f64, Ix2>,
-    mut J: &mut Array<f64, Ix2>,
-    mut K: &mut Array<f64, Ix2>,
-    D: &Array<f64, Ix2>,
-    H: &Array<f64, Ix2>,
-    basis_set: &basis::Basis,
-) {
-    basis::JK_direct(&mut J, &mut K, &basis_set, &D);
-    // F = (2.0_f64 * J - K) + H;
-    F.fill(0.0);
-    let nbasis = D.shape()[0];
-    for i in 0..nbasis {
-        for j in 0..nbasis {
-            F[[i, j]] += 2.0 * J[[i, j]] - K[[i, j]] + H[[i, j]];
+    if flag("--opt").is_some() {
+        let mut basis_set = basis_set;
+        let opt_scf_options = scf::SCFOptions {
+            verbose: false,
+            checkpoint: None,
+            restart: None,
+            symmetry: None,
+            docc: None,
+            ..options.clone()
+        };
+        let opt_options = optimize::OptimizerOptions {
+            trajectory: Some("rchem_opt.xyz".to_string()),
+            verbose: true,
+            ..Default::default()
+        };
+        let opt = optimize::optimize(atomcoords, &atomnos, &opt_options, |coords| {
+            basis_set.set_atomcoords(coords);
+            let scf = scf::rhf(&basis_set, coords, &atomnos, nocc, &opt_scf_options);
+            let mut grad = gradient::rhf_gradient(&basis_set, coords, &atomnos, &scf);
+            match &dispersion {
+                Some((damping, reference)) => {
+                    let d3 = dispersion::d3(coords, &atomnos, damping, reference);
+                    grad.values += &d3.gradient;
+                    (scf.energy + d3.energy, grad)
+                }
+                None => (scf.energy, grad),
+            }
+        });
+        if let Some(error) = &opt.trajectory_error {
+            eprintln!("warning: could not write trajectory {}", error);
+        }
+        println!("Optimized energy: {:20.12}", opt.energy);
+        for coords in opt.atomcoords.iter() {
+            println!(
+                "{:16.10} {:16.10} {:16.10}",
+                coords[0], coords[1], coords[2]
+            );
         }
     }
 }
-
-// fn build_fock_inmem(
-//     D: &Array<f64, Ix2>,
-//     H: &Array<f64, Ix2>,
-//     I: &Array<f64, Ix4>,
-// ) -> Array<f64, Ix2> {
-//     let (J, K) = basis::JK_inmem(&I, &D);
-//     (2.0 * J - K) + H
-// }
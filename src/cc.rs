@@ -0,0 +1,579 @@
+#![allow(non_snake_case)]
+
+//! Spin-orbital coupled-cluster singles and doubles (CCSD) and the
+//! perturbative triples correction (T), following the formulation of J. F.
+//! Stanton, J. Gauss, J. D. Watts and R. J. Bartlett, J. Chem. Phys. 94, 4334
+//! (1991) and the Crawford group programming projects.
+
+use ndarray::{Array, ArrayView2, Ix1, Ix2, Ix4};
+
+use crate::basis;
+use crate::diis::DIIS;
+use crate::scf::{JKAlgorithm, RHFResult, UHFResult};
+use crate::transform::AOIntegrals;
+
+/// Antisymmetrized two-electron integrals and the Fock matrix over spin
+/// orbitals, ordered with all occupied orbitals first (alpha then beta),
+/// followed by all virtual orbitals (alpha then beta).
+#[derive(Clone, Debug)]
+pub struct SpinOrbitalIntegrals {
+    pub nocc: usize,
+    pub nvirt: usize,
+    pub fock: Array<f64, Ix2>,
+    /// <pq||rs> = <pq|rs> - <pq|sr>
+    pub g: Array<f64, Ix4>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Spin {
+    Alpha,
+    Beta,
+}
+
+impl SpinOrbitalIntegrals {
+    pub fn from_rhf(
+        basis_set: &basis::Basis,
+        scf: &RHFResult,
+        algorithm: &JKAlgorithm,
+    ) -> SpinOrbitalIntegrals {
+        SpinOrbitalIntegrals::build(
+            basis_set,
+            algorithm,
+            [&scf.C, &scf.C],
+            [&scf.F, &scf.F],
+            [scf.nocc, scf.nocc],
+        )
+    }
+
+    pub fn from_uhf(
+        basis_set: &basis::Basis,
+        scf: &UHFResult,
+        algorithm: &JKAlgorithm,
+    ) -> SpinOrbitalIntegrals {
+        SpinOrbitalIntegrals::build(
+            basis_set,
+            algorithm,
+            [&scf.C_alpha, &scf.C_beta],
+            [&scf.F_alpha, &scf.F_beta],
+            [scf.nalpha, scf.nbeta],
+        )
+    }
+
+    fn build(
+        basis_set: &basis::Basis,
+        algorithm: &JKAlgorithm,
+        C: [&Array<f64, Ix2>; 2],
+        F: [&Array<f64, Ix2>; 2],
+        nocc: [usize; 2],
+    ) -> SpinOrbitalIntegrals {
+        let nmo = C[0].shape()[1];
+        let integrals = AOIntegrals::new(basis_set, algorithm);
+        let (Ca, Cb): (ArrayView2<f64>, ArrayView2<f64>) = (C[0].view(), C[1].view());
+        let aaaa = integrals.transform(&Ca, &Ca, &Ca, &Ca);
+        let bbbb = integrals.transform(&Cb, &Cb, &Cb, &Cb);
+        let aabb = integrals.transform(&Ca, &Ca, &Cb, &Cb);
+        let fock_mo = [C[0].t().dot(F[0]).dot(C[0]), C[1].t().dot(F[1]).dot(C[1])];
+
+        let mut orbitals: Vec<(Spin, usize)> = Vec::new();
+        orbitals.extend((0..nocc[0]).map(|p| (Spin::Alpha, p)));
+        orbitals.extend((0..nocc[1]).map(|p| (Spin::Beta, p)));
+        orbitals.extend((nocc[0]..nmo).map(|p| (Spin::Alpha, p)));
+        orbitals.extend((nocc[1]..nmo).map(|p| (Spin::Beta, p)));
+        let nso = orbitals.len();
+
+        // <pq|rs> = (pr|qs), which vanishes unless p and r, and q and s,
+        // have the same spin.
+        let physicist = |p: usize, q: usize, r: usize, s: usize| -> f64 {
+            let (sp, P) = orbitals[p];
+            let (sq, Q) = orbitals[q];
+            let (sr, R) = orbitals[r];
+            let (ss, S) = orbitals[s];
+            if sp != sr || sq != ss {
+                return 0.0;
+            }
+            match (sp, sq) {
+                (Spin::Alpha, Spin::Alpha) => aaaa[[P, R, Q, S]],
+                (Spin::Beta, Spin::Beta) => bbbb[[P, R, Q, S]],
+                (Spin::Alpha, Spin::Beta) => aabb[[P, R, Q, S]],
+                (Spin::Beta, Spin::Alpha) => aabb[[Q, S, P, R]],
+            }
+        };
+        let mut g: Array<f64, _> = Array::zeros((nso, nso, nso, nso));
+        for p in 0..nso {
+            for q in 0..nso {
+                for r in 0..nso {
+                    for s in 0..nso {
+                        g[[p, q, r, s]] = physicist(p, q, r, s) - physicist(p, q, s, r);
+                    }
+                }
+            }
+        }
+        let mut fock: Array<f64, _> = Array::zeros((nso, nso));
+        for p in 0..nso {
+            for q in 0..nso {
+                let (sp, P) = orbitals[p];
+                let (sq, Q) = orbitals[q];
+                if sp == sq {
+                    fock[[p, q]] = match sp {
+                        Spin::Alpha => fock_mo[0][[P, Q]],
+                        Spin::Beta => fock_mo[1][[P, Q]],
+                    };
+                }
+            }
+        }
+
+        SpinOrbitalIntegrals {
+            nocc: nocc[0] + nocc[1],
+            nvirt: nso - nocc[0] - nocc[1],
+            fock,
+            g,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CCOptions {
+    pub max_iterations: usize,
+    /// Convergence threshold on the change in the correlation energy.
+    pub thresh_e: f64,
+    /// Convergence threshold on the RMS change in the amplitudes.
+    pub thresh_t: f64,
+    /// Number of amplitude vectors kept for DIIS; 0 disables DIIS.
+    pub diis_size: usize,
+    pub verbose: bool,
+}
+
+impl Default for CCOptions {
+    fn default() -> CCOptions {
+        CCOptions {
+            max_iterations: 100,
+            thresh_e: 1.0e-10,
+            thresh_t: 1.0e-8,
+            diis_size: 8,
+            verbose: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CCSDResult {
+    pub correlation_energy: f64,
+    /// Singles amplitudes t_i^a, with shape `(nocc, nvirt)`.
+    pub t1: Array<f64, Ix2>,
+    /// Doubles amplitudes t_ij^ab, with shape `(nocc, nocc, nvirt, nvirt)`.
+    pub t2: Array<f64, Ix4>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+fn ccsd_energy(ints: &SpinOrbitalIntegrals, t1: &Array<f64, Ix2>, t2: &Array<f64, Ix4>) -> f64 {
+    let (o, v) = (ints.nocc, ints.nvirt);
+    let (f, g) = (&ints.fock, &ints.g);
+    let mut energy = 0.0;
+    for i in 0..o {
+        for a in 0..v {
+            energy += f[[i, o + a]] * t1[[i, a]];
+            for j in 0..o {
+                for b in 0..v {
+                    let ijab = g[[i, j, o + a, o + b]];
+                    energy += 0.25 * ijab * t2[[i, j, a, b]] + 0.5 * ijab * t1[[i, a]] * t1[[j, b]];
+                }
+            }
+        }
+    }
+    energy
+}
+
+fn pack(t1: &Array<f64, Ix2>, t2: &Array<f64, Ix4>) -> Array<f64, Ix1> {
+    t1.iter().chain(t2.iter()).cloned().collect()
+}
+
+fn unpack(vector: &Array<f64, Ix1>, o: usize, v: usize) -> (Array<f64, Ix2>, Array<f64, Ix4>) {
+    let n1 = o * v;
+    let t1 = Array::from_shape_vec((o, v), vector.slice(ndarray::s![..n1]).to_vec()).unwrap();
+    let t2 = Array::from_shape_vec((o, o, v, v), vector.slice(ndarray::s![n1..]).to_vec()).unwrap();
+    (t1, t2)
+}
+
+/// One application of the CCSD amplitude equations, giving updated T1 and T2
+/// from the current ones.
+fn update_amplitudes(
+    ints: &SpinOrbitalIntegrals,
+    t1: &Array<f64, Ix2>,
+    t2: &Array<f64, Ix4>,
+) -> (Array<f64, Ix2>, Array<f64, Ix4>) {
+    let (o, v) = (ints.nocc, ints.nvirt);
+    let (f, g) = (&ints.fock, &ints.g);
+
+    // effective doubles
+    let mut tau: Array<f64, _> = Array::zeros((o, o, v, v));
+    let mut tau_tilde: Array<f64, _> = Array::zeros((o, o, v, v));
+    for ((i, j, a, b), t) in t2.indexed_iter() {
+        let singles = t1[[i, a]] * t1[[j, b]] - t1[[i, b]] * t1[[j, a]];
+        tau[[i, j, a, b]] = t + singles;
+        tau_tilde[[i, j, a, b]] = t + 0.5 * singles;
+    }
+
+    // one-particle intermediates
+    let mut Fae: Array<f64, _> = Array::zeros((v, v));
+    for a in 0..v {
+        for e in 0..v {
+            let mut val = if a == e { 0.0 } else { f[[o + a, o + e]] };
+            for m in 0..o {
+                val -= 0.5 * f[[m, o + e]] * t1[[m, a]];
+                for ff in 0..v {
+                    val += t1[[m, ff]] * g[[m, o + a, o + ff, o + e]];
+                    for n in 0..o {
+                        val -= 0.5 * tau_tilde[[m, n, a, ff]] * g[[m, n, o + e, o + ff]];
+                    }
+                }
+            }
+            Fae[[a, e]] = val;
+        }
+    }
+    let mut Fmi: Array<f64, _> = Array::zeros((o, o));
+    for m in 0..o {
+        for i in 0..o {
+            let mut val = if m == i { 0.0 } else { f[[m, i]] };
+            for e in 0..v {
+                val += 0.5 * t1[[i, e]] * f[[m, o + e]];
+                for n in 0..o {
+                    val += t1[[n, e]] * g[[m, n, i, o + e]];
+                    for ff in 0..v {
+                        val += 0.5 * tau_tilde[[i, n, e, ff]] * g[[m, n, o + e, o + ff]];
+                    }
+                }
+            }
+            Fmi[[m, i]] = val;
+        }
+    }
+    let mut Fme: Array<f64, _> = Array::zeros((o, v));
+    for m in 0..o {
+        for e in 0..v {
+            let mut val = f[[m, o + e]];
+            for n in 0..o {
+                for ff in 0..v {
+                    val += t1[[n, ff]] * g[[m, n, o + e, o + ff]];
+                }
+            }
+            Fme[[m, e]] = val;
+        }
+    }
+
+    // two-particle intermediates
+    let mut Wmnij: Array<f64, _> = Array::zeros((o, o, o, o));
+    for m in 0..o {
+        for n in 0..o {
+            for i in 0..o {
+                for j in 0..o {
+                    let mut val = g[[m, n, i, j]];
+                    for e in 0..v {
+                        val += t1[[j, e]] * g[[m, n, i, o + e]] - t1[[i, e]] * g[[m, n, j, o + e]];
+                        for ff in 0..v {
+                            val += 0.25 * tau[[i, j, e, ff]] * g[[m, n, o + e, o + ff]];
+                        }
+                    }
+                    Wmnij[[m, n, i, j]] = val;
+                }
+            }
+        }
+    }
+    let mut Wabef: Array<f64, _> = Array::zeros((v, v, v, v));
+    for a in 0..v {
+        for b in 0..v {
+            for e in 0..v {
+                for ff in 0..v {
+                    let mut val = g[[o + a, o + b, o + e, o + ff]];
+                    for m in 0..o {
+                        val -= t1[[m, b]] * g[[o + a, m, o + e, o + ff]]
+                            - t1[[m, a]] * g[[o + b, m, o + e, o + ff]];
+                        for n in 0..o {
+                            val += 0.25 * tau[[m, n, a, b]] * g[[m, n, o + e, o + ff]];
+                        }
+                    }
+                    Wabef[[a, b, e, ff]] = val;
+                }
+            }
+        }
+    }
+    let mut Wmbej: Array<f64, _> = Array::zeros((o, v, v, o));
+    for m in 0..o {
+        for b in 0..v {
+            for e in 0..v {
+                for j in 0..o {
+                    let mut val = g[[m, o + b, o + e, j]];
+                    for ff in 0..v {
+                        val += t1[[j, ff]] * g[[m, o + b, o + e, o + ff]];
+                    }
+                    for n in 0..o {
+                        val -= t1[[n, b]] * g[[m, n, o + e, j]];
+                        for ff in 0..v {
+                            val -= (0.5 * t2[[j, n, ff, b]] + t1[[j, ff]] * t1[[n, b]])
+                                * g[[m, n, o + e, o + ff]];
+                        }
+                    }
+                    Wmbej[[m, b, e, j]] = val;
+                }
+            }
+        }
+    }
+
+    // singles
+    let mut t1_new: Array<f64, _> = Array::zeros((o, v));
+    for i in 0..o {
+        for a in 0..v {
+            let mut val = f[[i, o + a]];
+            for e in 0..v {
+                val += t1[[i, e]] * Fae[[a, e]];
+            }
+            for m in 0..o {
+                val -= t1[[m, a]] * Fmi[[m, i]];
+                for e in 0..v {
+                    val += t2[[i, m, a, e]] * Fme[[m, e]];
+                    val -= t1[[m, e]] * g[[m, o + a, i, o + e]];
+                    for ff in 0..v {
+                        val -= 0.5 * t2[[i, m, e, ff]] * g[[m, o + a, o + e, o + ff]];
+                    }
+                    for n in 0..o {
+                        val -= 0.5 * t2[[m, n, a, e]] * g[[n, m, o + e, i]];
+                    }
+                }
+            }
+            t1_new[[i, a]] = val / (f[[i, i]] - f[[o + a, o + a]]);
+        }
+    }
+
+    // doubles
+    let mut Fae_t = Fae.clone();
+    for b in 0..v {
+        for e in 0..v {
+            for m in 0..o {
+                Fae_t[[b, e]] -= 0.5 * t1[[m, b]] * Fme[[m, e]];
+            }
+        }
+    }
+    let mut Fmi_t = Fmi.clone();
+    for m in 0..o {
+        for j in 0..o {
+            for e in 0..v {
+                Fmi_t[[m, j]] += 0.5 * t1[[j, e]] * Fme[[m, e]];
+            }
+        }
+    }
+    // Terms that appear under the permutation operators P(ab) and P(ij),
+    // computed once for every index combination.
+    let mut term_ab: Array<f64, _> = Array::zeros((o, o, v, v));
+    let mut term_ij: Array<f64, _> = Array::zeros((o, o, v, v));
+    let mut term_ijab: Array<f64, _> = Array::zeros((o, o, v, v));
+    for i in 0..o {
+        for j in 0..o {
+            for a in 0..v {
+                for b in 0..v {
+                    let mut val_ab = 0.0;
+                    let mut val_ij = 0.0;
+                    for e in 0..v {
+                        val_ab += t2[[i, j, a, e]] * Fae_t[[b, e]];
+                        val_ij += t1[[i, e]] * g[[o + a, o + b, o + e, j]];
+                    }
+                    let mut val_ijab = 0.0;
+                    for m in 0..o {
+                        val_ab -= t1[[m, a]] * g[[m, o + b, i, j]];
+                        val_ij -= t2[[i, m, a, b]] * Fmi_t[[m, j]];
+                        for e in 0..v {
+                            val_ijab += t2[[i, m, a, e]] * Wmbej[[m, b, e, j]]
+                                - t1[[i, e]] * t1[[m, a]] * g[[m, o + b, o + e, j]];
+                        }
+                    }
+                    term_ab[[i, j, a, b]] = val_ab;
+                    term_ij[[i, j, a, b]] = val_ij;
+                    term_ijab[[i, j, a, b]] = val_ijab;
+                }
+            }
+        }
+    }
+    let mut t2_new: Array<f64, _> = Array::zeros((o, o, v, v));
+    for i in 0..o {
+        for j in 0..o {
+            for a in 0..v {
+                for b in 0..v {
+                    let mut val = g[[i, j, o + a, o + b]];
+                    val += term_ab[[i, j, a, b]] - term_ab[[i, j, b, a]];
+                    val += term_ij[[i, j, a, b]] - term_ij[[j, i, a, b]];
+                    val +=
+                        term_ijab[[i, j, a, b]] - term_ijab[[j, i, a, b]] - term_ijab[[i, j, b, a]]
+                            + term_ijab[[j, i, b, a]];
+                    for m in 0..o {
+                        for n in 0..o {
+                            val += 0.5 * tau[[m, n, a, b]] * Wmnij[[m, n, i, j]];
+                        }
+                    }
+                    for e in 0..v {
+                        for ff in 0..v {
+                            val += 0.5 * tau[[i, j, e, ff]] * Wabef[[a, b, e, ff]];
+                        }
+                    }
+                    let denominator = f[[i, i]] + f[[j, j]] - f[[o + a, o + a]] - f[[o + b, o + b]];
+                    t2_new[[i, j, a, b]] = val / denominator;
+                }
+            }
+        }
+    }
+
+    (t1_new, t2_new)
+}
+
+/// Iterate the CCSD equations to self-consistency, starting from the MP2
+/// amplitudes.
+pub fn ccsd(ints: &SpinOrbitalIntegrals, options: &CCOptions) -> CCSDResult {
+    let (o, v) = (ints.nocc, ints.nvirt);
+    let (f, g) = (&ints.fock, &ints.g);
+    let mut t1: Array<f64, _> = Array::zeros((o, v));
+    let mut t2: Array<f64, _> = Array::zeros((o, o, v, v));
+    for ((i, j, a, b), t) in t2.indexed_iter_mut() {
+        *t = g[[i, j, o + a, o + b]]
+            / (f[[i, i]] + f[[j, j]] - f[[o + a, o + a]] - f[[o + b, o + b]]);
+    }
+    let mut energy = ccsd_energy(ints, &t1, &t2);
+    if options.verbose {
+        println!("MP2 correlation energy: {:20.12}", energy);
+    }
+    let mut diis = if options.diis_size > 0 {
+        Some(DIIS::new(options.diis_size))
+    } else {
+        None
+    };
+    let mut iteration = 0;
+    let mut converged = false;
+
+    while iteration < options.max_iterations {
+        let (t1_new, t2_new) = update_amplitudes(ints, &t1, &t2);
+        let old = pack(&t1, &t2);
+        let mut new = pack(&t1_new, &t2_new);
+        let delta = &new - &old;
+        let rms_t = (delta.dot(&delta) / delta.len() as f64).sqrt();
+        if let Some(diis) = diis.as_mut() {
+            diis.push(new, delta);
+            new = diis.extrapolate();
+        }
+        let (t1_next, t2_next) = unpack(&new, o, v);
+        t1 = t1_next;
+        t2 = t2_next;
+        let energy_old = energy;
+        energy = ccsd_energy(ints, &t1, &t2);
+        let delta_e = energy - energy_old;
+        iteration += 1;
+        if options.verbose {
+            println!(
+                "{:4} {:20.12} {:20.12} {:20.12}",
+                iteration, energy, delta_e, rms_t
+            );
+        }
+        if delta_e.abs() < options.thresh_e && rms_t < options.thresh_t {
+            converged = true;
+            break;
+        }
+    }
+
+    CCSDResult {
+        correlation_energy: energy,
+        t1,
+        t2,
+        iterations: iteration,
+        converged,
+    }
+}
+
+/// The (T) correction to the CCSD energy,
+///
+/// E(T) = 1/36 sum_ijkabc t_ijk^abc(c) D_ijk^abc (t_ijk^abc(c) + t_ijk^abc(d))
+///
+/// with the connected and disconnected triples built from the converged
+/// CCSD amplitudes. Since the summand is symmetric under permutations of
+/// ijk and of abc, only i < j < k and a < b < c are visited.
+pub fn triples_correction(ints: &SpinOrbitalIntegrals, ccsd: &CCSDResult) -> f64 {
+    let (o, v) = (ints.nocc, ints.nvirt);
+    let (f, g) = (&ints.fock, &ints.g);
+    let (t1, t2) = (&ccsd.t1, &ccsd.t2);
+
+    let disconnected = |i: usize, j: usize, k: usize, a: usize, b: usize, c: usize| -> f64 {
+        t1[[i, a]] * g[[j, k, o + b, o + c]]
+    };
+    let connected = |i: usize, j: usize, k: usize, a: usize, b: usize, c: usize| -> f64 {
+        let mut val = 0.0;
+        for e in 0..v {
+            val += t2[[j, k, a, e]] * g[[o + e, i, o + b, o + c]];
+        }
+        for m in 0..o {
+            val -= t2[[i, m, b, c]] * g[[m, o + a, j, k]];
+        }
+        val
+    };
+    // P(i/jk) P(a/bc), where P(p/qr) X(pqr) = X(pqr) - X(qpr) - X(rqp)
+    let permute = |x: &dyn Fn(usize, usize, usize, usize, usize, usize) -> f64,
+                   i: usize,
+                   j: usize,
+                   k: usize,
+                   a: usize,
+                   b: usize,
+                   c: usize|
+     -> f64 {
+        let occ = [(i, j, k, 1.0), (j, i, k, -1.0), (k, j, i, -1.0)];
+        let virt = [(a, b, c, 1.0), (b, a, c, -1.0), (c, b, a, -1.0)];
+        let mut val = 0.0;
+        for &(p, q, r, s1) in occ.iter() {
+            for &(x1, y1, z1, s2) in virt.iter() {
+                val += s1 * s2 * x(p, q, r, x1, y1, z1);
+            }
+        }
+        val
+    };
+
+    let mut energy = 0.0;
+    for i in 0..o {
+        for j in i + 1..o {
+            for k in j + 1..o {
+                for a in 0..v {
+                    for b in a + 1..v {
+                        for c in b + 1..v {
+                            let denominator = f[[i, i]] + f[[j, j]] + f[[k, k]]
+                                - f[[o + a, o + a]]
+                                - f[[o + b, o + b]]
+                                - f[[o + c, o + c]];
+                            let tc = permute(&connected, i, j, k, a, b, c);
+                            let td = permute(&disconnected, i, j, k, a, b, c);
+                            energy += tc * (tc + td) / denominator;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    energy
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scf;
+    use crate::testing;
+
+    #[test]
+    fn test_ccsd_t_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = scf::SCFOptions {
+            thresh_e: 1.0e-12,
+            thresh_d: 1.0e-10,
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let scf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let ints = SpinOrbitalIntegrals::from_rhf(&basis_set, &scf, &scf::JKAlgorithm::InMemory);
+        assert_eq!((ints.nocc, ints.nvirt), (10, 4));
+        let result = ccsd(&ints, &Default::default());
+        assert!(result.converged);
+        // Crawford programming projects #5 and #6
+        assert!((result.correlation_energy - -0.070680088376).abs() < 1.0e-6);
+        let e_t = triples_correction(&ints, &result);
+        assert!((e_t - -0.000099877272).abs() < 1.0e-8);
+    }
+}
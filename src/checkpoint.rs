@@ -0,0 +1,388 @@
+#![allow(non_snake_case)]
+
+//! Checkpointing SCF iterations to disk so that a calculation killed by a
+//! crash or a walltime limit can pick up where it stopped.
+//!
+//! A checkpoint records the densities, orbitals, DIIS subspace (unless DIIS
+//! is turned off) and iteration count, along with a fingerprint of the
+//! geometry and basis set. `read_restart` refuses checkpoints whose
+//! fingerprint or kind of SCF does not match the calculation being
+//! restarted.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ndarray::{Array, Ix1, Ix2};
+use serde::{Deserialize, Serialize};
+
+use crate::basis::Basis;
+use crate::diis::DIIS;
+
+const FORMAT_VERSION: usize = 1;
+
+#[derive(Clone, Debug)]
+pub struct CheckpointOptions {
+    pub path: PathBuf,
+    /// Write a checkpoint every this many iterations, as well as once the
+    /// SCF converges.
+    pub interval: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SCFKind {
+    RHF,
+    UHF,
+    RKS,
+}
+
+#[derive(Clone, Debug)]
+pub struct Checkpoint {
+    pub kind: SCFKind,
+    pub fingerprint: String,
+    /// Number of iterations completed.
+    pub iteration: usize,
+    /// Electronic energy at the last completed iteration.
+    pub e_elec: f64,
+    /// One density matrix for restricted SCF, alpha then beta for
+    /// unrestricted.
+    pub densities: Vec<Array<f64, Ix2>>,
+    /// Orbitals in the same order as the densities.
+    pub orbitals: Vec<Array<f64, Ix2>>,
+    pub diis: Option<DIIS>,
+}
+
+/// A stable hash (64-bit FNV-1a) of the atoms, their positions to 1e-8
+/// bohr, and every basis function, so that restarts can be checked against
+/// the calculation they are meant for.
+pub fn fingerprint(basis_set: &Basis, atomcoords: &[[f64; 3]], atomnos: &[u64]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    };
+    let rounded = |x: f64| ((x * 1.0e8).round() as i64).to_le_bytes();
+    feed(basis_set.name().as_bytes());
+    for (coords, &atomno) in atomcoords.iter().zip(atomnos) {
+        feed(&atomno.to_le_bytes());
+        for &x in coords {
+            feed(&rounded(x));
+        }
+    }
+    for cgto in &basis_set.cgtos {
+        feed(&(cgto.atom as u64).to_le_bytes());
+        for &power in &cgto.powers {
+            feed(&(power as u64).to_le_bytes());
+        }
+        for (primitive, coef) in cgto.primitives.iter().zip(&cgto.coefs) {
+            feed(&primitive.exponent.to_bits().to_le_bytes());
+            feed(&coef.to_bits().to_le_bytes());
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// The file layout, with matrices flattened in row-major order.
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    version: usize,
+    kind: SCFKind,
+    fingerprint: String,
+    iteration: usize,
+    e_elec: f64,
+    nbasis: usize,
+    densities: Vec<Vec<f64>>,
+    orbitals: Vec<Vec<f64>>,
+    diis: Option<DIISFile>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DIISFile {
+    max_vectors: usize,
+    vectors: Vec<Vec<f64>>,
+    errors: Vec<Vec<f64>>,
+}
+
+impl Checkpoint {
+    /// Write the checkpoint to a temporary file next to `path` and then move
+    /// it into place, so that being killed mid-write never leaves a
+    /// truncated checkpoint behind.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        check_matrices(self.kind, &self.densities, &self.orbitals)?;
+        let flatten = |M: &Array<f64, Ix2>| M.iter().cloned().collect::<Vec<_>>();
+        let flatten_vector = |v: &Array<f64, Ix1>| v.to_vec();
+        let file = CheckpointFile {
+            version: FORMAT_VERSION,
+            kind: self.kind,
+            fingerprint: self.fingerprint.clone(),
+            iteration: self.iteration,
+            e_elec: self.e_elec,
+            nbasis: self.densities[0].shape()[0],
+            densities: self.densities.iter().map(flatten).collect(),
+            orbitals: self.orbitals.iter().map(flatten).collect(),
+            diis: self.diis.as_ref().map(|diis| DIISFile {
+                max_vectors: diis.max_vectors(),
+                vectors: diis.vectors().iter().map(flatten_vector).collect(),
+                errors: diis.errors().iter().map(flatten_vector).collect(),
+            }),
+        };
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+        fs::write(&temporary, serde_json::to_string(&file)?)?;
+        fs::rename(&temporary, path)
+    }
+
+    /// Fail unless the checkpoint was written by a `kind` SCF.
+    pub fn check_kind(&self, kind: SCFKind) -> io::Result<()> {
+        if self.kind == kind {
+            Ok(())
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("checkpoint is for {:?}, not {:?}", self.kind, kind),
+            ))
+        }
+    }
+
+    pub fn read<P: AsRef<Path>>(path: P) -> io::Result<Checkpoint> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let file: CheckpointFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        if file.version != FORMAT_VERSION {
+            return Err(invalid("unsupported checkpoint version"));
+        }
+        let n = file.nbasis;
+        let square = |values: Vec<f64>| {
+            Array::from_shape_vec((n, n), values).map_err(|_| invalid("bad matrix dimensions"))
+        };
+        let densities = file
+            .densities
+            .into_iter()
+            .map(square)
+            .collect::<io::Result<Vec<_>>>()?;
+        let orbitals = file
+            .orbitals
+            .into_iter()
+            .map(square)
+            .collect::<io::Result<Vec<_>>>()?;
+        check_matrices(file.kind, &densities, &orbitals)?;
+        Ok(Checkpoint {
+            kind: file.kind,
+            fingerprint: file.fingerprint,
+            iteration: file.iteration,
+            e_elec: file.e_elec,
+            densities,
+            orbitals,
+            diis: file.diis.map(|diis| {
+                DIIS::from_subspace(
+                    diis.max_vectors,
+                    diis.vectors.into_iter().map(Array::from).collect(),
+                    diis.errors.into_iter().map(Array::from).collect(),
+                )
+            }),
+        })
+    }
+}
+
+/// Fail unless there are as many densities and orbitals as `kind` has
+/// spins, all square and of one size.
+fn check_matrices(
+    kind: SCFKind,
+    densities: &[Array<f64, Ix2>],
+    orbitals: &[Array<f64, Ix2>],
+) -> io::Result<()> {
+    let nspin = match kind {
+        SCFKind::RHF | SCFKind::RKS => 1,
+        SCFKind::UHF => 2,
+    };
+    if densities.len() != nspin || orbitals.len() != nspin {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "{:?} checkpoint needs {} density and orbital matrices",
+                kind, nspin
+            ),
+        ));
+    }
+    let n = densities[0].nrows();
+    if densities.iter().chain(orbitals).any(|M| M.dim() != (n, n)) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "checkpoint matrices differ in size",
+        ));
+    }
+    Ok(())
+}
+
+/// The restart in `restart`, or an error if it is not for a `kind` SCF.
+pub(crate) fn restart_for(
+    restart: &Option<Checkpoint>,
+    kind: SCFKind,
+) -> io::Result<Option<&Checkpoint>> {
+    match restart {
+        Some(restart) => restart.check_kind(kind).map(|()| Some(restart)),
+        None => Ok(None),
+    }
+}
+
+/// Read a checkpoint to restart a `kind` SCF for this basis and geometry,
+/// refusing it if it was written for anything else.
+pub fn read_restart<P: AsRef<Path>>(
+    path: P,
+    kind: SCFKind,
+    basis_set: &Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+) -> io::Result<Checkpoint> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let checkpoint = Checkpoint::read(path)?;
+    checkpoint.check_kind(kind)?;
+    let expected = fingerprint(basis_set, atomcoords, atomnos);
+    if checkpoint.fingerprint != expected {
+        return Err(invalid(format!(
+            "checkpoint fingerprint {} does not match this geometry and basis ({})",
+            checkpoint.fingerprint, expected
+        )));
+    }
+    if checkpoint.densities[0].shape()[0] != basis_set.nbasis() {
+        return Err(invalid(
+            "checkpoint has the wrong number of basis functions".to_string(),
+        ));
+    }
+    Ok(checkpoint)
+}
+
+/// Write a checkpoint if one is due at this iteration (counting from one)
+/// or the SCF has converged. The SCF carries on if this fails, keeping the
+/// error to report in its result.
+pub(crate) fn save<F>(
+    options: &Option<CheckpointOptions>,
+    iteration: usize,
+    converged: bool,
+    make: F,
+) -> io::Result<()>
+where
+    F: FnOnce() -> Checkpoint,
+{
+    match options {
+        Some(options) if converged || iteration.is_multiple_of(options.interval.max(1)) => {
+            make().write(&options.path).map_err(|error| {
+                io::Error::new(
+                    error.kind(),
+                    format!("{}: {}", options.path.display(), error),
+                )
+            })
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scf;
+    use crate::testing;
+
+    #[test]
+    fn test_rhf_restart() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let path = std::env::temp_dir().join("rchem_test_water_rhf.chk");
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            checkpoint: Some(CheckpointOptions {
+                path: path.clone(),
+                interval: 2,
+            }),
+            ..Default::default()
+        };
+        let reference = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+
+        // Stop early as if killed, then pick up from the last checkpoint.
+        let killed = scf::rhf(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            5,
+            &scf::SCFOptions {
+                max_iterations: 5,
+                ..options.clone()
+            },
+        );
+        assert!(!killed.converged);
+        let checkpoint =
+            read_restart(&path, SCFKind::RHF, &basis_set, &atomcoords, &atomnos).unwrap();
+        assert_eq!(checkpoint.iteration, 4);
+        assert_eq!(checkpoint.diis.as_ref().map(|diis| diis.len()), Some(4));
+        let restarted = scf::rhf(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            5,
+            &scf::SCFOptions {
+                restart: Some(checkpoint),
+                ..options.clone()
+            },
+        );
+        assert!(restarted.converged);
+        // With the DIIS subspace restored, the restarted SCF retraces the
+        // uninterrupted one.
+        assert!((restarted.energy - reference.energy).abs() < 1.0e-10);
+        assert_eq!(restarted.iterations, reference.iterations);
+
+        // The final checkpoint is for the converged wavefunction.
+        let converged = Checkpoint::read(&path).unwrap();
+        assert_eq!(converged.iteration, restarted.iterations);
+        assert!((&converged.densities[0] - &restarted.D)
+            .iter()
+            .all(|x| x.abs() < 1.0e-12));
+
+        // Restarts for another geometry or another kind of SCF are refused.
+        let mut moved = atomcoords.clone();
+        moved[1][0] += 0.01;
+        assert!(read_restart(&path, SCFKind::RHF, &basis_set, &moved, &atomnos).is_err());
+        assert!(read_restart(&path, SCFKind::UHF, &basis_set, &atomcoords, &atomnos).is_err());
+        // A restart of the wrong kind that reaches an SCF is an error.
+        let restart = Some(converged);
+        assert!(restart_for(&restart, SCFKind::UHF).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_checkpoint_keeps_diis_subspace() {
+        let mut diis = DIIS::new(3);
+        for i in 0..4 {
+            let x = i as f64;
+            diis.push(
+                ndarray::arr1(&[x, 1.0]),
+                ndarray::arr1(&[1.0 / (x + 1.0), x]),
+            );
+        }
+        let checkpoint = Checkpoint {
+            kind: SCFKind::RKS,
+            fingerprint: "0".to_string(),
+            iteration: 4,
+            e_elec: -1.0,
+            densities: vec![Array::eye(2)],
+            orbitals: vec![Array::eye(2)],
+            diis: Some(diis.clone()),
+        };
+        let path = std::env::temp_dir().join("rchem_test_diis.chk");
+        checkpoint.write(&path).unwrap();
+        let read = Checkpoint::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let restored = read.diis.unwrap();
+        assert_eq!(restored.len(), 3);
+        let difference = &restored.extrapolate() - &diis.extrapolate();
+        assert!(difference.iter().all(|x| x.abs() < 1.0e-12));
+
+        // A checkpoint without a density for every spin is refused.
+        let empty = Checkpoint {
+            densities: vec![],
+            ..checkpoint
+        };
+        assert!(empty.write(&path).is_err());
+    }
+}
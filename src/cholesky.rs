@@ -0,0 +1,93 @@
+#![allow(non_snake_case)]
+
+//! Pivoted Cholesky decomposition of the two-electron integral supermatrix,
+//!
+//! (mu nu|lambda sigma) ~ sum_K L(K, mu, nu) L(K, lambda, sigma),
+//!
+//! which needs no auxiliary basis. The vectors are generated one column of
+//! integrals at a time until the largest remaining diagonal element falls
+//! below the threshold, which bounds the error in every integral.
+
+use ndarray::{Array, Ix2, Ix3};
+
+use crate::basis;
+use crate::df::DFTensor;
+
+/// Decompose the two-electron integrals to within `threshold`, returning the
+/// Cholesky vectors in the same form as density-fitted integrals.
+pub fn cholesky_eri(basis_set: &basis::Basis, threshold: f64) -> DFTensor {
+    let dim = basis_set.nbasis();
+    let mut diagonal = basis::schwarz_diagonal(basis_set);
+    let mut vectors: Vec<Array<f64, Ix2>> = Vec::new();
+    loop {
+        // Pivot on the largest remaining diagonal element among the unique
+        // pairs.
+        let mut pivot = (0, 0);
+        let mut max_diagonal = 0.0;
+        for mu in 0..dim {
+            for nu in 0..mu + 1 {
+                if diagonal[[mu, nu]] > max_diagonal {
+                    max_diagonal = diagonal[[mu, nu]];
+                    pivot = (mu, nu);
+                }
+            }
+        }
+        if max_diagonal < threshold || vectors.len() == dim * (dim + 1) / 2 {
+            break;
+        }
+        let (mu, nu) = pivot;
+        let mut column = basis::I_pair(basis_set, mu, nu);
+        for L in vectors.iter() {
+            column.scaled_add(-L[[mu, nu]], L);
+        }
+        let L = column / max_diagonal.sqrt();
+        diagonal -= &(&L * &L);
+        vectors.push(L);
+    }
+
+    let mut B: Array<f64, Ix3> = Array::zeros((vectors.len(), dim, dim));
+    for (K, L) in vectors.iter().enumerate() {
+        B.index_axis_mut(ndarray::Axis(0), K).assign(L);
+    }
+    DFTensor { B }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mp2;
+    use crate::scf::{self, JKAlgorithm};
+    use crate::testing;
+
+    #[test]
+    fn test_cholesky_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let I = basis::build_I(&basis_set);
+        let C = Array::eye(basis_set.nbasis());
+        let npairs = 7 * 8 / 2;
+        for &threshold in [1.0e-3, 1.0e-10].iter() {
+            let cholesky = cholesky_eri(&basis_set, threshold);
+            assert!(cholesky.naux() <= npairs);
+            let I_cd = cholesky.transform_I(&C.view(), &C.view(), &C.view(), &C.view());
+            let max_error = (&I - &I_cd).fold(0.0f64, |acc, x| acc.max(x.abs()));
+            assert!(max_error < threshold);
+        }
+        assert!(cholesky_eri(&basis_set, 1.0e-3).naux() < npairs);
+
+        let options = scf::SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let exact = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let options = scf::SCFOptions {
+            jk: JKAlgorithm::Cholesky(1.0e-8),
+            ..Default::default()
+        };
+        let decomposed = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!((decomposed.energy - exact.energy).abs() < 1.0e-7);
+
+        let mp2_exact = mp2::rhf_mp2(&basis_set, &exact, &JKAlgorithm::InMemory);
+        let mp2_cd = mp2::rhf_mp2(&basis_set, &exact, &JKAlgorithm::Cholesky(1.0e-8));
+        assert!((mp2_cd.correlation_energy() - mp2_exact.correlation_energy()).abs() < 1.0e-8);
+    }
+}
@@ -0,0 +1,446 @@
+#![allow(non_snake_case)]
+
+//! Configuration interaction: singly-excited CI (CIS) for excited states of
+//! a closed-shell reference, and determinant-based full CI.
+
+use std::collections::HashMap;
+
+use ndarray::{Array, Axis, Ix1, Ix2, Ix4};
+use ndarray_linalg::*;
+
+use crate::basis;
+use crate::davidson::{davidson, DavidsonOptions};
+use crate::scf::{self, JKAlgorithm, RHFResult};
+use crate::transform::{split_occupied, AOIntegrals};
+
+#[derive(Clone, Debug)]
+pub struct ExcitedState {
+    /// Excitation energy in hartree.
+    pub excitation_energy: f64,
+    /// Dipole oscillator strength, zero for spin-forbidden transitions.
+    pub oscillator_strength: f64,
+    /// Normalized excitation amplitudes, with shape `(nocc, nvirt)`.
+    pub amplitudes: Array<f64, Ix2>,
+}
+
+#[derive(Clone, Debug)]
+pub struct CISResult {
+    pub singlets: Vec<ExcitedState>,
+    pub triplets: Vec<ExcitedState>,
+}
+
+/// Spin-adapted CIS on top of a closed-shell RHF reference, by full
+/// diagonalization of
+///
+/// singlets: A_ia,jb = (e_a - e_i) d_ij d_ab + 2 (ia|jb) - (ij|ab)
+/// triplets: A_ia,jb = (e_a - e_i) d_ij d_ab - (ij|ab)
+pub fn cis(basis_set: &basis::Basis, scf: &RHFResult, algorithm: &JKAlgorithm) -> CISResult {
+    let nocc = scf.nocc;
+    let nvirt = scf.C.shape()[1] - nocc;
+    let integrals = AOIntegrals::new(basis_set, algorithm);
+    let (C_occ, C_virt) = split_occupied(&scf.C, nocc);
+    let ovov = integrals.transform(&C_occ, &C_virt, &C_occ, &C_virt);
+    let oovv = integrals.transform(&C_occ, &C_occ, &C_virt, &C_virt);
+
+    let dim = nocc * nvirt;
+    let mut A_singlet: Array<f64, _> = Array::zeros((dim, dim));
+    let mut A_triplet: Array<f64, _> = Array::zeros((dim, dim));
+    for i in 0..nocc {
+        for a in 0..nvirt {
+            let ia = i * nvirt + a;
+            for j in 0..nocc {
+                for b in 0..nvirt {
+                    let jb = j * nvirt + b;
+                    let mut diagonal = 0.0;
+                    if ia == jb {
+                        diagonal = scf.eps[nocc + a] - scf.eps[i];
+                    }
+                    A_singlet[[ia, jb]] = diagonal + 2.0 * ovov[[i, a, j, b]] - oovv[[i, j, a, b]];
+                    A_triplet[[ia, jb]] = diagonal - oovv[[i, j, a, b]];
+                }
+            }
+        }
+    }
+
+    let dipole: Vec<Array<f64, Ix2>> = basis::dipole(basis_set, &[0.0, 0.0, 0.0])
+        .iter()
+        .map(|M| C_occ.t().dot(M).dot(&C_virt))
+        .collect();
+    let states = |A: &Array<f64, Ix2>, singlet: bool| -> Vec<ExcitedState> {
+        let (energies, vectors) = A.eigh(UPLO::Upper).unwrap();
+        energies
+            .iter()
+            .zip(vectors.axis_iter(Axis(1)))
+            .map(|(&omega, x)| {
+                let amplitudes = x.to_owned().into_shape_with_order((nocc, nvirt)).unwrap();
+                let oscillator_strength = if singlet {
+                    // The singlet is (|ia> + |i'a'>) / sqrt(2) in terms of
+                    // spin orbitals.
+                    let transition: f64 = dipole
+                        .iter()
+                        .map(|mu| (2.0f64.sqrt() * (mu * &amplitudes).sum()).powi(2))
+                        .sum();
+                    2.0 / 3.0 * omega * transition
+                } else {
+                    0.0
+                };
+                ExcitedState {
+                    excitation_energy: omega,
+                    oscillator_strength,
+                    amplitudes,
+                }
+            })
+            .collect()
+    };
+
+    CISResult {
+        singlets: states(&A_singlet, true),
+        triplets: states(&A_triplet, false),
+    }
+}
+
+/// All strings of `nelec` electrons in `norb` spatial orbitals, stored as
+/// occupation bitmasks, together with their single replacements.
+struct Strings {
+    strings: Vec<u64>,
+    /// For every string I, each (J, p, q, sign) such that E_pq |I> = sign |J>.
+    singles: Vec<Vec<(usize, usize, usize, f64)>>,
+}
+
+/// The sign from moving an operator past the electrons in orbitals below
+/// `orbital`.
+fn phase(string: u64, orbital: usize) -> f64 {
+    if (string & ((1u64 << orbital) - 1)).count_ones() & 1 == 0 {
+        1.0
+    } else {
+        -1.0
+    }
+}
+
+impl Strings {
+    fn new(norb: usize, nelec: usize) -> Strings {
+        assert!(norb < 64);
+        let strings: Vec<u64> = (0..(1u64 << norb))
+            .filter(|s| s.count_ones() as usize == nelec)
+            .collect();
+        let index: HashMap<u64, usize> = strings.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+        let singles = strings
+            .iter()
+            .map(|&string| {
+                let mut replacements = Vec::new();
+                for q in (0..norb).filter(|&q| string & (1 << q) != 0) {
+                    let removed = string ^ (1 << q);
+                    for p in (0..norb).filter(|&p| removed & (1 << p) == 0) {
+                        let created = removed | (1 << p);
+                        let sign = phase(string, q) * phase(removed, p);
+                        replacements.push((index[&created], p, q, sign));
+                    }
+                }
+                replacements
+            })
+            .collect();
+        Strings { strings, singles }
+    }
+
+    fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    fn occupied(&self, index: usize) -> Vec<usize> {
+        let string = self.strings[index];
+        (0..64).filter(|&p| string & (1 << p) != 0).collect()
+    }
+}
+
+/// The Hamiltonian in the basis of all determinants with `nalpha` and
+/// `nbeta` electrons, never stored explicitly.
+pub struct FCIHamiltonian {
+    h: Array<f64, Ix2>,
+    eri: Array<f64, Ix4>,
+    /// h_pq - 1/2 sum_r (pr|rq)
+    k: Array<f64, Ix2>,
+    e_nuc: f64,
+    alpha: Strings,
+    beta: Strings,
+}
+
+impl FCIHamiltonian {
+    /// From the MO one-electron integrals, the MO two-electron integrals
+    /// (pq|rs) and the nuclear repulsion energy.
+    pub fn new(
+        h: Array<f64, Ix2>,
+        eri: Array<f64, Ix4>,
+        e_nuc: f64,
+        nalpha: usize,
+        nbeta: usize,
+    ) -> FCIHamiltonian {
+        let norb = h.shape()[0];
+        let mut k = h.clone();
+        for p in 0..norb {
+            for q in 0..norb {
+                for r in 0..norb {
+                    k[[p, q]] -= 0.5 * eri[[p, r, r, q]];
+                }
+            }
+        }
+        FCIHamiltonian {
+            h,
+            eri,
+            k,
+            e_nuc,
+            alpha: Strings::new(norb, nalpha),
+            beta: Strings::new(norb, nbeta),
+        }
+    }
+
+    /// Full CI in the basis of the RHF orbitals, correlating all electrons.
+    pub fn from_rhf(
+        basis_set: &basis::Basis,
+        atomcoords: &[[f64; 3]],
+        atomnos: &[u64],
+        scf: &RHFResult,
+        algorithm: &JKAlgorithm,
+    ) -> FCIHamiltonian {
+        let C = scf.C.view();
+        let h = C
+            .t()
+            .dot(&scf::core_hamiltonian(basis_set, atomcoords, atomnos))
+            .dot(&C);
+        let eri = AOIntegrals::new(basis_set, algorithm).transform(&C, &C, &C, &C);
+        FCIHamiltonian::new(h, eri, scf.e_nuc, scf.nocc, scf.nocc)
+    }
+
+    pub fn ndeterminants(&self) -> usize {
+        self.alpha.len() * self.beta.len()
+    }
+
+    /// Diagonal elements <I|H|I> from the Slater-Condon rules, without the
+    /// nuclear repulsion.
+    pub fn diagonal(&self) -> Array<f64, Ix1> {
+        let same_spin = |occ: &[usize]| -> f64 {
+            let mut e = 0.0;
+            for &p in occ {
+                e += self.h[[p, p]];
+                for &q in occ {
+                    e += 0.5 * (self.eri[[p, p, q, q]] - self.eri[[p, q, q, p]]);
+                }
+            }
+            e
+        };
+        let alpha: Vec<(Vec<usize>, f64)> = (0..self.alpha.len())
+            .map(|i| {
+                let occ = self.alpha.occupied(i);
+                let e = same_spin(&occ);
+                (occ, e)
+            })
+            .collect();
+        let beta: Vec<(Vec<usize>, f64)> = (0..self.beta.len())
+            .map(|i| {
+                let occ = self.beta.occupied(i);
+                let e = same_spin(&occ);
+                (occ, e)
+            })
+            .collect();
+        let mut diagonal: Array<f64, _> = Array::zeros(self.ndeterminants());
+        for (ia, (occ_a, e_a)) in alpha.iter().enumerate() {
+            for (ib, (occ_b, e_b)) in beta.iter().enumerate() {
+                let mut e = e_a + e_b;
+                for &p in occ_a {
+                    for &q in occ_b {
+                        e += self.eri[[p, p, q, q]];
+                    }
+                }
+                diagonal[ia * self.beta.len() + ib] = e;
+            }
+        }
+        diagonal
+    }
+
+    /// sigma = H c, where c is indexed by (alpha string, beta string) in
+    /// row-major order, without the nuclear repulsion.
+    pub fn sigma(&self, c: &Array<f64, Ix1>) -> Array<f64, Ix1> {
+        let (na, nb) = (self.alpha.len(), self.beta.len());
+        let C = c.view().into_shape_with_order((na, nb)).unwrap();
+        let mut S: Array<f64, _> = Array::zeros((na, nb));
+
+        // Same-spin contributions, sum_kl k_kl E_kl + 1/2 sum_ijkl (ij|kl) E_ij E_kl,
+        // gathered into F(J) for each string I.
+        let same_spin = |strings: &Strings, I: usize| -> Array<f64, Ix1> {
+            let mut F: Array<f64, _> = Array::zeros(strings.len());
+            for &(K, k, l, s1) in strings.singles[I].iter() {
+                F[K] += s1 * self.k[[k, l]];
+                for &(J, i, j, s2) in strings.singles[K].iter() {
+                    F[J] += 0.5 * s1 * s2 * self.eri[[i, j, k, l]];
+                }
+            }
+            F
+        };
+        for Ib in 0..nb {
+            let F = same_spin(&self.beta, Ib);
+            let contribution = C.dot(&F);
+            let mut column = S.column_mut(Ib);
+            column += &contribution;
+        }
+        for Ia in 0..na {
+            let F = same_spin(&self.alpha, Ia);
+            let contribution = C.t().dot(&F);
+            let mut row = S.row_mut(Ia);
+            row += &contribution;
+        }
+
+        // Opposite-spin contribution, sum_ijkl (ij|kl) E^alpha_ij E^beta_kl
+        for Ia in 0..na {
+            for &(Ja, i, j, s1) in self.alpha.singles[Ia].iter() {
+                for Ib in 0..nb {
+                    let c_value = C[[Ia, Ib]];
+                    if c_value == 0.0 {
+                        continue;
+                    }
+                    for &(Jb, k, l, s2) in self.beta.singles[Ib].iter() {
+                        S[[Ja, Jb]] += s1 * s2 * self.eri[[i, j, k, l]] * c_value;
+                    }
+                }
+            }
+        }
+
+        S.into_shape_with_order(na * nb).unwrap()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FCIResult {
+    /// Total energies of each root, including nuclear repulsion.
+    pub energies: Array<f64, Ix1>,
+    /// CI vectors as columns.
+    pub vectors: Array<f64, Ix2>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// The lowest `nroots` eigenstates of the full CI Hamiltonian.
+pub fn fci(hamiltonian: &FCIHamiltonian, nroots: usize, options: &DavidsonOptions) -> FCIResult {
+    let result = davidson(&hamiltonian.diagonal(), nroots, options, |c| {
+        hamiltonian.sigma(c)
+    });
+    FCIResult {
+        energies: result.eigenvalues + hamiltonian.e_nuc,
+        vectors: result.eigenvectors,
+        iterations: result.iterations,
+        converged: result.converged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basis::Basis;
+    use crate::cc;
+    use crate::testing;
+
+    fn options() -> scf::SCFOptions {
+        scf::SCFOptions {
+            thresh_e: 1.0e-12,
+            thresh_d: 1.0e-10,
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_cis_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options());
+        let result = cis(&basis_set, &scf, &JKAlgorithm::InMemory);
+        assert_eq!(result.singlets.len(), 10);
+        assert_eq!(result.triplets.len(), 10);
+        // Crawford programming project #12, where each triplet appears three
+        // times in the spin-orbital basis.
+        assert!((result.triplets[0].excitation_energy - 0.2872554996).abs() < 1.0e-6);
+        assert!((result.triplets[1].excitation_energy - 0.3444249963).abs() < 1.0e-6);
+        assert!((result.singlets[0].excitation_energy - 0.3564617587).abs() < 1.0e-6);
+        for state in result.singlets.iter() {
+            assert!(state.oscillator_strength >= 0.0);
+        }
+        assert!(result
+            .singlets
+            .iter()
+            .any(|s| s.oscillator_strength > 1.0e-3));
+    }
+
+    #[test]
+    fn test_fci_h2_matches_ccsd() {
+        // For two electrons, CCSD is exact.
+        let atomcoords = [[0.0, 0.0, 0.0], [0.0, 0.0, 1.4]];
+        let atomnos = [1, 1];
+        let basis_set = Basis::from_json(&atomnos, &atomcoords, testing::STO3G_JSON);
+        let scf = scf::rhf(&basis_set, &atomcoords, &atomnos, 1, &options());
+        let hamiltonian = FCIHamiltonian::from_rhf(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            &scf,
+            &JKAlgorithm::InMemory,
+        );
+        let result = fci(&hamiltonian, 1, &Default::default());
+        assert!(result.converged);
+        let ints = cc::SpinOrbitalIntegrals::from_rhf(&basis_set, &scf, &JKAlgorithm::InMemory);
+        let ccsd = cc::ccsd(&ints, &Default::default());
+        assert!((result.energies[0] - (scf.energy + ccsd.correlation_energy)).abs() < 1.0e-8);
+    }
+
+    #[test]
+    fn test_fci_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options());
+        let hamiltonian = FCIHamiltonian::from_rhf(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            &scf,
+            &JKAlgorithm::InMemory,
+        );
+        assert_eq!(hamiltonian.ndeterminants(), 441);
+        let result = fci(&hamiltonian, 1, &Default::default());
+        assert!(result.converged);
+        // The reference determinant is the first one, and FCI lies just
+        // below CCSD(T) (-75.012859894).
+        assert!((hamiltonian.diagonal()[0] + scf.e_nuc - scf.energy).abs() < 1.0e-9);
+        assert!(result.energies[0] < -75.012859894);
+        assert!(result.energies[0] > -75.0139);
+
+        // Two electrons of each spin in five of the orbitals is small enough
+        // to check against diagonalizing the explicit Hamiltonian, built one
+        // column at a time from sigma vectors.
+        let active = ndarray::s![1..6, 1..6];
+        let small = FCIHamiltonian::new(
+            hamiltonian.h.slice(active).to_owned(),
+            hamiltonian
+                .eri
+                .slice(ndarray::s![1..6, 1..6, 1..6, 1..6])
+                .to_owned(),
+            0.0,
+            2,
+            2,
+        );
+        let dim = small.ndeterminants();
+        assert_eq!(dim, 100);
+        let mut H: Array<f64, _> = Array::zeros((dim, dim));
+        for i in 0..dim {
+            let mut unit: Array<f64, _> = Array::zeros(dim);
+            unit[i] = 1.0;
+            H.column_mut(i).assign(&small.sigma(&unit));
+        }
+        let diagonal = small.diagonal();
+        for i in 0..dim {
+            assert!((H[[i, i]] - diagonal[i]).abs() < 1.0e-12);
+            for j in 0..i {
+                assert!((H[[i, j]] - H[[j, i]]).abs() < 1.0e-12);
+            }
+        }
+        let (eigenvalues, _) = H.eigh(UPLO::Upper).unwrap();
+        let result = fci(&small, 3, &Default::default());
+        for root in 0..3 {
+            assert!((result.energies[root] - eigenvalues[root]).abs() < 1.0e-9);
+        }
+    }
+}
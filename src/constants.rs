@@ -0,0 +1,20 @@
+//! Physical constants and unit conversions (CODATA 2014).
+
+pub const BOHR_TO_ANGSTROM: f64 = 0.52917721067;
+pub const ANGSTROM_TO_BOHR: f64 = 1.0 / BOHR_TO_ANGSTROM;
+pub const BOHR_TO_METER: f64 = BOHR_TO_ANGSTROM * 1.0e-10;
+
+pub const AMU_TO_ELECTRON_MASS: f64 = 1822.888486;
+pub const AMU_TO_KG: f64 = 1.660539040e-27;
+
+pub const HARTREE_TO_JOULE: f64 = 4.359744650e-18;
+pub const HARTREE_TO_WAVENUMBER: f64 = 219474.6313702;
+
+/// J/K
+pub const BOLTZMANN: f64 = 1.38064852e-23;
+/// J s
+pub const PLANCK: f64 = 6.626070040e-34;
+/// m/s
+pub const SPEED_OF_LIGHT: f64 = 299792458.0;
+/// 1/mol
+pub const AVOGADRO: f64 = 6.022140857e23;
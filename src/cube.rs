@@ -0,0 +1,335 @@
+#![allow(non_snake_case)]
+
+//! Molecular orbitals, densities and the electrostatic potential on regular
+//! 3D grids, written as Gaussian cube files for VMD, Avogadro and friends.
+//!
+//! Everything is in bohr. Grid points run over x slowest and z fastest,
+//! which is the order the cube format stores them in.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ndarray::{Array, Ix1, Ix2};
+
+use crate::basis::{self, Basis};
+use crate::fchk::fortran_real;
+
+#[derive(Clone, Debug)]
+pub struct CubeOptions {
+    /// Distance between neighbouring points along each axis.
+    pub spacing: f64,
+    /// Extra space around the outermost atoms on every side.
+    pub padding: f64,
+}
+
+impl Default for CubeOptions {
+    fn default() -> CubeOptions {
+        CubeOptions {
+            spacing: 0.2,
+            padding: 4.0,
+        }
+    }
+}
+
+/// An axis-aligned box of evenly spaced points.
+#[derive(Clone, Debug)]
+pub struct CubeGrid {
+    pub origin: [f64; 3],
+    pub spacing: f64,
+    pub npoints: [usize; 3],
+}
+
+impl CubeGrid {
+    /// The smallest grid with the requested spacing that covers every atom
+    /// plus the padding.
+    pub fn new(atomcoords: &[[f64; 3]], options: &CubeOptions) -> CubeGrid {
+        let mut origin = [0.0; 3];
+        let mut npoints = [0; 3];
+        for k in 0..3 {
+            let lo = atomcoords
+                .iter()
+                .map(|c| c[k])
+                .fold(f64::INFINITY, f64::min);
+            let hi = atomcoords
+                .iter()
+                .map(|c| c[k])
+                .fold(f64::NEG_INFINITY, f64::max);
+            let extent = hi - lo + 2.0 * options.padding;
+            npoints[k] = (extent / options.spacing).ceil() as usize + 1;
+            // Center the box on the molecule, since rounding up the number
+            // of points makes it slightly larger than requested.
+            let actual = (npoints[k] - 1) as f64 * options.spacing;
+            origin[k] = 0.5 * (lo + hi) - 0.5 * actual;
+        }
+        CubeGrid {
+            origin,
+            spacing: options.spacing,
+            npoints,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.npoints.iter().product()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The points in one plane of constant x.
+    fn slab(&self, i: usize) -> Vec<[f64; 3]> {
+        let [_, ny, nz] = self.npoints;
+        let mut points = Vec::with_capacity(ny * nz);
+        for j in 0..ny {
+            for k in 0..nz {
+                points.push([
+                    self.origin[0] + i as f64 * self.spacing,
+                    self.origin[1] + j as f64 * self.spacing,
+                    self.origin[2] + k as f64 * self.spacing,
+                ]);
+            }
+        }
+        points
+    }
+
+    pub fn points(&self) -> Vec<[f64; 3]> {
+        (0..self.npoints[0]).flat_map(|i| self.slab(i)).collect()
+    }
+}
+
+/// Values of the given orbitals (columns of `C`) at every grid point, with
+/// shape `(npoints, indices.len())`.
+pub fn orbitals(
+    basis_set: &Basis,
+    grid: &CubeGrid,
+    C: &Array<f64, Ix2>,
+    indices: &[usize],
+) -> Array<f64, Ix2> {
+    let mut values: Array<f64, _> = Array::zeros((grid.len(), indices.len()));
+    let slab_len = grid.npoints[1] * grid.npoints[2];
+    // Evaluate a plane at a time so the basis function values never need
+    // to be held for the whole grid at once.
+    for i in 0..grid.npoints[0] {
+        let (phi, _) = basis::evaluate(basis_set, &grid.slab(i));
+        for (n, &index) in indices.iter().enumerate() {
+            let column = phi.dot(&C.column(index));
+            values
+                .slice_mut(ndarray::s![i * slab_len..(i + 1) * slab_len, n])
+                .assign(&column);
+        }
+    }
+    values
+}
+
+/// The density rho(r) = sum_mu,nu phi_mu(r) D_mu,nu phi_nu(r) at every grid
+/// point. Pass the sum of the alpha and beta densities for the total
+/// density and their difference for the spin density.
+pub fn density(basis_set: &Basis, grid: &CubeGrid, D: &Array<f64, Ix2>) -> Array<f64, Ix1> {
+    let mut values: Array<f64, _> = Array::zeros(grid.len());
+    let slab_len = grid.npoints[1] * grid.npoints[2];
+    for i in 0..grid.npoints[0] {
+        let (phi, _) = basis::evaluate(basis_set, &grid.slab(i));
+        let rho = (&phi.dot(D) * &phi).sum_axis(ndarray::Axis(1));
+        values
+            .slice_mut(ndarray::s![i * slab_len..(i + 1) * slab_len])
+            .assign(&rho);
+    }
+    values
+}
+
+/// The electrostatic potential of the nuclei and the total density `D` at
+/// each of the points,
+///
+/// V(r) = sum_A Z_A / |r - R_A| - int rho(r') / |r - r'| dr'.
+///
+/// Nuclei closer than 1e-8 bohr to a point are left out rather than making
+/// it infinite.
+pub fn electrostatic_potential(
+    basis_set: &Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    D: &Array<f64, Ix2>,
+    points: &[[f64; 3]],
+) -> Array<f64, Ix1> {
+    points
+        .iter()
+        .map(|point| {
+            let nuclear: f64 = atomcoords
+                .iter()
+                .zip(atomnos)
+                .map(|(c, &z)| {
+                    let r = ((point[0] - c[0]).powi(2)
+                        + (point[1] - c[1]).powi(2)
+                        + (point[2] - c[2]).powi(2))
+                    .sqrt();
+                    if r > 1.0e-8 {
+                        z as f64 / r
+                    } else {
+                        0.0
+                    }
+                })
+                .sum();
+            // The attraction of each basis function pair to a unit positive
+            // charge at the point is already negative.
+            let V = basis::V(basis_set, &[*point], &[1]);
+            nuclear + (D * &V).sum()
+        })
+        .collect()
+}
+
+/// Write a cube file holding one or more values per grid point, laid out as
+/// `(npoints, nvalues)`. Orbital cubes also list which orbitals they hold,
+/// numbered from 1 as other programs expect.
+pub fn write_cube<P: AsRef<Path>>(
+    path: P,
+    title: &str,
+    grid: &CubeGrid,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    values: &Array<f64, Ix2>,
+    orbitals: Option<&[usize]>,
+) -> io::Result<()> {
+    // Orbital cubes flag themselves with a negative number of atoms and
+    // give the number of orbitals on their own line instead.
+    let natoms = atomcoords.len() as i64;
+    let (natoms, nvalues) = match orbitals {
+        Some(_) => (-natoms, 1),
+        None => (natoms, values.shape()[1]),
+    };
+    let mut contents = format!("{}\nGenerated by rchem\n", title);
+    contents.push_str(&format!(
+        "{:5} {:12.6} {:12.6} {:12.6} {:4}\n",
+        natoms, grid.origin[0], grid.origin[1], grid.origin[2], nvalues
+    ));
+    for k in 0..3 {
+        let mut axis = [0.0; 3];
+        axis[k] = grid.spacing;
+        contents.push_str(&format!(
+            "{:5} {:12.6} {:12.6} {:12.6}\n",
+            grid.npoints[k], axis[0], axis[1], axis[2]
+        ));
+    }
+    for (c, &z) in atomcoords.iter().zip(atomnos) {
+        contents.push_str(&format!(
+            "{:5} {:12.6} {:12.6} {:12.6} {:12.6}\n",
+            z, z as f64, c[0], c[1], c[2]
+        ));
+    }
+    if let Some(orbitals) = orbitals {
+        let numbers: Vec<String> = orbitals.iter().map(|i| format!("{:5}", i + 1)).collect();
+        contents.push_str(&format!("{:5}{}\n", orbitals.len(), numbers.join("")));
+    }
+    // Each line of constant x and y starts on a new line, with at most six
+    // values per line.
+    let row_len = grid.npoints[2] * values.shape()[1];
+    let flat: Vec<f64> = values.iter().cloned().collect();
+    for row in flat.chunks(row_len) {
+        for line in row.chunks(6) {
+            let line: Vec<String> = line.iter().map(|&v| fortran_real(v, 5, 13)).collect();
+            contents.push_str(&line.join(""));
+            contents.push('\n');
+        }
+    }
+    fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scf;
+    use crate::testing;
+
+    #[test]
+    fn test_density_integrates_to_nelectrons() {
+        // The STO-3G hydrogen functions are smooth enough for a plain sum
+        // over an evenly spaced grid to integrate them accurately.
+        let atomcoords = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 1.4]];
+        let atomnos = vec![1, 1];
+        let basis_set = Basis::from_json(&atomnos, &atomcoords, testing::STO3G_JSON);
+        let scf_options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 1, &scf_options);
+        let options = CubeOptions {
+            spacing: 0.1,
+            padding: 6.0,
+        };
+        let grid = CubeGrid::new(&atomcoords, &options);
+        assert_eq!(grid.len(), grid.points().len());
+        let volume = grid.spacing.powi(3);
+        let rho = density(&basis_set, &grid, &(2.0 * &result.D));
+        assert!((rho.sum() * volume - 2.0).abs() < 1.0e-4);
+        let phi = orbitals(&basis_set, &grid, &result.C, &[0, 1]);
+        for n in 0..2 {
+            let norm: f64 = phi.column(n).iter().map(|v| v * v).sum();
+            assert!((norm * volume - 1.0).abs() < 1.0e-4);
+        }
+    }
+
+    #[test]
+    fn test_electrostatic_potential_water() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf_options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &scf_options);
+        let P = 2.0 * &result.D;
+
+        // Far from a neutral molecule the potential is that of its dipole.
+        let dipole_integrals = basis::dipole(&basis_set, &[0.0, 0.0, 0.0]);
+        let mut dipole = [0.0; 3];
+        for k in 0..3 {
+            let nuclear: f64 = atomcoords
+                .iter()
+                .zip(&atomnos)
+                .map(|(c, &z)| z as f64 * c[k])
+                .sum();
+            dipole[k] = nuclear - (&P * &dipole_integrals[k]).sum();
+        }
+        let point = [0.0, 40.0, 0.0];
+        let esp = electrostatic_potential(&basis_set, &atomcoords, &atomnos, &P, &[point]);
+        let expected = dipole[1] / (point[1] * point[1]);
+        assert!((esp[0] - expected).abs() < 0.05 * expected.abs());
+
+        let grid = CubeGrid::new(
+            &atomcoords,
+            &CubeOptions {
+                spacing: 1.0,
+                padding: 2.0,
+            },
+        );
+        let values = orbitals(&basis_set, &grid, &result.C, &[3, 4]);
+        let path = std::env::temp_dir().join("rchem_test_water_homo.cube");
+        write_cube(
+            &path,
+            "water",
+            &grid,
+            &atomcoords,
+            &atomnos,
+            &values,
+            Some(&[3, 4]),
+        )
+        .unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[2].split_whitespace().next(), Some("-3"));
+        assert_eq!(
+            lines[9].split_whitespace().collect::<Vec<_>>(),
+            ["2", "4", "5"]
+        );
+        let nvalues: usize = lines[10..]
+            .iter()
+            .map(|l| l.split_whitespace().count())
+            .sum();
+        assert_eq!(nvalues, 2 * grid.len());
+        // Values are in Fortran E13.5 format, with two-digit exponents.
+        assert_eq!(lines[10].len(), 6 * 13);
+        assert!(lines[10]
+            .split_whitespace()
+            .all(|v| v.rfind('E') == Some(v.len() - 4) && v.parse::<f64>().is_ok()));
+        fs::remove_file(&path).unwrap();
+    }
+}
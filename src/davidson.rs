@@ -0,0 +1,234 @@
+#![allow(non_snake_case)]
+
+//! Davidson's method for the lowest eigenpairs of a large symmetric matrix
+//! that is only available through matrix-vector products.
+
+use ndarray::{Array, Axis, Ix1, Ix2};
+use ndarray_linalg::*;
+
+#[derive(Clone, Debug)]
+pub struct DavidsonOptions {
+    pub max_iterations: usize,
+    /// Convergence threshold on the norm of each residual.
+    pub thresh_residual: f64,
+    /// The subspace is collapsed onto the current Ritz vectors when it grows
+    /// beyond this many vectors per root.
+    pub max_subspace_per_root: usize,
+}
+
+impl Default for DavidsonOptions {
+    fn default() -> DavidsonOptions {
+        DavidsonOptions {
+            max_iterations: 100,
+            thresh_residual: 1.0e-6,
+            max_subspace_per_root: 16,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct DavidsonResult {
+    pub eigenvalues: Array<f64, Ix1>,
+    /// Eigenvectors as columns.
+    pub eigenvectors: Array<f64, Ix2>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+/// Orthogonalize `v` against the columns of `basis` (twice, for numerical
+/// stability) and normalize it, returning `None` if nothing is left.
+fn orthonormalize(basis: &[Array<f64, Ix1>], mut v: Array<f64, Ix1>) -> Option<Array<f64, Ix1>> {
+    for _ in 0..2 {
+        for b in basis.iter() {
+            let overlap = b.dot(&v);
+            v.scaled_add(-overlap, b);
+        }
+    }
+    let norm = v.dot(&v).sqrt();
+    if norm > 1.0e-8 {
+        Some(v / norm)
+    } else {
+        None
+    }
+}
+
+/// Find the `nroots` lowest eigenpairs of the matrix whose diagonal is
+/// `diagonal` and whose action on a vector is given by `sigma`, using the
+/// diagonal as the preconditioner.
+pub fn davidson<F>(
+    diagonal: &Array<f64, Ix1>,
+    nroots: usize,
+    options: &DavidsonOptions,
+    mut sigma: F,
+) -> DavidsonResult
+where
+    F: FnMut(&Array<f64, Ix1>) -> Array<f64, Ix1>,
+{
+    let dim = diagonal.len();
+    let nroots = nroots.min(dim);
+    let max_subspace = (options.max_subspace_per_root * nroots).min(dim);
+
+    // Start from unit vectors on the smallest diagonal elements.
+    let mut order: Vec<usize> = (0..dim).collect();
+    order.sort_by(|&i, &j| diagonal[i].partial_cmp(&diagonal[j]).unwrap());
+    let mut basis: Vec<Array<f64, Ix1>> = Vec::new();
+    let mut sigmas: Vec<Array<f64, Ix1>> = Vec::new();
+    for &i in order.iter().take(nroots) {
+        let mut v: Array<f64, _> = Array::zeros(dim);
+        v[i] = 1.0;
+        sigmas.push(sigma(&v));
+        basis.push(v);
+    }
+
+    let mut eigenvalues: Array<f64, _> = Array::zeros(nroots);
+    let mut eigenvectors: Array<f64, _> = Array::zeros((dim, nroots));
+    let mut iteration = 0;
+    let mut converged = false;
+
+    while iteration < options.max_iterations {
+        iteration += 1;
+        let n = basis.len();
+        let mut G: Array<f64, _> = Array::zeros((n, n));
+        for i in 0..n {
+            for j in 0..=i {
+                G[[i, j]] = basis[i].dot(&sigmas[j]);
+                G[[j, i]] = G[[i, j]];
+            }
+        }
+        let (theta, alpha) = G.eigh(UPLO::Upper).unwrap();
+
+        let mut new_vectors = Vec::new();
+        let mut all_converged = true;
+        for root in 0..nroots {
+            let mut x: Array<f64, _> = Array::zeros(dim);
+            let mut r: Array<f64, _> = Array::zeros(dim);
+            for k in 0..n {
+                x.scaled_add(alpha[[k, root]], &basis[k]);
+                r.scaled_add(alpha[[k, root]], &sigmas[k]);
+            }
+            r.scaled_add(-theta[root], &x);
+            eigenvalues[root] = theta[root];
+            eigenvectors.column_mut(root).assign(&x);
+            if r.dot(&r).sqrt() > options.thresh_residual {
+                all_converged = false;
+                for i in 0..dim {
+                    let denominator = theta[root] - diagonal[i];
+                    r[i] /= if denominator.abs() > 1.0e-8 {
+                        denominator
+                    } else {
+                        1.0e-8
+                    };
+                }
+                new_vectors.push(r);
+            }
+        }
+        if all_converged {
+            converged = true;
+            break;
+        }
+
+        // Collapse onto the current Ritz vectors when the subspace is full,
+        // or when none of the corrections add a new direction to it.
+        let add =
+            |basis: &mut Vec<Array<f64, Ix1>>, sigmas: &mut Vec<Array<f64, Ix1>>, sigma: &mut F| {
+                let mut added = 0;
+                for v in new_vectors.iter() {
+                    if let Some(v) = orthonormalize(basis, v.clone()) {
+                        sigmas.push(sigma(&v));
+                        basis.push(v);
+                        added += 1;
+                    }
+                }
+                added
+            };
+        let collapse = |basis: &mut Vec<Array<f64, Ix1>>, sigmas: &mut Vec<Array<f64, Ix1>>| {
+            let ritz_sigmas: Vec<Array<f64, Ix1>> = (0..nroots)
+                .map(|root| {
+                    let mut s: Array<f64, _> = Array::zeros(dim);
+                    for k in 0..n {
+                        s.scaled_add(alpha[[k, root]], &sigmas[k]);
+                    }
+                    s
+                })
+                .collect();
+            *basis = eigenvectors
+                .axis_iter(Axis(1))
+                .map(|x| x.to_owned())
+                .collect();
+            *sigmas = ritz_sigmas;
+        };
+        let collapsed = n + new_vectors.len() > max_subspace;
+        if collapsed {
+            collapse(&mut basis, &mut sigmas);
+        }
+        if add(&mut basis, &mut sigmas, &mut sigma) == 0 {
+            if collapsed || n == nroots {
+                // Nothing new even from the Ritz vectors alone: the residual
+                // norms are still above the threshold, so give up unconverged.
+                break;
+            }
+            collapse(&mut basis, &mut sigmas);
+            if add(&mut basis, &mut sigmas, &mut sigma) == 0 {
+                break;
+            }
+        }
+    }
+
+    DavidsonResult {
+        eigenvalues,
+        eigenvectors,
+        iterations: iteration,
+        converged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_davidson_matches_full_diagonalization() {
+        let dim = 60;
+        let mut A: Array<f64, _> = Array::zeros((dim, dim));
+        for i in 0..dim {
+            A[[i, i]] = 1.0 + i as f64;
+            for j in 0..i {
+                let x = 0.01 * ((i * 7 + j * 3) % 11) as f64;
+                A[[i, j]] = x;
+                A[[j, i]] = x;
+            }
+        }
+        let (reference, _) = A.eigh(UPLO::Upper).unwrap();
+        let result = davidson(&A.diag().to_owned(), 3, &Default::default(), |v| A.dot(v));
+        assert!(result.converged);
+        for root in 0..3 {
+            assert!((result.eigenvalues[root] - reference[root]).abs() < 1.0e-10);
+            let x = result.eigenvectors.column(root);
+            let residual = A.dot(&x) - result.eigenvalues[root] * &x;
+            assert!(residual.dot(&residual).sqrt() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn test_davidson_reports_unreachable_threshold() {
+        // Once the subspace spans the whole space nothing can be added, and
+        // a zero threshold is still not met by rounding-level residuals.
+        let dim = 6;
+        let A = Array::from_shape_fn((dim, dim), |(i, j)| {
+            if i == j {
+                1.0 + i as f64
+            } else {
+                0.1 / (1.0 + i as f64 + j as f64)
+            }
+        });
+        let options = DavidsonOptions {
+            thresh_residual: 0.0,
+            ..Default::default()
+        };
+        let result = davidson(&A.diag().to_owned(), 2, &options, |v| A.dot(v));
+        assert!(!result.converged);
+        assert!(result.iterations < options.max_iterations);
+        let (reference, _) = A.eigh(UPLO::Upper).unwrap();
+        assert!((result.eigenvalues[0] - reference[0]).abs() < 1.0e-10);
+    }
+}
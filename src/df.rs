@@ -0,0 +1,142 @@
+#![allow(non_snake_case)]
+
+//! Density fitting (resolution of the identity) for the two-electron
+//! integrals,
+//!
+//! (mu nu|lambda sigma) ~ sum_P B(P, mu, nu) B(P, lambda, sigma),
+//!
+//! where B = L^{-1} (Q|mu nu) and (P|Q) = L L^T is the Cholesky-decomposed
+//! Coulomb metric of the auxiliary basis. The auxiliary basis is an ordinary
+//! `basis::Basis`, for example
+//! `Basis::new(&atomnos, &atomcoords, "def2-universal-jkfit")`.
+
+use ndarray::{Array, ArrayView2, Axis, Ix2, Ix3, Ix4};
+use ndarray_linalg::*;
+
+use crate::basis;
+
+/// The fitted three-index integrals B(P, mu, nu), also used to hold
+/// Cholesky vectors, which have the same form.
+#[derive(Clone, Debug)]
+pub struct DFTensor {
+    pub B: Array<f64, Ix3>,
+}
+
+impl DFTensor {
+    pub fn new(basis_set: &basis::Basis, aux: &basis::Basis) -> DFTensor {
+        let metric = basis::two_center(aux);
+        let L = metric.cholesky(UPLO::Lower).unwrap();
+        let L_inv = L.inv().unwrap();
+        let three_center = basis::three_center(basis_set, aux);
+        DFTensor {
+            B: contract_first(&three_center, &L_inv.view()),
+        }
+    }
+
+    pub fn naux(&self) -> usize {
+        self.B.shape()[0]
+    }
+
+    /// RI-J and RI-K for a density matrix, which need not be symmetric:
+    ///
+    /// J = sum_P B(P) (B(P) . D), K = sum_P B(P) D B(P)
+    pub fn JK(&self, D: &Array<f64, Ix2>) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+        let dim = D.shape()[0];
+        let mut J: Array<f64, _> = Array::zeros((dim, dim));
+        let mut K: Array<f64, _> = Array::zeros((dim, dim));
+        for BP in self.B.axis_iter(Axis(0)) {
+            J.scaled_add((&BP * D).sum(), &BP);
+            K += &BP.dot(D).dot(&BP);
+        }
+        (J, K)
+    }
+
+    /// B(P, p, q) = sum C1(mu, p) C2(nu, q) B(P, mu, nu)
+    pub fn transform(&self, C1: &ArrayView2<f64>, C2: &ArrayView2<f64>) -> Array<f64, Ix3> {
+        let (n1, n2) = (C1.shape()[1], C2.shape()[1]);
+        let mut BT: Array<f64, _> = Array::zeros((self.naux(), n1, n2));
+        for (BP, mut BTP) in self.B.axis_iter(Axis(0)).zip(BT.axis_iter_mut(Axis(0))) {
+            BTP.assign(&C1.t().dot(&BP).dot(C2));
+        }
+        BT
+    }
+
+    /// (pq|rs) ~ sum_P B(P, p, q) B(P, r, s) in the MO basis.
+    pub fn transform_I(
+        &self,
+        C1: &ArrayView2<f64>,
+        C2: &ArrayView2<f64>,
+        C3: &ArrayView2<f64>,
+        C4: &ArrayView2<f64>,
+    ) -> Array<f64, Ix4> {
+        let naux = self.naux();
+        let (n1, n2, n3, n4) = (C1.shape()[1], C2.shape()[1], C3.shape()[1], C4.shape()[1]);
+        let left = self
+            .transform(C1, C2)
+            .into_shape_with_order((naux, n1 * n2))
+            .unwrap();
+        let right = self
+            .transform(C3, C4)
+            .into_shape_with_order((naux, n3 * n4))
+            .unwrap();
+        left.t()
+            .dot(&right)
+            .into_shape_with_order((n1, n2, n3, n4))
+            .unwrap()
+    }
+}
+
+/// T'(P, mu, nu) = sum_Q M(P, Q) T(Q, mu, nu)
+fn contract_first(T: &Array<f64, Ix3>, M: &ArrayView2<f64>) -> Array<f64, Ix3> {
+    let shape = T.shape().to_vec();
+    let T2 = T
+        .view()
+        .into_shape_with_order((shape[0], shape[1] * shape[2]))
+        .unwrap();
+    M.dot(&T2)
+        .into_shape_with_order((M.shape()[0], shape[1], shape[2]))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basis::Basis;
+    use crate::mp2;
+    use crate::scf::{self, JKAlgorithm};
+    use crate::testing;
+
+    #[test]
+    fn test_density_fitting_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let aux = Basis::from_json(&atomnos, &atomcoords, testing::FITTING_JSON);
+        let df = DFTensor::new(&basis_set, &aux);
+
+        // The fitted integrals are close to the exact ones.
+        let I = basis::build_I(&basis_set);
+        let nbasis = basis_set.nbasis();
+        let C = Array::eye(nbasis);
+        let I_df = df.transform_I(&C.view(), &C.view(), &C.view(), &C.view());
+        let max_error = (&I - &I_df).fold(0.0f64, |acc, x| acc.max(x.abs()));
+        assert!(max_error < 1.0e-3);
+
+        let options = scf::SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let exact = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let options = scf::SCFOptions {
+            jk: JKAlgorithm::DensityFitting(aux.clone()),
+            ..Default::default()
+        };
+        let fitted = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!(fitted.converged);
+        assert!((fitted.energy - exact.energy).abs() < 1.0e-3);
+
+        // RI-MP2 reuses the same three-index integrals, here with the exact
+        // orbitals to isolate the fitting error in the correlation energy.
+        let mp2_exact = mp2::rhf_mp2(&basis_set, &exact, &JKAlgorithm::InMemory);
+        let mp2_ri = mp2::rhf_mp2(&basis_set, &exact, &JKAlgorithm::DensityFitting(aux));
+        assert!((mp2_ri.correlation_energy() - mp2_exact.correlation_energy()).abs() < 1.0e-4);
+    }
+}
@@ -0,0 +1,432 @@
+#![allow(non_snake_case)]
+
+//! Closed-shell Kohn-Sham DFT.
+//!
+//! The exchange-correlation energy and potential are integrated numerically
+//! on a `grid::MolecularGrid`. For a functional f(rho, sigma) of the density
+//! and sigma = |grad rho|^2, the potential matrix is
+//!
+//! V_mn = sum_g w_g [v_rho phi_m phi_n + 2 v_sigma grad rho . grad(phi_m phi_n)]
+//!
+//! and is added to the core Hamiltonian and Coulomb matrix to form the Fock
+//! matrix, together with the exact exchange of hybrid functionals.
+
+use ndarray::{Array, Axis, Ix1, Ix2};
+
+use crate::basis;
+use crate::checkpoint::{self, Checkpoint, SCFKind};
+use crate::diis::DIIS;
+use crate::grid::{GridOptions, MolecularGrid};
+use crate::guess;
+use crate::scf::{self, RHFResult, SCFOptions};
+use crate::xc::{Functional, XCOutput};
+
+/// The basis functions and their gradients tabulated on a molecular grid,
+/// from which the density and the exchange-correlation matrix are built.
+pub struct XCIntegrator {
+    pub grid: MolecularGrid,
+    /// Basis function values with shape `(npoints, nbasis)`.
+    phi: Array<f64, Ix2>,
+    /// x, y and z gradients of the basis functions.
+    dphi: [Array<f64, Ix2>; 3],
+    weights: Array<f64, Ix1>,
+}
+
+impl XCIntegrator {
+    pub fn new(
+        basis_set: &basis::Basis,
+        atomcoords: &[[f64; 3]],
+        atomnos: &[u64],
+        options: &GridOptions,
+    ) -> XCIntegrator {
+        let grid = MolecularGrid::new(atomcoords, atomnos, options);
+        let (phi, dphi) = basis::evaluate(basis_set, &grid.points);
+        let weights = Array::from(grid.weights.clone());
+        XCIntegrator {
+            grid,
+            phi,
+            dphi,
+            weights,
+        }
+    }
+
+    /// The density and its gradient at the grid points, given the total
+    /// density matrix P.
+    pub fn density(&self, P: &Array<f64, Ix2>) -> (Array<f64, Ix1>, [Array<f64, Ix1>; 3]) {
+        let phi_P = self.phi.dot(P);
+        let rho = (&phi_P * &self.phi).sum_axis(Axis(1));
+        let gradient = [
+            2.0 * (&phi_P * &self.dphi[0]).sum_axis(Axis(1)),
+            2.0 * (&phi_P * &self.dphi[1]).sum_axis(Axis(1)),
+            2.0 * (&phi_P * &self.dphi[2]).sum_axis(Axis(1)),
+        ];
+        (rho, gradient)
+    }
+
+    /// The exchange-correlation energy and potential matrix for the total
+    /// density matrix P, where `functional` maps the density and sigma at
+    /// every grid point to the energy density and its derivatives.
+    pub fn matrix<F>(&self, P: &Array<f64, Ix2>, functional: F) -> (f64, Array<f64, Ix2>)
+    where
+        F: Fn(&Array<f64, Ix1>, &Array<f64, Ix1>) -> XCOutput,
+    {
+        let (rho, gradient) = self.density(P);
+        let sigma =
+            &gradient[0] * &gradient[0] + &gradient[1] * &gradient[1] + &gradient[2] * &gradient[2];
+        let output = functional(&rho, &sigma);
+        let energy = (&self.weights * &output.exc).sum();
+
+        let wv = &self.weights * &output.vrho;
+        let weighted_phi = &self.phi * &wv.insert_axis(Axis(1));
+        let mut V = self.phi.t().dot(&weighted_phi);
+        if let Some(vsigma) = output.vsigma {
+            let wv = 2.0 * &self.weights * &vsigma;
+            let mut Z: Array<f64, Ix2> = Array::zeros(self.phi.raw_dim());
+            for (dphi, gradient) in self.dphi.iter().zip(gradient.iter()) {
+                let factor = (&wv * gradient).insert_axis(Axis(1));
+                Z += &(dphi * &factor);
+            }
+            let half = self.phi.t().dot(&Z);
+            V += &half;
+            V += &half.t();
+        }
+        (energy, V)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct KSOptions {
+    pub scf: SCFOptions,
+    pub grid: GridOptions,
+    pub functional: Functional,
+}
+
+impl Default for KSOptions {
+    fn default() -> KSOptions {
+        KSOptions {
+            scf: SCFOptions::default(),
+            grid: GridOptions::default(),
+            functional: Functional::svwn5(),
+        }
+    }
+}
+
+/// Closed-shell restricted Kohn-Sham, starting from the guess in
+/// `options.scf` and accelerated by DIIS on the Kohn-Sham
+/// matrix, with the orbital gradient FDS - SDF as the error. The result has
+/// the same form as for RHF, with F the Kohn-Sham matrix. There is no
+/// second-order optimizer for Kohn-Sham, so `options.scf.second_order` must
+/// be unset.
+pub fn rks(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    nocc: usize,
+    options: &KSOptions,
+) -> RHFResult {
+    assert!(
+        options.scf.second_order.is_none(),
+        "second-order SCF is only available for Hartree-Fock, not Kohn-Sham"
+    );
+    let S = basis::S(basis_set);
+    let X = scf::symmetric_orthogonalization(&S);
+    let H = scf::core_hamiltonian(basis_set, atomcoords, atomnos);
+    let e_nuc = scf::nuclear_repulsion(atomcoords, atomnos);
+    let jk = scf::JKEngine::new(basis_set, &options.scf.jk);
+    let xc = XCIntegrator::new(basis_set, atomcoords, atomnos, &options.grid);
+    let functional = &options.functional;
+    let mut diis = (options.scf.diis_size > 0).then(|| DIIS::new(options.scf.diis_size));
+    let occupations = options.scf.irrep_occupations(false);
+    let dim = H.shape()[0];
+
+    let mut eps: Array<f64, _> = Array::zeros(dim);
+    let mut C: Array<f64, _> = Array::zeros((dim, dim));
+    let mut F = H.clone();
+    let (mut D, mut e_elec_new, mut iteration) =
+        match checkpoint::restart_for(&options.scf.restart, SCFKind::RKS)
+            .unwrap_or_else(|error| panic!("{}", error))
+        {
+            Some(restart) => {
+                if let (Some(diis), Some(saved)) = (&mut diis, &restart.diis) {
+                    *diis = saved.clone();
+                }
+                C = restart.orbitals[0].clone();
+                (
+                    restart.densities[0].clone(),
+                    restart.e_elec,
+                    restart.iteration,
+                )
+            }
+            None => {
+                let D =
+                    guess::initial_density(&options.scf.guess, basis_set, atomnos, &S, &H, nocc);
+                let e_elec = 2.0 * (&D * &H).sum();
+                (D, e_elec, 0)
+            }
+        };
+    let mut converged = false;
+    let mut checkpoint_error = None;
+
+    while iteration < options.scf.max_iterations {
+        let (J, K) = jk.build(&D);
+        let (e_xc, V_xc) = xc.matrix(&(2.0 * &D), |rho, sigma| functional.evaluate(rho, sigma));
+        let G = 2.0 * &J - functional.exact_exchange * &K;
+        F = &H + &G + &V_xc;
+        let e_elec_old = e_elec_new;
+        e_elec_new = (&D * &(2.0 * &H + &G)).sum() + e_xc;
+        let FDS = F.dot(&D).dot(&S);
+        let error = X.t().dot(&(&FDS - &FDS.t())).dot(&X);
+        let F_diis = scf::extrapolate_fock(&mut diis, &[&F], &[error]).remove(0);
+        let F_shifted = scf::level_shift(&F_diis, &S, &C, nocc, options.scf.level_shift);
+        let (eps_new, C_new) =
+            scf::diagonalize_symmetric(&F_shifted, &X, occupations.as_deref(), &options.scf);
+        eps = eps_new;
+        C = C_new;
+        let D_old = D;
+        D = scf::next_density(&C, &eps, nocc, &D_old, iteration, &options.scf);
+        let delta_e = e_elec_new - e_elec_old;
+        let rms_d = (&D - &D_old).mapv(|x| x * x).mean().unwrap().sqrt();
+        if options.scf.verbose {
+            println!(
+                "{:4} {:20.12} {:20.12} {:20.12}",
+                iteration,
+                e_elec_new + e_nuc,
+                delta_e,
+                rms_d
+            );
+        }
+        iteration += 1;
+        let done = delta_e.abs() < options.scf.thresh_e && rms_d < options.scf.thresh_d;
+        if let Err(error) =
+            checkpoint::save(&options.scf.checkpoint, iteration, done, || Checkpoint {
+                kind: SCFKind::RKS,
+                fingerprint: checkpoint::fingerprint(basis_set, atomcoords, atomnos),
+                iteration,
+                e_elec: e_elec_new,
+                densities: vec![D.clone()],
+                orbitals: vec![C.clone()],
+                diis: diis.clone(),
+            })
+        {
+            checkpoint_error = Some(error.to_string());
+        }
+        if done {
+            converged = true;
+            break;
+        }
+    }
+    if options.scf.verbose && converged {
+        println!("Convergence achieved!");
+    }
+    if options.scf.level_shift != 0.0 {
+        let (eps_new, C_new) =
+            scf::diagonalize_symmetric(&F, &X, occupations.as_deref(), &options.scf);
+        eps = eps_new;
+        C = C_new;
+    }
+
+    RHFResult {
+        energy: e_elec_new + e_nuc,
+        e_nuc,
+        C,
+        eps,
+        D,
+        F,
+        nocc,
+        iterations: iteration,
+        converged,
+        checkpoint_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::xc::Component;
+
+    fn small_grid() -> GridOptions {
+        GridOptions {
+            radial_points: 40,
+            angular_points: 110,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_xc_integrator_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let hf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let P = 2.0 * &hf.D;
+        let xc = XCIntegrator::new(&basis_set, &atomcoords, &atomnos, &small_grid());
+
+        // The grid recovers the number of electrons, to within the error of
+        // this coarse grid.
+        let (rho, _) = xc.density(&P);
+        assert!((xc.grid.integrate(rho.as_slice().unwrap()) - 10.0).abs() < 1.0e-3);
+
+        // Basis function gradients agree with finite differences.
+        let points = &xc.grid.points[..50];
+        let (_, gradients) = basis::evaluate(&basis_set, points);
+        let h = 1.0e-5;
+        for k in 0..3 {
+            let shift = |s: f64| -> Vec<[f64; 3]> {
+                points
+                    .iter()
+                    .map(|p| {
+                        let mut p = *p;
+                        p[k] += s;
+                        p
+                    })
+                    .collect()
+            };
+            let (plus, _) = basis::evaluate(&basis_set, &shift(h));
+            let (minus, _) = basis::evaluate(&basis_set, &shift(-h));
+            let numerical = (plus - minus) / (2.0 * h);
+            let max_error = (&numerical - &gradients[k]).fold(0.0f64, |acc, x| acc.max(x.abs()));
+            assert!(max_error < 1.0e-6);
+        }
+
+        // The potential matrix is the derivative of the energy with respect
+        // to the density matrix, for both a local functional and a model
+        // gradient-dependent one.
+        let model = |rho: &Array<f64, Ix1>, sigma: &Array<f64, Ix1>| XCOutput {
+            exc: rho.mapv(|r| r * r) + 0.1 * sigma,
+            vrho: rho.mapv(|r| 2.0 * r),
+            vsigma: Some(sigma.mapv(|_| 0.1)),
+        };
+        let slater = Functional::slater();
+        let lda = |rho: &Array<f64, Ix1>, sigma: &Array<f64, Ix1>| slater.evaluate(rho, sigma);
+        let (_, V_lda) = xc.matrix(&P, lda);
+        let (_, V_gga) = xc.matrix(&P, model);
+        for &(mu, nu) in [(0, 0), (1, 3), (2, 5), (4, 6)].iter() {
+            let mut dP = Array::zeros(P.raw_dim());
+            dP[[mu, nu]] = 0.5 * h;
+            dP[[nu, mu]] += 0.5 * h;
+            let (lda_plus, _) = xc.matrix(&(&P + &dP), lda);
+            let (lda_minus, _) = xc.matrix(&(&P - &dP), lda);
+            assert!(((lda_plus - lda_minus) / (2.0 * h) - V_lda[[mu, nu]]).abs() < 1.0e-6);
+            let (gga_plus, _) = xc.matrix(&(&P + &dP), model);
+            let (gga_minus, _) = xc.matrix(&(&P - &dP), model);
+            assert!(((gga_plus - gga_minus) / (2.0 * h) - V_gga[[mu, nu]]).abs() < 1.0e-6);
+        }
+    }
+
+    #[test]
+    fn test_rks_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = KSOptions {
+            scf: SCFOptions {
+                jk: scf::JKAlgorithm::InMemory,
+                ..Default::default()
+            },
+            grid: small_grid(),
+            functional: Functional::slater(),
+            ..Default::default()
+        };
+        let ks = rks(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!(ks.converged);
+        // Local exchange alone recovers most but not all of the exact
+        // exchange energy.
+        let hf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options.scf);
+        assert!(ks.energy > hf.energy);
+        assert!(ks.energy - hf.energy < 1.0);
+
+        // The Kohn-Sham energy is stationary, so the energy recomputed from
+        // the converged density matches.
+        let xc = XCIntegrator::new(&basis_set, &atomcoords, &atomnos, &options.grid);
+        let (e_xc, _) = xc.matrix(&(2.0 * &ks.D), |rho, sigma| {
+            options.functional.evaluate(rho, sigma)
+        });
+        let jk = scf::JKEngine::new(&basis_set, &options.scf.jk);
+        let (J, _) = jk.build(&ks.D);
+        let H = scf::core_hamiltonian(&basis_set, &atomcoords, &atomnos);
+        let energy = (&ks.D * &(2.0 * &H + 2.0 * &J)).sum() + e_xc + ks.e_nuc;
+        assert!((energy - ks.energy).abs() < 1.0e-8);
+    }
+
+    #[test]
+    fn test_rks_hybrids_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf_options = SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let hf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &scf_options);
+        let options = KSOptions {
+            scf: scf_options,
+            grid: small_grid(),
+            functional: Functional::hartree_fock(),
+            ..Default::default()
+        };
+        // Pure exact exchange reproduces Hartree-Fock.
+        let ks = rks(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!(ks.converged);
+        assert!((ks.energy - hf.energy).abs() < 1.0e-8);
+        for functional in [Functional::b3lyp(), Functional::pbe0()].iter() {
+            let options = KSOptions {
+                functional: functional.clone(),
+                ..options.clone()
+            };
+            let ks = rks(&basis_set, &atomcoords, &atomnos, 5, &options);
+            assert!(ks.converged);
+            // Correlation lowers the energy below Hartree-Fock.
+            assert!(ks.energy < hf.energy);
+            assert!(hf.energy - ks.energy < 1.0);
+        }
+    }
+
+    #[test]
+    fn test_functionals_helium() {
+        // An even-tempered s basis close to the Hartree-Fock limit for He.
+        let shells: Vec<String> = (0..14)
+            .map(|k| {
+                format!(
+                    r#"{{"function_type": "gto", "region": "", "angular_momentum": [0], "exponents": ["{}"], "coefficients": [["1.0"]]}}"#,
+                    0.05 * 2.4f64.powi(k)
+                )
+            })
+            .collect();
+        let json = format!(
+            r#"{{"name": "even-tempered", "description": "", "elements": {{"2": {{"electron_shells": [{}]}}}}}}"#,
+            shells.join(", ")
+        );
+        let atomcoords = vec![[0.0, 0.0, 0.0]];
+        let atomnos = vec![2];
+        let basis_set = basis::Basis::from_json(&atomnos, &atomcoords, &json);
+        let options = SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let hf = scf::rhf(&basis_set, &atomcoords, &atomnos, 1, &options);
+        assert!((hf.energy - -2.86168).abs() < 1.0e-4);
+
+        // Exchange and correlation energies of the Hartree-Fock density from
+        // J. Tao, J. P. Perdew, V. N. Staroverov and G. E. Scuseria, Phys.
+        // Rev. Lett. 91, 146401 (2003), and from the B88 and LYP papers.
+        let grid = GridOptions {
+            radial_points: 75,
+            angular_points: 14,
+            ..Default::default()
+        };
+        let xc = XCIntegrator::new(&basis_set, &atomcoords, &atomnos, &grid);
+        let P = 2.0 * &hf.D;
+        let reference = [
+            (Component::Slater, -0.884),
+            (Component::B88, -1.025),
+            (Component::PBEExchange, -1.014),
+            (Component::PW92, -0.112),
+            (Component::PBECorrelation, -0.042),
+            (Component::LYP, -0.0437),
+        ];
+        for &(component, energy) in reference.iter() {
+            let functional = Functional::new("", vec![(component, 1.0)], 0.0);
+            let (e_xc, _) = xc.matrix(&P, |rho, sigma| functional.evaluate(rho, sigma));
+            assert!((e_xc - energy).abs() < 1.0e-3);
+        }
+    }
+}
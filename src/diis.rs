@@ -0,0 +1,143 @@
+#![allow(non_snake_case)]
+
+//! Pulay's direct inversion in the iterative subspace (DIIS), for
+//! accelerating any fixed-point iteration whose state and error can be
+//! flattened into vectors.
+
+use std::collections::VecDeque;
+
+use ndarray::{Array, Ix1};
+use ndarray_linalg::*;
+
+#[derive(Clone, Debug)]
+pub struct DIIS {
+    max_vectors: usize,
+    vectors: VecDeque<Array<f64, Ix1>>,
+    errors: VecDeque<Array<f64, Ix1>>,
+}
+
+impl DIIS {
+    pub fn new(max_vectors: usize) -> DIIS {
+        assert!(max_vectors > 0);
+        DIIS {
+            max_vectors,
+            vectors: VecDeque::new(),
+            errors: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.vectors.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.vectors.is_empty()
+    }
+
+    pub fn max_vectors(&self) -> usize {
+        self.max_vectors
+    }
+
+    /// The stored vectors, oldest first.
+    pub fn vectors(&self) -> Vec<Array<f64, Ix1>> {
+        self.vectors.iter().cloned().collect()
+    }
+
+    /// The errors of the stored vectors, oldest first.
+    pub fn errors(&self) -> Vec<Array<f64, Ix1>> {
+        self.errors.iter().cloned().collect()
+    }
+
+    /// Rebuild a subspace saved with `vectors` and `errors`, keeping the newest pairs if
+    /// there are more than `max_vectors`.
+    pub fn from_subspace(
+        max_vectors: usize,
+        vectors: Vec<Array<f64, Ix1>>,
+        errors: Vec<Array<f64, Ix1>>,
+    ) -> DIIS {
+        let mut diis = DIIS::new(max_vectors);
+        for (vector, error) in vectors.into_iter().zip(errors) {
+            diis.push(vector, error);
+        }
+        diis
+    }
+
+    pub fn clear(&mut self) {
+        self.vectors.clear();
+        self.errors.clear();
+    }
+
+    /// Add a vector and its error to the subspace, dropping the oldest pair
+    /// if the subspace is full.
+    pub fn push(&mut self, vector: Array<f64, Ix1>, error: Array<f64, Ix1>) {
+        assert_eq!(vector.len(), error.len());
+        if self.vectors.len() == self.max_vectors {
+            self.vectors.pop_front();
+            self.errors.pop_front();
+        }
+        self.vectors.push_back(vector);
+        self.errors.push_back(error);
+    }
+
+    /// The linear combination of the stored vectors whose combined error has
+    /// the smallest norm, subject to the coefficients summing to one. If the
+    /// DIIS equations are singular, the most recent vector is returned.
+    pub fn extrapolate(&self) -> Array<f64, Ix1> {
+        let n = self.vectors.len();
+        assert!(n > 0);
+        let mut B: Array<f64, _> = Array::zeros((n + 1, n + 1));
+        for i in 0..n {
+            for j in 0..=i {
+                B[[i, j]] = self.errors[i].dot(&self.errors[j]);
+                B[[j, i]] = B[[i, j]];
+            }
+            B[[i, n]] = -1.0;
+            B[[n, i]] = -1.0;
+        }
+        // Scaling by the largest diagonal element keeps the equations well
+        // conditioned as the errors go to zero.
+        let scale = (0..n).fold(0.0, |acc: f64, i| acc.max(B[[i, i]]));
+        if scale > 0.0 {
+            for i in 0..n {
+                for j in 0..n {
+                    B[[i, j]] /= scale;
+                }
+            }
+        }
+        let mut rhs: Array<f64, _> = Array::zeros(n + 1);
+        rhs[n] = -1.0;
+        match B.solve(&rhs) {
+            Ok(c) => {
+                let mut result: Array<f64, _> = Array::zeros(self.vectors[0].len());
+                for (ci, vector) in c.iter().zip(self.vectors.iter()) {
+                    result.scaled_add(*ci, vector);
+                }
+                result
+            }
+            Err(_) => self.vectors[n - 1].clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_fixed_point() {
+        // x = A x + b converges slowly by plain iteration but DIIS solves it
+        // exactly once the subspace spans the problem.
+        let A = ndarray::arr2(&[[0.9, 0.05], [0.05, 0.8]]);
+        let b = ndarray::arr1(&[1.0, 2.0]);
+        let mut diis = DIIS::new(4);
+        let mut x: Array<f64, _> = Array::zeros(2);
+        for _ in 0..4 {
+            let x_new = A.dot(&x) + &b;
+            let error = &x_new - &x;
+            diis.push(x_new, error);
+            x = diis.extrapolate();
+        }
+        let residual = A.dot(&x) + &b - &x;
+        assert!(residual.iter().all(|r| r.abs() < 1.0e-10));
+    }
+}
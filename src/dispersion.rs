@@ -0,0 +1,610 @@
+//! Grimme's D3 dispersion correction with zero or Becke-Johnson damping,
+//!
+//! E = -sum_{A<B} sum_{n=6,8} s_n C_n^AB / r_AB^n f_n(r_AB),
+//!
+//! where the C6 coefficients are interpolated between reference values
+//! according to the coordination number of each atom.
+//!
+//! S. Grimme, J. Antony, S. Ehrlich and H. Krieg, J. Chem. Phys. 132, 154104
+//! (2010); S. Grimme, S. Ehrlich and L. Goerigk, J. Comput. Chem. 32, 1456
+//! (2011).
+//!
+//! The reference C6 coefficients and the zero-damping cutoff radii are
+//! tabulated data distributed with the `dftd3` program, which are read by
+//! `D3Reference` rather than compiled in: the C6 table from `pars.f` and
+//! the radii from the `setr0ab` routine of `dftd3.f`, both Fortran source.
+
+use std::collections::HashMap as Map;
+use std::io;
+
+use ndarray::{Array, Ix1, Ix2};
+
+use crate::elements;
+
+const K1: f64 = 16.0;
+const K2: f64 = 4.0 / 3.0;
+const K3: f64 = 4.0;
+
+// sqrt(0.5 sqrt(Z) <r^4>/<r^2>) for H through Kr, which gives
+// C8 = 3 C6 r2r4_A r2r4_B.
+const R2R4: [f64; 37] = [
+    0.0, 2.00734898, 1.56637132, 5.01986934, 3.85379032, 3.64446594, 3.10492822, 2.71175247,
+    2.59361680, 2.38825250, 2.21522516, 6.58585536, 5.46295967, 5.65216669, 4.88284902, 4.29727576,
+    4.04108902, 3.72932356, 3.44677275, 7.97762753, 7.07623947, 6.60844053, 6.28791364, 6.07728703,
+    5.54643096, 5.80491167, 5.58415602, 5.41374528, 5.28497229, 5.22592821, 5.09817141, 6.12149689,
+    5.54083734, 5.06696878, 4.87005108, 4.59089647, 4.31176304,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Damping {
+    /// The original damping function that goes to zero at short range,
+    /// f_n = 1 / (1 + 6 (r / (s_{r,n} R0_AB))^(-alpha_n)).
+    Zero { s6: f64, rs6: f64, s8: f64 },
+    /// Becke-Johnson damping to a finite value at short range,
+    /// f_n = r^n / (r^n + (a1 R0_AB + a2)^n), with R0_AB = sqrt(C8 / C6).
+    BeckeJohnson { s6: f64, a1: f64, s8: f64, a2: f64 },
+}
+
+impl Damping {
+    /// The published zero-damping parameters for a method.
+    pub fn zero(method: &str) -> Option<Damping> {
+        let (rs6, s8) = match method.to_lowercase().as_str() {
+            "hf" => (1.158, 1.746),
+            "blyp" => (1.094, 1.682),
+            "pbe" => (1.217, 0.722),
+            "b3lyp" => (1.261, 1.703),
+            "pbe0" => (1.287, 0.928),
+            _ => return None,
+        };
+        Some(Damping::Zero { s6: 1.0, rs6, s8 })
+    }
+
+    /// The published Becke-Johnson damping parameters for a method.
+    pub fn becke_johnson(method: &str) -> Option<Damping> {
+        let (a1, s8, a2) = match method.to_lowercase().as_str() {
+            "hf" => (0.3385, 0.9171, 2.8830),
+            "blyp" => (0.4298, 2.6996, 4.2359),
+            "pbe" => (0.4289, 0.7875, 4.4407),
+            "b3lyp" => (0.3981, 1.9889, 4.4211),
+            "pbe0" => (0.4145, 1.2177, 4.8593),
+            _ => return None,
+        };
+        Some(Damping::BeckeJohnson {
+            s6: 1.0,
+            a1,
+            s8,
+            a2,
+        })
+    }
+}
+
+/// Reference C6 coefficients for pairs of elements in each of their
+/// reference coordination states, and the pairwise cutoff radii used by
+/// zero damping.
+#[derive(Clone, Debug, Default)]
+pub struct D3Reference {
+    /// The coordination numbers of the reference states of each element.
+    pub cn: Map<u64, Vec<f64>>,
+    /// C6 for a pair of elements (with the smaller atomic number first),
+    /// indexed by the reference states of each.
+    pub c6: Map<(u64, u64), Array<f64, Ix2>>,
+    /// Cutoff radii R0_AB in bohr, keyed like `c6`.
+    pub r0ab: Map<(u64, u64), f64>,
+}
+
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn parse_number(token: &str) -> io::Result<f64> {
+    token
+        .replace(['D', 'd'], "e")
+        .parse::<f64>()
+        .map_err(|_| invalid(format!("not a number: {}", token)))
+}
+
+fn parse_numbers(text: &str) -> io::Result<Vec<f64>> {
+    text.split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|s| !s.is_empty())
+        .map(parse_number)
+        .collect()
+}
+
+/// Fixed-form Fortran source without comment lines, with continuation
+/// lines joined and, since blanks mean nothing there, without whitespace.
+fn fortran_source(text: &str) -> String {
+    let mut source = String::new();
+    for line in text.lines() {
+        if line.starts_with(['c', 'C', '*', '!']) {
+            continue;
+        }
+        let line = line.split('!').next().unwrap();
+        let continuation =
+            line.len() > 6 && line[..5].trim().is_empty() && !line[5..6].starts_with([' ', '0']);
+        let line = if continuation { &line[6..] } else { line };
+        source.extend(line.chars().filter(|c| !c.is_whitespace()));
+    }
+    source.to_lowercase()
+}
+
+/// The values that Fortran source assigns to the array `name` with array
+/// constructors, `name(first:last)=(/ ... /)`, in order of their (1-based)
+/// indices, or `None` if there are no such assignments.
+fn fortran_array(text: &str, name: &str) -> io::Result<Option<Vec<f64>>> {
+    let source = fortran_source(text);
+    let pattern = format!("{}(", name);
+    let mut values: Vec<Option<f64>> = Vec::new();
+    let mut rest = source.as_str();
+    while let Some(position) = rest.find(&pattern) {
+        let preceding = rest[..position].chars().last();
+        rest = &rest[position + pattern.len()..];
+        if preceding.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            continue;
+        }
+        // Skip uses of the array that are not whole-range assignments.
+        let range = match rest.find(')') {
+            Some(close) if rest[close..].starts_with(")=(/") => &rest[..close],
+            _ => continue,
+        };
+        let (first, last) = match range.split_once(':') {
+            Some((first, last)) => (first.parse::<usize>(), last.parse::<usize>()),
+            None => continue,
+        };
+        let (first, last) = match (first, last) {
+            (Ok(first), Ok(last)) if first >= 1 && last >= first => (first, last),
+            _ => return Err(invalid(format!("bad range {}({})", name, range))),
+        };
+        rest = &rest[range.len() + ")=(/".len()..];
+        let end = rest
+            .find("/)")
+            .ok_or_else(|| invalid(format!("unterminated {}({})", name, range)))?;
+        let numbers: Vec<f64> = rest[..end]
+            .split(',')
+            .map(parse_number)
+            .collect::<io::Result<_>>()?;
+        if numbers.len() != last - first + 1 {
+            return Err(invalid(format!(
+                "{}({}) has {} values",
+                name,
+                range,
+                numbers.len()
+            )));
+        }
+        if values.len() < last {
+            values.resize(last, None);
+        }
+        for (k, value) in numbers.into_iter().enumerate() {
+            values[first - 1 + k] = Some(value);
+        }
+        rest = &rest[end + 2..];
+    }
+    if values.is_empty() {
+        return Ok(None);
+    }
+    values
+        .into_iter()
+        .enumerate()
+        .map(|(k, value)| value.ok_or_else(|| invalid(format!("{}({}) is never set", name, k + 1))))
+        .collect::<io::Result<Vec<_>>>()
+        .map(Some)
+}
+
+impl D3Reference {
+    /// Read the reference C6 coefficients from `pars.f` of `dftd3`, which
+    /// assigns them to the array `pars` in groups of five numbers
+    ///
+    /// C6, Z_A + 100 k_A, Z_B + 100 k_B, CN_A, CN_B
+    ///
+    /// for reference states k_A and k_B. The same numbers as a plain list,
+    /// separated by whitespace or commas, are read too.
+    pub fn from_pars(text: &str) -> io::Result<D3Reference> {
+        let numbers = match fortran_array(text, "pars")? {
+            Some(numbers) => numbers,
+            None => parse_numbers(text)?,
+        };
+        if numbers.is_empty() || numbers.len() % 5 != 0 {
+            return Err(invalid(format!(
+                "expected groups of five numbers, found {}",
+                numbers.len()
+            )));
+        }
+        let mut cn: Map<u64, Vec<f64>> = Map::new();
+        let mut entries = Vec::new();
+        for chunk in numbers.chunks(5) {
+            if chunk[1] < 1.0 || chunk[2] < 1.0 {
+                return Err(invalid(format!("bad reference {} {}", chunk[1], chunk[2])));
+            }
+            let (ia, ib) = (chunk[1].round() as u64, chunk[2].round() as u64);
+            let (za, ka) = (ia % 100, (ia / 100) as usize);
+            let (zb, kb) = (ib % 100, (ib / 100) as usize);
+            for &(z, k, value) in [(za, ka, chunk[3]), (zb, kb, chunk[4])].iter() {
+                let refs = cn.entry(z).or_default();
+                if refs.len() <= k {
+                    refs.resize(k + 1, f64::NAN);
+                }
+                refs[k] = value;
+            }
+            entries.push((za, ka, zb, kb, chunk[0]));
+        }
+        let mut c6: Map<(u64, u64), Array<f64, Ix2>> = Map::new();
+        for (za, ka, zb, kb, value) in entries {
+            let (za, ka, zb, kb) = if za <= zb {
+                (za, ka, zb, kb)
+            } else {
+                (zb, kb, za, ka)
+            };
+            let shape = (cn[&za].len(), cn[&zb].len());
+            let table = c6
+                .entry((za, zb))
+                .or_insert_with(|| Array::from_elem(shape, f64::NAN));
+            table[[ka, kb]] = value;
+            if za == zb {
+                table[[kb, ka]] = value;
+            }
+        }
+        Ok(D3Reference {
+            cn,
+            c6,
+            r0ab: Map::new(),
+        })
+    }
+
+    /// Read the cutoff radii in angstrom in the order of `dftd3`, where the
+    /// pairs of elements run over Z_A = 1, 2, ... and Z_B = 1, ..., Z_A.
+    /// They are taken from the assignments to `r0ab` in `dftd3.f`, or from a
+    /// plain list of numbers.
+    pub fn set_r0ab(&mut self, text: &str) -> io::Result<()> {
+        let numbers = match fortran_array(text, "r0ab")? {
+            Some(numbers) => numbers,
+            None => parse_numbers(text)?,
+        };
+        if numbers.is_empty() {
+            return Err(invalid("no cutoff radii".to_string()));
+        }
+        let mut values = numbers.iter();
+        let mut za = 1;
+        'outer: loop {
+            for zb in 1..=za {
+                match values.next() {
+                    Some(&r0) => {
+                        self.r0ab
+                            .insert((zb, za), r0 * crate::constants::ANGSTROM_TO_BOHR);
+                    }
+                    None => break 'outer,
+                }
+            }
+            za += 1;
+        }
+        Ok(())
+    }
+
+    /// Fail unless there is reference data for every pair of the elements,
+    /// including the cutoff radii that zero damping needs.
+    pub fn check(&self, atomnos: &[u64], damping: &Damping) -> io::Result<()> {
+        for (i, &za) in atomnos.iter().enumerate() {
+            if za as usize >= R2R4.len() {
+                return Err(invalid(format!(
+                    "D3 is only available up to Kr, not {}",
+                    elements::symbol(za)
+                )));
+            }
+            for &zb in &atomnos[..=i] {
+                let pair = self.pair(za, zb);
+                let missing = if !self.c6.contains_key(&pair) {
+                    Some("reference C6")
+                } else if matches!(damping, Damping::Zero { .. }) && !self.r0ab.contains_key(&pair)
+                {
+                    Some("cutoff radius")
+                } else {
+                    None
+                };
+                if let Some(missing) = missing {
+                    return Err(invalid(format!(
+                        "no D3 {} for {}-{}",
+                        missing,
+                        elements::symbol(za),
+                        elements::symbol(zb)
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn pair(&self, za: u64, zb: u64) -> (u64, u64) {
+        if za <= zb {
+            (za, zb)
+        } else {
+            (zb, za)
+        }
+    }
+
+    /// C6 for two atoms with the given coordination numbers, together with
+    /// its derivatives with respect to each coordination number.
+    pub fn c6(&self, za: u64, zb: u64, cna: f64, cnb: f64) -> (f64, f64, f64) {
+        let (za, zb, cna, cnb, swapped) = if za <= zb {
+            (za, zb, cna, cnb, false)
+        } else {
+            (zb, za, cnb, cna, true)
+        };
+        let table = self.c6.get(&(za, zb)).unwrap_or_else(|| {
+            panic!(
+                "no D3 reference C6 for {}-{}",
+                elements::symbol(za),
+                elements::symbol(zb)
+            )
+        });
+        let (refs_a, refs_b) = (&self.cn[&za], &self.cn[&zb]);
+        let (mut z, mut w) = (0.0, 0.0);
+        let (mut dz_a, mut dw_a, mut dz_b, mut dw_b) = (0.0, 0.0, 0.0, 0.0);
+        // The reference with the largest coordination numbers, used when
+        // all of the Gaussian weights underflow.
+        let (mut fallback, mut max_cn) = (0.0, f64::NEG_INFINITY);
+        for (i, cn_i) in refs_a.iter().enumerate() {
+            for (j, cn_j) in refs_b.iter().enumerate() {
+                let c6_ref = table[[i, j]];
+                if c6_ref.is_nan() {
+                    continue;
+                }
+                if cn_i + cn_j > max_cn {
+                    max_cn = cn_i + cn_j;
+                    fallback = c6_ref;
+                }
+                let l = (-K3 * ((cna - cn_i).powi(2) + (cnb - cn_j).powi(2))).exp();
+                let dl_a = -2.0 * K3 * (cna - cn_i) * l;
+                let dl_b = -2.0 * K3 * (cnb - cn_j) * l;
+                z += c6_ref * l;
+                w += l;
+                dz_a += c6_ref * dl_a;
+                dw_a += dl_a;
+                dz_b += c6_ref * dl_b;
+                dw_b += dl_b;
+            }
+        }
+        let (c6, dc6_a, dc6_b) = if w > 1.0e-100 {
+            (
+                z / w,
+                (dz_a * w - z * dw_a) / (w * w),
+                (dz_b * w - z * dw_b) / (w * w),
+            )
+        } else {
+            (fallback, 0.0, 0.0)
+        };
+        if swapped {
+            (c6, dc6_b, dc6_a)
+        } else {
+            (c6, dc6_a, dc6_b)
+        }
+    }
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (0..3).map(|k| (a[k] - b[k]).powi(2)).sum::<f64>().sqrt()
+}
+
+/// The counting function for a pair of atoms and its derivative with respect
+/// to their distance.
+fn counting(r: f64, rcov: f64) -> (f64, f64) {
+    let e = (-K1 * (rcov / r - 1.0)).exp();
+    let f = 1.0 / (1.0 + e);
+    (f, -K1 * rcov / (r * r) * e * f * f)
+}
+
+fn scaled_covalent_radii(atomnos: &[u64]) -> Vec<f64> {
+    atomnos
+        .iter()
+        .map(|&z| K2 * elements::covalent_radius(z))
+        .collect()
+}
+
+/// The fractional coordination number of every atom.
+pub fn coordination_numbers(atomcoords: &[[f64; 3]], atomnos: &[u64]) -> Array<f64, Ix1> {
+    let rcov = scaled_covalent_radii(atomnos);
+    let natoms = atomcoords.len();
+    let mut cn: Array<f64, _> = Array::zeros(natoms);
+    for a in 0..natoms {
+        for b in 0..a {
+            let r = distance(&atomcoords[a], &atomcoords[b]);
+            let (f, _) = counting(r, rcov[a] + rcov[b]);
+            cn[a] += f;
+            cn[b] += f;
+        }
+    }
+    cn
+}
+
+#[derive(Clone, Debug)]
+pub struct DispersionResult {
+    pub energy: f64,
+    /// Shape `(natoms, 3)`.
+    pub gradient: Array<f64, Ix2>,
+}
+
+/// The D3 dispersion energy and its nuclear gradient, without the
+/// three-body term.
+pub fn d3(
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    damping: &Damping,
+    reference: &D3Reference,
+) -> DispersionResult {
+    let natoms = atomcoords.len();
+    let cn = coordination_numbers(atomcoords, atomnos);
+    let mut energy = 0.0;
+    let mut gradient: Array<f64, _> = Array::zeros((natoms, 3));
+    // dE/dCN for every atom, accumulated from the C6 of each pair.
+    let mut de_dcn: Array<f64, _> = Array::zeros(natoms);
+
+    for a in 0..natoms {
+        for b in 0..a {
+            let (za, zb) = (atomnos[a], atomnos[b]);
+            let r = distance(&atomcoords[a], &atomcoords[b]);
+            let (c6, dc6_a, dc6_b) = reference.c6(za, zb, cn[a], cn[b]);
+            let q = 3.0 * R2R4[za as usize] * R2R4[zb as usize];
+            // The pair energy is c6 * g(r), since C8 = q C6.
+            let (g, dg) = match *damping {
+                Damping::Zero { s6, rs6, s8 } => {
+                    let r0 = *reference
+                        .r0ab
+                        .get(&reference.pair(za, zb))
+                        .unwrap_or_else(|| {
+                            panic!(
+                                "no D3 cutoff radius for {}-{}",
+                                elements::symbol(za),
+                                elements::symbol(zb)
+                            )
+                        });
+                    let term = |s: f64, n: i32, sr: f64, alpha: i32| {
+                        let t = 6.0 * (r / (sr * r0)).powi(-alpha);
+                        let f = 1.0 / (1.0 + t);
+                        let df = alpha as f64 * t / r * f * f;
+                        let rn = r.powi(-n);
+                        (-s * rn * f, -s * (-(n as f64) * rn / r * f + rn * df))
+                    };
+                    let (g6, dg6) = term(s6, 6, rs6, 14);
+                    let (g8, dg8) = term(s8, 8, 1.0, 16);
+                    (g6 + q * g8, dg6 + q * dg8)
+                }
+                Damping::BeckeJohnson { s6, a1, s8, a2 } => {
+                    let cutoff = a1 * q.sqrt() + a2;
+                    let term = |s: f64, n: i32| {
+                        let d = r.powi(n) + cutoff.powi(n);
+                        (-s / d, s * n as f64 * r.powi(n - 1) / (d * d))
+                    };
+                    let (g6, dg6) = term(s6, 6);
+                    let (g8, dg8) = term(s8, 8);
+                    (g6 + q * g8, dg6 + q * dg8)
+                }
+            };
+            energy += c6 * g;
+            de_dcn[a] += g * dc6_a;
+            de_dcn[b] += g * dc6_b;
+            for k in 0..3 {
+                let dr = (atomcoords[a][k] - atomcoords[b][k]) / r;
+                gradient[[a, k]] += c6 * dg * dr;
+                gradient[[b, k]] -= c6 * dg * dr;
+            }
+        }
+    }
+
+    // The coordination numbers depend on the geometry as well.
+    let rcov = scaled_covalent_radii(atomnos);
+    for a in 0..natoms {
+        for b in 0..a {
+            let r = distance(&atomcoords[a], &atomcoords[b]);
+            let (_, df) = counting(r, rcov[a] + rcov[b]);
+            let factor = (de_dcn[a] + de_dcn[b]) * df;
+            for k in 0..3 {
+                let dr = (atomcoords[a][k] - atomcoords[b][k]) / r;
+                gradient[[a, k]] += factor * dr;
+                gradient[[b, k]] -= factor * dr;
+            }
+        }
+    }
+
+    DispersionResult { energy, gradient }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradient;
+    use crate::testing;
+
+    // A made-up reference table for H and O, with two reference states for
+    // each element, laid out like `pars.f`.
+    const PARS: &str = "
+c     reference C6 for H and O
+      pars(   1:  25)=(/
+     .  3.0267D+00,  1.0000D+00,  1.0000D+00,  0.9118D+00,  0.9118D+00,
+     .  7.5916D+00,  101.00D+00,  1.0000D+00,  0.0000D+00,  0.9118D+00,
+     .  14.3000D+00, 101.00D+00,  101.00D+00,  0.0000D+00,  0.0000D+00,
+     .  10.0000D+00, 8.0000D+00,  1.0000D+00,  0.0000D+00,  0.9118D+00,
+     .  21.0000D+00, 8.0000D+00,  101.00D+00,  0.0000D+00,  0.0000D+00
+     ./)
+      pars(  26:  50)=(/
+     .  5.5000D+00,  108.00D+00,  1.0000D+00,  1.9887D+00,  0.9118D+00,
+     .  11.0000D+00, 108.00D+00,  101.00D+00,  1.9887D+00,  0.0000D+00,
+     .  18.0000D+00, 8.0000D+00,  8.0000D+00,  0.0000D+00,  0.0000D+00,
+     .  12.0000D+00, 108.00D+00,  8.0000D+00,  1.9887D+00,  0.0000D+00,
+     .  9.0000D+00,  108.00D+00,  108.00D+00,  1.9887D+00,  1.9887D+00
+     ./)
+";
+
+    fn reference() -> D3Reference {
+        let mut reference = D3Reference::from_pars(PARS).unwrap();
+        // Zero damping needs the H-H, O-H and O-O radii in the order
+        // (1,1), (2,1), (2,2), ..., so pad the pairs in between.
+        let mut radii = Vec::new();
+        for za in 1..=8 {
+            for zb in 1..=za {
+                radii.push(match (za, zb) {
+                    (1, 1) => "2.1823",
+                    (8, 1) => "2.4000",
+                    (8, 8) => "2.5000",
+                    _ => "0.0",
+                });
+            }
+        }
+        reference
+            .set_r0ab(&format!(
+                "      r0ab(1:36)=(/\n     . {}/)",
+                radii.join(",")
+            ))
+            .unwrap();
+        reference
+    }
+
+    #[test]
+    fn test_reference_data_errors() {
+        assert!(D3Reference::from_pars("3.0 1.0 1.0 0.9").is_err());
+        assert!(D3Reference::from_pars("3.0 1.0 x 0.9 0.9").is_err());
+        assert!(D3Reference::from_pars("      pars(1:10)=(/ 3.0, 1.0, 1.0, 0.9, 0.9 /)").is_err());
+        let mut reference = D3Reference::from_pars(PARS).unwrap();
+        let damping = Damping::zero("b3lyp").unwrap();
+        assert!(reference.check(&[8, 1, 1], &damping).is_err());
+        assert!(reference
+            .set_r0ab("      r0ab(1:3)=(/ 2.1823, 1.8547 /)")
+            .is_err());
+        reference.set_r0ab("2.1823 1.8547 2.0").unwrap();
+        assert!(reference.check(&[1, 1], &damping).is_ok());
+        assert!(reference
+            .check(&[6, 1], &Damping::becke_johnson("b3lyp").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_d3_water() {
+        let (atomcoords, atomnos) = testing::water();
+        let reference = reference();
+        assert_eq!(reference.cn[&8], vec![0.0, 1.9887]);
+        assert_eq!(reference.cn[&1], vec![0.9118, 0.0]);
+
+        // Oxygen has about two bonds and each hydrogen about one.
+        let cn = coordination_numbers(&atomcoords, &atomnos);
+        assert!((cn[0] - 2.0).abs() < 0.2);
+        assert!((cn[1] - 1.0).abs() < 0.2);
+
+        // At the coordination numbers of a reference state, C6 is dominated
+        // by that state, whichever order the atoms are given in.
+        let (c6, _, _) = reference.c6(8, 1, 1.9887, 0.9118);
+        assert!((c6 - 5.5).abs() < 0.5);
+        assert_eq!(reference.c6(1, 8, 0.9118, 1.9887).0, c6);
+
+        for damping in [
+            Damping::zero("b3lyp").unwrap(),
+            Damping::becke_johnson("b3lyp").unwrap(),
+        ]
+        .iter()
+        {
+            let result = d3(&atomcoords, &atomnos, damping, &reference);
+            assert!(result.energy < 0.0);
+            let numerical = gradient::finite_difference_gradient(&atomcoords, 1.0e-4, |coords| {
+                d3(coords, &atomnos, damping, &reference).energy
+            });
+            let max_error =
+                (&result.gradient - &numerical.values).fold(0.0f64, |acc, x| acc.max(x.abs()));
+            assert!(max_error < 1.0e-9);
+        }
+    }
+}
@@ -0,0 +1,108 @@
+//! Per-element data, indexed by atomic number.
+
+use crate::constants::ANGSTROM_TO_BOHR;
+
+const SYMBOLS: [&str; 37] = [
+    "X", "H", "He", "Li", "Be", "B", "C", "N", "O", "F", "Ne", "Na", "Mg", "Al", "Si", "P", "S",
+    "Cl", "Ar", "K", "Ca", "Sc", "Ti", "V", "Cr", "Mn", "Fe", "Co", "Ni", "Cu", "Zn", "Ga", "Ge",
+    "As", "Se", "Br", "Kr",
+];
+
+// Single-bond covalent radii in angstrom from P. Pyykkö and M. Atsumi,
+// Chem. Eur. J. 15, 186 (2009).
+const COVALENT_RADII: [f64; 37] = [
+    0.00, 0.32, 0.46, 1.33, 1.02, 0.85, 0.75, 0.71, 0.63, 0.64, 0.67, 1.55, 1.39, 1.26, 1.16, 1.11,
+    1.03, 0.99, 0.96, 1.96, 1.71, 1.48, 1.36, 1.34, 1.22, 1.19, 1.16, 1.11, 1.10, 1.12, 1.18, 1.24,
+    1.21, 1.21, 1.16, 1.14, 1.17,
+];
+
+// Mass in amu of the most abundant isotope of each element, from the NIST
+// Atomic Weights and Isotopic Compositions database.
+const MASSES: [f64; 37] = [
+    0.0,
+    1.00782503223,
+    4.00260325413,
+    7.0160034366,
+    9.012183065,
+    11.00930536,
+    12.0,
+    14.00307400443,
+    15.99491461957,
+    18.99840316273,
+    19.9924401762,
+    22.989769282,
+    23.985041697,
+    26.98153853,
+    27.97692653465,
+    30.97376199842,
+    31.9720711744,
+    34.968852682,
+    39.9623831237,
+    38.9637064864,
+    39.962590863,
+    44.95590828,
+    47.94794198,
+    50.94395704,
+    51.94050623,
+    54.93804391,
+    55.93493633,
+    58.93319429,
+    57.93534241,
+    62.92959772,
+    63.92914201,
+    68.9255735,
+    73.921177761,
+    74.92159457,
+    79.9165218,
+    78.9183376,
+    83.9114977282,
+];
+
+pub fn symbol(atomno: u64) -> &'static str {
+    SYMBOLS[atomno as usize]
+}
+
+pub fn atomic_number(symbol: &str) -> Option<u64> {
+    SYMBOLS
+        .iter()
+        .skip(1)
+        .position(|s| s.eq_ignore_ascii_case(symbol))
+        .map(|i| i as u64 + 1)
+}
+
+/// Mass of the most abundant isotope in amu.
+pub fn mass(atomno: u64) -> f64 {
+    MASSES[atomno as usize]
+}
+
+/// Covalent radius in bohr.
+pub fn covalent_radius(atomno: u64) -> f64 {
+    COVALENT_RADII[atomno as usize] * ANGSTROM_TO_BOHR
+}
+
+/// Row of the periodic table, starting from 1 for H and He.
+pub fn period(atomno: u64) -> usize {
+    match atomno {
+        0..=2 => 1,
+        3..=10 => 2,
+        11..=18 => 3,
+        19..=36 => 4,
+        37..=54 => 5,
+        55..=86 => 6,
+        _ => 7,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_atomic_number() {
+        assert_eq!(atomic_number("H"), Some(1));
+        assert_eq!(atomic_number("he"), Some(2));
+        assert_eq!(atomic_number("O"), Some(8));
+        assert_eq!(atomic_number("Xx"), None);
+        assert_eq!(symbol(atomic_number("Kr").unwrap()), "Kr");
+    }
+}
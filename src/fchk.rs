@@ -0,0 +1,631 @@
+#![allow(non_snake_case)]
+
+//! Reading and writing Gaussian formatted checkpoint (fchk) files, for
+//! exchanging wavefunctions with Multiwfn and other analysis programs.
+//!
+//! Only Cartesian shells are supported. Gaussian orders the components of
+//! each shell differently from `shell::get_ijk_list` and normalizes all of
+//! them like x^l, so orbital coefficients and densities are converted on
+//! the way in and out.
+
+use std::collections::HashMap as Map;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ndarray::{Array, Ix1, Ix2};
+
+use crate::basis::{self, Basis, Shell};
+use crate::scf;
+use crate::shell;
+
+/// Everything rchem writes to or reads from an fchk file. Orbital
+/// coefficients (one orbital per column) and densities are in `basis`.
+#[derive(Clone, Debug)]
+pub struct Fchk {
+    pub title: String,
+    pub method: String,
+    pub basis: Basis,
+    pub atomnos: Vec<u64>,
+    pub atomcoords: Vec<[f64; 3]>,
+    pub charge: i64,
+    pub multiplicity: usize,
+    pub nalpha: usize,
+    pub nbeta: usize,
+    pub energy: f64,
+    pub eps_alpha: Array<f64, Ix1>,
+    pub C_alpha: Array<f64, Ix2>,
+    /// Only present for unrestricted wavefunctions.
+    pub eps_beta: Option<Array<f64, Ix1>>,
+    pub C_beta: Option<Array<f64, Ix2>>,
+    /// The sum of the alpha and beta densities.
+    pub total_density: Array<f64, Ix2>,
+    /// The alpha minus the beta density, for unrestricted wavefunctions.
+    pub spin_density: Option<Array<f64, Ix2>>,
+}
+
+impl Fchk {
+    pub fn from_rhf(
+        basis_set: &Basis,
+        atomcoords: &[[f64; 3]],
+        atomnos: &[u64],
+        scf: &scf::RHFResult,
+    ) -> Fchk {
+        let nelectrons = 2 * scf.nocc;
+        Fchk {
+            title: "rchem RHF".to_string(),
+            method: "RHF".to_string(),
+            basis: basis_set.clone(),
+            atomnos: atomnos.to_vec(),
+            atomcoords: atomcoords.to_vec(),
+            charge: atomnos.iter().sum::<u64>() as i64 - nelectrons as i64,
+            multiplicity: 1,
+            nalpha: scf.nocc,
+            nbeta: scf.nocc,
+            energy: scf.energy,
+            eps_alpha: scf.eps.clone(),
+            C_alpha: scf.C.clone(),
+            eps_beta: None,
+            C_beta: None,
+            total_density: 2.0 * &scf.D,
+            spin_density: None,
+        }
+    }
+
+    pub fn from_uhf(
+        basis_set: &Basis,
+        atomcoords: &[[f64; 3]],
+        atomnos: &[u64],
+        scf: &scf::UHFResult,
+    ) -> Fchk {
+        let nelectrons = scf.nalpha + scf.nbeta;
+        Fchk {
+            title: "rchem UHF".to_string(),
+            method: "UHF".to_string(),
+            basis: basis_set.clone(),
+            atomnos: atomnos.to_vec(),
+            atomcoords: atomcoords.to_vec(),
+            charge: atomnos.iter().sum::<u64>() as i64 - nelectrons as i64,
+            multiplicity: scf.nalpha.abs_diff(scf.nbeta) + 1,
+            nalpha: scf.nalpha,
+            nbeta: scf.nbeta,
+            energy: scf.energy,
+            eps_alpha: scf.eps_alpha.clone(),
+            C_alpha: scf.C_alpha.clone(),
+            eps_beta: Some(scf.eps_beta.clone()),
+            C_beta: Some(scf.C_beta.clone()),
+            total_density: &scf.D_alpha + &scf.D_beta,
+            spin_density: Some(&scf.D_alpha - &scf.D_beta),
+        }
+    }
+}
+
+/// The Cartesian components of a shell in the order Gaussian uses.
+fn gaussian_order(l: usize) -> Vec<[usize; 3]> {
+    match l {
+        0 => vec![[0, 0, 0]],
+        1 => vec![[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        2 => vec![
+            [2, 0, 0],
+            [0, 2, 0],
+            [0, 0, 2],
+            [1, 1, 0],
+            [1, 0, 1],
+            [0, 1, 1],
+        ],
+        3 => vec![
+            [3, 0, 0],
+            [0, 3, 0],
+            [0, 0, 3],
+            [1, 2, 0],
+            [2, 1, 0],
+            [2, 0, 1],
+            [1, 0, 2],
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 1, 1],
+        ],
+        4 => vec![
+            [0, 0, 4],
+            [0, 1, 3],
+            [0, 2, 2],
+            [0, 3, 1],
+            [0, 4, 0],
+            [1, 0, 3],
+            [1, 1, 2],
+            [1, 2, 1],
+            [1, 3, 0],
+            [2, 0, 2],
+            [2, 1, 1],
+            [2, 2, 0],
+            [3, 0, 1],
+            [3, 1, 0],
+            [4, 0, 0],
+        ],
+        _ => panic!("fchk files are only supported up to g functions"),
+    }
+}
+
+/// The matrix T that takes orbital coefficients in our basis to Gaussian's,
+/// C_gaussian = T C. With `normalize_contractions`, Gaussian's functions
+/// are taken to have their contractions renormalized, as `write_fchk`
+/// writes them.
+fn transformation(basis_set: &Basis, normalize_contractions: bool) -> Array<f64, Ix2> {
+    let nbasis = basis_set.nbasis();
+    let mut T: Array<f64, _> = Array::zeros((nbasis, nbasis));
+    let mut offset = 0;
+    for shell in basis_set.shells() {
+        let norm = if normalize_contractions {
+            shell.contraction_norm()
+        } else {
+            1.0
+        };
+        let ours = shell::get_ijk_list(shell.angular_momentum);
+        for (row, powers) in gaussian_order(shell.angular_momentum)
+            .into_iter()
+            .enumerate()
+        {
+            let k = ours.iter().position(|p| *p == powers).unwrap();
+            T[[offset + row, offset + k]] = basis::axis_normalization_ratio(powers) * norm;
+        }
+        offset += shell.nfunctions();
+    }
+    T
+}
+
+/// The inverse of a matrix from `transformation`, which has a single
+/// nonzero element in each row and column.
+fn invert_transformation(T: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+    T.t().mapv(|t| if t != 0.0 { 1.0 / t } else { 0.0 })
+}
+
+/// Format a real the way Fortran's E format does, with a two-digit exponent.
+pub(crate) fn fortran_real(x: f64, precision: usize, width: usize) -> String {
+    let formatted = format!("{:.*E}", precision, x);
+    let (mantissa, exponent) = formatted.split_once('E').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let sign = if exponent < 0 { '-' } else { '+' };
+    format!(
+        "{:>width$}",
+        format!("{}E{}{:02}", mantissa, sign, exponent.abs()),
+        width = width
+    )
+}
+
+fn push_integer(contents: &mut String, label: &str, value: i64) {
+    contents.push_str(&format!("{:<40}   I     {:12}\n", label, value));
+}
+
+fn push_real(contents: &mut String, label: &str, value: f64) {
+    contents.push_str(&format!(
+        "{:<40}   R     {}\n",
+        label,
+        fortran_real(value, 15, 22)
+    ));
+}
+
+fn push_integers(contents: &mut String, label: &str, values: &[i64]) {
+    contents.push_str(&format!("{:<40}   I   N={:12}\n", label, values.len()));
+    for line in values.chunks(6) {
+        let line: Vec<String> = line.iter().map(|v| format!("{:12}", v)).collect();
+        contents.push_str(&line.join(""));
+        contents.push('\n');
+    }
+}
+
+fn push_reals(contents: &mut String, label: &str, values: &[f64]) {
+    contents.push_str(&format!("{:<40}   R   N={:12}\n", label, values.len()));
+    for line in values.chunks(5) {
+        let line: Vec<String> = line.iter().map(|&v| fortran_real(v, 8, 16)).collect();
+        contents.push_str(&line.join(""));
+        contents.push('\n');
+    }
+}
+
+fn lower_triangle(M: &Array<f64, Ix2>) -> Vec<f64> {
+    let mut values = Vec::new();
+    for i in 0..M.shape()[0] {
+        for j in 0..=i {
+            values.push(M[[i, j]]);
+        }
+    }
+    values
+}
+
+fn from_lower_triangle(values: &[f64], dim: usize) -> Array<f64, Ix2> {
+    let mut M: Array<f64, _> = Array::zeros((dim, dim));
+    let mut n = 0;
+    for i in 0..dim {
+        for j in 0..=i {
+            M[[i, j]] = values[n];
+            M[[j, i]] = values[n];
+            n += 1;
+        }
+    }
+    M
+}
+
+/// Orbital coefficients with one orbital after another, as fchk files
+/// store them.
+fn orbital_major(C: &Array<f64, Ix2>) -> Vec<f64> {
+    C.t().iter().cloned().collect()
+}
+
+pub fn write_fchk<P: AsRef<Path>>(path: P, fchk: &Fchk) -> io::Result<()> {
+    let basis_set = &fchk.basis;
+    let shells = basis_set.shells();
+    let nbasis = basis_set.nbasis();
+    let T = transformation(basis_set, true);
+
+    let mut contents = format!("{}\n", fchk.title);
+    contents.push_str(&format!(
+        "{:<10}{:<30}{:<30}\n",
+        "SP",
+        fchk.method,
+        basis_set.name()
+    ));
+    push_integer(&mut contents, "Number of atoms", fchk.atomnos.len() as i64);
+    push_integer(&mut contents, "Charge", fchk.charge);
+    push_integer(&mut contents, "Multiplicity", fchk.multiplicity as i64);
+    push_integer(
+        &mut contents,
+        "Number of electrons",
+        (fchk.nalpha + fchk.nbeta) as i64,
+    );
+    push_integer(
+        &mut contents,
+        "Number of alpha electrons",
+        fchk.nalpha as i64,
+    );
+    push_integer(&mut contents, "Number of beta electrons", fchk.nbeta as i64);
+    push_integer(&mut contents, "Number of basis functions", nbasis as i64);
+    push_integer(
+        &mut contents,
+        "Number of independent functions",
+        fchk.C_alpha.shape()[1] as i64,
+    );
+    let atomnos: Vec<i64> = fchk.atomnos.iter().map(|&z| z as i64).collect();
+    push_integers(&mut contents, "Atomic numbers", &atomnos);
+    let charges: Vec<f64> = fchk.atomnos.iter().map(|&z| z as f64).collect();
+    push_reals(&mut contents, "Nuclear charges", &charges);
+    let coords: Vec<f64> = fchk.atomcoords.iter().flatten().cloned().collect();
+    push_reals(&mut contents, "Current cartesian coordinates", &coords);
+
+    // Positive shell types are Cartesian, so the pure/Cartesian flags are
+    // both set to Cartesian.
+    push_integer(&mut contents, "Pure/Cartesian d shells", 1);
+    push_integer(&mut contents, "Pure/Cartesian f shells", 1);
+    push_integer(
+        &mut contents,
+        "Number of contracted shells",
+        shells.len() as i64,
+    );
+    let nprimitives: Vec<i64> = shells.iter().map(|s| s.exponents.len() as i64).collect();
+    push_integer(
+        &mut contents,
+        "Number of primitive shells",
+        nprimitives.iter().sum(),
+    );
+    let lmax = shells.iter().map(|s| s.angular_momentum).max().unwrap_or(0);
+    push_integer(&mut contents, "Highest angular momentum", lmax as i64);
+    push_integer(
+        &mut contents,
+        "Largest degree of contraction",
+        nprimitives.iter().cloned().max().unwrap_or(0),
+    );
+    let types: Vec<i64> = shells.iter().map(|s| s.angular_momentum as i64).collect();
+    push_integers(&mut contents, "Shell types", &types);
+    push_integers(
+        &mut contents,
+        "Number of primitives per shell",
+        &nprimitives,
+    );
+    let atoms: Vec<i64> = shells.iter().map(|s| s.atom as i64 + 1).collect();
+    push_integers(&mut contents, "Shell to atom map", &atoms);
+    let exponents: Vec<f64> = shells.iter().flat_map(|s| s.exponents.clone()).collect();
+    push_reals(&mut contents, "Primitive exponents", &exponents);
+    // Normalizing each contraction as a whole means readers get the same
+    // functions whether or not they renormalize.
+    let coefficients: Vec<f64> = shells
+        .iter()
+        .flat_map(|s| {
+            let norm = s.contraction_norm();
+            s.coefficients.iter().map(move |c| c / norm)
+        })
+        .collect();
+    push_reals(&mut contents, "Contraction coefficients", &coefficients);
+    let shell_coords: Vec<f64> = shells
+        .iter()
+        .flat_map(|s| fchk.atomcoords[s.atom].to_vec())
+        .collect();
+    push_reals(&mut contents, "Coordinates of each shell", &shell_coords);
+
+    push_real(&mut contents, "SCF Energy", fchk.energy);
+    push_real(&mut contents, "Total Energy", fchk.energy);
+    push_reals(
+        &mut contents,
+        "Alpha Orbital Energies",
+        &fchk.eps_alpha.iter().cloned().collect::<Vec<_>>(),
+    );
+    if let Some(eps_beta) = &fchk.eps_beta {
+        push_reals(
+            &mut contents,
+            "Beta Orbital Energies",
+            &eps_beta.iter().cloned().collect::<Vec<_>>(),
+        );
+    }
+    push_reals(
+        &mut contents,
+        "Alpha MO coefficients",
+        &orbital_major(&T.dot(&fchk.C_alpha)),
+    );
+    if let Some(C_beta) = &fchk.C_beta {
+        push_reals(
+            &mut contents,
+            "Beta MO coefficients",
+            &orbital_major(&T.dot(C_beta)),
+        );
+    }
+    push_reals(
+        &mut contents,
+        "Total SCF Density",
+        &lower_triangle(&T.dot(&fchk.total_density).dot(&T.t())),
+    );
+    if let Some(spin_density) = &fchk.spin_density {
+        push_reals(
+            &mut contents,
+            "Spin SCF Density",
+            &lower_triangle(&T.dot(spin_density).dot(&T.t())),
+        );
+    }
+    fs::write(path, contents)
+}
+
+#[derive(Debug)]
+enum Value {
+    Integer(i64),
+    Real(f64),
+    Integers(Vec<i64>),
+    Reals(Vec<f64>),
+    /// Character and logical entries, which rchem has no use for.
+    Other,
+}
+
+fn parse_entries(contents: &str) -> io::Result<(String, String, Map<String, Value>)> {
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut lines = contents.lines();
+    let title = lines.next().unwrap_or("").trim().to_string();
+    let method = lines
+        .next()
+        .and_then(|line| line.get(10..40))
+        .unwrap_or("")
+        .trim()
+        .to_string();
+    let mut entries = Map::new();
+    while let Some(line) = lines.next() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let label = line.get(..40).unwrap_or(line).trim().to_string();
+        let fields: Vec<&str> = line.get(40..).unwrap_or("").split_whitespace().collect();
+        let bad = || invalid(format!("malformed entry: {}", line));
+        let kind = *fields.first().ok_or_else(bad)?;
+        let value = if fields.get(1) == Some(&"N=") {
+            let count: usize = fields.get(2).ok_or_else(bad)?.parse().map_err(|_| bad())?;
+            let per_line = match kind {
+                "I" => 6,
+                "R" | "C" => 5,
+                _ => 72,
+            };
+            let nlines = count.div_ceil(per_line);
+            let data: Vec<&str> = (0..nlines)
+                .map(|_| lines.next().ok_or_else(bad))
+                .collect::<io::Result<_>>()?;
+            let tokens = data.iter().flat_map(|l| l.split_whitespace());
+            match kind {
+                "I" => Value::Integers(
+                    tokens
+                        .map(|t| t.parse().map_err(|_| bad()))
+                        .collect::<io::Result<_>>()?,
+                ),
+                "R" => Value::Reals(
+                    tokens
+                        .map(|t| t.replace('D', "E").parse().map_err(|_| bad()))
+                        .collect::<io::Result<_>>()?,
+                ),
+                _ => Value::Other,
+            }
+        } else {
+            let text = fields.get(1).ok_or_else(bad)?;
+            match kind {
+                "I" => Value::Integer(text.parse().map_err(|_| bad())?),
+                "R" => Value::Real(text.replace('D', "E").parse().map_err(|_| bad())?),
+                _ => Value::Other,
+            }
+        };
+        entries.insert(label, value);
+    }
+    Ok((title, method, entries))
+}
+
+/// Read an fchk file written by rchem or Gaussian, converting the orbitals
+/// and densities to the basis built from its shells.
+pub fn read_fchk<P: AsRef<Path>>(path: P) -> io::Result<Fchk> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let contents = fs::read_to_string(path)?;
+    let (title, method, entries) = parse_entries(&contents)?;
+    let missing = |label: &str| invalid(&format!("missing {}", label));
+    let integer = |label: &str| match entries.get(label) {
+        Some(Value::Integer(i)) => Ok(*i),
+        _ => Err(missing(label)),
+    };
+    let real = |label: &str| match entries.get(label) {
+        Some(Value::Real(x)) => Ok(*x),
+        _ => Err(missing(label)),
+    };
+    let integers = |label: &str| match entries.get(label) {
+        Some(Value::Integers(v)) => Ok(v.clone()),
+        _ => Err(missing(label)),
+    };
+    let reals = |label: &str| match entries.get(label) {
+        Some(Value::Reals(v)) => Ok(v.clone()),
+        _ => Err(missing(label)),
+    };
+
+    let atomnos: Vec<u64> = integers("Atomic numbers")?
+        .iter()
+        .map(|&z| z as u64)
+        .collect();
+    let atomcoords: Vec<[f64; 3]> = reals("Current cartesian coordinates")?
+        .chunks(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect();
+
+    // SP shells become an s shell followed by a p shell, which matches
+    // Gaussian's function order within them.
+    let types = integers("Shell types")?;
+    let nprimitives = integers("Number of primitives per shell")?;
+    let shell_atoms = integers("Shell to atom map")?;
+    let exponents = reals("Primitive exponents")?;
+    let coefficients = reals("Contraction coefficients")?;
+    let sp_coefficients = reals("P(S=P) Contraction coefficients").ok();
+    let mut shells = Vec::new();
+    let mut start = 0;
+    for ((&kind, &n), &atom) in types.iter().zip(&nprimitives).zip(&shell_atoms) {
+        let range = start..start + n as usize;
+        let shell = |angular_momentum: usize, coefficients: &[f64]| Shell {
+            atom: atom as usize - 1,
+            angular_momentum,
+            exponents: exponents[range.clone()].to_vec(),
+            coefficients: coefficients[range.clone()].to_vec(),
+        };
+        match kind {
+            -1 => {
+                let sp = sp_coefficients
+                    .as_ref()
+                    .ok_or_else(|| missing("P(S=P) Contraction coefficients"))?;
+                shells.push(shell(0, &coefficients));
+                shells.push(shell(1, sp));
+            }
+            l if l >= 0 => shells.push(shell(l as usize, &coefficients)),
+            _ => return Err(invalid("spherical harmonic shells are not supported")),
+        }
+        start += n as usize;
+    }
+    let basis_name = contents
+        .lines()
+        .nth(1)
+        .and_then(|line| line.get(40..))
+        .unwrap_or("")
+        .trim();
+    let basis_set = Basis::from_shells(basis_name, &atomcoords, &shells);
+    let nbasis = basis_set.nbasis();
+    if integer("Number of basis functions")? as usize != nbasis {
+        return Err(invalid("shells do not match the number of basis functions"));
+    }
+
+    let T_inv = invert_transformation(&transformation(&basis_set, false));
+    let orbitals = |label: &str| -> io::Result<Array<f64, Ix2>> {
+        let values = reals(label)?;
+        let norbitals = values.len() / nbasis;
+        let C = Array::from_shape_vec((norbitals, nbasis), values)
+            .map_err(|_| invalid("bad orbital coefficients"))?;
+        Ok(T_inv.dot(&C.t()))
+    };
+    let density = |label: &str| -> io::Result<Array<f64, Ix2>> {
+        let D = from_lower_triangle(&reals(label)?, nbasis);
+        Ok(T_inv.dot(&D).dot(&T_inv.t()))
+    };
+    let energies = |label: &str| reals(label).map(Array::from);
+
+    Ok(Fchk {
+        title,
+        method,
+        atomnos,
+        atomcoords,
+        charge: integer("Charge")?,
+        multiplicity: integer("Multiplicity")? as usize,
+        nalpha: integer("Number of alpha electrons")? as usize,
+        nbeta: integer("Number of beta electrons")? as usize,
+        energy: real("Total Energy")?,
+        eps_alpha: energies("Alpha Orbital Energies")?,
+        C_alpha: orbitals("Alpha MO coefficients")?,
+        eps_beta: energies("Beta Orbital Energies").ok(),
+        C_beta: orbitals("Beta MO coefficients").ok(),
+        total_density: density("Total SCF Density")?,
+        spin_density: density("Spin SCF Density").ok(),
+        basis: basis_set,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_fchk_round_trip_water() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let path = std::env::temp_dir().join("rchem_test_water.fchk");
+        write_fchk(
+            &path,
+            &Fchk::from_rhf(&basis_set, &atomcoords, &atomnos, &result),
+        )
+        .unwrap();
+        let fchk = read_fchk(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(fchk.atomnos, atomnos);
+        assert_eq!(fchk.charge, 0);
+        assert_eq!(fchk.nalpha, 5);
+        assert!(fchk.C_beta.is_none());
+        assert!((fchk.energy - result.energy).abs() < 1.0e-12);
+        assert!((&fchk.eps_alpha - &result.eps)
+            .iter()
+            .all(|x| x.abs() < 1.0e-6));
+
+        // The orbitals and density read back describe the same functions,
+        // even though the contractions were renormalized on the way out.
+        let points = [[0.3, -0.2, 0.5], [-1.1, 0.8, 0.1], [1.6, 1.2, -0.3]];
+        let (phi, _) = basis::evaluate(&basis_set, &points);
+        let (phi_read, _) = basis::evaluate(&fchk.basis, &points);
+        let orbitals = phi.dot(&result.C);
+        let orbitals_read = phi_read.dot(&fchk.C_alpha);
+        assert!((&orbitals - &orbitals_read)
+            .iter()
+            .all(|x| x.abs() < 1.0e-6));
+        let rho = (&phi.dot(&(2.0 * &result.D)) * &phi).sum();
+        let rho_read = (&phi_read.dot(&fchk.total_density) * &phi_read).sum();
+        assert!((rho - rho_read).abs() < 1.0e-6);
+
+        // And can seed a new calculation, which converges immediately.
+        let guess = scf::SCFOptions {
+            guess: crate::guess::Guess::Read {
+                basis: fchk.basis.clone(),
+                C: fchk.C_alpha.clone(),
+            },
+            ..options
+        };
+        let restarted = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &guess);
+        assert!((restarted.energy - result.energy).abs() < 1.0e-8);
+        assert!(restarted.iterations <= 3);
+    }
+
+    #[test]
+    fn test_gaussian_order_covers_shells() {
+        for l in 0..5 {
+            let mut ours = shell::get_ijk_list(l);
+            let mut theirs = gaussian_order(l);
+            ours.sort();
+            theirs.sort();
+            assert_eq!(ours, theirs);
+        }
+        assert_eq!(fortran_real(-74.9420799, 8, 16), " -7.49420799E+01");
+        assert_eq!(fortran_real(0.0, 8, 16), "  0.00000000E+00");
+    }
+}
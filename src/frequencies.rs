@@ -0,0 +1,534 @@
+#![allow(non_snake_case)]
+
+//! Harmonic vibrational analysis from a finite-difference Hessian, with IR
+//! intensities and ideal gas/rigid rotor/harmonic oscillator thermochemistry.
+
+use std::f64::consts::PI;
+
+use ndarray::{Array, Axis, Ix1, Ix2};
+use ndarray_linalg::*;
+
+use crate::constants::*;
+use crate::gradient::Gradient;
+
+/// Converts squared dipole derivatives from e^2/amu to km/mol.
+const IR_INTENSITY_TO_KM_PER_MOL: f64 = 974.8801;
+
+/// Hessian from central differences of the analytic gradient, with
+/// displacements of `step` bohr, symmetrized.
+pub fn finite_difference_hessian<F>(
+    atomcoords: &[[f64; 3]],
+    step: f64,
+    mut gradient: F,
+) -> Array<f64, Ix2>
+where
+    F: FnMut(&[[f64; 3]]) -> Gradient,
+{
+    let natoms = atomcoords.len();
+    let mut hessian: Array<f64, _> = Array::zeros((3 * natoms, 3 * natoms));
+    let mut displaced = atomcoords.to_vec();
+    for atom in 0..natoms {
+        for k in 0..3 {
+            displaced[atom][k] = atomcoords[atom][k] + step;
+            let g_plus = gradient(&displaced).values;
+            displaced[atom][k] = atomcoords[atom][k] - step;
+            let g_minus = gradient(&displaced).values;
+            displaced[atom][k] = atomcoords[atom][k];
+            let row = (g_plus - g_minus) / (2.0 * step);
+            for (j, value) in row.iter().enumerate() {
+                hessian[[3 * atom + k, j]] = *value;
+            }
+        }
+    }
+    0.5 * (&hessian + &hessian.t())
+}
+
+/// Hessian from second differences of the energy alone, for methods without
+/// an analytic gradient.
+pub fn finite_difference_hessian_energies<F>(
+    atomcoords: &[[f64; 3]],
+    step: f64,
+    mut energy: F,
+) -> Array<f64, Ix2>
+where
+    F: FnMut(&[[f64; 3]]) -> f64,
+{
+    let ncoords = 3 * atomcoords.len();
+    let mut hessian: Array<f64, _> = Array::zeros((ncoords, ncoords));
+    let mut displaced = atomcoords.to_vec();
+    let mut energy_at = |displacements: &[(usize, f64)]| {
+        for &(i, d) in displacements {
+            displaced[i / 3][i % 3] += d;
+        }
+        let e = energy(&displaced);
+        for &(i, d) in displacements {
+            displaced[i / 3][i % 3] -= d;
+        }
+        e
+    };
+    let e0 = energy_at(&[]);
+    for i in 0..ncoords {
+        let e_plus = energy_at(&[(i, step)]);
+        let e_minus = energy_at(&[(i, -step)]);
+        hessian[[i, i]] = (e_plus - 2.0 * e0 + e_minus) / (step * step);
+        for j in 0..i {
+            let e_pp = energy_at(&[(i, step), (j, step)]);
+            let e_pm = energy_at(&[(i, step), (j, -step)]);
+            let e_mp = energy_at(&[(i, -step), (j, step)]);
+            let e_mm = energy_at(&[(i, -step), (j, -step)]);
+            hessian[[i, j]] = (e_pp - e_pm - e_mp + e_mm) / (4.0 * step * step);
+            hessian[[j, i]] = hessian[[i, j]];
+        }
+    }
+    hessian
+}
+
+/// Derivatives of the dipole moment with respect to the nuclear coordinates,
+/// with shape `(3 * natoms, 3)`.
+pub fn finite_difference_dipole_derivatives<F>(
+    atomcoords: &[[f64; 3]],
+    step: f64,
+    mut dipole: F,
+) -> Array<f64, Ix2>
+where
+    F: FnMut(&[[f64; 3]]) -> [f64; 3],
+{
+    let natoms = atomcoords.len();
+    let mut derivatives: Array<f64, _> = Array::zeros((3 * natoms, 3));
+    let mut displaced = atomcoords.to_vec();
+    for atom in 0..natoms {
+        for k in 0..3 {
+            displaced[atom][k] = atomcoords[atom][k] + step;
+            let mu_plus = dipole(&displaced);
+            displaced[atom][k] = atomcoords[atom][k] - step;
+            let mu_minus = dipole(&displaced);
+            displaced[atom][k] = atomcoords[atom][k];
+            for x in 0..3 {
+                derivatives[[3 * atom + k, x]] = (mu_plus[x] - mu_minus[x]) / (2.0 * step);
+            }
+        }
+    }
+    derivatives
+}
+
+#[derive(Clone, Debug)]
+pub struct HarmonicAnalysis {
+    /// Harmonic frequencies in cm^-1, in ascending order. Imaginary
+    /// frequencies are reported as negative numbers.
+    pub frequencies: Array<f64, Ix1>,
+    /// Mass-weighted normal coordinates as columns, with shape
+    /// `(3 * natoms, nmodes)`.
+    pub modes: Array<f64, Ix2>,
+    /// Normalized Cartesian displacements for each mode as columns.
+    pub displacements: Array<f64, Ix2>,
+    /// Reduced mass of each mode in amu.
+    pub reduced_masses: Array<f64, Ix1>,
+    /// Atomic masses in amu.
+    pub masses: Vec<f64>,
+    /// Principal moments of inertia in amu bohr^2, in ascending order.
+    pub moments_of_inertia: [f64; 3],
+    pub linear: bool,
+}
+
+fn center_of_mass(atomcoords: &[[f64; 3]], masses: &[f64]) -> [f64; 3] {
+    let total: f64 = masses.iter().sum();
+    let mut com = [0.0; 3];
+    for (coords, mass) in atomcoords.iter().zip(masses.iter()) {
+        for k in 0..3 {
+            com[k] += mass * coords[k] / total;
+        }
+    }
+    com
+}
+
+fn inertia_tensor(atomcoords: &[[f64; 3]], masses: &[f64]) -> Array<f64, Ix2> {
+    let com = center_of_mass(atomcoords, masses);
+    let mut I: Array<f64, _> = Array::zeros((3, 3));
+    for (coords, mass) in atomcoords.iter().zip(masses.iter()) {
+        let r = [coords[0] - com[0], coords[1] - com[1], coords[2] - com[2]];
+        let r2 = r[0] * r[0] + r[1] * r[1] + r[2] * r[2];
+        for i in 0..3 {
+            I[[i, i]] += mass * r2;
+            for j in 0..3 {
+                I[[i, j]] -= mass * r[i] * r[j];
+            }
+        }
+    }
+    I
+}
+
+/// Orthonormal basis for the mass-weighted translations and rotations.
+/// There are 5 vectors for a linear molecule and 3 for an atom.
+fn rigid_body_modes(atomcoords: &[[f64; 3]], masses: &[f64]) -> Vec<Array<f64, Ix1>> {
+    let natoms = atomcoords.len();
+    let com = center_of_mass(atomcoords, masses);
+    let mut candidates = Vec::new();
+    for k in 0..3 {
+        let mut v: Array<f64, _> = Array::zeros(3 * natoms);
+        for atom in 0..natoms {
+            v[3 * atom + k] = masses[atom].sqrt();
+        }
+        candidates.push(v);
+    }
+    for k in 0..3 {
+        // infinitesimal rotation about axis k: e_k x r
+        let mut v: Array<f64, _> = Array::zeros(3 * natoms);
+        for atom in 0..natoms {
+            let r = [
+                atomcoords[atom][0] - com[0],
+                atomcoords[atom][1] - com[1],
+                atomcoords[atom][2] - com[2],
+            ];
+            let (a, b) = ((k + 1) % 3, (k + 2) % 3);
+            v[3 * atom + b] = masses[atom].sqrt() * r[a];
+            v[3 * atom + a] = -masses[atom].sqrt() * r[b];
+        }
+        candidates.push(v);
+    }
+    // Gram-Schmidt, dropping rotations that vanish for linear molecules
+    let mut basis: Vec<Array<f64, Ix1>> = Vec::new();
+    for mut v in candidates {
+        for u in basis.iter() {
+            let overlap = u.dot(&v);
+            v.scaled_add(-overlap, u);
+        }
+        let norm = v.dot(&v).sqrt();
+        if norm > 1.0e-6 {
+            basis.push(v / norm);
+        }
+    }
+    basis
+}
+
+/// Mass-weight the Cartesian Hessian (in hartree/bohr^2), project out the
+/// translations and rotations, and diagonalize to give the harmonic
+/// frequencies and normal modes.
+///
+/// `masses` are in amu, so that isotopic substitution only requires changing
+/// them; `elements::mass` gives the most abundant isotope.
+pub fn harmonic_analysis(
+    hessian: &Array<f64, Ix2>,
+    atomcoords: &[[f64; 3]],
+    masses: &[f64],
+) -> HarmonicAnalysis {
+    let natoms = atomcoords.len();
+    let ncoords = 3 * natoms;
+    let inv_sqrt_m: Array<f64, Ix1> = (0..ncoords).map(|i| 1.0 / masses[i / 3].sqrt()).collect();
+    let mut H_mw = hessian.clone();
+    for i in 0..ncoords {
+        for j in 0..ncoords {
+            H_mw[[i, j]] *= inv_sqrt_m[i] * inv_sqrt_m[j];
+        }
+    }
+
+    // Build an orthonormal basis for the internal (vibrational) space as the
+    // complement of the rigid-body motions.
+    let rigid = rigid_body_modes(atomcoords, masses);
+    let mut P: Array<f64, _> = Array::eye(ncoords);
+    for v in rigid.iter() {
+        for i in 0..ncoords {
+            for j in 0..ncoords {
+                P[[i, j]] -= v[i] * v[j];
+            }
+        }
+    }
+    let (p_eigvals, p_eigvecs) = P.eigh(UPLO::Upper).unwrap();
+    let internal_indices: Vec<usize> = (0..ncoords).filter(|&i| p_eigvals[i] > 0.5).collect();
+    let D = p_eigvecs.select(Axis(1), &internal_indices);
+
+    let (eigvals, eigvecs) = D.t().dot(&H_mw).dot(&D).eigh(UPLO::Upper).unwrap();
+    let modes = D.dot(&eigvecs);
+    let frequencies = eigvals.mapv(|lambda| {
+        let omega = (lambda.abs() / AMU_TO_ELECTRON_MASS).sqrt() * HARTREE_TO_WAVENUMBER;
+        if lambda < 0.0 {
+            -omega
+        } else {
+            omega
+        }
+    });
+
+    let mut displacements = modes.clone();
+    let mut reduced_masses: Array<f64, _> = Array::zeros(modes.shape()[1]);
+    for (n, mut column) in displacements.axis_iter_mut(Axis(1)).enumerate() {
+        column *= &inv_sqrt_m;
+        let norm2 = column.dot(&column);
+        reduced_masses[n] = 1.0 / norm2;
+        column /= norm2.sqrt();
+    }
+
+    let (moments, _) = inertia_tensor(atomcoords, masses)
+        .eigh(UPLO::Upper)
+        .unwrap();
+    HarmonicAnalysis {
+        frequencies,
+        modes,
+        displacements,
+        reduced_masses,
+        masses: masses.to_vec(),
+        moments_of_inertia: [moments[0], moments[1], moments[2]],
+        linear: natoms > 1 && rigid.len() == 5,
+    }
+}
+
+/// IR intensities in km/mol for each mode, from the Cartesian dipole
+/// derivatives in atomic units with shape `(3 * natoms, 3)`.
+pub fn ir_intensities(
+    analysis: &HarmonicAnalysis,
+    dipole_derivatives: &Array<f64, Ix2>,
+) -> Array<f64, Ix1> {
+    let ncoords = dipole_derivatives.shape()[0];
+    let mut weighted = dipole_derivatives.clone();
+    for i in 0..ncoords {
+        let scale = 1.0 / analysis.masses[i / 3].sqrt();
+        weighted.row_mut(i).mapv_inplace(|x| x * scale);
+    }
+    // dmu/dQ for each mode, with shape (nmodes, 3)
+    let dmu_dQ = analysis.modes.t().dot(&weighted);
+    dmu_dQ.map_axis(Axis(1), |row| row.dot(&row) * IR_INTENSITY_TO_KM_PER_MOL)
+}
+
+#[derive(Clone, Debug)]
+pub struct ThermochemistryOptions {
+    /// K
+    pub temperature: f64,
+    /// Pa
+    pub pressure: f64,
+    /// Rotational symmetry number.
+    pub symmetry_number: usize,
+    /// Spin multiplicity of the electronic ground state.
+    pub multiplicity: usize,
+}
+
+impl Default for ThermochemistryOptions {
+    fn default() -> ThermochemistryOptions {
+        ThermochemistryOptions {
+            temperature: 298.15,
+            pressure: 101325.0,
+            symmetry_number: 1,
+            multiplicity: 1,
+        }
+    }
+}
+
+/// Ideal gas, rigid rotor, harmonic oscillator thermochemistry. Energies are
+/// in hartree and entropies in hartree/K, all per molecule.
+#[derive(Clone, Debug)]
+pub struct Thermochemistry {
+    pub zpe: f64,
+    /// Thermal correction to the energy, including the ZPE.
+    pub thermal_energy: f64,
+    /// Thermal correction to the enthalpy.
+    pub thermal_enthalpy: f64,
+    pub entropy: f64,
+    /// Electronic energy plus the thermal correction to the enthalpy.
+    pub enthalpy: f64,
+    /// Electronic energy plus the thermal correction to the free energy.
+    pub gibbs: f64,
+}
+
+/// Thermochemistry given the harmonic analysis and the electronic energy.
+/// Imaginary modes are skipped.
+pub fn thermochemistry(
+    analysis: &HarmonicAnalysis,
+    energy: f64,
+    options: &ThermochemistryOptions,
+) -> Thermochemistry {
+    let T = options.temperature;
+    let kT = BOLTZMANN * T / HARTREE_TO_JOULE;
+    let k = BOLTZMANN / HARTREE_TO_JOULE;
+
+    // translation
+    let mass: f64 = analysis.masses.iter().sum::<f64>() * AMU_TO_KG;
+    let q_trans = (2.0 * PI * mass * BOLTZMANN * T / PLANCK.powi(2)).powf(1.5) * BOLTZMANN * T
+        / options.pressure;
+    let e_trans = 1.5 * kT;
+    let s_trans = k * (q_trans.ln() + 2.5);
+
+    // rotation, using the rotational temperatures h^2 / (8 pi^2 I k)
+    let theta: Vec<f64> = analysis
+        .moments_of_inertia
+        .iter()
+        .map(|I| {
+            PLANCK.powi(2) / (8.0 * PI * PI * I * AMU_TO_KG * BOHR_TO_METER.powi(2) * BOLTZMANN)
+        })
+        .collect();
+    let sigma = options.symmetry_number as f64;
+    let (e_rot, s_rot) = if analysis.masses.len() == 1 {
+        (0.0, 0.0)
+    } else if analysis.linear {
+        let q_rot = T / (sigma * theta[2]);
+        (kT, k * (q_rot.ln() + 1.0))
+    } else {
+        let q_rot = PI.sqrt() / sigma * T.powf(1.5) / (theta[0] * theta[1] * theta[2]).sqrt();
+        (1.5 * kT, k * (q_rot.ln() + 1.5))
+    };
+
+    // vibration
+    let mut zpe = 0.0;
+    let mut e_vib = 0.0;
+    let mut s_vib = 0.0;
+    for &frequency in analysis.frequencies.iter().filter(|&&f| f > 0.0) {
+        let hv = frequency / HARTREE_TO_WAVENUMBER;
+        let x = hv / kT;
+        zpe += 0.5 * hv;
+        e_vib += 0.5 * hv + hv / x.exp_m1();
+        s_vib += k * (x / x.exp_m1() - (-(-x).exp_m1()).ln());
+    }
+
+    let s_elec = k * (options.multiplicity as f64).ln();
+
+    let thermal_energy = e_trans + e_rot + e_vib;
+    let thermal_enthalpy = thermal_energy + kT;
+    let entropy = s_trans + s_rot + s_vib + s_elec;
+    Thermochemistry {
+        zpe,
+        thermal_energy,
+        thermal_enthalpy,
+        entropy,
+        enthalpy: energy + thermal_enthalpy,
+        gibbs: energy + thermal_enthalpy - T * entropy,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::elements;
+    use crate::gradient;
+    use crate::optimize;
+    use crate::scf;
+    use crate::testing;
+
+    #[test]
+    fn test_diatomic_harmonic_oscillator() {
+        // A harmonic bond between two atoms, so that omega = sqrt(k / mu).
+        let force_constant = 0.37;
+        let r0 = 1.4;
+        let bond = |coords: &[[f64; 3]]| {
+            let d: Vec<f64> = (0..3).map(|k| coords[1][k] - coords[0][k]).collect();
+            let r = d.iter().map(|x| x * x).sum::<f64>().sqrt();
+            (r, d)
+        };
+        let atomcoords = [[0.1, -0.2, 0.3], [0.9, 0.5, 1.1]];
+        let (r, _) = bond(&atomcoords);
+        let atomcoords = [
+            atomcoords[0],
+            [
+                atomcoords[0][0] + (atomcoords[1][0] - atomcoords[0][0]) * r0 / r,
+                atomcoords[0][1] + (atomcoords[1][1] - atomcoords[0][1]) * r0 / r,
+                atomcoords[0][2] + (atomcoords[1][2] - atomcoords[0][2]) * r0 / r,
+            ],
+        ];
+        let hessian = finite_difference_hessian(&atomcoords, 1.0e-4, |coords| {
+            let (r, d) = bond(coords);
+            let mut values: Array<f64, _> = Array::zeros((2, 3));
+            for k in 0..3 {
+                values[[1, k]] = force_constant * (r - r0) * d[k] / r;
+                values[[0, k]] = -values[[1, k]];
+            }
+            Gradient { values }
+        });
+        let masses = [elements::mass(1), 2.0 * elements::mass(1)];
+        let analysis = harmonic_analysis(&hessian, &atomcoords, &masses);
+        assert!(analysis.linear);
+        assert_eq!(analysis.frequencies.len(), 1);
+        let mu = masses[0] * masses[1] / (masses[0] + masses[1]);
+        let expected =
+            (force_constant / (mu * AMU_TO_ELECTRON_MASS)).sqrt() * HARTREE_TO_WAVENUMBER;
+        assert_relative_eq!(analysis.frequencies[0], expected, max_relative = 1.0e-6);
+        // The reduced mass is that of the normalized Cartesian displacement,
+        // which for a diatomic is m1 m2 (m1 + m2) / (m1^2 + m2^2) rather than
+        // the classical m1 m2 / (m1 + m2).
+        let (m1, m2) = (masses[0], masses[1]);
+        assert_relative_eq!(
+            analysis.reduced_masses[0],
+            m1 * m2 * (m1 + m2) / (m1 * m1 + m2 * m2),
+            max_relative = 1.0e-6
+        );
+    }
+
+    #[test]
+    fn test_argon_translational_entropy() {
+        // The standard entropy of argon at 298.15 K is 154.85 J/(mol K) at 1
+        // bar, or 154.74 J/(mol K) at 1 atm.
+        let hessian: Array<f64, _> = Array::zeros((3, 3));
+        let analysis = harmonic_analysis(&hessian, &[[0.0, 0.0, 0.0]], &[elements::mass(18)]);
+        assert_eq!(analysis.frequencies.len(), 0);
+        let thermo = thermochemistry(&analysis, 0.0, &Default::default());
+        let entropy = thermo.entropy * HARTREE_TO_JOULE * AVOGADRO;
+        assert!((entropy - 154.74).abs() < 0.01);
+        assert_relative_eq!(
+            thermo.thermal_enthalpy,
+            2.5 * BOLTZMANN * 298.15 / HARTREE_TO_JOULE,
+            max_relative = 1.0e-12
+        );
+    }
+
+    #[test]
+    fn test_water_sto3g_frequencies() {
+        let (mut basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf_options = scf::SCFOptions {
+            thresh_e: 1.0e-12,
+            thresh_d: 1.0e-10,
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let opt_options = optimize::OptimizerOptions {
+            thresh_max_force: 1.0e-6,
+            thresh_rms_force: 1.0e-6,
+            ..Default::default()
+        };
+        let opt = optimize::optimize(&atomcoords, &atomnos, &opt_options, |coords| {
+            basis_set.set_atomcoords(coords);
+            let scf = scf::rhf(&basis_set, coords, &atomnos, 5, &scf_options);
+            let grad = gradient::rhf_gradient(&basis_set, coords, &atomnos, &scf);
+            (scf.energy, grad)
+        });
+        assert!(opt.converged);
+        let hessian = finite_difference_hessian(&opt.atomcoords, 1.0e-3, |coords| {
+            basis_set.set_atomcoords(coords);
+            let scf = scf::rhf(&basis_set, coords, &atomnos, 5, &scf_options);
+            gradient::rhf_gradient(&basis_set, coords, &atomnos, &scf)
+        });
+        let masses: Vec<f64> = atomnos
+            .iter()
+            .map(|&atomno| elements::mass(atomno))
+            .collect();
+        let analysis = harmonic_analysis(&hessian, &opt.atomcoords, &masses);
+        assert!(!analysis.linear);
+        assert_eq!(analysis.frequencies.len(), 3);
+        for (computed, reference) in analysis.frequencies.iter().zip(&[2170.0, 4140.0, 4391.0]) {
+            assert!((computed - reference).abs() < 5.0);
+        }
+
+        let dipole_derivatives =
+            finite_difference_dipole_derivatives(&opt.atomcoords, 1.0e-3, |coords| {
+                basis_set.set_atomcoords(coords);
+                let scf = scf::rhf(&basis_set, coords, &atomnos, 5, &scf_options);
+                scf::dipole_moment(&basis_set, coords, &atomnos, &(2.0 * &scf.D))
+            });
+        // The dipole of a neutral molecule doesn't depend on where it is.
+        for k in 0..3 {
+            for x in 0..3 {
+                let total: f64 = (0..3)
+                    .map(|atom| dipole_derivatives[[3 * atom + k, x]])
+                    .sum();
+                assert!(total.abs() < 1.0e-5);
+            }
+        }
+        let intensities = ir_intensities(&analysis, &dipole_derivatives);
+        assert!(intensities.iter().all(|&i| i > 0.0));
+
+        let thermo = thermochemistry(
+            &analysis,
+            opt.energy,
+            &ThermochemistryOptions {
+                symmetry_number: 2,
+                ..Default::default()
+            },
+        );
+        let zpe: f64 = analysis.frequencies.sum() / (2.0 * HARTREE_TO_WAVENUMBER);
+        assert_relative_eq!(thermo.zpe, zpe, max_relative = 1.0e-12);
+        assert!(thermo.gibbs < thermo.enthalpy);
+    }
+}
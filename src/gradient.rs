@@ -0,0 +1,217 @@
+#![allow(non_snake_case)]
+
+use ndarray::{Array, Axis, Ix2, Ix4, Slice};
+
+use crate::basis;
+use crate::scf::{RHFResult, UHFResult};
+
+/// Derivative of the total energy with respect to the nuclear coordinates,
+/// in hartree/bohr.
+#[derive(Clone, Debug)]
+pub struct Gradient {
+    /// Shape `(natoms, 3)`.
+    pub values: Array<f64, Ix2>,
+}
+
+impl Gradient {
+    pub fn natoms(&self) -> usize {
+        self.values.shape()[0]
+    }
+
+    pub fn max_abs(&self) -> f64 {
+        self.values.iter().fold(0.0, |acc, x| acc.max(x.abs()))
+    }
+
+    pub fn rms(&self) -> f64 {
+        self.values.mapv(|x| x * x).mean().unwrap().sqrt()
+    }
+}
+
+pub fn nuclear_repulsion_gradient(atomcoords: &[[f64; 3]], atomnos: &[u64]) -> Array<f64, Ix2> {
+    let natoms = atomcoords.len();
+    let mut grad: Array<f64, _> = Array::zeros((natoms, 3));
+    for i in 0..natoms {
+        for j in 0..natoms {
+            if i == j {
+                continue;
+            }
+            let r = (0..3)
+                .map(|k| (atomcoords[i][k] - atomcoords[j][k]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            let zz = (atomnos[i] * atomnos[j]) as f64;
+            for k in 0..3 {
+                grad[[i, k]] -= zz * (atomcoords[i][k] - atomcoords[j][k]) / r.powi(3);
+            }
+        }
+    }
+    grad
+}
+
+/// Energy-weighted density matrix, W = sum_i^occ eps_i C_i C_i^T.
+fn energy_weighted_density(
+    C: &Array<f64, Ix2>,
+    eps: &ndarray::Array1<f64>,
+    nocc: usize,
+) -> Array<f64, Ix2> {
+    let C_occ = C.slice_axis(Axis(1), Slice::from(..nocc));
+    let eps_occ = eps.slice_axis(Axis(0), Slice::from(..nocc));
+    (&C_occ * &eps_occ).dot(&C_occ.t())
+}
+
+/// Contract a `(natoms, 3, nbasis, nbasis)` derivative matrix with a density.
+fn contract_deriv(M: &Array<f64, Ix4>, D: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+    let natoms = M.shape()[0];
+    let mut grad: Array<f64, _> = Array::zeros((natoms, 3));
+    for atom in 0..natoms {
+        for k in 0..3 {
+            grad[[atom, k]] = (&M.slice(ndarray::s![atom, k, .., ..]) * D).sum();
+        }
+    }
+    grad
+}
+
+/// Analytic gradient for a converged closed-shell RHF wavefunction.
+///
+/// With D = C_occ C_occ^T, the gradient is
+///
+/// dE/dX = sum 2 D h^X + sum (2 D_mn D_ls - D_ml D_ns) (mn|ls)^X - sum 2 W S^X + dV_nn/dX
+pub fn rhf_gradient(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    scf: &RHFResult,
+) -> Gradient {
+    let D = &scf.D;
+    let W = energy_weighted_density(&scf.C, &scf.eps, scf.nocc);
+    let h_deriv = basis::T_deriv(basis_set) + basis::V_deriv(basis_set, atomcoords, atomnos);
+    let S_deriv = basis::S_deriv(basis_set);
+    let eri_grad = basis::I_deriv_contract(basis_set, |mu, nu, lambda, sigma| {
+        2.0 * D[[mu, nu]] * D[[lambda, sigma]] - D[[mu, lambda]] * D[[nu, sigma]]
+    });
+    let values = 2.0 * contract_deriv(&h_deriv, D) - 2.0 * contract_deriv(&S_deriv, &W)
+        + eri_grad
+        + nuclear_repulsion_gradient(atomcoords, atomnos);
+    Gradient { values }
+}
+
+/// Analytic gradient for a converged UHF wavefunction.
+pub fn uhf_gradient(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    scf: &UHFResult,
+) -> Gradient {
+    let D_alpha = &scf.D_alpha;
+    let D_beta = &scf.D_beta;
+    let D_total = D_alpha + D_beta;
+    let W = energy_weighted_density(&scf.C_alpha, &scf.eps_alpha, scf.nalpha)
+        + energy_weighted_density(&scf.C_beta, &scf.eps_beta, scf.nbeta);
+    let h_deriv = basis::T_deriv(basis_set) + basis::V_deriv(basis_set, atomcoords, atomnos);
+    let S_deriv = basis::S_deriv(basis_set);
+    let eri_grad = basis::I_deriv_contract(basis_set, |mu, nu, lambda, sigma| {
+        0.5 * (D_total[[mu, nu]] * D_total[[lambda, sigma]]
+            - D_alpha[[mu, lambda]] * D_alpha[[nu, sigma]]
+            - D_beta[[mu, lambda]] * D_beta[[nu, sigma]])
+    });
+    let values = contract_deriv(&h_deriv, &D_total) - contract_deriv(&S_deriv, &W)
+        + eri_grad
+        + nuclear_repulsion_gradient(atomcoords, atomnos);
+    Gradient { values }
+}
+
+/// Gradient from central differences of an arbitrary energy function of the
+/// nuclear coordinates, with displacements of `step` bohr.
+pub fn finite_difference_gradient<F>(atomcoords: &[[f64; 3]], step: f64, mut energy: F) -> Gradient
+where
+    F: FnMut(&[[f64; 3]]) -> f64,
+{
+    let natoms = atomcoords.len();
+    let mut values: Array<f64, _> = Array::zeros((natoms, 3));
+    let mut displaced = atomcoords.to_vec();
+    for atom in 0..natoms {
+        for k in 0..3 {
+            displaced[atom][k] = atomcoords[atom][k] + step;
+            let e_plus = energy(&displaced);
+            displaced[atom][k] = atomcoords[atom][k] - step;
+            let e_minus = energy(&displaced);
+            displaced[atom][k] = atomcoords[atom][k];
+            values[[atom, k]] = (e_plus - e_minus) / (2.0 * step);
+        }
+    }
+    Gradient { values }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scf;
+    use crate::testing;
+
+    fn options() -> scf::SCFOptions {
+        scf::SCFOptions {
+            thresh_e: 1.0e-12,
+            thresh_d: 1.0e-10,
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_S_deriv() {
+        let (mut basis_set, atomcoords, _) = testing::water_sto3g();
+        let S_deriv = basis::S_deriv(&basis_set);
+        let step = 1.0e-5;
+        let mut displaced = atomcoords.clone();
+        displaced[1][0] += step;
+        basis_set.set_atomcoords(&displaced);
+        let S_plus = basis::S(&basis_set);
+        displaced[1][0] -= 2.0 * step;
+        basis_set.set_atomcoords(&displaced);
+        let S_minus = basis::S(&basis_set);
+        let fd = (S_plus - S_minus) / (2.0 * step);
+        for (a, b) in S_deriv
+            .slice(ndarray::s![1, 0, .., ..])
+            .iter()
+            .zip(fd.iter())
+        {
+            assert!((a - b).abs() < 1.0e-8);
+        }
+    }
+
+    #[test]
+    fn test_rhf_gradient_water_sto3g() {
+        let (mut basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options());
+        assert!(result.converged);
+        let analytic = rhf_gradient(&basis_set, &atomcoords, &atomnos, &result);
+        let numeric = finite_difference_gradient(&atomcoords, 1.0e-4, |coords| {
+            basis_set.set_atomcoords(coords);
+            scf::rhf(&basis_set, coords, &atomnos, 5, &options()).energy
+        });
+        // The incomplete gamma function in libpyquante2 is only converged to
+        // a relative 3e-7, which limits how well the two can agree.
+        for (a, n) in analytic.values.iter().zip(numeric.values.iter()) {
+            assert!((a - n).abs() < 2.0e-6);
+        }
+        // The total force on the molecule vanishes.
+        for k in 0..3 {
+            assert!(analytic.values.column(k).sum().abs() < 1.0e-8);
+        }
+    }
+
+    #[test]
+    fn test_uhf_gradient_water_cation_sto3g() {
+        let (mut basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let result = scf::uhf(&basis_set, &atomcoords, &atomnos, 5, 4, &options());
+        assert!(result.converged);
+        let analytic = uhf_gradient(&basis_set, &atomcoords, &atomnos, &result);
+        let numeric = finite_difference_gradient(&atomcoords, 1.0e-4, |coords| {
+            basis_set.set_atomcoords(coords);
+            scf::uhf(&basis_set, coords, &atomnos, 5, 4, &options()).energy
+        });
+        for (a, n) in analytic.values.iter().zip(numeric.values.iter()) {
+            assert!((a - n).abs() < 2.0e-6);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+//! Atom-centered molecular integration grids for the exchange-correlation
+//! terms in Kohn-Sham DFT.
+//!
+//! Each atom carries a product of a radial grid and Lebedev angular grids,
+//! and the atomic grids are combined with Becke's fuzzy-cell partitioning,
+//! so that sum_g w_g f(r_g) approximates the integral of f over all space.
+//!
+//! A. D. Becke, J. Chem. Phys. 88, 2547 (1988).
+
+use std::f64::consts::PI;
+
+use crate::elements;
+use crate::lebedev;
+
+/// How the radial points of each atomic grid are placed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RadialScheme {
+    /// Chebyshev quadrature of the second kind with the M4 mapping,
+    /// O. Treutler and R. Ahlrichs, J. Chem. Phys. 102, 346 (1995).
+    TreutlerAhlrichs,
+    /// The Euler-Maclaurin log^3 mapping of M. E. Mura and P. J. Knowles,
+    /// J. Chem. Phys. 104, 9848 (1996).
+    MuraKnowles,
+}
+
+#[derive(Clone, Debug)]
+pub struct GridOptions {
+    /// Number of radial points per atom.
+    pub radial_points: usize,
+    /// Number of Lebedev points per radial shell, one of `lebedev::ORDERS`.
+    pub angular_points: usize,
+    pub radial_scheme: RadialScheme,
+    /// Use coarser angular grids close to the nuclei, where the density is
+    /// nearly spherical.
+    pub prune: bool,
+}
+
+impl Default for GridOptions {
+    fn default() -> GridOptions {
+        GridOptions {
+            radial_points: 50,
+            angular_points: 194,
+            radial_scheme: RadialScheme::TreutlerAhlrichs,
+            prune: true,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct MolecularGrid {
+    pub points: Vec<[f64; 3]>,
+    /// Quadrature weights including the Becke partition function.
+    pub weights: Vec<f64>,
+    /// The atom each point belongs to.
+    pub atoms: Vec<usize>,
+}
+
+/// Atomic size parameter of the Treutler-Ahlrichs mapping.
+fn treutler_xi(atomno: u64) -> f64 {
+    match atomno {
+        1 => 0.8,
+        2 => 0.9,
+        3 => 1.8,
+        4 => 1.4,
+        5 => 1.3,
+        6 => 1.1,
+        7..=10 => 0.9,
+        11 => 1.4,
+        12 | 13 => 1.3,
+        14 => 1.2,
+        15 => 1.1,
+        16..=18 => 1.0,
+        19 => 1.5,
+        20 => 1.4,
+        21 => 1.3,
+        22..=27 => 1.2,
+        28..=31 => 1.1,
+        32 => 1.0,
+        33..=36 => 0.9,
+        _ => 1.0,
+    }
+}
+
+/// Radial points and weights (including the r^2 Jacobian) for one atom.
+pub fn radial_grid(atomno: u64, npoints: usize, scheme: RadialScheme) -> Vec<(f64, f64)> {
+    match scheme {
+        RadialScheme::TreutlerAhlrichs => {
+            let alpha = 0.6;
+            let scale = treutler_xi(atomno) / 2f64.ln();
+            (1..npoints + 1)
+                .map(|i| {
+                    let theta = i as f64 * PI / (npoints + 1) as f64;
+                    let x = theta.cos();
+                    let log = (2.0 / (1.0 - x)).ln();
+                    let r = scale * (1.0 + x).powf(alpha) * log;
+                    let dr = scale
+                        * (alpha * (1.0 + x).powf(alpha - 1.0) * log
+                            + (1.0 + x).powf(alpha) / (1.0 - x));
+                    let w = PI / (npoints + 1) as f64 * theta.sin() * dr * r * r;
+                    (r, w)
+                })
+                .collect()
+        }
+        RadialScheme::MuraKnowles => {
+            let alpha = match atomno {
+                3 | 4 | 11 | 12 | 19 | 20 => 7.0,
+                _ => 5.0,
+            };
+            (0..npoints)
+                .map(|i| {
+                    let x = (i as f64 + 0.5) / npoints as f64;
+                    let x3 = x.powi(3);
+                    let r = -alpha * (1.0 - x3).ln();
+                    let dr = alpha * 3.0 * x * x / (1.0 - x3);
+                    (r, dr * r * r / npoints as f64)
+                })
+                .collect()
+        }
+    }
+}
+
+/// Number of angular points for a radial shell at distance `r` from a
+/// nucleus with covalent radius `radius`.
+fn pruned_angular_points(r: f64, radius: f64, full: usize) -> usize {
+    let points = if r < 0.25 * radius {
+        14
+    } else if r < 0.5 * radius {
+        50
+    } else if r < radius {
+        110
+    } else {
+        full
+    };
+    points.min(full)
+}
+
+fn distance(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    (0..3).map(|k| (a[k] - b[k]).powi(2)).sum::<f64>().sqrt()
+}
+
+/// Becke's smoothed step function of the elliptical coordinate.
+fn becke_step(nu: f64) -> f64 {
+    let f = |x: f64| 1.5 * x - 0.5 * x.powi(3);
+    0.5 * (1.0 - f(f(f(nu))))
+}
+
+/// The fraction of the point `r` belonging to each atom, with the cell
+/// boundaries shifted according to the covalent radii.
+fn becke_partition(r: &[f64; 3], atomcoords: &[[f64; 3]], radii: &[f64]) -> Vec<f64> {
+    let natoms = atomcoords.len();
+    let distances: Vec<f64> = atomcoords.iter().map(|a| distance(r, a)).collect();
+    let mut cell = vec![1.0; natoms];
+    for i in 0..natoms {
+        for j in 0..natoms {
+            if i == j {
+                continue;
+            }
+            let mu = (distances[i] - distances[j]) / distance(&atomcoords[i], &atomcoords[j]);
+            let chi = radii[i] / radii[j];
+            let u = (chi - 1.0) / (chi + 1.0);
+            let a = (u / (u * u - 1.0)).clamp(-0.5, 0.5);
+            cell[i] *= becke_step(mu + a * (1.0 - mu * mu));
+        }
+    }
+    let total: f64 = cell.iter().sum();
+    cell.iter().map(|p| p / total).collect()
+}
+
+impl MolecularGrid {
+    pub fn new(atomcoords: &[[f64; 3]], atomnos: &[u64], options: &GridOptions) -> MolecularGrid {
+        let radii: Vec<f64> = atomnos
+            .iter()
+            .map(|&atomno| elements::covalent_radius(atomno))
+            .collect();
+        let mut points = Vec::new();
+        let mut weights = Vec::new();
+        let mut atoms = Vec::new();
+        for (atom, (center, &atomno)) in atomcoords.iter().zip(atomnos).enumerate() {
+            for (r, w_radial) in radial_grid(atomno, options.radial_points, options.radial_scheme) {
+                let nangular = if options.prune {
+                    pruned_angular_points(r, radii[atom], options.angular_points)
+                } else {
+                    options.angular_points
+                };
+                for (direction, w_angular) in lebedev::lebedev(nangular) {
+                    let point = [
+                        center[0] + r * direction[0],
+                        center[1] + r * direction[1],
+                        center[2] + r * direction[2],
+                    ];
+                    let partition = becke_partition(&point, atomcoords, &radii)[atom];
+                    let weight = 4.0 * PI * w_radial * w_angular * partition;
+                    // Points deep inside another atom's cell contribute
+                    // nothing.
+                    if weight.abs() > 1.0e-15 {
+                        points.push(point);
+                        weights.push(weight);
+                        atoms.push(atom);
+                    }
+                }
+            }
+        }
+        MolecularGrid {
+            points,
+            weights,
+            atoms,
+        }
+    }
+
+    pub fn npoints(&self) -> usize {
+        self.points.len()
+    }
+
+    /// Integrate a function given by its values at the grid points.
+    pub fn integrate(&self, values: &[f64]) -> f64 {
+        self.weights.iter().zip(values).map(|(w, f)| w * f).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_grid_integrates_gaussians() {
+        // A normalized Gaussian on each atom of water integrates to one per
+        // atom, for both radial schemes and with or without pruning.
+        let (atomcoords, atomnos) = testing::water();
+        for &radial_scheme in [RadialScheme::TreutlerAhlrichs, RadialScheme::MuraKnowles].iter() {
+            for &prune in [true, false].iter() {
+                let options = GridOptions {
+                    radial_scheme,
+                    prune,
+                    ..Default::default()
+                };
+                let grid = MolecularGrid::new(&atomcoords, &atomnos, &options);
+                let values: Vec<f64> = grid
+                    .points
+                    .iter()
+                    .map(|p| {
+                        atomcoords
+                            .iter()
+                            .map(|a| {
+                                let r2 = distance(p, a).powi(2);
+                                (0.5 / PI).powf(1.5) * (-0.5 * r2).exp()
+                            })
+                            .sum()
+                    })
+                    .collect();
+                assert!((grid.integrate(&values) - 3.0).abs() < 1.0e-5);
+            }
+        }
+    }
+}
@@ -0,0 +1,427 @@
+#![allow(non_snake_case)]
+
+//! Initial guesses for the SCF density.
+//!
+//! Besides the core Hamiltonian, the guesses are the superposition of
+//! atomic densities (SAD) from spherically averaged atomic calculations in
+//! the molecular basis, an extended Hückel guess in the minimal basis of
+//! occupied atomic orbitals from the same calculations, the generalized
+//! Wolfsberg-Helmholz (GWH) guess, and orbitals from an earlier calculation
+//! projected into the current basis.
+//!
+//! S. Lehtola, J. Chem. Theory Comput. 15, 1593 (2019).
+
+use std::collections::HashMap as Map;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ndarray::{Array, Axis, Ix1, Ix2, Slice};
+use ndarray_linalg::*;
+
+use crate::basis::{self, Basis};
+use crate::diis::DIIS;
+use crate::scf;
+
+/// The Wolfsberg-Helmholz constant used by both the Hückel and GWH guesses.
+const WOLFSBERG_HELMHOLZ: f64 = 1.75;
+
+#[derive(Clone, Debug)]
+pub enum Guess {
+    /// Diagonalize the core Hamiltonian H = T + V.
+    Core,
+    /// Superposition of spherically averaged atomic densities.
+    SAD,
+    /// Extended Hückel in the minimal basis of occupied atomic orbitals.
+    Huckel,
+    /// The core Hamiltonian with off-diagonal elements replaced by
+    /// K/2 (H_ii + H_jj) S_ij.
+    GWH,
+    /// Orbitals from an earlier calculation, in the basis they were
+    /// computed in, projected into the current basis.
+    Read { basis: Basis, C: Array<f64, Ix2> },
+}
+
+impl Guess {
+    pub fn from_name(name: &str) -> Option<Guess> {
+        match name.to_lowercase().as_str() {
+            "core" => Some(Guess::Core),
+            "sad" => Some(Guess::SAD),
+            "huckel" | "hückel" => Some(Guess::Huckel),
+            "gwh" => Some(Guess::GWH),
+            _ => None,
+        }
+    }
+
+    /// Fail unless orbitals to read are for their basis and include at
+    /// least `nocc` of them to occupy.
+    pub fn check(&self, nocc: usize) -> io::Result<()> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+        if let Guess::Read { basis, C } = self {
+            if C.nrows() != basis.nbasis() {
+                return Err(invalid(format!(
+                    "orbitals have {} coefficients but {} has {} basis functions",
+                    C.nrows(),
+                    basis.name(),
+                    basis.nbasis()
+                )));
+            }
+            if C.ncols() < nocc {
+                return Err(invalid(format!(
+                    "{} orbitals read but {} are occupied",
+                    C.ncols(),
+                    nocc
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The initial density matrix for `nocc` doubly occupied (or, for UHF, singly
+/// occupied) orbitals, in the same form as `scf::build_density`. Orbitals
+/// to read must pass `Guess::check`.
+pub fn initial_density(
+    guess: &Guess,
+    basis_set: &Basis,
+    atomnos: &[u64],
+    S: &Array<f64, Ix2>,
+    H: &Array<f64, Ix2>,
+    nocc: usize,
+) -> Array<f64, Ix2> {
+    match guess {
+        Guess::Core => {
+            let X = scf::symmetric_orthogonalization(S);
+            let (_, C) = scf::diagonalize(H, &X);
+            scf::build_density(&C, nocc)
+        }
+        Guess::SAD => {
+            let atoms = atomic_calculations(basis_set, atomnos);
+            let nbasis = basis_set.nbasis();
+            let mut D: Array<f64, _> = Array::zeros((nbasis, nbasis));
+            for (atom, atomno) in atomnos.iter().enumerate() {
+                let (_, indices) = basis_set.atom_basis(atom);
+                let D_atom = &atoms[atomno].D;
+                for (i, &mu) in indices.iter().enumerate() {
+                    for (j, &nu) in indices.iter().enumerate() {
+                        D[[mu, nu]] = D_atom[[i, j]];
+                    }
+                }
+            }
+            // The atomic densities hold half of the neutral atoms' electrons
+            // each, so scale them to the number of occupied orbitals.
+            let nelectrons: u64 = atomnos.iter().sum();
+            D * (2.0 * nocc as f64 / nelectrons as f64)
+        }
+        Guess::Huckel => {
+            let (C_min, eps_min) = minimal_basis(basis_set, atomnos);
+            let S_min = C_min.t().dot(S).dot(&C_min);
+            let H_min = wolfsberg_helmholz(&eps_min, &S_min);
+            let X_min = scf::symmetric_orthogonalization(&S_min);
+            let (_, C) = scf::diagonalize(&H_min, &X_min);
+            scf::build_density(&C_min.dot(&C), nocc)
+        }
+        Guess::GWH => {
+            let H_gwh = wolfsberg_helmholz(&H.diag().to_owned(), S);
+            let X = scf::symmetric_orthogonalization(S);
+            let (_, C) = scf::diagonalize(&H_gwh, &X);
+            scf::build_density(&C, nocc)
+        }
+        Guess::Read {
+            basis: old_basis,
+            C,
+        } => {
+            if let Err(error) = guess.check(nocc) {
+                panic!("{}", error);
+            }
+            let C_occ = C.slice_axis(Axis(1), Slice::from(..nocc)).to_owned();
+            let C_new = project_orbitals(old_basis, &C_occ, basis_set, S);
+            scf::build_density(&C_new, nocc)
+        }
+    }
+}
+
+fn wolfsberg_helmholz(diagonal: &Array<f64, Ix1>, S: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+    let dim = diagonal.len();
+    Array::from_shape_fn((dim, dim), |(i, j)| {
+        if i == j {
+            diagonal[i]
+        } else {
+            0.5 * WOLFSBERG_HELMHOLZ * (diagonal[i] + diagonal[j]) * S[[i, j]]
+        }
+    })
+}
+
+/// Project orbitals from `old_basis` into `new_basis`, whose overlap matrix
+/// is `S`, by least squares, C' = S^{-1} S_{new,old} C, and then
+/// orthonormalize them symmetrically.
+pub fn project_orbitals(
+    old_basis: &Basis,
+    C: &Array<f64, Ix2>,
+    new_basis: &Basis,
+    S: &Array<f64, Ix2>,
+) -> Array<f64, Ix2> {
+    let S_cross = basis::S_cross(new_basis, old_basis);
+    let C_new = S.inv().unwrap().dot(&S_cross).dot(C);
+    let M = C_new.t().dot(S).dot(&C_new);
+    C_new.dot(&scf::symmetric_orthogonalization(&M))
+}
+
+struct AtomicSCF {
+    /// Density matrix for a single spin.
+    D: Array<f64, Ix2>,
+    C: Array<f64, Ix2>,
+    eps: Array<f64, Ix1>,
+    /// Occupation of each orbital for a single spin.
+    occupations: Array<f64, Ix1>,
+}
+
+/// Occupy the lowest orbitals with `npairs` electron pairs, spreading the
+/// electrons of a partially filled shell evenly over its degenerate
+/// orbitals so that the density stays spherical.
+fn fractional_occupations(eps: &Array<f64, Ix1>, npairs: f64) -> Array<f64, Ix1> {
+    let mut occupations = vec![0.0; eps.len()];
+    let mut remaining = npairs;
+    let mut start = 0;
+    while start < eps.len() && remaining > 0.0 {
+        let end = (start..eps.len())
+            .find(|&i| (eps[i] - eps[start]).abs() > 1.0e-6)
+            .unwrap_or(eps.len());
+        let occupation = (remaining / (end - start) as f64).min(1.0);
+        for n in occupations[start..end].iter_mut() {
+            *n = occupation;
+        }
+        remaining -= occupation * (end - start) as f64;
+        start = end;
+    }
+    Array::from(occupations)
+}
+
+/// Spherically averaged restricted Hartree-Fock on a neutral atom, whose
+/// basis functions are all centered on the nucleus.
+fn atomic_scf(basis_set: &Basis, atomno: u64) -> AtomicSCF {
+    let atomcoords = [basis_set.cgtos[0].origin];
+    let S = basis::S(basis_set);
+    let X = scf::symmetric_orthogonalization(&S);
+    let H = scf::core_hamiltonian(basis_set, &atomcoords, &[atomno]);
+    let jk = scf::JKEngine::new(basis_set, &scf::JKAlgorithm::Direct);
+    let mut diis = DIIS::new(8);
+    let dim = H.shape()[0];
+    let npairs = atomno as f64 / 2.0;
+
+    let (mut eps, mut C) = scf::diagonalize(&H, &X);
+    let mut occupations = fractional_occupations(&eps, npairs);
+    let mut D = scf::build_density_fractional(&C, &occupations);
+    for _ in 0..100 {
+        let (J, K) = jk.build(&D);
+        let F = &H + &(2.0 * &J) - &K;
+        let FDS = F.dot(&D).dot(&S);
+        let error = X.t().dot(&(&FDS - &FDS.t())).dot(&X);
+        diis.push(
+            F.into_shape_with_order(dim * dim).unwrap(),
+            error.into_shape_with_order(dim * dim).unwrap(),
+        );
+        let F_diis = diis
+            .extrapolate()
+            .into_shape_with_order((dim, dim))
+            .unwrap();
+        let (eps_new, C_new) = scf::diagonalize(&F_diis, &X);
+        eps = eps_new;
+        C = C_new;
+        occupations = fractional_occupations(&eps, npairs);
+        let D_old = D;
+        D = scf::build_density_fractional(&C, &occupations);
+        let rms_d = (&D - &D_old).mapv(|x| x * x).mean().unwrap().sqrt();
+        if rms_d < 1.0e-7 {
+            break;
+        }
+    }
+
+    AtomicSCF {
+        D,
+        C,
+        eps,
+        occupations,
+    }
+}
+
+/// Atomic calculations for each element in the molecule.
+fn atomic_calculations(basis_set: &Basis, atomnos: &[u64]) -> Map<u64, AtomicSCF> {
+    let mut atoms = Map::new();
+    for (atom, &atomno) in atomnos.iter().enumerate() {
+        atoms.entry(atomno).or_insert_with(|| {
+            let (atom_basis, _) = basis_set.atom_basis(atom);
+            atomic_scf(&atom_basis, atomno)
+        });
+    }
+    atoms
+}
+
+/// The occupied orbitals of every atom expressed in the molecular basis,
+/// with their orbital energies.
+fn minimal_basis(basis_set: &Basis, atomnos: &[u64]) -> (Array<f64, Ix2>, Array<f64, Ix1>) {
+    let atoms = atomic_calculations(basis_set, atomnos);
+    let mut columns = Vec::new();
+    let mut energies = Vec::new();
+    for (atom, atomno) in atomnos.iter().enumerate() {
+        let (_, indices) = basis_set.atom_basis(atom);
+        let result = &atoms[atomno];
+        for (p, &occupation) in result.occupations.iter().enumerate() {
+            if occupation > 0.0 {
+                let mut column: Array<f64, _> = Array::zeros(basis_set.nbasis());
+                for (i, &mu) in indices.iter().enumerate() {
+                    column[mu] = result.C[[i, p]];
+                }
+                columns.push(column);
+                energies.push(result.eps[p]);
+            }
+        }
+    }
+    let mut C_min: Array<f64, _> = Array::zeros((basis_set.nbasis(), columns.len()));
+    for (p, column) in columns.iter().enumerate() {
+        C_min.column_mut(p).assign(column);
+    }
+    (C_min, Array::from(energies))
+}
+
+/// Write MO coefficients as plain text: the name of the basis set on the
+/// first line, the number of basis functions and orbitals on the second,
+/// then the coefficients one basis function per line.
+pub fn write_orbitals<P: AsRef<Path>>(
+    path: P,
+    basis_name: &str,
+    C: &Array<f64, Ix2>,
+) -> io::Result<()> {
+    let mut contents = format!("{}\n{} {}\n", basis_name, C.shape()[0], C.shape()[1]);
+    for row in C.rows() {
+        let line: Vec<String> = row.iter().map(|c| format!("{:.16e}", c)).collect();
+        contents.push_str(&line.join(" "));
+        contents.push('\n');
+    }
+    fs::write(path, contents)
+}
+
+/// Read orbitals written by `write_orbitals`, returning the name of the
+/// basis set they belong to and the coefficients.
+pub fn read_orbitals<P: AsRef<Path>>(path: P) -> io::Result<(String, Array<f64, Ix2>)> {
+    let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+    let contents = fs::read_to_string(path)?;
+    let mut lines = contents.lines();
+    let basis_name = lines
+        .next()
+        .ok_or_else(|| invalid("missing basis set name"))?
+        .trim()
+        .to_string();
+    let dims: Vec<usize> = lines
+        .next()
+        .ok_or_else(|| invalid("missing dimensions"))?
+        .split_whitespace()
+        .map(|x| x.parse().map_err(|_| invalid("bad dimensions")))
+        .collect::<io::Result<_>>()?;
+    if dims.len() != 2 {
+        return Err(invalid("bad dimensions"));
+    }
+    let values: Vec<f64> = lines
+        .flat_map(|line| line.split_whitespace())
+        .map(|x| x.parse().map_err(|_| invalid("bad coefficient")))
+        .collect::<io::Result<_>>()?;
+    let C = Array::from_shape_vec((dims[0], dims[1]), values)
+        .map_err(|_| invalid("wrong number of coefficients"))?;
+    Ok((basis_name, C))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_guesses_converge_to_rhf() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let run = |guess: Guess| {
+            let options = scf::SCFOptions {
+                jk: scf::JKAlgorithm::InMemory,
+                guess,
+                ..Default::default()
+            };
+            scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options)
+        };
+        let core = run(Guess::Core);
+
+        // Every guess density holds the right number of electrons.
+        let S = basis::S(&basis_set);
+        let H = scf::core_hamiltonian(&basis_set, &atomcoords, &atomnos);
+        for guess in [Guess::SAD, Guess::Huckel, Guess::GWH].iter() {
+            let D = initial_density(guess, &basis_set, &atomnos, &S, &H, 5);
+            assert!(((&D * &S).sum() - 5.0).abs() < 1.0e-8);
+            let result = run(guess.clone());
+            assert!(result.converged);
+            assert!((result.energy - core.energy).abs() < 1.0e-8);
+        }
+        let sad = run(Guess::SAD);
+        assert!(sad.iterations < core.iterations);
+
+        // Orbitals from a distorted geometry, written out and read back in,
+        // are a better start than the core Hamiltonian.
+        let mut distorted = atomcoords.clone();
+        distorted[1][0] += 0.1;
+        distorted[2][1] -= 0.1;
+        let old_basis = Basis::from_json(&atomnos, &distorted, testing::STO3G_JSON);
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let old = scf::rhf(&old_basis, &distorted, &atomnos, 5, &options);
+        let path = std::env::temp_dir().join("rchem_test_orbitals.txt");
+        write_orbitals(&path, old_basis.name(), &old.C).unwrap();
+        let (name, C) = read_orbitals(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(name, "STO-3G");
+        assert!((&C - &old.C).fold(0.0f64, |acc, x| acc.max(x.abs())) < 1.0e-14);
+        let read = run(Guess::Read {
+            basis: old_basis,
+            C,
+        });
+        assert!((read.energy - core.energy).abs() < 1.0e-8);
+        assert!(read.iterations < core.iterations);
+    }
+
+    #[test]
+    fn test_read_guess_into_larger_basis() {
+        // STO-3G orbitals projected into 6-31G converge to the same state as
+        // the core guess, in fewer iterations.
+        let (sto3g, atomcoords, atomnos) = testing::water_sto3g();
+        let basis_set = Basis::from_json(&atomnos, &atomcoords, testing::BASIS_631G_JSON);
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let small = scf::rhf(&sto3g, &atomcoords, &atomnos, 5, &options);
+        let run = |guess: Guess| {
+            let options = scf::SCFOptions {
+                guess,
+                ..options.clone()
+            };
+            scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options)
+        };
+        let core = run(Guess::Core);
+        let guess = Guess::Read {
+            basis: sto3g,
+            C: small.C.clone(),
+        };
+        assert!(guess.check(5).is_ok());
+        let read = run(guess);
+        assert!(core.converged && read.converged);
+        assert!(core.energy < small.energy);
+        assert!((read.energy - core.energy).abs() < 1.0e-8);
+        assert!(read.iterations < core.iterations);
+
+        // Too few orbitals to occupy are refused.
+        let (sto3g, _, _) = testing::water_sto3g();
+        let truncated = Guess::Read {
+            basis: sto3g,
+            C: small.C.slice_axis(Axis(1), Slice::from(..3)).to_owned(),
+        };
+        assert!(truncated.check(5).is_err());
+    }
+}
@@ -185,6 +185,45 @@ pub fn get_coulomb(
     rc: &[f64; 3],
     rd: &[f64; 3],
     c: &[usize; 12],
+) -> f64 {
+    get_coulomb_attenuated(za, zb, zc, zd, ra, rb, rc, rd, c, None)
+}
+
+/// Two-electron integral over the long-range operator erf(omega r12)/r12.
+#[allow(clippy::too_many_arguments)]
+pub fn get_coulomb_erf(
+    za: f64,
+    zb: f64,
+    zc: f64,
+    zd: f64,
+    ra: &[f64; 3],
+    rb: &[f64; 3],
+    rc: &[f64; 3],
+    rd: &[f64; 3],
+    c: &[usize; 12],
+    omega: f64,
+) -> f64 {
+    get_coulomb_attenuated(za, zb, zc, zd, ra, rb, rc, rd, c, Some(omega))
+}
+
+/// The recurrence relations are unchanged by the attenuation, which only
+/// enters through the auxiliary integrals
+///
+/// [0]^(m) = s kappa^(m + 1/2) F_m(kappa T), kappa = omega^2 / (omega^2 + rho),
+///
+/// with kappa = 1 for the bare Coulomb operator.
+#[allow(clippy::too_many_arguments)]
+fn get_coulomb_attenuated(
+    za: f64,
+    zb: f64,
+    zc: f64,
+    zd: f64,
+    ra: &[f64; 3],
+    rb: &[f64; 3],
+    rc: &[f64; 3],
+    rd: &[f64; 3],
+    c: &[usize; 12],
+    omega: Option<f64>,
 ) -> f64 {
     let rp = get_bi_center(za, zb, ra, rb);
     let rq = get_bi_center(zc, zd, rc, rd);
@@ -192,7 +231,11 @@ pub fn get_coulomb(
     let z = za + zb;
     let n = zc + zd;
     let rho = z * n / (z + n);
-    let t = rho * get_r12_squared(&rp, &rq);
+    let kappa = match omega {
+        Some(omega) => omega * omega / (omega * omega + rho),
+        None => 1.0,
+    };
+    let t = kappa * rho * get_r12_squared(&rp, &rq);
     let s = get_aux(za, zb, zc, zd, ra, rb, rc, rd);
 
     let prefac = [
@@ -246,7 +289,7 @@ pub fn get_coulomb(
     let mut integral = 0.0;
     let c_sum: usize = c.iter().sum();
     for i in 0..(c_sum + 1) {
-        let b = boys(i as u64, t) * s;
+        let b = boys(i as u64, t) * s * kappa.powf(i as f64 + 0.5);
         for f in expansion.iter() {
             if f.order as usize == i {
                 let mut g = 1.0;
@@ -772,7 +815,7 @@ pub fn get_nuclear(
     integral
 }
 
-fn get_moment(
+pub fn get_moment(
     za: f64,
     zb: f64,
     ra: &[f64; 3],
@@ -831,11 +874,12 @@ fn get_moment(
 mod tests {
     use super::find_component_to_lower;
     use super::find_fun_to_lower;
-    use super::get_coulomb;
     use super::get_kinetic;
     use super::get_moment;
     use super::get_nuclear;
     use super::get_overlap;
+    use super::{get_coulomb, get_coulomb_erf};
+    use boys::micb25::boys;
 
     #[test]
     fn test_find_fun_to_lower() {
@@ -936,6 +980,42 @@ mod tests {
         assert!((integral - reference).abs() < thresh);
     }
 
+    #[test]
+    fn test_get_coulomb_erf() {
+        let (za, zb, zc, zd) = (1.1, 1.2, 1.3, 1.4);
+        let ra = [1.0, 0.0, 1.0];
+        let rb = [0.0, 1.0, 2.0];
+        let rc = [0.0, 0.0, 3.0];
+        let rd = [0.0, 0.0, 4.0];
+        let shells = [
+            [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            [2, 1, 0, 1, 0, 0, 1, 0, 0, 0, 1, 0],
+        ];
+
+        // A very large omega recovers the Coulomb operator.
+        for c in shells.iter() {
+            let coulomb = get_coulomb(za, zb, zc, zd, &ra, &rb, &rc, &rd, c);
+            let erf = get_coulomb_erf(za, zb, zc, zd, &ra, &rb, &rc, &rd, c, 1.0e8);
+            assert!((erf - coulomb).abs() < 1.0e-12);
+        }
+
+        // For s functions the attenuation only rescales the Boys function,
+        // (ss|ss)_omega / (ss|ss) = sqrt(kappa) F_0(kappa T) / F_0(T).
+        let omega = 0.4;
+        let (p, q) = (za + zb, zc + zd);
+        let rho = p * q / (p + q);
+        let kappa = omega * omega / (omega * omega + rho);
+        let rpq2: f64 = (0..3)
+            .map(|k| ((za * ra[k] + zb * rb[k]) / p - (zc * rc[k] + zd * rd[k]) / q).powi(2))
+            .sum();
+        let t = rho * rpq2;
+        let coulomb = get_coulomb(za, zb, zc, zd, &ra, &rb, &rc, &rd, &shells[0]);
+        let erf = get_coulomb_erf(za, zb, zc, zd, &ra, &rb, &rc, &rd, &shells[0], omega);
+        let reference = coulomb * kappa.sqrt() * boys(0, kappa * t) / boys(0, t);
+        assert!((erf - reference).abs() < 1.0e-15);
+    }
+
     #[test]
     fn test_get_overlap() {
         let za = 1.8;
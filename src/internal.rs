@@ -0,0 +1,294 @@
+#![allow(non_snake_case)]
+
+//! Redundant internal coordinates: bonds, angles and dihedrals generated from
+//! the covalent connectivity, together with the Wilson B matrix.
+
+use std::f64::consts::PI;
+
+use ndarray::{Array, Ix1, Ix2};
+
+use crate::elements;
+
+/// Angles closer than this to 180 degrees are not used, since their
+/// derivatives are ill-defined.
+const LINEAR_THRESH: f64 = 175.0 * PI / 180.0;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Primitive {
+    Bond(usize, usize),
+    /// The second atom is the apex.
+    Angle(usize, usize, usize),
+    /// Rotation about the bond between the middle two atoms.
+    Dihedral(usize, usize, usize, usize),
+}
+
+fn sub(a: &[f64; 3], b: &[f64; 3]) -> [f64; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn dot(a: &[f64; 3], b: &[f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: &[f64; 3], b: &[f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn norm(a: &[f64; 3]) -> f64 {
+    dot(a, a).sqrt()
+}
+
+fn scale(a: &[f64; 3], s: f64) -> [f64; 3] {
+    [a[0] * s, a[1] * s, a[2] * s]
+}
+
+pub fn distance(atomcoords: &[[f64; 3]], i: usize, j: usize) -> f64 {
+    norm(&sub(&atomcoords[i], &atomcoords[j]))
+}
+
+pub fn angle(atomcoords: &[[f64; 3]], i: usize, j: usize, k: usize) -> f64 {
+    let u = sub(&atomcoords[i], &atomcoords[j]);
+    let v = sub(&atomcoords[k], &atomcoords[j]);
+    (dot(&u, &v) / (norm(&u) * norm(&v)))
+        .clamp(-1.0, 1.0)
+        .acos()
+}
+
+impl Primitive {
+    pub fn value(&self, atomcoords: &[[f64; 3]]) -> f64 {
+        match *self {
+            Primitive::Bond(i, j) => distance(atomcoords, i, j),
+            Primitive::Angle(i, j, k) => angle(atomcoords, i, j, k),
+            Primitive::Dihedral(i, j, k, l) => {
+                let F = sub(&atomcoords[i], &atomcoords[j]);
+                let G = sub(&atomcoords[j], &atomcoords[k]);
+                let H = sub(&atomcoords[l], &atomcoords[k]);
+                let A = cross(&F, &G);
+                let B = cross(&H, &G);
+                let y = dot(&cross(&B, &A), &G) / norm(&G);
+                let x = dot(&A, &B);
+                y.atan2(x)
+            }
+        }
+    }
+
+    /// Non-zero elements of this coordinate's row of the Wilson B matrix, as
+    /// (atom, d/dx_atom) pairs.
+    pub fn derivatives(&self, atomcoords: &[[f64; 3]]) -> Vec<(usize, [f64; 3])> {
+        match *self {
+            Primitive::Bond(i, j) => {
+                let r = sub(&atomcoords[i], &atomcoords[j]);
+                let u = scale(&r, 1.0 / norm(&r));
+                vec![(i, u), (j, scale(&u, -1.0))]
+            }
+            Primitive::Angle(i, j, k) => {
+                let rij = sub(&atomcoords[i], &atomcoords[j]);
+                let rkj = sub(&atomcoords[k], &atomcoords[j]);
+                let (lij, lkj) = (norm(&rij), norm(&rkj));
+                let u = scale(&rij, 1.0 / lij);
+                let v = scale(&rkj, 1.0 / lkj);
+                let cos = dot(&u, &v);
+                let sin = (1.0 - cos * cos).max(1.0e-12).sqrt();
+                let di = scale(&sub(&scale(&u, cos), &v), 1.0 / (lij * sin));
+                let dk = scale(&sub(&scale(&v, cos), &u), 1.0 / (lkj * sin));
+                let dj = [-di[0] - dk[0], -di[1] - dk[1], -di[2] - dk[2]];
+                vec![(i, di), (j, dj), (k, dk)]
+            }
+            Primitive::Dihedral(i, j, k, l) => {
+                // R. E. Bruccoleri et al. / Blondel and Karplus,
+                // J. Comput. Chem. 17, 1132 (1996).
+                let F = sub(&atomcoords[i], &atomcoords[j]);
+                let G = sub(&atomcoords[j], &atomcoords[k]);
+                let H = sub(&atomcoords[l], &atomcoords[k]);
+                let A = cross(&F, &G);
+                let B = cross(&H, &G);
+                let (a2, b2, g) = (dot(&A, &A), dot(&B, &B), norm(&G));
+                let fg = dot(&F, &G);
+                let hg = dot(&H, &G);
+                let di = scale(&A, -g / a2);
+                let dl = scale(&B, g / b2);
+                let mut dj = [0.0; 3];
+                let mut dk = [0.0; 3];
+                for x in 0..3 {
+                    let t = fg / (a2 * g) * A[x] - hg / (b2 * g) * B[x];
+                    dj[x] = -di[x] + t;
+                    dk[x] = -dl[x] - t;
+                }
+                vec![(i, di), (j, dj), (k, dk), (l, dl)]
+            }
+        }
+    }
+
+    /// Difference between two values of this coordinate, wrapping dihedrals
+    /// into (-pi, pi].
+    pub fn difference(&self, new: f64, old: f64) -> f64 {
+        let d = new - old;
+        match *self {
+            Primitive::Dihedral(..) => {
+                if d > PI {
+                    d - 2.0 * PI
+                } else if d <= -PI {
+                    d + 2.0 * PI
+                } else {
+                    d
+                }
+            }
+            _ => d,
+        }
+    }
+}
+
+/// Pairs of atoms closer than 1.3 times the sum of their covalent radii.
+/// Disconnected fragments are joined through their closest pair of atoms so
+/// that the coordinate set spans all relative motions.
+pub fn connectivity(atomcoords: &[[f64; 3]], atomnos: &[u64]) -> Vec<(usize, usize)> {
+    let natoms = atomcoords.len();
+    let mut bonds = Vec::new();
+    for i in 0..natoms {
+        for j in 0..i {
+            let rcov =
+                elements::covalent_radius(atomnos[i]) + elements::covalent_radius(atomnos[j]);
+            if distance(atomcoords, i, j) < 1.3 * rcov {
+                bonds.push((j, i));
+            }
+        }
+    }
+    // Assign fragments and connect them until there is only one.
+    loop {
+        let mut fragment: Vec<usize> = (0..natoms).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &(i, j) in bonds.iter() {
+                let f = fragment[i].min(fragment[j]);
+                if fragment[i] != f || fragment[j] != f {
+                    fragment[i] = f;
+                    fragment[j] = f;
+                    changed = true;
+                }
+            }
+        }
+        let mut closest: Option<(f64, usize, usize)> = None;
+        for i in 0..natoms {
+            for j in 0..i {
+                if fragment[i] == fragment[0] && fragment[j] != fragment[0]
+                    || fragment[j] == fragment[0] && fragment[i] != fragment[0]
+                {
+                    let r = distance(atomcoords, i, j);
+                    if closest.is_none_or(|(rmin, _, _)| r < rmin) {
+                        closest = Some((r, j, i));
+                    }
+                }
+            }
+        }
+        match closest {
+            Some((_, j, i)) => bonds.push((j, i)),
+            None => break,
+        }
+    }
+    bonds
+}
+
+/// Bonds, angles and dihedrals built from the connectivity.
+pub fn redundant_internals(atomcoords: &[[f64; 3]], atomnos: &[u64]) -> Vec<Primitive> {
+    let natoms = atomcoords.len();
+    let bonds = connectivity(atomcoords, atomnos);
+    let mut neighbors: Vec<Vec<usize>> = vec![Vec::new(); natoms];
+    for &(i, j) in bonds.iter() {
+        neighbors[i].push(j);
+        neighbors[j].push(i);
+    }
+    let mut primitives: Vec<Primitive> =
+        bonds.iter().map(|&(i, j)| Primitive::Bond(i, j)).collect();
+    for (j, bonded) in neighbors.iter().enumerate() {
+        for (n, &i) in bonded.iter().enumerate() {
+            for &k in bonded.iter().skip(n + 1) {
+                if angle(atomcoords, i, j, k) < LINEAR_THRESH {
+                    primitives.push(Primitive::Angle(i, j, k));
+                }
+            }
+        }
+    }
+    for &(j, k) in bonds.iter() {
+        for &i in neighbors[j].iter().filter(|&&i| i != k) {
+            for &l in neighbors[k].iter().filter(|&&l| l != j && l != i) {
+                if angle(atomcoords, i, j, k) < LINEAR_THRESH
+                    && angle(atomcoords, j, k, l) < LINEAR_THRESH
+                {
+                    primitives.push(Primitive::Dihedral(i, j, k, l));
+                }
+            }
+        }
+    }
+    primitives
+}
+
+pub fn values(primitives: &[Primitive], atomcoords: &[[f64; 3]]) -> Array<f64, Ix1> {
+    primitives.iter().map(|p| p.value(atomcoords)).collect()
+}
+
+/// The Wilson B matrix, dq/dx, with shape `(nprimitives, 3 * natoms)`.
+pub fn wilson_B(primitives: &[Primitive], atomcoords: &[[f64; 3]]) -> Array<f64, Ix2> {
+    let mut B: Array<f64, _> = Array::zeros((primitives.len(), 3 * atomcoords.len()));
+    for (row, primitive) in primitives.iter().enumerate() {
+        for (atom, d) in primitive.derivatives(atomcoords) {
+            for x in 0..3 {
+                B[[row, 3 * atom + x]] += d[x];
+            }
+        }
+    }
+    B
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_water_internals() {
+        let (atomcoords, atomnos) = testing::water();
+        let primitives = redundant_internals(&atomcoords, &atomnos);
+        assert_eq!(
+            primitives,
+            vec![
+                Primitive::Bond(0, 1),
+                Primitive::Bond(0, 2),
+                Primitive::Angle(1, 0, 2)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wilson_B() {
+        // hydrogen peroxide, which has a dihedral
+        let atomcoords = [
+            [0.0, 1.3, -0.1],
+            [0.0, -1.3, -0.1],
+            [1.6, 1.6, 0.8],
+            [-1.1, -1.7, 1.4],
+        ];
+        let atomnos = [8, 8, 1, 1];
+        let primitives = redundant_internals(&atomcoords, &atomnos);
+        assert!(primitives.contains(&Primitive::Dihedral(2, 0, 1, 3)));
+        let B = wilson_B(&primitives, &atomcoords);
+        let step = 1.0e-6;
+        for atom in 0..atomcoords.len() {
+            for x in 0..3 {
+                let mut plus = atomcoords;
+                plus[atom][x] += step;
+                let mut minus = atomcoords;
+                minus[atom][x] -= step;
+                for (row, primitive) in primitives.iter().enumerate() {
+                    let fd = primitive.difference(primitive.value(&plus), primitive.value(&minus))
+                        / (2.0 * step);
+                    assert!((B[[row, 3 * atom + x]] - fd).abs() < 1.0e-7);
+                }
+            }
+        }
+    }
+}
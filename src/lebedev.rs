@@ -0,0 +1,205 @@
+//! Lebedev-Laikov quadratures on the unit sphere, generated from the
+//! parameters of their octahedrally symmetric orbits.
+//!
+//! V. I. Lebedev and D. N. Laikov, Dokl. Math. 59, 477 (1999).
+
+/// The available numbers of points and the polynomial degree each one
+/// integrates exactly.
+pub const ORDERS: [(usize, usize); 9] = [
+    (6, 3),
+    (14, 5),
+    (26, 7),
+    (38, 9),
+    (50, 11),
+    (74, 13),
+    (110, 17),
+    (194, 23),
+    (302, 29),
+];
+
+/// The generators of point sets invariant under the octahedral group, with
+/// the weight shared by every point of the orbit.
+enum Orbit {
+    /// (1, 0, 0), 6 points
+    A1(f64),
+    /// (0, a, a) with a = 1/sqrt(2), 12 points
+    A2(f64),
+    /// (a, a, a) with a = 1/sqrt(3), 8 points
+    A3(f64),
+    /// (l, l, m) with m = sqrt(1 - 2 l^2), 24 points
+    B(f64, f64),
+    /// (p, q, 0) with q = sqrt(1 - p^2), 24 points
+    C(f64, f64),
+    /// (r, s, t) with t = sqrt(1 - r^2 - s^2), 48 points
+    D(f64, f64, f64),
+}
+
+fn orbits(npoints: usize) -> Vec<Orbit> {
+    use Orbit::*;
+    match npoints {
+        6 => vec![A1(1.0 / 6.0)],
+        14 => vec![A1(1.0 / 15.0), A3(3.0 / 40.0)],
+        26 => vec![A1(1.0 / 21.0), A2(4.0 / 105.0), A3(9.0 / 280.0)],
+        38 => vec![
+            A1(1.0 / 105.0),
+            A3(9.0 / 280.0),
+            C(0.4597008433809831, 1.0 / 35.0),
+        ],
+        50 => vec![
+            A1(0.126984126984127e-1),
+            A2(0.2257495590828924e-1),
+            A3(0.2109375e-1),
+            B(0.3015113445777636, 0.2017333553791887e-1),
+        ],
+        74 => vec![
+            A1(0.5130671797338464e-3),
+            A2(0.1660406956574204e-1),
+            A3(-0.2958603896103896e-1),
+            B(0.4803844614152614, 0.2657620708215946e-1),
+            C(0.3207726489807764, 0.1652217099371571e-1),
+        ],
+        110 => vec![
+            A1(0.3828270494937162e-2),
+            A3(0.9793737512487512e-2),
+            B(0.1851156353447362, 0.8211737283191111e-2),
+            B(0.6904210483822922, 0.9942814891178103e-2),
+            B(0.3956894730559419, 0.9595471336070963e-2),
+            C(0.4783690288121502, 0.9694996361663028e-2),
+        ],
+        194 => vec![
+            A1(0.1782340447244611e-2),
+            A2(0.5716905949977102e-2),
+            A3(0.5573383178848738e-2),
+            B(0.6712973442695226, 0.5608704082587997e-2),
+            B(0.2892465627575439, 0.5158237711805383e-2),
+            B(0.4446933178717437, 0.5518771467273614e-2),
+            B(0.1299335447650067, 0.4106777028169394e-2),
+            C(0.3457702197611283, 0.5051846064614808e-2),
+            D(0.159041710538353, 0.8360360154824589, 0.5530248916233094e-2),
+        ],
+        302 => vec![
+            A1(0.8545911725128148e-3),
+            A3(0.3599119285025571e-2),
+            B(0.3515640345570105, 0.3449788424305883e-2),
+            B(0.6566329410219612, 0.3604822601419882e-2),
+            B(0.4729054132581005, 0.3576729661743367e-2),
+            B(0.9618308522614784e-1, 0.2352101413689164e-2),
+            B(0.2219645236294178, 0.3108953122413675e-2),
+            B(0.7011766416089545, 0.3650045807677255e-2),
+            C(0.2644152887060663, 0.2982344963171804e-2),
+            C(0.5718955891878961, 0.360082093221646e-2),
+            D(
+                0.2510034751770465,
+                0.8000727494073952,
+                0.3571540554273387e-2,
+            ),
+            D(0.1233548532583327, 0.4127724083168531, 0.339231220500617e-2),
+        ],
+        _ => panic!(
+            "no Lebedev grid with {} points, choose one of {:?}",
+            npoints,
+            ORDERS.iter().map(|&(n, _)| n).collect::<Vec<_>>()
+        ),
+    }
+}
+
+/// All distinct points generated from `v` by permuting its components and
+/// changing their signs.
+fn expand(v: [f64; 3]) -> Vec<[f64; 3]> {
+    const PERMUTATIONS: [[usize; 3]; 6] = [
+        [0, 1, 2],
+        [0, 2, 1],
+        [1, 0, 2],
+        [1, 2, 0],
+        [2, 0, 1],
+        [2, 1, 0],
+    ];
+    let mut points: Vec<[f64; 3]> = Vec::new();
+    for permutation in PERMUTATIONS.iter() {
+        for signs in 0..8 {
+            let mut point = [0.0; 3];
+            for k in 0..3 {
+                let sign = if signs & (1 << k) == 0 { 1.0 } else { -1.0 };
+                point[k] = sign * v[permutation[k]];
+            }
+            let duplicate = points
+                .iter()
+                .any(|p| (0..3).all(|k| (p[k] - point[k]).abs() < 1.0e-12));
+            if !duplicate {
+                points.push(point);
+            }
+        }
+    }
+    points
+}
+
+/// The points and weights of the Lebedev grid with `npoints` points, with
+/// the weights summing to one.
+pub fn lebedev(npoints: usize) -> Vec<([f64; 3], f64)> {
+    let mut grid = Vec::with_capacity(npoints);
+    for orbit in orbits(npoints) {
+        let (generator, weight) = match orbit {
+            Orbit::A1(w) => ([1.0, 0.0, 0.0], w),
+            Orbit::A2(w) => {
+                let a = 0.5f64.sqrt();
+                ([0.0, a, a], w)
+            }
+            Orbit::A3(w) => {
+                let a = (1.0f64 / 3.0).sqrt();
+                ([a, a, a], w)
+            }
+            Orbit::B(l, w) => ([l, l, (1.0 - 2.0 * l * l).sqrt()], w),
+            Orbit::C(p, w) => ([p, (1.0 - p * p).sqrt(), 0.0], w),
+            Orbit::D(r, s, w) => ([r, s, (1.0 - r * r - s * s).sqrt()], w),
+        };
+        for point in expand(generator) {
+            grid.push((point, weight));
+        }
+    }
+    assert_eq!(grid.len(), npoints);
+    grid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn double_factorial(n: i32) -> f64 {
+        if n <= 0 {
+            1.0
+        } else {
+            n as f64 * double_factorial(n - 2)
+        }
+    }
+
+    #[test]
+    fn test_lebedev_integrates_monomials() {
+        // The average of x^a y^b z^c over the sphere is
+        // (a-1)!! (b-1)!! (c-1)!! / (a+b+c+1)!! when all powers are even.
+        for &(npoints, degree) in ORDERS.iter() {
+            let grid = lebedev(npoints);
+            for n in 0..=degree {
+                for a in 0..=n {
+                    for b in 0..=(n - a) {
+                        let c = n - a - b;
+                        let approx: f64 = grid
+                            .iter()
+                            .map(|(p, w)| {
+                                w * p[0].powi(a as i32) * p[1].powi(b as i32) * p[2].powi(c as i32)
+                            })
+                            .sum();
+                        let exact = if a % 2 == 0 && b % 2 == 0 && c % 2 == 0 {
+                            double_factorial(a as i32 - 1)
+                                * double_factorial(b as i32 - 1)
+                                * double_factorial(c as i32 - 1)
+                                / double_factorial((a + b + c) as i32 + 1)
+                        } else {
+                            0.0
+                        };
+                        assert!((approx - exact).abs() < 1.0e-12);
+                    }
+                }
+            }
+        }
+    }
+}
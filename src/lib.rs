@@ -2,5 +2,39 @@
 extern crate approx;
 
 pub mod basis;
+pub mod cc;
+pub mod checkpoint;
+pub mod cholesky;
+pub mod ci;
+pub mod constants;
+pub mod cube;
+pub mod davidson;
+pub mod df;
+pub mod dft;
+pub mod diis;
+pub mod dispersion;
+pub mod elements;
+pub mod fchk;
+pub mod frequencies;
+pub mod gradient;
+pub mod grid;
+pub mod guess;
 pub mod integrals;
+pub mod internal;
+pub mod lebedev;
+pub mod molden;
+pub mod mp2;
+pub mod optimize;
+pub mod population;
+pub mod qcschema;
+pub mod response;
+pub mod scf;
 pub mod shell;
+pub mod soscf;
+pub mod stability;
+pub mod symmetry;
+pub mod transform;
+pub mod xc;
+
+#[cfg(test)]
+mod testing;
@@ -0,0 +1,325 @@
+#![allow(non_snake_case)]
+
+//! Export of geometries, basis sets and molecular orbitals in the Molden
+//! format, which most visualization programs can read.
+//!
+//! Molden files use Cartesian functions in their own order within each
+//! shell, all normalized like the x^l component, and readers renormalize
+//! each contraction themselves. Our functions are normalized component by
+//! component, in the order of `shell::get_ijk_list`, so the orbital
+//! coefficients are permuted and rescaled on the way out.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use ndarray::{Array, Ix1, Ix2};
+
+use crate::basis::{self, Basis};
+use crate::elements;
+use crate::fchk::fortran_real;
+use crate::scf;
+use crate::shell;
+
+const SHELL_LABELS: [&str; 5] = ["s", "p", "d", "f", "g"];
+
+/// The Cartesian components of a shell in the order Molden expects.
+fn molden_order(l: usize) -> Vec<[usize; 3]> {
+    match l {
+        0 => vec![[0, 0, 0]],
+        1 => vec![[1, 0, 0], [0, 1, 0], [0, 0, 1]],
+        2 => vec![
+            [2, 0, 0],
+            [0, 2, 0],
+            [0, 0, 2],
+            [1, 1, 0],
+            [1, 0, 1],
+            [0, 1, 1],
+        ],
+        3 => vec![
+            [3, 0, 0],
+            [0, 3, 0],
+            [0, 0, 3],
+            [1, 2, 0],
+            [2, 1, 0],
+            [2, 0, 1],
+            [1, 0, 2],
+            [0, 1, 2],
+            [0, 2, 1],
+            [1, 1, 1],
+        ],
+        4 => vec![
+            [4, 0, 0],
+            [0, 4, 0],
+            [0, 0, 4],
+            [3, 1, 0],
+            [3, 0, 1],
+            [1, 3, 0],
+            [0, 3, 1],
+            [1, 0, 3],
+            [0, 1, 3],
+            [2, 2, 0],
+            [2, 0, 2],
+            [0, 2, 2],
+            [2, 1, 1],
+            [1, 2, 1],
+            [1, 1, 2],
+        ],
+        _ => panic!("Molden files only support up to g functions"),
+    }
+}
+
+/// The matrix T that takes orbital coefficients in our basis to the Molden
+/// basis, C_molden = T C.
+fn transformation(basis_set: &Basis) -> Array<f64, Ix2> {
+    let nbasis = basis_set.nbasis();
+    let mut T: Array<f64, _> = Array::zeros((nbasis, nbasis));
+    let mut offset = 0;
+    for shell in basis_set.shells() {
+        // Molden readers divide out the norm of the contraction.
+        let norm = shell.contraction_norm();
+        let ours = shell::get_ijk_list(shell.angular_momentum);
+        for (row, powers) in molden_order(shell.angular_momentum).into_iter().enumerate() {
+            let k = ours.iter().position(|p| *p == powers).unwrap();
+            T[[offset + row, offset + k]] = basis::axis_normalization_ratio(powers) * norm;
+        }
+        offset += shell.nfunctions();
+    }
+    T
+}
+
+/// Orbitals of one spin with their energies and occupation numbers.
+pub struct MoldenOrbitals<'a> {
+    pub C: &'a Array<f64, Ix2>,
+    pub energies: &'a Array<f64, Ix1>,
+    pub occupations: Array<f64, Ix1>,
+}
+
+/// Write a Molden file with the atoms, basis set and orbitals. Pass one set
+/// of orbitals for restricted wavefunctions and alpha then beta orbitals
+/// for unrestricted ones.
+pub fn write_molden<P: AsRef<Path>>(
+    path: P,
+    title: &str,
+    basis_set: &Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    orbitals: &[MoldenOrbitals],
+) -> io::Result<()> {
+    let mut contents = format!("[Molden Format]\n[Title]\n{}\n[Atoms] AU\n", title);
+    for (i, (c, &z)) in atomcoords.iter().zip(atomnos).enumerate() {
+        contents.push_str(&format!(
+            "{:>2} {:5} {:3} {:20.12} {:20.12} {:20.12}\n",
+            elements::symbol(z),
+            i + 1,
+            z,
+            c[0],
+            c[1],
+            c[2]
+        ));
+    }
+
+    contents.push_str("[GTO]\n");
+    let shells = basis_set.shells();
+    for atom in 0..atomcoords.len() {
+        contents.push_str(&format!("{:5} 0\n", atom + 1));
+        for shell in shells.iter().filter(|shell| shell.atom == atom) {
+            contents.push_str(&format!(
+                " {} {:4} 1.00\n",
+                SHELL_LABELS[shell.angular_momentum],
+                shell.exponents.len()
+            ));
+            for (exponent, coef) in shell.exponents.iter().zip(&shell.coefficients) {
+                contents.push_str(&format!(
+                    "{} {}\n",
+                    fortran_real(*exponent, 10, 20),
+                    fortran_real(*coef, 10, 20)
+                ));
+            }
+        }
+        contents.push('\n');
+    }
+
+    contents.push_str("[MO]\n");
+    let T = transformation(basis_set);
+    let spins = if orbitals.len() == 1 {
+        ["Alpha"].iter()
+    } else {
+        ["Alpha", "Beta"].iter()
+    };
+    for (set, spin) in orbitals.iter().zip(spins) {
+        let C = T.dot(set.C);
+        for p in 0..C.shape()[1] {
+            contents.push_str(&format!(
+                " Sym= A\n Ene= {:.10}\n Spin= {}\n Occup= {:.6}\n",
+                set.energies[p], spin, set.occupations[p]
+            ));
+            for (mu, c) in C.column(p).iter().enumerate() {
+                contents.push_str(&format!("{:5} {:20.12}\n", mu + 1, c));
+            }
+        }
+    }
+    fs::write(path, contents)
+}
+
+/// Write the orbitals of a restricted SCF, doubly occupying the lowest
+/// `nocc`.
+pub fn write_rhf<P: AsRef<Path>>(
+    path: P,
+    basis_set: &Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    scf: &scf::RHFResult,
+) -> io::Result<()> {
+    let occupations = (0..scf.eps.len())
+        .map(|p| if p < scf.nocc { 2.0 } else { 0.0 })
+        .collect();
+    let orbitals = MoldenOrbitals {
+        C: &scf.C,
+        energies: &scf.eps,
+        occupations,
+    };
+    write_molden(path, "RHF", basis_set, atomcoords, atomnos, &[orbitals])
+}
+
+/// Write the alpha and beta orbitals of an unrestricted SCF.
+pub fn write_uhf<P: AsRef<Path>>(
+    path: P,
+    basis_set: &Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    scf: &scf::UHFResult,
+) -> io::Result<()> {
+    let occupied = |n: usize, nocc: usize| -> Array<f64, Ix1> {
+        (0..n).map(|p| if p < nocc { 1.0 } else { 0.0 }).collect()
+    };
+    let orbitals = [
+        MoldenOrbitals {
+            C: &scf.C_alpha,
+            energies: &scf.eps_alpha,
+            occupations: occupied(scf.eps_alpha.len(), scf.nalpha),
+        },
+        MoldenOrbitals {
+            C: &scf.C_beta,
+            energies: &scf.eps_beta,
+            occupations: occupied(scf.eps_beta.len(), scf.nbeta),
+        },
+    ];
+    write_molden(path, "UHF", basis_set, atomcoords, atomnos, &orbitals)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::f64::consts::PI;
+
+    const DF_JSON: &str = r#"{
+      "name": "d-and-f",
+      "description": "Contracted d and f shells for tests",
+      "elements": {
+        "8": {
+          "electron_shells": [
+            {
+              "function_type": "gto",
+              "region": "",
+              "angular_momentum": [2],
+              "exponents": ["1.6", "0.4"],
+              "coefficients": [["0.7", "0.5"]]
+            },
+            {
+              "function_type": "gto",
+              "region": "",
+              "angular_momentum": [3],
+              "exponents": ["1.1", "0.3"],
+              "coefficients": [["0.6", "0.6"]]
+            }
+          ]
+        }
+      }
+    }"#;
+
+    /// A Molden basis function as a reader would build it: every component
+    /// carries the x^l normalization, and the contraction is renormalized.
+    fn molden_function(exponents: &[f64], coefs: &[f64], powers: [usize; 3], r: [f64; 3]) -> f64 {
+        let l: usize = powers.iter().sum();
+        let primitive_norm = |a: f64| {
+            (2f64.powf(2.0 * l as f64 + 1.5) * a.powf(l as f64 + 1.5)
+                / ((1..2 * l).step_by(2).product::<usize>() as f64 * PI.powf(1.5)))
+            .sqrt()
+        };
+        let primitive = |a: f64, r: [f64; 3]| {
+            primitive_norm(a)
+                * r[0].powi(powers[0] as i32)
+                * r[1].powi(powers[1] as i32)
+                * r[2].powi(powers[2] as i32)
+                * (-a * (r[0] * r[0] + r[1] * r[1] + r[2] * r[2])).exp()
+        };
+        let mut self_overlap = 0.0;
+        for (a, ca) in exponents.iter().zip(coefs) {
+            for (b, cb) in exponents.iter().zip(coefs) {
+                self_overlap += ca * cb * (2.0 * (a * b).sqrt() / (a + b)).powf(l as f64 + 1.5);
+            }
+        }
+        let value: f64 = exponents
+            .iter()
+            .zip(coefs)
+            .map(|(&a, c)| c * primitive(a, r))
+            .sum();
+        value / self_overlap.sqrt()
+    }
+
+    #[test]
+    fn test_molden_coefficients_reproduce_orbitals() {
+        let atomcoords = vec![[0.0, 0.0, 0.0]];
+        let atomnos = vec![8];
+        let basis_set = Basis::from_json(&atomnos, &atomcoords, DF_JSON);
+        let nbasis = basis_set.nbasis();
+        assert_eq!(nbasis, 16);
+        let C: Array<f64, _> =
+            Array::from_shape_fn((nbasis, 1), |(mu, _)| (0.3 * mu as f64 + 0.1).sin());
+        let C_molden = transformation(&basis_set).dot(&C);
+
+        let points = [[0.3, -0.2, 0.5], [-0.7, 0.4, 0.1], [0.2, 0.9, -0.6]];
+        let (phi, _) = basis::evaluate(&basis_set, &points);
+        let ours = phi.dot(&C);
+        for (g, &r) in points.iter().enumerate() {
+            let mut theirs = 0.0;
+            let mut row = 0;
+            for (l, exponents, coefs) in [(2, [1.6, 0.4], [0.7, 0.5]), (3, [1.1, 0.3], [0.6, 0.6])]
+            {
+                for powers in molden_order(l) {
+                    theirs += C_molden[[row, 0]] * molden_function(&exponents, &coefs, powers, r);
+                    row += 1;
+                }
+            }
+            assert!((ours[[g, 0]] - theirs).abs() < 1.0e-12);
+        }
+    }
+
+    #[test]
+    fn test_write_molden_water() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let path = std::env::temp_dir().join("rchem_test_water.molden");
+        write_rhf(&path, &basis_set, &atomcoords, &atomnos, &result).unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert!(contents.starts_with("[Molden Format]"));
+        // O has 1s, 2s and 2p shells, and each H a 1s.
+        let shell_lines = contents
+            .lines()
+            .filter(|line| line.starts_with(" s ") || line.starts_with(" p "))
+            .count();
+        assert_eq!(shell_lines, 5);
+        // Primitives are in Fortran E20.10 format, as Molden expects.
+        let primitive = contents.lines().find(|line| line.contains("E+02")).unwrap();
+        assert_eq!(primitive, "    1.3070932000E+02     1.5432897000E-01");
+        assert_eq!(contents.matches("Ene=").count(), 7);
+        assert_eq!(contents.matches("Occup= 2.000000").count(), 5);
+    }
+}
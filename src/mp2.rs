@@ -0,0 +1,160 @@
+#![allow(non_snake_case)]
+
+//! Second-order Moller-Plesset perturbation theory on top of RHF and UHF
+//! references, including the spin-component-scaled (SCS) variant.
+
+use ndarray::{Array, Ix1, Ix4};
+
+use crate::basis;
+use crate::scf::{JKAlgorithm, RHFResult, UHFResult};
+use crate::transform::{split_occupied, AOIntegrals};
+
+/// Scaling factors for the opposite- and same-spin components in SCS-MP2,
+/// from S. Grimme, J. Chem. Phys. 118, 9095 (2003).
+pub const SCS_OPPOSITE_SPIN: f64 = 6.0 / 5.0;
+pub const SCS_SAME_SPIN: f64 = 1.0 / 3.0;
+
+#[derive(Clone, Debug)]
+pub struct MP2Result {
+    /// Correlation energy from pairs of electrons with the same spin.
+    pub same_spin: f64,
+    /// Correlation energy from pairs of electrons with opposite spins.
+    pub opposite_spin: f64,
+}
+
+impl MP2Result {
+    pub fn correlation_energy(&self) -> f64 {
+        self.same_spin + self.opposite_spin
+    }
+
+    pub fn scs_correlation_energy(&self) -> f64 {
+        SCS_SAME_SPIN * self.same_spin + SCS_OPPOSITE_SPIN * self.opposite_spin
+    }
+}
+
+/// Opposite-spin MP2 energy from the (ia|jb) integrals of two spins.
+fn opposite_spin_energy(
+    ovov: &Array<f64, Ix4>,
+    eps_occ_1: &Array<f64, Ix1>,
+    eps_virt_1: &Array<f64, Ix1>,
+    eps_occ_2: &Array<f64, Ix1>,
+    eps_virt_2: &Array<f64, Ix1>,
+) -> f64 {
+    let mut energy = 0.0;
+    for ((i, a, j, b), iajb) in ovov.indexed_iter() {
+        energy += iajb * iajb / (eps_occ_1[i] + eps_occ_2[j] - eps_virt_1[a] - eps_virt_2[b]);
+    }
+    energy
+}
+
+/// Same-spin MP2 energy from the (ia|jb) integrals of a single spin,
+/// 1/4 sum_ijab |<ij||ab>|^2 / D_ijab.
+fn same_spin_energy(
+    ovov: &Array<f64, Ix4>,
+    eps_occ: &Array<f64, Ix1>,
+    eps_virt: &Array<f64, Ix1>,
+) -> f64 {
+    let mut energy = 0.0;
+    for ((i, a, j, b), iajb) in ovov.indexed_iter() {
+        let antisym = iajb - ovov[[i, b, j, a]];
+        energy += 0.25 * antisym * antisym / (eps_occ[i] + eps_occ[j] - eps_virt[a] - eps_virt[b]);
+    }
+    energy
+}
+
+/// Closed-shell MP2 using the orbitals and orbital energies of a converged
+/// RHF calculation. With `JKAlgorithm::DensityFitting` this is RI-MP2.
+pub fn rhf_mp2(basis_set: &basis::Basis, scf: &RHFResult, algorithm: &JKAlgorithm) -> MP2Result {
+    let integrals = AOIntegrals::new(basis_set, algorithm);
+    let (C_occ, C_virt) = split_occupied(&scf.C, scf.nocc);
+    let ovov = integrals.transform(&C_occ, &C_virt, &C_occ, &C_virt);
+    let eps_occ = scf.eps.slice(ndarray::s![..scf.nocc]).to_owned();
+    let eps_virt = scf.eps.slice(ndarray::s![scf.nocc..]).to_owned();
+    // Both spins have the same orbitals, so the alpha-alpha and beta-beta
+    // contributions are equal.
+    MP2Result {
+        same_spin: 2.0 * same_spin_energy(&ovov, &eps_occ, &eps_virt),
+        opposite_spin: opposite_spin_energy(&ovov, &eps_occ, &eps_virt, &eps_occ, &eps_virt),
+    }
+}
+
+/// Unrestricted MP2 using the orbitals and orbital energies of a converged
+/// UHF calculation.
+pub fn uhf_mp2(basis_set: &basis::Basis, scf: &UHFResult, algorithm: &JKAlgorithm) -> MP2Result {
+    let integrals = AOIntegrals::new(basis_set, algorithm);
+    let (Ca_occ, Ca_virt) = split_occupied(&scf.C_alpha, scf.nalpha);
+    let (Cb_occ, Cb_virt) = split_occupied(&scf.C_beta, scf.nbeta);
+    let ea_occ = scf.eps_alpha.slice(ndarray::s![..scf.nalpha]).to_owned();
+    let ea_virt = scf.eps_alpha.slice(ndarray::s![scf.nalpha..]).to_owned();
+    let eb_occ = scf.eps_beta.slice(ndarray::s![..scf.nbeta]).to_owned();
+    let eb_virt = scf.eps_beta.slice(ndarray::s![scf.nbeta..]).to_owned();
+    let ovov_aa = integrals.transform(&Ca_occ, &Ca_virt, &Ca_occ, &Ca_virt);
+    let ovov_bb = integrals.transform(&Cb_occ, &Cb_virt, &Cb_occ, &Cb_virt);
+    let ovov_ab = integrals.transform(&Ca_occ, &Ca_virt, &Cb_occ, &Cb_virt);
+    MP2Result {
+        same_spin: same_spin_energy(&ovov_aa, &ea_occ, &ea_virt)
+            + same_spin_energy(&ovov_bb, &eb_occ, &eb_virt),
+        opposite_spin: opposite_spin_energy(&ovov_ab, &ea_occ, &ea_virt, &eb_occ, &eb_virt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scf;
+    use crate::testing;
+
+    fn options() -> scf::SCFOptions {
+        scf::SCFOptions {
+            thresh_e: 1.0e-12,
+            thresh_d: 1.0e-10,
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rhf_mp2_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options());
+        let mp2 = rhf_mp2(&basis_set, &scf, &scf::JKAlgorithm::InMemory);
+        // Crawford programming project #4
+        assert!((mp2.correlation_energy() - -0.049149636120).abs() < 1.0e-6);
+        let mp2_direct = rhf_mp2(&basis_set, &scf, &scf::JKAlgorithm::Direct);
+        assert_relative_eq!(
+            mp2.correlation_energy(),
+            mp2_direct.correlation_energy(),
+            max_relative = 1.0e-10
+        );
+    }
+
+    #[test]
+    fn test_scs_mp2_h2_sto3g() {
+        // H2 in STO-3G at 1.4 bohr, where Szabo and Ostlund (section 6.5)
+        // give E(2) = K12^2 / 2(e1 - e2) = -0.0132 hartree. With a single
+        // pair of electrons it is all opposite-spin, so SCS scales it by 6/5.
+        let atomcoords = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 1.4]];
+        let atomnos = vec![1, 1];
+        let basis_set = basis::Basis::from_json(&atomnos, &atomcoords, testing::STO3G_JSON);
+        let scf = scf::rhf(&basis_set, &atomcoords, &atomnos, 1, &options());
+        let mp2 = rhf_mp2(&basis_set, &scf, &scf::JKAlgorithm::InMemory);
+        assert!((mp2.opposite_spin - -0.0132).abs() < 1.0e-4);
+        assert!(mp2.same_spin.abs() < 1.0e-12);
+        assert!((mp2.scs_correlation_energy() - -0.0158).abs() < 1.0e-4);
+    }
+
+    #[test]
+    fn test_uhf_mp2_closed_shell_matches_rhf() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let rhf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options());
+        let uhf = scf::uhf(&basis_set, &atomcoords, &atomnos, 5, 5, &options());
+        let rmp2 = rhf_mp2(&basis_set, &rhf, &scf::JKAlgorithm::InMemory);
+        let ump2 = uhf_mp2(&basis_set, &uhf, &scf::JKAlgorithm::InMemory);
+        assert_relative_eq!(rmp2.same_spin, ump2.same_spin, max_relative = 1.0e-8);
+        assert_relative_eq!(
+            rmp2.opposite_spin,
+            ump2.opposite_spin,
+            max_relative = 1.0e-8
+        );
+    }
+}
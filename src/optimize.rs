@@ -0,0 +1,447 @@
+#![allow(non_snake_case)]
+
+//! Quasi-Newton geometry optimization with BFGS updates of a Lindh model
+//! Hessian, in either Cartesian or redundant internal coordinates.
+
+use ndarray::{Array, Ix1, Ix2};
+use ndarray_linalg::*;
+
+use crate::constants::BOHR_TO_ANGSTROM;
+use crate::elements;
+use crate::gradient::Gradient;
+use crate::internal::{self, Primitive};
+
+#[derive(Clone, Debug)]
+pub enum CoordinateSystem {
+    Cartesian,
+    RedundantInternal,
+}
+
+#[derive(Clone, Debug)]
+pub struct OptimizerOptions {
+    pub max_iterations: usize,
+    pub coordinates: CoordinateSystem,
+    /// Convergence thresholds in atomic units, defaulting to those used by
+    /// Gaussian.
+    pub thresh_max_force: f64,
+    pub thresh_rms_force: f64,
+    pub thresh_max_displacement: f64,
+    pub thresh_rms_displacement: f64,
+    /// Largest allowed step length in the optimization coordinates.
+    pub max_step: f64,
+    /// If set, every geometry visited is appended to this XYZ file (in
+    /// angstrom). The optimization carries on if the file cannot be
+    /// written, reporting why in `OptimizationResult::trajectory_error`.
+    pub trajectory: Option<String>,
+    pub verbose: bool,
+}
+
+impl Default for OptimizerOptions {
+    fn default() -> OptimizerOptions {
+        OptimizerOptions {
+            max_iterations: 100,
+            coordinates: CoordinateSystem::RedundantInternal,
+            thresh_max_force: 4.5e-4,
+            thresh_rms_force: 3.0e-4,
+            thresh_max_displacement: 1.8e-3,
+            thresh_rms_displacement: 1.2e-3,
+            max_step: 0.3,
+            trajectory: None,
+            verbose: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct OptimizationResult {
+    pub atomcoords: Vec<[f64; 3]>,
+    pub energy: f64,
+    pub gradient: Gradient,
+    /// Number of energy and gradient evaluations.
+    pub iterations: usize,
+    pub converged: bool,
+    /// Why the trajectory could not be written, if it failed.
+    pub trajectory_error: Option<String>,
+}
+
+// Parameters for the model Hessian of R. Lindh et al., Chem. Phys. Lett. 241,
+// 423 (1995), indexed by period (1, 2, 3+).
+const LINDH_ALPHA: [[f64; 3]; 3] = [
+    [1.0000, 0.3949, 0.3949],
+    [0.3949, 0.2800, 0.2800],
+    [0.3949, 0.2800, 0.2800],
+];
+const LINDH_R_REF: [[f64; 3]; 3] = [[1.35, 2.10, 2.53], [2.10, 2.87, 3.40], [2.53, 3.40, 3.40]];
+const LINDH_K_R: f64 = 0.45;
+const LINDH_K_PHI: f64 = 0.15;
+const LINDH_K_TAU: f64 = 0.005;
+
+fn lindh_rho(atomcoords: &[[f64; 3]], atomnos: &[u64], i: usize, j: usize) -> f64 {
+    let pi = elements::period(atomnos[i]).min(3) - 1;
+    let pj = elements::period(atomnos[j]).min(3) - 1;
+    let r = internal::distance(atomcoords, i, j);
+    (LINDH_ALPHA[pi][pj] * (LINDH_R_REF[pi][pj].powi(2) - r * r)).exp()
+}
+
+/// Diagonal model Hessian over the primitive internal coordinates.
+pub fn model_hessian(
+    primitives: &[Primitive],
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+) -> Array<f64, Ix2> {
+    let rho = |i, j| lindh_rho(atomcoords, atomnos, i, j);
+    let diagonal: Array<f64, Ix1> = primitives
+        .iter()
+        .map(|primitive| match *primitive {
+            Primitive::Bond(i, j) => LINDH_K_R * rho(i, j),
+            Primitive::Angle(i, j, k) => LINDH_K_PHI * rho(i, j) * rho(j, k),
+            Primitive::Dihedral(i, j, k, l) => LINDH_K_TAU * rho(i, j) * rho(j, k) * rho(k, l),
+        })
+        .collect();
+    Array::from_diag(&diagonal)
+}
+
+/// Moore-Penrose inverse of a symmetric matrix, discarding eigenvalues below
+/// `thresh`.
+fn pseudo_inverse(A: &Array<f64, Ix2>, thresh: f64) -> Array<f64, Ix2> {
+    let (eigvals, eigvecs) = A.eigh(UPLO::Upper).unwrap();
+    let inv = Array::from_diag(&eigvals.mapv(|x| if x.abs() > thresh { 1.0 / x } else { 0.0 }));
+    eigvecs.dot(&inv).dot(&eigvecs.t())
+}
+
+/// Newton step -H^{-1} g taken in the eigenbasis of H, using the magnitude of
+/// each eigenvalue so that the step always goes downhill, ignoring modes with
+/// (near-)zero curvature, and scaled back to `max_step` if necessary.
+fn newton_step(H: &Array<f64, Ix2>, g: &Array<f64, Ix1>, max_step: f64) -> Array<f64, Ix1> {
+    let (eigvals, eigvecs) = H.eigh(UPLO::Upper).unwrap();
+    let mut step: Array<f64, Ix1> = Array::zeros(g.len());
+    for (n, lambda) in eigvals.iter().enumerate() {
+        if lambda.abs() < 1.0e-6 {
+            continue;
+        }
+        let v = eigvecs.column(n);
+        step.scaled_add(-v.dot(g) / lambda.abs(), &v);
+    }
+    let length = step.dot(&step).sqrt();
+    if length > max_step {
+        step *= max_step / length;
+    }
+    step
+}
+
+/// BFGS update of the Hessian H given the step s and the change in gradient
+/// y. The update is skipped if it would not keep H positive definite.
+fn bfgs_update(H: &mut Array<f64, Ix2>, s: &Array<f64, Ix1>, y: &Array<f64, Ix1>) {
+    let ys = y.dot(s);
+    let Hs = H.dot(s);
+    let sHs = s.dot(&Hs);
+    if ys < 1.0e-10 || sHs < 1.0e-10 {
+        return;
+    }
+    let n = s.len();
+    for i in 0..n {
+        for j in 0..n {
+            H[[i, j]] += y[i] * y[j] / ys - Hs[i] * Hs[j] / sHs;
+        }
+    }
+}
+
+fn flatten(atomcoords: &[[f64; 3]]) -> Array<f64, Ix1> {
+    atomcoords.iter().flat_map(|x| x.iter().cloned()).collect()
+}
+
+fn unflatten(x: &Array<f64, Ix1>) -> Vec<[f64; 3]> {
+    x.as_slice()
+        .unwrap()
+        .chunks(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect()
+}
+
+/// Differences between two sets of internal coordinate values, accounting
+/// for the periodicity of dihedrals.
+fn internal_difference(
+    primitives: &[Primitive],
+    new: &Array<f64, Ix1>,
+    old: &Array<f64, Ix1>,
+) -> Array<f64, Ix1> {
+    primitives
+        .iter()
+        .enumerate()
+        .map(|(n, p)| p.difference(new[n], old[n]))
+        .collect()
+}
+
+/// Find Cartesian coordinates matching the internal coordinate step `dq`
+/// by iterating x_{k+1} = x_k + B^T (B B^T)^- (q_target - q(x_k)). If the
+/// iterations diverge, the first-order step is used instead.
+fn back_transform(
+    primitives: &[Primitive],
+    atomcoords: &[[f64; 3]],
+    dq: &Array<f64, Ix1>,
+) -> Vec<[f64; 3]> {
+    let q0 = internal::values(primitives, atomcoords);
+    let q_target = &q0 + dq;
+    let mut x = flatten(atomcoords);
+    let mut first_order: Option<Array<f64, Ix1>> = None;
+    let mut remaining = dq.clone();
+    for _ in 0..50 {
+        let coords = unflatten(&x);
+        let B = internal::wilson_B(primitives, &coords);
+        let G_inv = pseudo_inverse(&B.dot(&B.t()), 1.0e-8);
+        let dx = B.t().dot(&G_inv).dot(&remaining);
+        x += &dx;
+        if first_order.is_none() {
+            first_order = Some(x.clone());
+        }
+        let q = internal::values(primitives, &unflatten(&x));
+        let remaining_new = internal_difference(primitives, &q_target, &q);
+        let rms_dx = (dx.dot(&dx) / dx.len() as f64).sqrt();
+        if rms_dx < 1.0e-10 {
+            return unflatten(&x);
+        }
+        if remaining_new.dot(&remaining_new) > remaining.dot(&remaining) {
+            break;
+        }
+        remaining = remaining_new;
+    }
+    unflatten(&first_order.unwrap())
+}
+
+fn write_frame(
+    trajectory: &mut chemfiles::Trajectory,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+) -> Result<(), chemfiles::Error> {
+    let mut frame = chemfiles::Frame::new();
+    for (coords, atomno) in atomcoords.iter().zip(atomnos.iter()) {
+        let position = [
+            coords[0] * BOHR_TO_ANGSTROM,
+            coords[1] * BOHR_TO_ANGSTROM,
+            coords[2] * BOHR_TO_ANGSTROM,
+        ];
+        frame.add_atom(
+            &chemfiles::Atom::new(elements::symbol(*atomno)),
+            position,
+            None,
+        );
+    }
+    trajectory.write(&frame)
+}
+
+/// Minimize the energy with respect to the nuclear coordinates (in bohr).
+///
+/// `energy_and_gradient` is called once per iteration with the current
+/// geometry. Convergence requires that the maximum and RMS Cartesian force
+/// and the maximum and RMS Cartesian components of the next predicted step
+/// all fall below their thresholds.
+pub fn optimize<F>(
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    options: &OptimizerOptions,
+    mut energy_and_gradient: F,
+) -> OptimizationResult
+where
+    F: FnMut(&[[f64; 3]]) -> (f64, Gradient),
+{
+    let mut trajectory_error = None;
+    let mut trajectory = match &options.trajectory {
+        Some(path) => match chemfiles::Trajectory::open(path, 'w') {
+            Ok(trajectory) => Some(trajectory),
+            Err(error) => {
+                trajectory_error = Some(format!("{}: {}", path, error));
+                None
+            }
+        },
+        None => None,
+    };
+    let primitives = internal::redundant_internals(atomcoords, atomnos);
+    let mut coords = atomcoords.to_vec();
+    let mut H = match options.coordinates {
+        CoordinateSystem::Cartesian => {
+            let B = internal::wilson_B(&primitives, &coords);
+            B.t()
+                .dot(&model_hessian(&primitives, &coords, atomnos))
+                .dot(&B)
+        }
+        CoordinateSystem::RedundantInternal => model_hessian(&primitives, &coords, atomnos),
+    };
+    // Position and gradient in the optimization coordinates from the
+    // previous iteration, for the BFGS update.
+    let mut previous: Option<(Array<f64, Ix1>, Array<f64, Ix1>)> = None;
+    let mut iteration = 0;
+
+    loop {
+        if let Some(file) = trajectory.as_mut() {
+            if let Err(error) = write_frame(file, &coords, atomnos) {
+                let path = options.trajectory.as_deref().unwrap_or_default();
+                trajectory_error = Some(format!("{}: {}", path, error));
+                trajectory = None;
+            }
+        }
+        let (energy, gradient) = energy_and_gradient(&coords);
+        iteration += 1;
+        let g_x = flatten(
+            &gradient
+                .values
+                .outer_iter()
+                .map(|row| [row[0], row[1], row[2]])
+                .collect::<Vec<_>>(),
+        );
+
+        let new_coords = match options.coordinates {
+            CoordinateSystem::Cartesian => {
+                let x = flatten(&coords);
+                if let Some((x_old, g_old)) = previous.as_ref() {
+                    bfgs_update(&mut H, &(&x - x_old), &(&g_x - g_old));
+                }
+                let step = newton_step(&H, &g_x, options.max_step);
+                previous = Some((x.clone(), g_x));
+                unflatten(&(x + step))
+            }
+            CoordinateSystem::RedundantInternal => {
+                let q = internal::values(&primitives, &coords);
+                let B = internal::wilson_B(&primitives, &coords);
+                let G_inv = pseudo_inverse(&B.dot(&B.t()), 1.0e-8);
+                let g_q = G_inv.dot(&B).dot(&g_x);
+                if let Some((q_old, g_old)) = previous.as_ref() {
+                    let s = internal_difference(&primitives, &q, q_old);
+                    bfgs_update(&mut H, &s, &(&g_q - g_old));
+                }
+                // Project the Hessian onto the non-redundant space, and push
+                // the redundant combinations far away so they aren't stepped
+                // along.
+                let P = B.dot(&B.t()).dot(&G_inv);
+                let nq = primitives.len();
+                let H_proj = P.dot(&H).dot(&P) + 1000.0 * (Array::eye(nq) - &P);
+                let step = newton_step(&H_proj, &P.dot(&g_q), options.max_step);
+                previous = Some((q, g_q));
+                back_transform(&primitives, &coords, &step)
+            }
+        };
+
+        let displacement = flatten(&new_coords) - flatten(&coords);
+        let max_force = gradient.max_abs();
+        let rms_force = gradient.rms();
+        let max_displacement = displacement
+            .iter()
+            .fold(0.0, |acc: f64, x| acc.max(x.abs()));
+        let rms_displacement = (displacement.dot(&displacement) / displacement.len() as f64).sqrt();
+        if options.verbose {
+            println!(
+                "{:4} {:20.12} {:12.8} {:12.8} {:12.8} {:12.8}",
+                iteration, energy, max_force, rms_force, max_displacement, rms_displacement
+            );
+        }
+        let converged = max_force < options.thresh_max_force
+            && rms_force < options.thresh_rms_force
+            && max_displacement < options.thresh_max_displacement
+            && rms_displacement < options.thresh_rms_displacement;
+        if converged || iteration >= options.max_iterations {
+            if options.verbose && converged {
+                println!("Optimization converged!");
+            }
+            return OptimizationResult {
+                atomcoords: coords,
+                energy,
+                gradient,
+                iterations: iteration,
+                converged,
+                trajectory_error,
+            };
+        }
+        coords = new_coords;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gradient;
+    use crate::scf;
+    use crate::testing;
+
+    /// Harmonic bonds and angle for a triatomic, with its minimum at
+    /// r = 1.8 bohr and a 104.5 degree angle.
+    fn model_potential(atomcoords: &[[f64; 3]]) -> (f64, Gradient) {
+        let primitives = [
+            Primitive::Bond(0, 1),
+            Primitive::Bond(0, 2),
+            Primitive::Angle(1, 0, 2),
+        ];
+        let q0 = [1.8, 1.8, 104.5_f64.to_radians()];
+        let k = [0.5, 0.5, 0.2];
+        let q = internal::values(&primitives, atomcoords);
+        let B = internal::wilson_B(&primitives, atomcoords);
+        let mut energy = 0.0;
+        let mut g_q: Array<f64, Ix1> = Array::zeros(3);
+        for n in 0..3 {
+            energy += 0.5 * k[n] * (q[n] - q0[n]).powi(2);
+            g_q[n] = k[n] * (q[n] - q0[n]);
+        }
+        let values = B.t().dot(&g_q).into_shape_with_order((3, 3)).unwrap();
+        (energy, Gradient { values })
+    }
+
+    fn check_model_minimum(coordinates: CoordinateSystem) {
+        let (atomcoords, atomnos) = testing::water();
+        let options = OptimizerOptions {
+            coordinates,
+            ..Default::default()
+        };
+        let result = optimize(&atomcoords, &atomnos, &options, model_potential);
+        assert!(result.converged);
+        assert!(result.energy < 1.0e-6);
+        let r1 = internal::distance(&result.atomcoords, 0, 1);
+        let r2 = internal::distance(&result.atomcoords, 0, 2);
+        let theta = internal::angle(&result.atomcoords, 1, 0, 2).to_degrees();
+        assert!((r1 - 1.8).abs() < 1.0e-3);
+        assert!((r2 - 1.8).abs() < 1.0e-3);
+        assert!((theta - 104.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_model_potential_cartesian() {
+        check_model_minimum(CoordinateSystem::Cartesian);
+    }
+
+    #[test]
+    fn test_model_potential_internal() {
+        check_model_minimum(CoordinateSystem::RedundantInternal);
+    }
+
+    #[test]
+    fn test_unwritable_trajectory_is_reported() {
+        let (atomcoords, atomnos) = testing::water();
+        let path = std::env::temp_dir().join("rchem_missing_directory/opt.xyz");
+        let options = OptimizerOptions {
+            trajectory: Some(path.to_string_lossy().into_owned()),
+            ..Default::default()
+        };
+        let result = optimize(&atomcoords, &atomnos, &options, model_potential);
+        assert!(result.converged);
+        assert!(result.trajectory_error.is_some());
+    }
+
+    #[test]
+    fn test_optimize_water_sto3g() {
+        let (mut basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf_options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = optimize(&atomcoords, &atomnos, &Default::default(), |coords| {
+            basis_set.set_atomcoords(coords);
+            let scf = scf::rhf(&basis_set, coords, &atomnos, 5, &scf_options);
+            let grad = gradient::rhf_gradient(&basis_set, coords, &atomnos, &scf);
+            (scf.energy, grad)
+        });
+        assert!(result.converged);
+        // RHF/STO-3G equilibrium geometry: r(OH) = 0.989 angstrom and
+        // a(HOH) = 100.0 degrees.
+        let r = internal::distance(&result.atomcoords, 0, 1) * BOHR_TO_ANGSTROM;
+        let theta = internal::angle(&result.atomcoords, 1, 0, 2).to_degrees();
+        assert!((r - 0.989).abs() < 2.0e-3);
+        assert!((theta - 100.0).abs() < 0.3);
+    }
+}
@@ -0,0 +1,415 @@
+#![allow(non_snake_case)]
+
+//! Population analysis of SCF densities: Mulliken, Löwdin and natural
+//! charges and spin populations, the first two broken down by shell and by
+//! angular momentum, and Mayer and Wiberg bond orders.
+//!
+//! All of these take the alpha and beta densities in the form of
+//! `scf::build_density`, so for RHF both are the same matrix.
+
+use ndarray::{Array, Axis, Ix1, Ix2};
+use ndarray_linalg::*;
+
+use crate::basis::{self, Basis};
+use crate::scf;
+
+#[derive(Clone, Debug)]
+pub struct Population {
+    /// Number of electrons assigned to each basis function.
+    pub functions: Array<f64, Ix1>,
+    /// Excess of alpha over beta electrons on each basis function.
+    pub function_spins: Array<f64, Ix1>,
+    /// Nuclear charge minus the electrons assigned to each atom.
+    pub charges: Array<f64, Ix1>,
+    /// Excess of alpha over beta electrons on each atom.
+    pub spins: Array<f64, Ix1>,
+    /// Number of electrons in each shell, numbered as in
+    /// `Basis::function_shells`.
+    pub shells: Array<f64, Ix1>,
+    /// Number of electrons on each atom in functions of each angular
+    /// momentum, with shape `(natoms, lmax + 1)`.
+    pub angular_momenta: Array<f64, Ix2>,
+}
+
+impl Population {
+    fn new(
+        basis_set: &Basis,
+        atomnos: &[u64],
+        functions: Array<f64, Ix1>,
+        function_spins: Array<f64, Ix1>,
+    ) -> Population {
+        let atoms = basis_set.function_atoms();
+        let shells = basis_set.function_shells();
+        let angular_momenta = basis_set.function_angular_momenta();
+        let nshells = shells.iter().max().map_or(0, |&s| s + 1);
+        let lmax = angular_momenta.iter().max().cloned().unwrap_or(0);
+
+        let mut charges: Array<f64, _> = atomnos.iter().map(|&z| z as f64).collect();
+        let mut spins: Array<f64, _> = Array::zeros(atomnos.len());
+        let mut shell_populations: Array<f64, _> = Array::zeros(nshells);
+        let mut l_populations: Array<f64, _> = Array::zeros((atomnos.len(), lmax + 1));
+        for mu in 0..functions.len() {
+            charges[atoms[mu]] -= functions[mu];
+            spins[atoms[mu]] += function_spins[mu];
+            shell_populations[shells[mu]] += functions[mu];
+            l_populations[[atoms[mu], angular_momenta[mu]]] += functions[mu];
+        }
+        Population {
+            functions,
+            function_spins,
+            charges,
+            spins,
+            shells: shell_populations,
+            angular_momenta: l_populations,
+        }
+    }
+}
+
+/// S^{1/2}, the transformation to the Löwdin orthogonalized basis.
+fn overlap_sqrt(S: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+    S.dot(&scf::symmetric_orthogonalization(S))
+}
+
+/// Mulliken populations, which split the overlap population of each pair
+/// of basis functions equally between them: q_mu = (P S)_mu,mu.
+pub fn mulliken(
+    basis_set: &Basis,
+    atomnos: &[u64],
+    D_alpha: &Array<f64, Ix2>,
+    D_beta: &Array<f64, Ix2>,
+) -> Population {
+    let S = basis::S(basis_set);
+    let total = (D_alpha + D_beta).dot(&S).diag().to_owned();
+    let spin = (D_alpha - D_beta).dot(&S).diag().to_owned();
+    Population::new(basis_set, atomnos, total, spin)
+}
+
+/// Löwdin populations, the diagonal of the density in the symmetrically
+/// orthogonalized basis: q_mu = (S^{1/2} P S^{1/2})_mu,mu.
+pub fn lowdin(
+    basis_set: &Basis,
+    atomnos: &[u64],
+    D_alpha: &Array<f64, Ix2>,
+    D_beta: &Array<f64, Ix2>,
+) -> Population {
+    let S_half = overlap_sqrt(&basis::S(basis_set));
+    let transform = |D: &Array<f64, Ix2>| S_half.dot(D).dot(&S_half).diag().to_owned();
+    let total = transform(&(D_alpha + D_beta));
+    let spin = transform(&(D_alpha - D_beta));
+    Population::new(basis_set, atomnos, total, spin)
+}
+
+/// The natural atomic orbitals (NAOs) and the populations in them.
+#[derive(Clone, Debug)]
+pub struct NaturalPopulation {
+    /// NAO coefficients as columns, orthonormal in the AO metric.
+    pub C: Array<f64, Ix2>,
+    /// Atom of each NAO.
+    pub atoms: Vec<usize>,
+    /// Number of electrons in each NAO.
+    pub occupations: Array<f64, Ix1>,
+    /// Excess of alpha over beta electrons in each NAO.
+    pub orbital_spins: Array<f64, Ix1>,
+    /// Nuclear charge minus the electrons in the NAOs of each atom.
+    pub charges: Array<f64, Ix1>,
+    /// Excess of alpha over beta electrons on each atom.
+    pub spins: Array<f64, Ix1>,
+}
+
+/// Number of orbitals in the natural minimal basis of an element: the
+/// core and valence shells of the free atom.
+fn minimal_basis_size(atomno: u64) -> usize {
+    match atomno {
+        0..=2 => 1,
+        3..=10 => 5,
+        11..=18 => 9,
+        19..=20 => 13,
+        _ => 18,
+    }
+}
+
+/// Occupancy-weighted symmetric orthogonalization of the columns of `N`,
+/// N W (W N^T S N W)^{-1/2} for the diagonal weights W, which keeps the
+/// strongly occupied orbitals closest to their original form.
+fn occupancy_weighted_orthogonalization(
+    S: &Array<f64, Ix2>,
+    N: &Array<f64, Ix2>,
+    weights: &Array<f64, Ix1>,
+) -> Array<f64, Ix2> {
+    let NW = N * weights;
+    NW.dot(&scf::symmetric_orthogonalization(&NW.t().dot(S).dot(&NW)))
+}
+
+/// Natural population analysis: charges from the occupations of natural
+/// atomic orbitals, which, unlike Mulliken and Löwdin populations, barely
+/// depend on the basis set.
+///
+/// The pre-NAOs of each atom diagonalize its block of the density in its
+/// block of the overlap. The most strongly occupied of them, as many as the
+/// natural minimal basis of the element holds, are orthogonalized with
+/// occupancy weighting; the rest, the Rydberg orbitals, are Schmidt
+/// orthogonalized to them and then symmetrically among themselves. The
+/// density is finally diagonalized again within each atom to restore the
+/// natural character of the orbitals. Cartesian basis functions are used as
+/// they are, without averaging over the components of each shell.
+///
+/// A. E. Reed, R. B. Weinstock and F. Weinhold, J. Chem. Phys. 83, 735
+/// (1985).
+pub fn natural(
+    basis_set: &Basis,
+    atomnos: &[u64],
+    D_alpha: &Array<f64, Ix2>,
+    D_beta: &Array<f64, Ix2>,
+) -> NaturalPopulation {
+    let S = basis::S(basis_set);
+    let nbasis = basis_set.nbasis();
+    let function_atoms = basis_set.function_atoms();
+    let atom_functions: Vec<Vec<usize>> = (0..atomnos.len())
+        .map(|atom| {
+            (0..nbasis)
+                .filter(|&mu| function_atoms[mu] == atom)
+                .collect()
+        })
+        .collect();
+    // The density in covariant form, P = S D S, whose atomic blocks are the
+    // populations of the atom's functions.
+    let P = S.dot(&(D_alpha + D_beta)).dot(&S);
+
+    // Pre-NAOs, split into the natural minimal basis and Rydberg orbitals.
+    let (mut minimal, mut rydberg) = (Vec::new(), Vec::new());
+    let mut pre_naos: Array<f64, _> = Array::zeros((nbasis, 0));
+    let mut occupations = Vec::new();
+    for (atom, functions) in atom_functions.iter().enumerate() {
+        let S_A = S.select(Axis(0), functions).select(Axis(1), functions);
+        let P_A = P.select(Axis(0), functions).select(Axis(1), functions);
+        let X = scf::symmetric_orthogonalization(&S_A);
+        let (w, U) = X.dot(&P_A).dot(&X).eigh(UPLO::Upper).unwrap();
+        let N_A = X.dot(&U);
+        let nminimal = minimal_basis_size(atomnos[atom]).min(functions.len());
+        // eigh sorts the occupations in ascending order.
+        for k in (0..functions.len()).rev() {
+            let mut column: Array<f64, _> = Array::zeros(nbasis);
+            for (i, &mu) in functions.iter().enumerate() {
+                column[mu] = N_A[[i, k]];
+            }
+            let index = pre_naos.ncols();
+            pre_naos.push_column(column.view()).unwrap();
+            occupations.push(w[k].max(0.0));
+            if k + nminimal >= functions.len() {
+                minimal.push(index);
+            } else {
+                rydberg.push(index);
+            }
+        }
+    }
+    let occupations: Array<f64, _> = Array::from(occupations);
+    let owso = occupancy_weighted_orthogonalization(
+        &S,
+        &pre_naos.select(Axis(1), &minimal),
+        &occupations.select(Axis(0), &minimal),
+    );
+    let mut R = pre_naos.select(Axis(1), &rydberg);
+    R = &R - &owso.dot(&owso.t().dot(&S).dot(&R));
+    let R = R.dot(&scf::symmetric_orthogonalization(&R.t().dot(&S).dot(&R)));
+    let mut T: Array<f64, _> = Array::zeros((nbasis, nbasis));
+    for (k, &index) in minimal.iter().enumerate() {
+        T.column_mut(index).assign(&owso.column(k));
+    }
+    for (k, &index) in rydberg.iter().enumerate() {
+        T.column_mut(index).assign(&R.column(k));
+    }
+
+    // Restore the natural character within each atom.
+    let P_T = T.t().dot(&P).dot(&T);
+    let mut C: Array<f64, _> = Array::zeros((nbasis, nbasis));
+    let mut atoms = Vec::new();
+    let mut start = 0;
+    for (atom, functions) in atom_functions.iter().enumerate() {
+        let block: Vec<usize> = (start..start + functions.len()).collect();
+        let (_, U) = P_T
+            .select(Axis(0), &block)
+            .select(Axis(1), &block)
+            .eigh(UPLO::Upper)
+            .unwrap();
+        let naos = T.select(Axis(1), &block).dot(&U);
+        for k in 0..functions.len() {
+            C.column_mut(start + k)
+                .assign(&naos.column(functions.len() - 1 - k));
+            atoms.push(atom);
+        }
+        start += functions.len();
+    }
+
+    let populations = |D: &Array<f64, Ix2>| C.t().dot(&S).dot(D).dot(&S).dot(&C).diag().to_owned();
+    let occupations = populations(&(D_alpha + D_beta));
+    let orbital_spins = populations(&(D_alpha - D_beta));
+    let mut charges: Array<f64, _> = atomnos.iter().map(|&z| z as f64).collect();
+    let mut spins: Array<f64, _> = Array::zeros(atomnos.len());
+    for (k, &atom) in atoms.iter().enumerate() {
+        charges[atom] -= occupations[k];
+        spins[atom] += orbital_spins[k];
+    }
+    NaturalPopulation {
+        C,
+        atoms,
+        occupations,
+        orbital_spins,
+        charges,
+        spins,
+    }
+}
+
+/// B_AB = 2 sum_{mu in A, nu in B} sum_{sigma in a, b}
+/// (D^sigma M)_mu,nu (D^sigma M)_nu,mu, for the metric-weighted densities
+/// DM = D M.
+fn bond_orders(
+    basis_set: &Basis,
+    DM_alpha: &Array<f64, Ix2>,
+    DM_beta: &Array<f64, Ix2>,
+) -> Array<f64, Ix2> {
+    let atoms = basis_set.function_atoms();
+    let natoms = basis_set.natoms();
+    let mut orders: Array<f64, _> = Array::zeros((natoms, natoms));
+    for mu in 0..atoms.len() {
+        for nu in 0..atoms.len() {
+            if atoms[mu] != atoms[nu] {
+                orders[[atoms[mu], atoms[nu]]] += 2.0
+                    * (DM_alpha[[mu, nu]] * DM_alpha[[nu, mu]]
+                        + DM_beta[[mu, nu]] * DM_beta[[nu, mu]]);
+            }
+        }
+    }
+    orders
+}
+
+/// Mayer bond orders between every pair of atoms, with zeros on the
+/// diagonal.
+///
+/// I. Mayer, Chem. Phys. Lett. 97, 270 (1983).
+pub fn mayer_bond_orders(
+    basis_set: &Basis,
+    D_alpha: &Array<f64, Ix2>,
+    D_beta: &Array<f64, Ix2>,
+) -> Array<f64, Ix2> {
+    let S = basis::S(basis_set);
+    bond_orders(basis_set, &D_alpha.dot(&S), &D_beta.dot(&S))
+}
+
+/// Wiberg bond orders, evaluated in the Löwdin orthogonalized basis, with
+/// zeros on the diagonal.
+///
+/// K. B. Wiberg, Tetrahedron 24, 1083 (1968).
+pub fn wiberg_bond_orders(
+    basis_set: &Basis,
+    D_alpha: &Array<f64, Ix2>,
+    D_beta: &Array<f64, Ix2>,
+) -> Array<f64, Ix2> {
+    let S_half = overlap_sqrt(&basis::S(basis_set));
+    let transform = |D: &Array<f64, Ix2>| S_half.dot(D).dot(&S_half);
+    bond_orders(basis_set, &transform(D_alpha), &transform(D_beta))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_population_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let D = &result.D;
+
+        for population in [
+            mulliken(&basis_set, &atomnos, D, D),
+            lowdin(&basis_set, &atomnos, D, D),
+        ]
+        .iter()
+        {
+            // Neutral and closed shell, with a negative oxygen and two
+            // equivalent hydrogens.
+            assert!(population.charges.sum().abs() < 1.0e-10);
+            assert!(population.spins.iter().all(|s| s.abs() < 1.0e-10));
+            assert!(population.charges[0] < -0.1);
+            assert!((population.charges[1] - population.charges[2]).abs() < 1.0e-8);
+            // O 1s, O 2s, O 2p, H 1s, H 1s.
+            assert_eq!(population.shells.len(), 5);
+            assert!((population.shells.sum() - 10.0).abs() < 1.0e-10);
+            assert!((population.shells[0] - 2.0).abs() < 0.01);
+            let oxygen = population.angular_momenta.row(0).sum();
+            assert!((oxygen - (8.0 - population.charges[0])).abs() < 1.0e-10);
+        }
+
+        // Natural populations of orthonormal NAOs, with a doubly occupied
+        // oxygen core.
+        let natural = natural(&basis_set, &atomnos, D, D);
+        let overlap = natural.C.t().dot(&basis::S(&basis_set)).dot(&natural.C);
+        assert!((overlap - Array::<f64, _>::eye(basis_set.nbasis()))
+            .iter()
+            .all(|x| x.abs() < 1.0e-8));
+        assert!((natural.occupations.sum() - 10.0).abs() < 1.0e-8);
+        assert!((natural.occupations[0] - 2.0).abs() < 0.01);
+        assert!(natural.charges.sum().abs() < 1.0e-8);
+        assert!((natural.charges[1] - natural.charges[2]).abs() < 1.0e-8);
+        assert!(natural.charges[0] < -0.1);
+
+        // Crawford programming project #3.
+        let charges = mulliken(&basis_set, &atomnos, D, D).charges;
+        assert!((charges[0] - -0.253146).abs() < 1.0e-5);
+
+        for orders in [
+            mayer_bond_orders(&basis_set, D, D),
+            wiberg_bond_orders(&basis_set, D, D),
+        ]
+        .iter()
+        {
+            assert!(orders[[0, 1]] > 0.8 && orders[[0, 1]] < 1.1);
+            assert!((orders[[0, 1]] - orders[[1, 0]]).abs() < 1.0e-10);
+            assert!(orders[[1, 2]].abs() < 0.1);
+        }
+    }
+
+    #[test]
+    fn test_spin_populations_stretched_h2() {
+        // Broken-symmetry UHF for H2 far from equilibrium has an alpha
+        // electron on one atom and a beta electron on the other.
+        let atomcoords = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 6.0]];
+        let atomnos = vec![1, 1];
+        let basis_set = Basis::from_json(&atomnos, &atomcoords, testing::STO3G_JSON);
+        let S = basis::S(&basis_set);
+        let left: Array<f64, _> = Array::from_shape_vec((2, 1), vec![1.0, 0.0]).unwrap();
+        let right: Array<f64, _> = Array::from_shape_vec((2, 1), vec![0.0, 1.0]).unwrap();
+        let normalize = |c: Array<f64, Ix2>| {
+            let norm = c.t().dot(&S).dot(&c)[[0, 0]].sqrt();
+            c / norm
+        };
+        let densities = (
+            scf::build_density(&normalize(left), 1),
+            scf::build_density(&normalize(right), 1),
+        );
+        let uhf = scf::uhf_from_densities(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            1,
+            1,
+            densities,
+            &scf::SCFOptions {
+                jk: scf::JKAlgorithm::InMemory,
+                ..Default::default()
+            },
+        );
+        let population = mulliken(&basis_set, &atomnos, &uhf.D_alpha, &uhf.D_beta);
+        assert!(population.spins.sum().abs() < 1.0e-10);
+        assert!(population.spins[0].abs() > 0.95);
+        assert!((population.spins[0] + population.spins[1]).abs() < 1.0e-10);
+        let natural = natural(&basis_set, &atomnos, &uhf.D_alpha, &uhf.D_beta);
+        assert!((natural.spins[0] - population.spins[0]).abs() < 0.05);
+        let orders = mayer_bond_orders(&basis_set, &uhf.D_alpha, &uhf.D_beta);
+        assert!(orders[[0, 1]] < 0.1);
+    }
+}
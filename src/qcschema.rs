@@ -0,0 +1,698 @@
+#![allow(non_snake_case)]
+
+//! Running calculations described by MolSSI QCSchema `AtomicInput` JSON and
+//! reporting them as `AtomicResult` JSON, so that QCEngine-style workflow
+//! tools can drive rchem.
+//!
+//! The model method is `hf` (RHF, or UHF for open shells) or the name of a
+//! functional known to `xc::Functional::from_name` (closed-shell RKS),
+//! optionally followed by `-d3` or `-d3bj`. The basis is either a name for
+//! the Basis Set Exchange or an inline `qcschema_basis` object with
+//! Cartesian shells. Supported keywords are
+//!
+//! - `reference`: `rhf` or `uhf`
+//! - `maxiter`, `e_convergence`, `d_convergence`
+//! - `scf_type`: `direct`, `in_memory` or `cd` (with `cholesky_tolerance`)
+//! - `guess`: `core`, `sad`, `huckel` or `gwh`
+//! - `level_shift`
+//! - `dftd3_pars` and, for zero damping, `dftd3_r0ab`: paths to the dftd3
+//!   reference data files
+
+use std::collections::HashMap as Map;
+
+use ndarray::{Array, Ix2};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::basis::{Basis, Shell};
+use crate::dft;
+use crate::dispersion;
+use crate::elements;
+use crate::frequencies;
+use crate::gradient;
+use crate::guess::Guess;
+use crate::scf;
+use crate::xc::Functional;
+
+const BOHR_STEP: f64 = 0.005;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct AtomicInput {
+    pub molecule: Molecule,
+    pub driver: Driver,
+    pub model: Model,
+    #[serde(default)]
+    pub keywords: Map<String, Value>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Molecule {
+    pub symbols: Vec<String>,
+    /// Flattened Cartesian coordinates in bohr.
+    pub geometry: Vec<f64>,
+    #[serde(default)]
+    pub molecular_charge: f64,
+    #[serde(default = "singlet")]
+    pub molecular_multiplicity: usize,
+}
+
+fn singlet() -> usize {
+    1
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Driver {
+    Energy,
+    Gradient,
+    Hessian,
+    Properties,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Model {
+    pub method: String,
+    pub basis: BasisSpec,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum BasisSpec {
+    Name(String),
+    Schema(BasisSchema),
+}
+
+/// A `qcschema_basis` object, which names a set of shells for each center.
+#[derive(Clone, Debug, Deserialize)]
+pub struct BasisSchema {
+    pub name: String,
+    pub center_data: Map<String, CenterData>,
+    pub atom_map: Vec<String>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct CenterData {
+    pub electron_shells: Vec<ElectronShell>,
+}
+
+/// Exponents and coefficients may be given as numbers or, as the Basis Set
+/// Exchange does, as strings.
+#[derive(Clone, Debug, Deserialize)]
+pub struct ElectronShell {
+    pub harmonic_type: String,
+    pub angular_momentum: Vec<usize>,
+    pub exponents: Vec<Value>,
+    pub coefficients: Vec<Vec<Value>>,
+}
+
+/// The `error` field of a failed `AtomicResult`.
+#[derive(Clone, Debug)]
+pub struct QCSchemaError {
+    pub error_type: &'static str,
+    pub error_message: String,
+}
+
+impl QCSchemaError {
+    fn input(message: String) -> QCSchemaError {
+        QCSchemaError {
+            error_type: "input_error",
+            error_message: message,
+        }
+    }
+}
+
+/// What a successful calculation adds to the input to make an
+/// `AtomicResult`.
+#[derive(Clone, Debug)]
+pub struct Computed {
+    /// The energy, flattened gradient or flattened Hessian, depending on
+    /// the driver.
+    pub return_result: Value,
+    pub properties: serde_json::Map<String, Value>,
+}
+
+fn number(value: &Value) -> Result<f64, QCSchemaError> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().replace(['D', 'd'], "E").parse().ok(),
+        _ => None,
+    }
+    .ok_or_else(|| QCSchemaError::input(format!("expected a number, found {}", value)))
+}
+
+fn basis_from_schema(
+    schema: &BasisSchema,
+    atomcoords: &[[f64; 3]],
+) -> Result<Basis, QCSchemaError> {
+    if schema.atom_map.len() != atomcoords.len() {
+        return Err(QCSchemaError::input(
+            "atom_map does not match the molecule".to_string(),
+        ));
+    }
+    let mut shells = Vec::new();
+    for (atom, label) in schema.atom_map.iter().enumerate() {
+        let center = schema
+            .center_data
+            .get(label)
+            .ok_or_else(|| QCSchemaError::input(format!("no center_data for {}", label)))?;
+        for shell in &center.electron_shells {
+            if shell.harmonic_type != "cartesian" && shell.angular_momentum.iter().any(|&l| l > 1) {
+                return Err(QCSchemaError::input(
+                    "only Cartesian shells are supported".to_string(),
+                ));
+            }
+            let exponents = shell
+                .exponents
+                .iter()
+                .map(number)
+                .collect::<Result<Vec<_>, _>>()?;
+            // Fused shells such as SP have one row of coefficients per
+            // angular momentum.
+            for (k, row) in shell.coefficients.iter().enumerate() {
+                let angular_momentum = if shell.angular_momentum.len() == 1 {
+                    shell.angular_momentum[0]
+                } else {
+                    shell.angular_momentum[k]
+                };
+                shells.push(Shell {
+                    atom,
+                    angular_momentum,
+                    exponents: exponents.clone(),
+                    coefficients: row.iter().map(number).collect::<Result<_, _>>()?,
+                });
+            }
+        }
+    }
+    Ok(Basis::from_shells(&schema.name, atomcoords, &shells))
+}
+
+/// The electronic structure method part of `model.method`.
+enum Method {
+    HartreeFock,
+    KohnSham(Functional),
+}
+
+struct Settings {
+    method: Method,
+    unrestricted: bool,
+    scf: scf::SCFOptions,
+    dispersion: Option<(dispersion::Damping, dispersion::D3Reference)>,
+}
+
+fn settings(input: &AtomicInput, nalpha: usize, nbeta: usize) -> Result<Settings, QCSchemaError> {
+    let method_name = input.model.method.to_lowercase();
+    let (base, suffix) = match method_name.rsplit_once('-') {
+        Some((base, suffix)) if suffix == "d3" || suffix == "d3bj" => (base, Some(suffix)),
+        _ => (method_name.as_str(), None),
+    };
+    let method = match base {
+        "hf" | "scf" => Method::HartreeFock,
+        _ => Method::KohnSham(
+            Functional::from_name(base)
+                .ok_or_else(|| QCSchemaError::input(format!("unknown method {}", base)))?,
+        ),
+    };
+
+    let keyword = |name: &str| input.keywords.get(name);
+    let mut scf_options = scf::SCFOptions {
+        guess: Guess::SAD,
+        ..Default::default()
+    };
+    let mut unrestricted = nalpha != nbeta;
+    let mut pars = None;
+    let mut r0ab = None;
+    for (name, value) in &input.keywords {
+        let bad = || QCSchemaError::input(format!("bad value for keyword {}: {}", name, value));
+        let text = || value.as_str().map(|s| s.to_lowercase()).ok_or_else(bad);
+        match name.as_str() {
+            "reference" => {
+                unrestricted = match text()?.as_str() {
+                    "rhf" | "rks" => false,
+                    "uhf" | "uks" => true,
+                    _ => return Err(bad()),
+                }
+            }
+            "maxiter" => scf_options.max_iterations = value.as_u64().ok_or_else(bad)? as usize,
+            "e_convergence" => scf_options.thresh_e = number(value)?,
+            "d_convergence" => scf_options.thresh_d = number(value)?,
+            "scf_type" => {
+                scf_options.jk = match text()?.as_str() {
+                    "direct" => scf::JKAlgorithm::Direct,
+                    "in_memory" | "pk" => scf::JKAlgorithm::InMemory,
+                    "cd" => scf::JKAlgorithm::Cholesky(
+                        keyword("cholesky_tolerance")
+                            .map(number)
+                            .transpose()?
+                            .unwrap_or(1.0e-4),
+                    ),
+                    _ => return Err(bad()),
+                }
+            }
+            "cholesky_tolerance" => {}
+            "guess" => scf_options.guess = Guess::from_name(&text()?).ok_or_else(bad)?,
+            "level_shift" => scf_options.level_shift = number(value)?,
+            "dftd3_pars" => pars = Some(value.as_str().ok_or_else(bad)?),
+            "dftd3_r0ab" => r0ab = Some(value.as_str().ok_or_else(bad)?),
+            _ => return Err(QCSchemaError::input(format!("unknown keyword {}", name))),
+        }
+    }
+    if nalpha != nbeta && !unrestricted {
+        return Err(QCSchemaError::input(
+            "open-shell molecules need an unrestricted reference".to_string(),
+        ));
+    }
+    if unrestricted && matches!(method, Method::KohnSham(_)) {
+        return Err(QCSchemaError::input(
+            "Kohn-Sham is only available for closed shells".to_string(),
+        ));
+    }
+
+    let read = |path: Option<&str>, keyword: &str| {
+        let path = path.ok_or_else(|| {
+            QCSchemaError::input(format!("{} needs the keyword {}", method_name, keyword))
+        })?;
+        std::fs::read_to_string(path)
+            .map_err(|e| QCSchemaError::input(format!("could not read {}: {}", path, e)))
+    };
+    let no_parameters = || QCSchemaError::input(format!("no D3 parameters for {}", base));
+    let bad_data =
+        |e: std::io::Error| QCSchemaError::input(format!("bad D3 reference data: {}", e));
+    let dispersion = match suffix {
+        Some("d3bj") => Some((
+            dispersion::Damping::becke_johnson(base).ok_or_else(no_parameters)?,
+            dispersion::D3Reference::from_pars(&read(pars, "dftd3_pars")?).map_err(bad_data)?,
+        )),
+        Some(_) => {
+            let mut reference =
+                dispersion::D3Reference::from_pars(&read(pars, "dftd3_pars")?).map_err(bad_data)?;
+            reference
+                .set_r0ab(&read(r0ab, "dftd3_r0ab")?)
+                .map_err(bad_data)?;
+            Some((
+                dispersion::Damping::zero(base).ok_or_else(no_parameters)?,
+                reference,
+            ))
+        }
+        None => None,
+    };
+
+    Ok(Settings {
+        method,
+        unrestricted,
+        scf: scf_options,
+        dispersion,
+    })
+}
+
+/// One SCF at one geometry.
+struct Single {
+    energy: f64,
+    gradient: Option<Array<f64, Ix2>>,
+    properties: serde_json::Map<String, Value>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn single_point(
+    basis_set: &Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    nalpha: usize,
+    nbeta: usize,
+    settings: &Settings,
+    want_gradient: bool,
+) -> Result<Single, QCSchemaError> {
+    let not_converged = || QCSchemaError {
+        error_type: "convergence_error",
+        error_message: "the SCF did not converge".to_string(),
+    };
+    let (energy, e_nuc, D_total, nmo, iterations, grad) = if settings.unrestricted {
+        let result = scf::uhf(basis_set, atomcoords, atomnos, nalpha, nbeta, &settings.scf);
+        if !result.converged {
+            return Err(not_converged());
+        }
+        let grad = if want_gradient {
+            Some(gradient::uhf_gradient(basis_set, atomcoords, atomnos, &result).values)
+        } else {
+            None
+        };
+        let D_total = &result.D_alpha + &result.D_beta;
+        let nmo = result.C_alpha.shape()[1];
+        (
+            result.energy,
+            result.e_nuc,
+            D_total,
+            nmo,
+            result.iterations,
+            grad,
+        )
+    } else {
+        let result = match &settings.method {
+            Method::HartreeFock => scf::rhf(basis_set, atomcoords, atomnos, nalpha, &settings.scf),
+            Method::KohnSham(functional) => {
+                let options = dft::KSOptions {
+                    scf: settings.scf.clone(),
+                    functional: functional.clone(),
+                    ..Default::default()
+                };
+                dft::rks(basis_set, atomcoords, atomnos, nalpha, &options)
+            }
+        };
+        if !result.converged {
+            return Err(not_converged());
+        }
+        let grad = match (&settings.method, want_gradient) {
+            (Method::HartreeFock, true) => {
+                Some(gradient::rhf_gradient(basis_set, atomcoords, atomnos, &result).values)
+            }
+            (Method::KohnSham(_), true) => {
+                return Err(QCSchemaError::input(
+                    "analytic gradients are only available for Hartree-Fock".to_string(),
+                ))
+            }
+            _ => None,
+        };
+        let D_total = 2.0 * &result.D;
+        let nmo = result.C.shape()[1];
+        (
+            result.energy,
+            result.e_nuc,
+            D_total,
+            nmo,
+            result.iterations,
+            grad,
+        )
+    };
+
+    let H = scf::core_hamiltonian(basis_set, atomcoords, atomnos);
+    let one_electron = (&D_total * &H).sum();
+    let dipole = scf::dipole_moment(basis_set, atomcoords, atomnos, &D_total);
+
+    let mut properties = serde_json::Map::new();
+    properties.insert("calcinfo_natom".to_string(), json!(atomnos.len()));
+    properties.insert("calcinfo_nbasis".to_string(), json!(basis_set.nbasis()));
+    properties.insert("calcinfo_nmo".to_string(), json!(nmo));
+    properties.insert("calcinfo_nalpha".to_string(), json!(nalpha));
+    properties.insert("calcinfo_nbeta".to_string(), json!(nbeta));
+    properties.insert("nuclear_repulsion_energy".to_string(), json!(e_nuc));
+    properties.insert("scf_one_electron_energy".to_string(), json!(one_electron));
+    if let Method::HartreeFock = settings.method {
+        properties.insert(
+            "scf_two_electron_energy".to_string(),
+            json!(energy - e_nuc - one_electron),
+        );
+    }
+    properties.insert("scf_iterations".to_string(), json!(iterations));
+    properties.insert("scf_dipole_moment".to_string(), json!(dipole));
+
+    let mut total = energy;
+    let mut grad = grad;
+    if let Some((damping, reference)) = &settings.dispersion {
+        let d3 = dispersion::d3(atomcoords, atomnos, damping, reference);
+        properties.insert(
+            "scf_dispersion_correction_energy".to_string(),
+            json!(d3.energy),
+        );
+        total += d3.energy;
+        if let Some(grad) = grad.as_mut() {
+            *grad += &d3.gradient;
+        }
+    }
+    properties.insert("scf_total_energy".to_string(), json!(total));
+    properties.insert("return_energy".to_string(), json!(total));
+    if let Some(grad) = &grad {
+        let flat: Vec<f64> = grad.iter().cloned().collect();
+        properties.insert("scf_total_gradient".to_string(), json!(flat));
+    }
+    Ok(Single {
+        energy: total,
+        gradient: grad,
+        properties,
+    })
+}
+
+/// Run the calculation an `AtomicInput` describes, creating the basis with
+/// the Basis Set Exchange when it is given by name.
+pub fn compute(input: &AtomicInput) -> Result<Computed, QCSchemaError> {
+    let molecule = &input.molecule;
+    if molecule.geometry.len() != 3 * molecule.symbols.len() {
+        return Err(QCSchemaError::input(
+            "geometry does not match symbols".to_string(),
+        ));
+    }
+    let atomnos = molecule
+        .symbols
+        .iter()
+        .map(|s| {
+            elements::atomic_number(s)
+                .ok_or_else(|| QCSchemaError::input(format!("unknown element {}", s)))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let atomcoords: Vec<[f64; 3]> = molecule
+        .geometry
+        .chunks(3)
+        .map(|c| [c[0], c[1], c[2]])
+        .collect();
+    if molecule.molecular_charge.fract() != 0.0 {
+        return Err(QCSchemaError::input(
+            "fractional charges are not supported".to_string(),
+        ));
+    }
+    let nuclear_charge = atomnos.iter().sum::<u64>();
+    if molecule.molecular_charge > nuclear_charge as f64 {
+        return Err(QCSchemaError::input(format!(
+            "charge {} exceeds the nuclear charge {}",
+            molecule.molecular_charge, nuclear_charge
+        )));
+    }
+    let nelectrons = scf::nelectrons(&atomnos, molecule.molecular_charge as i64);
+    let unpaired = molecule.molecular_multiplicity.saturating_sub(1);
+    if molecule.molecular_multiplicity == 0
+        || unpaired > nelectrons
+        || !(nelectrons - unpaired).is_multiple_of(2)
+    {
+        return Err(QCSchemaError::input(format!(
+            "multiplicity {} is impossible with {} electrons",
+            molecule.molecular_multiplicity, nelectrons
+        )));
+    }
+    let nbeta = (nelectrons - unpaired) / 2;
+    let nalpha = nbeta + unpaired;
+    let settings = settings(input, nalpha, nbeta)?;
+    if let Some((damping, reference)) = &settings.dispersion {
+        reference
+            .check(&atomnos, damping)
+            .map_err(|e| QCSchemaError::input(e.to_string()))?;
+    }
+
+    let mut basis_set = match &input.model.basis {
+        BasisSpec::Name(name) => Basis::try_new(&atomnos, &atomcoords, name)
+            .map_err(|e| QCSchemaError::input(e.to_string()))?,
+        BasisSpec::Schema(schema) => basis_from_schema(schema, &atomcoords)?,
+    };
+
+    let want_gradient = input.driver != Driver::Energy && input.driver != Driver::Properties;
+    let single = single_point(
+        &basis_set,
+        &atomcoords,
+        &atomnos,
+        nalpha,
+        nbeta,
+        &settings,
+        want_gradient,
+    )?;
+    let return_result = match input.driver {
+        Driver::Energy | Driver::Properties => json!(single.energy),
+        Driver::Gradient => json!(single.gradient.unwrap().iter().collect::<Vec<_>>()),
+        Driver::Hessian => {
+            // Central differences of the analytic gradient, remembering the
+            // first failure since the gradient callback cannot return one.
+            let mut failure = None;
+            let hessian =
+                frequencies::finite_difference_hessian(&atomcoords, BOHR_STEP, |coords| {
+                    basis_set.set_atomcoords(coords);
+                    let values = match single_point(
+                        &basis_set, coords, &atomnos, nalpha, nbeta, &settings, true,
+                    ) {
+                        Ok(single) => single.gradient.unwrap(),
+                        Err(error) => {
+                            failure.get_or_insert(error);
+                            Array::zeros((coords.len(), 3))
+                        }
+                    };
+                    gradient::Gradient { values }
+                });
+            if let Some(error) = failure {
+                return Err(error);
+            }
+            json!(hessian.iter().collect::<Vec<_>>())
+        }
+    };
+    Ok(Computed {
+        return_result,
+        properties: single.properties,
+    })
+}
+
+/// Turn an `AtomicInput` document into an `AtomicResult` document. The
+/// input fields are echoed back, and problems with the input or the
+/// calculation are reported in the result rather than by panicking.
+pub fn run(input: &str) -> String {
+    let document: Value = match serde_json::from_str(input) {
+        Ok(document) => document,
+        Err(error) => {
+            let error = QCSchemaError::input(format!("invalid JSON: {}", error));
+            return serde_json::to_string_pretty(&result(json!({}), Err(error))).unwrap();
+        }
+    };
+    let computed = serde_json::from_value::<AtomicInput>(document.clone())
+        .map_err(|error| QCSchemaError::input(format!("invalid AtomicInput: {}", error)))
+        .and_then(|input| compute(&input));
+    serde_json::to_string_pretty(&result(document, computed)).unwrap()
+}
+
+fn result(input: Value, computed: Result<Computed, QCSchemaError>) -> Value {
+    let mut output = match input {
+        Value::Object(fields) => fields,
+        _ => serde_json::Map::new(),
+    };
+    output.insert("schema_name".to_string(), json!("qcschema_output"));
+    output.insert("schema_version".to_string(), json!(1));
+    output.insert(
+        "provenance".to_string(),
+        json!({
+            "creator": "rchem",
+            "version": env!("CARGO_PKG_VERSION"),
+            "routine": "rchem::qcschema::run",
+        }),
+    );
+    output.insert("stdout".to_string(), Value::Null);
+    output.insert("native_files".to_string(), json!({}));
+    match computed {
+        Ok(computed) => {
+            output.insert("success".to_string(), json!(true));
+            output.insert("return_result".to_string(), computed.return_result);
+            output.insert("properties".to_string(), Value::Object(computed.properties));
+            output.insert("error".to_string(), Value::Null);
+        }
+        Err(error) => {
+            output.insert("success".to_string(), json!(false));
+            output.insert("return_result".to_string(), Value::Null);
+            output.insert("properties".to_string(), json!({}));
+            output.insert(
+                "error".to_string(),
+                json!({
+                    "error_type": error.error_type,
+                    "error_message": error.error_message,
+                }),
+            );
+        }
+    }
+    Value::Object(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// The STO-3G fixture as a `qcschema_basis`, with SP shells fused as in
+    /// the Basis Set Exchange.
+    fn water_input(driver: &str, keywords: Value) -> Value {
+        let bse: Value = serde_json::from_str(testing::STO3G_JSON).unwrap();
+        let (atomcoords, _) = testing::water();
+        let shells = |z: &str| bse["elements"][z]["electron_shells"].clone();
+        let mut center_data = serde_json::Map::new();
+        for (label, z) in [("o", "8"), ("h", "1")] {
+            let mut shells = shells(z);
+            for shell in shells.as_array_mut().unwrap() {
+                shell["harmonic_type"] = json!("cartesian");
+            }
+            center_data.insert(label.to_string(), json!({ "electron_shells": shells }));
+        }
+        json!({
+            "schema_name": "qcschema_input",
+            "schema_version": 1,
+            "id": "water",
+            "molecule": {
+                "schema_name": "qcschema_molecule",
+                "schema_version": 2,
+                "symbols": ["O", "H", "H"],
+                "geometry": atomcoords.iter().flatten().collect::<Vec<_>>(),
+            },
+            "driver": driver,
+            "model": {
+                "method": "HF",
+                "basis": {
+                    "schema_name": "qcschema_basis",
+                    "schema_version": 1,
+                    "name": "STO-3G",
+                    "center_data": center_data,
+                    "atom_map": ["o", "h", "h"],
+                },
+            },
+            "keywords": keywords,
+        })
+    }
+
+    #[test]
+    fn test_qcschema_water_gradient() {
+        let input = water_input("gradient", json!({"scf_type": "in_memory"}));
+        let output: Value = serde_json::from_str(&run(&input.to_string())).unwrap();
+        assert_eq!(output["success"], json!(true), "{}", output["error"]);
+        assert_eq!(output["schema_name"], json!("qcschema_output"));
+        assert_eq!(output["id"], json!("water"));
+        assert_eq!(output["provenance"]["creator"], json!("rchem"));
+
+        let properties = &output["properties"];
+        let energy = properties["return_energy"].as_f64().unwrap();
+        assert!((energy - -74.942079928192).abs() < 1.0e-6);
+        assert_eq!(properties["calcinfo_nbasis"], json!(7));
+        assert_eq!(properties["calcinfo_nalpha"], json!(5));
+        let one_electron = properties["scf_one_electron_energy"].as_f64().unwrap();
+        let two_electron = properties["scf_two_electron_energy"].as_f64().unwrap();
+        let e_nuc = properties["nuclear_repulsion_energy"].as_f64().unwrap();
+        assert!((one_electron + two_electron + e_nuc - energy).abs() < 1.0e-10);
+
+        // The gradient of a symmetric molecule at any geometry sums to zero
+        // and has no out-of-plane component.
+        let gradient: Vec<f64> = output["return_result"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x.as_f64().unwrap())
+            .collect();
+        assert_eq!(gradient.len(), 9);
+        for k in 0..3 {
+            let total: f64 = (0..3).map(|atom| gradient[3 * atom + k]).sum();
+            assert!(total.abs() < 1.0e-8);
+            assert!(gradient[3 * k + 2].abs() < 1.0e-10);
+        }
+    }
+
+    #[test]
+    fn test_qcschema_errors_are_reported() {
+        let input = water_input("energy", json!({"not_a_keyword": 1}));
+        let output: Value = serde_json::from_str(&run(&input.to_string())).unwrap();
+        assert_eq!(output["success"], json!(false));
+        assert_eq!(output["error"]["error_type"], json!("input_error"));
+        assert_eq!(output["return_result"], Value::Null);
+
+        let mut input = water_input("energy", json!({}));
+        input["molecule"]["molecular_multiplicity"] = json!(2);
+        let output: Value = serde_json::from_str(&run(&input.to_string())).unwrap();
+        assert_eq!(output["success"], json!(false));
+
+        let mut input = water_input("energy", json!({}));
+        input["molecule"]["molecular_charge"] = json!(11);
+        let output: Value = serde_json::from_str(&run(&input.to_string())).unwrap();
+        assert_eq!(output["error"]["error_type"], json!("input_error"));
+
+        let mut input = water_input("energy", json!({}));
+        input["model"]["basis"] = json!("not-a-basis-set");
+        let output: Value = serde_json::from_str(&run(&input.to_string())).unwrap();
+        assert_eq!(output["error"]["error_type"], json!("input_error"));
+
+        let output: Value = serde_json::from_str(&run("{")).unwrap();
+        assert_eq!(output["error"]["error_type"], json!("input_error"));
+    }
+}
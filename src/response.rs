@@ -0,0 +1,401 @@
+#![allow(non_snake_case)]
+
+//! Linear response of a closed-shell RHF reference: RPA/TDHF excitation
+//! energies and coupled-perturbed Hartree-Fock (CPHF) dipole
+//! polarizabilities.
+//!
+//! Both are built on the electronic Hessian blocks
+//!
+//! A_ia,jb = (e_a - e_i) d_ij d_ab + 2 (ia|jb) - (ij|ab)
+//! B_ia,jb = 2 (ia|jb) - (ib|ja)
+//!
+//! (singlet spin adaptation), whose action on a trial vector is formed in
+//! the AO basis from J and K of the corresponding response density.
+
+use ndarray::{Array, Axis, Ix1, Ix2};
+use ndarray_linalg::*;
+
+use crate::basis;
+use crate::diis::DIIS;
+use crate::scf::{JKAlgorithm, JKEngine, RHFResult};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Spin {
+    Singlet,
+    Triplet,
+}
+
+/// The orbital Hessian of an RHF wavefunction, applied through J/K builds.
+pub(crate) struct OrbitalHessian<'a> {
+    jk: JKEngine<'a>,
+    C_occ: Array<f64, Ix2>,
+    C_virt: Array<f64, Ix2>,
+    /// e_a - e_i, with shape `(nocc, nvirt)`
+    pub(crate) delta: Array<f64, Ix2>,
+}
+
+impl<'a> OrbitalHessian<'a> {
+    pub(crate) fn new(
+        basis_set: &'a basis::Basis,
+        scf: &RHFResult,
+        algorithm: &JKAlgorithm,
+    ) -> Self {
+        let nocc = scf.nocc;
+        let nmo = scf.C.shape()[1];
+        let delta =
+            Array::from_shape_fn((nocc, nmo - nocc), |(i, a)| scf.eps[nocc + a] - scf.eps[i]);
+        OrbitalHessian {
+            jk: JKEngine::new(basis_set, algorithm),
+            C_occ: scf.C.slice(ndarray::s![.., ..nocc]).to_owned(),
+            C_virt: scf.C.slice(ndarray::s![.., nocc..]).to_owned(),
+            delta,
+        }
+    }
+
+    pub(crate) fn nocc(&self) -> usize {
+        self.delta.shape()[0]
+    }
+
+    pub(crate) fn nvirt(&self) -> usize {
+        self.delta.shape()[1]
+    }
+
+    /// The AO density C_occ x C_virt^T of a trial vector.
+    fn density(&self, x: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+        self.C_occ.dot(x).dot(&self.C_virt.t())
+    }
+
+    /// The two-electron part of (A + B) x, from the symmetric density
+    /// d + d^T.
+    pub(crate) fn coupling_plus(&self, x: &Array<f64, Ix2>, spin: Spin) -> Array<f64, Ix2> {
+        let d = self.density(x);
+        let (J, K) = self.jk.build(&(&d + &d.t()));
+        let G = match spin {
+            Spin::Singlet => 2.0 * J - K,
+            Spin::Triplet => -K,
+        };
+        self.C_occ.t().dot(&G).dot(&self.C_virt)
+    }
+
+    /// The two-electron part of (A - B) x, from the antisymmetric density
+    /// d - d^T, which only has an exchange contribution for either spin.
+    pub(crate) fn coupling_minus(&self, x: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+        let d = self.density(x);
+        let (_, K) = self.jk.build_general(&(&d - &d.t()));
+        -self.C_occ.t().dot(&K).dot(&self.C_virt)
+    }
+
+    /// (A + B) and (A - B) as explicit matrices, one column per trial unit
+    /// vector.
+    fn explicit(&self, spin: Spin) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+        let (nocc, nvirt) = (self.nocc(), self.nvirt());
+        let dim = nocc * nvirt;
+        let mut plus: Array<f64, _> = Array::zeros((dim, dim));
+        let mut minus: Array<f64, _> = Array::zeros((dim, dim));
+        for jb in 0..dim {
+            let mut x: Array<f64, _> = Array::zeros((nocc, nvirt));
+            x[[jb / nvirt, jb % nvirt]] = 1.0;
+            let delta_x = &self.delta * &x;
+            let column_plus = &delta_x + &self.coupling_plus(&x, spin);
+            let column_minus = &delta_x + &self.coupling_minus(&x);
+            plus.column_mut(jb)
+                .assign(&column_plus.into_shape_with_order(dim).unwrap());
+            minus
+                .column_mut(jb)
+                .assign(&column_minus.into_shape_with_order(dim).unwrap());
+        }
+        (plus, minus)
+    }
+}
+
+/// The MO dipole integrals <i|r|a> about the origin.
+fn dipole_ov(basis_set: &basis::Basis, hessian: &OrbitalHessian) -> Vec<Array<f64, Ix2>> {
+    basis::dipole(basis_set, &[0.0, 0.0, 0.0])
+        .iter()
+        .map(|M| hessian.C_occ.t().dot(M).dot(&hessian.C_virt))
+        .collect()
+}
+
+#[derive(Clone, Debug)]
+pub struct RPAState {
+    /// Excitation energy in hartree.
+    pub excitation_energy: f64,
+    /// <0|r|n>, zero for triplets.
+    pub transition_dipole: [f64; 3],
+    pub oscillator_strength: f64,
+    /// Excitation and de-excitation amplitudes, with shape `(nocc, nvirt)`,
+    /// normalized so that X.X - Y.Y = 1.
+    pub X: Array<f64, Ix2>,
+    pub Y: Array<f64, Ix2>,
+}
+
+#[derive(Clone, Debug)]
+pub struct RPAResult {
+    pub singlets: Vec<RPAState>,
+    pub triplets: Vec<RPAState>,
+}
+
+/// RPA (TDHF) excitation energies from the Hermitian form of the response
+/// equations,
+///
+/// (A - B)^{1/2} (A + B) (A - B)^{1/2} T = w^2 T,
+///
+/// with X + Y = (A - B)^{1/2} T / sqrt(w) and X - Y = (A - B)^{-1/2} T sqrt(w).
+/// All states are found by full diagonalization.
+pub fn rpa(basis_set: &basis::Basis, scf: &RHFResult, algorithm: &JKAlgorithm) -> RPAResult {
+    let hessian = OrbitalHessian::new(basis_set, scf, algorithm);
+    let (nocc, nvirt) = (hessian.nocc(), hessian.nvirt());
+    let dipole = dipole_ov(basis_set, &hessian);
+
+    let states = |spin: Spin| -> Vec<RPAState> {
+        let (plus, minus) = hessian.explicit(spin);
+        let (minus_eigvals, minus_eigvecs) = minus.eigh(UPLO::Upper).unwrap();
+        let sqrt_minus = minus_eigvecs
+            .dot(&Array::from_diag(&minus_eigvals.mapv(f64::sqrt)))
+            .dot(&minus_eigvecs.t());
+        let inv_sqrt_minus = minus_eigvecs
+            .dot(&Array::from_diag(&minus_eigvals.mapv(|x| 1.0 / x.sqrt())))
+            .dot(&minus_eigvecs.t());
+        let (omega_squared, T) = sqrt_minus
+            .dot(&plus)
+            .dot(&sqrt_minus)
+            .eigh(UPLO::Upper)
+            .unwrap();
+        omega_squared
+            .iter()
+            .zip(T.axis_iter(Axis(1)))
+            .map(|(&w2, t)| {
+                let omega = w2.sqrt();
+                let X_plus_Y = (sqrt_minus.dot(&t) / omega.sqrt())
+                    .into_shape_with_order((nocc, nvirt))
+                    .unwrap();
+                let X_minus_Y = (inv_sqrt_minus.dot(&t) * omega.sqrt())
+                    .into_shape_with_order((nocc, nvirt))
+                    .unwrap();
+                let mut transition_dipole = [0.0; 3];
+                if spin == Spin::Singlet {
+                    for k in 0..3 {
+                        transition_dipole[k] = 2.0f64.sqrt() * (&dipole[k] * &X_plus_Y).sum();
+                    }
+                }
+                let oscillator_strength =
+                    2.0 / 3.0 * omega * transition_dipole.iter().map(|d| d * d).sum::<f64>();
+                RPAState {
+                    excitation_energy: omega,
+                    transition_dipole,
+                    oscillator_strength,
+                    X: 0.5 * (&X_plus_Y + &X_minus_Y),
+                    Y: 0.5 * (&X_plus_Y - &X_minus_Y),
+                }
+            })
+            .collect()
+    };
+
+    RPAResult {
+        singlets: states(Spin::Singlet),
+        triplets: states(Spin::Triplet),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CPHFOptions {
+    pub max_iterations: usize,
+    /// Convergence threshold on the largest element of the residual.
+    pub thresh_residual: f64,
+    pub diis_size: usize,
+    pub verbose: bool,
+}
+
+impl Default for CPHFOptions {
+    fn default() -> CPHFOptions {
+        CPHFOptions {
+            max_iterations: 100,
+            thresh_residual: 1.0e-8,
+            diis_size: 8,
+            verbose: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PolarizabilityResult {
+    /// The frequency of the applied field in hartree.
+    pub frequency: f64,
+    /// alpha_xy in atomic units.
+    pub alpha: Array<f64, Ix2>,
+    /// The converged response vectors X + Y for each Cartesian direction.
+    pub responses: Vec<Array<f64, Ix2>>,
+    pub iterations: usize,
+    pub converged: bool,
+}
+
+impl PolarizabilityResult {
+    /// The isotropic polarizability, one third of the trace.
+    pub fn isotropic(&self) -> f64 {
+        self.alpha.diag().sum() / 3.0
+    }
+}
+
+/// Solve the (frequency-dependent) CPHF equations for a perturbation with MO
+/// integrals `rhs`,
+///
+/// (A + B) P - w Q = rhs
+/// (A - B) Q - w P = 0,
+///
+/// where P = X + Y and Q = X - Y, by Jacobi iterations on the orbital energy
+/// differences accelerated with DIIS.
+fn solve_cphf(
+    hessian: &OrbitalHessian,
+    rhs: &Array<f64, Ix2>,
+    frequency: f64,
+    options: &CPHFOptions,
+) -> (Array<f64, Ix2>, usize, bool) {
+    let (nocc, nvirt) = (hessian.nocc(), hessian.nvirt());
+    let dim = nocc * nvirt;
+    let denominator = hessian.delta.mapv(|d| d * d - frequency * frequency);
+    // Exact when the two-electron coupling vanishes.
+    let uncoupled = |r1: &Array<f64, Ix2>, r2: &Array<f64, Ix2>| {
+        let P = (&hessian.delta * r1 + frequency * r2) / &denominator;
+        let Q = (frequency * r1 + &hessian.delta * r2) / &denominator;
+        (P, Q)
+    };
+
+    let zeros: Array<f64, _> = Array::zeros((nocc, nvirt));
+    let (mut P, mut Q) = uncoupled(rhs, &zeros);
+    let mut diis = DIIS::new(options.diis_size);
+    let mut iteration = 0;
+    let mut converged = false;
+    while iteration < options.max_iterations {
+        iteration += 1;
+        let G_plus = hessian.coupling_plus(&P, Spin::Singlet);
+        let G_minus = if frequency == 0.0 {
+            zeros.clone()
+        } else {
+            hessian.coupling_minus(&Q)
+        };
+        let (P_new, Q_new) = uncoupled(&(rhs - &G_plus), &(-G_minus));
+        let error_P = &P_new - &P;
+        let error_Q = &Q_new - &Q;
+        let max_error = error_P
+            .iter()
+            .chain(error_Q.iter())
+            .fold(0.0f64, |acc, e| acc.max(e.abs()));
+        if options.verbose {
+            println!("{:4} {:20.12e}", iteration, max_error);
+        }
+        if max_error < options.thresh_residual {
+            P = P_new;
+            converged = true;
+            break;
+        }
+        let flatten = |a: Array<f64, Ix2>, b: Array<f64, Ix2>| -> Array<f64, Ix1> {
+            let mut v = a.into_shape_with_order(dim).unwrap().to_vec();
+            v.extend(b.iter());
+            Array::from(v)
+        };
+        diis.push(flatten(P_new, Q_new), flatten(error_P, error_Q));
+        let extrapolated = diis.extrapolate();
+        P = extrapolated
+            .slice(ndarray::s![..dim])
+            .to_owned()
+            .into_shape_with_order((nocc, nvirt))
+            .unwrap();
+        Q = extrapolated
+            .slice(ndarray::s![dim..])
+            .to_owned()
+            .into_shape_with_order((nocc, nvirt))
+            .unwrap();
+    }
+    (P, iteration, converged)
+}
+
+/// The dipole polarizability at the given frequency (zero for the static
+/// polarizability), alpha_xy(w) = 4 mu_x . P_y, where P_y solves the CPHF
+/// equations with the dipole integrals <i|y|a> as the perturbation.
+pub fn polarizability(
+    basis_set: &basis::Basis,
+    scf: &RHFResult,
+    frequency: f64,
+    algorithm: &JKAlgorithm,
+    options: &CPHFOptions,
+) -> PolarizabilityResult {
+    let hessian = OrbitalHessian::new(basis_set, scf, algorithm);
+    let dipole = dipole_ov(basis_set, &hessian);
+    let mut iterations = 0;
+    let mut converged = true;
+    let responses: Vec<Array<f64, Ix2>> = dipole
+        .iter()
+        .map(|mu| {
+            let (P, niter, conv) = solve_cphf(&hessian, mu, frequency, options);
+            iterations = iterations.max(niter);
+            converged &= conv;
+            P
+        })
+        .collect();
+    let alpha = Array::from_shape_fn((3, 3), |(x, y)| 4.0 * (&dipole[x] * &responses[y]).sum());
+    PolarizabilityResult {
+        frequency,
+        alpha,
+        responses,
+        iterations,
+        converged,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scf;
+    use crate::testing;
+
+    #[test]
+    fn test_rpa_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = scf::SCFOptions {
+            thresh_e: 1.0e-12,
+            thresh_d: 1.0e-10,
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let scf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let result = rpa(&basis_set, &scf, &JKAlgorithm::InMemory);
+        // Crawford programming project #12
+        assert!((result.triplets[0].excitation_energy - 0.2851637170).abs() < 1.0e-6);
+        assert!((result.triplets[2].excitation_energy - 0.3526266606).abs() < 1.0e-6);
+        assert!((result.singlets[0].excitation_energy - 0.3547782530).abs() < 1.0e-6);
+        for state in result.singlets.iter() {
+            let norm = (&state.X * &state.X).sum() - (&state.Y * &state.Y).sum();
+            assert!((norm - 1.0).abs() < 1.0e-10);
+        }
+
+        // The CPHF polarizability agrees with the sum over the RPA states.
+        let sum_over_states = |frequency: f64| {
+            Array::from_shape_fn((3, 3), |(x, y)| {
+                result
+                    .singlets
+                    .iter()
+                    .map(|s| {
+                        let w = s.excitation_energy;
+                        2.0 * w * s.transition_dipole[x] * s.transition_dipole[y]
+                            / (w * w - frequency * frequency)
+                    })
+                    .sum::<f64>()
+            })
+        };
+        for &frequency in [0.0, 0.1].iter() {
+            let alpha = polarizability(
+                &basis_set,
+                &scf,
+                frequency,
+                &JKAlgorithm::InMemory,
+                &Default::default(),
+            );
+            assert!(alpha.converged);
+            let reference = sum_over_states(frequency);
+            for (a, b) in alpha.alpha.iter().zip(reference.iter()) {
+                assert!((a - b).abs() < 1.0e-6);
+            }
+            assert!(alpha.isotropic() > 0.0);
+        }
+    }
+}
@@ -0,0 +1,890 @@
+#![allow(non_snake_case)]
+
+use ndarray::{Array, Axis, Ix1, Ix2, Ix4, Slice};
+use ndarray_linalg::*;
+
+use crate::basis;
+use crate::checkpoint::{self, Checkpoint, CheckpointOptions, SCFKind};
+use crate::cholesky;
+use crate::df;
+use crate::diis::DIIS;
+use crate::guess::{self, Guess};
+use crate::soscf::{self, SOSCFOptions};
+use crate::symmetry::{self, SymmetryAdaptation};
+
+/// How the Coulomb (J) and exchange (K) matrices are formed.
+#[derive(Clone, Debug)]
+pub enum JKAlgorithm {
+    /// Recompute the two-electron integrals every time J and K are needed.
+    Direct,
+    /// Compute the two-electron integrals once and keep them in memory.
+    InMemory,
+    /// Approximate the two-electron integrals by density fitting in the
+    /// given auxiliary basis.
+    DensityFitting(basis::Basis),
+    /// Approximate the two-electron integrals by their pivoted Cholesky
+    /// decomposition to within the given threshold.
+    Cholesky(f64),
+}
+
+pub enum JKEngine<'a> {
+    Direct(&'a basis::Basis),
+    InMemory(Array<f64, Ix4>),
+    /// Density-fitted or Cholesky three-index integrals.
+    ThreeIndex(df::DFTensor),
+}
+
+impl<'a> JKEngine<'a> {
+    pub fn new(basis_set: &'a basis::Basis, algorithm: &JKAlgorithm) -> JKEngine<'a> {
+        match algorithm {
+            JKAlgorithm::Direct => JKEngine::Direct(basis_set),
+            JKAlgorithm::InMemory => JKEngine::InMemory(basis::build_I(basis_set)),
+            JKAlgorithm::DensityFitting(aux) => {
+                JKEngine::ThreeIndex(df::DFTensor::new(basis_set, aux))
+            }
+            JKAlgorithm::Cholesky(threshold) => {
+                JKEngine::ThreeIndex(cholesky::cholesky_eri(basis_set, *threshold))
+            }
+        }
+    }
+
+    /// Build J and K for a symmetric density matrix.
+    pub fn build(&self, D: &Array<f64, Ix2>) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+        match self {
+            JKEngine::Direct(basis_set) => {
+                let nbasis = D.shape()[0];
+                let mut J: Array<f64, _> = Array::zeros((nbasis, nbasis));
+                let mut K: Array<f64, _> = Array::zeros((nbasis, nbasis));
+                basis::JK_direct(&mut J, &mut K, basis_set, D);
+                (J, K)
+            }
+            JKEngine::InMemory(I) => basis::JK_inmem(I, D),
+            JKEngine::ThreeIndex(df) => df.JK(D),
+        }
+    }
+
+    /// Build J and K for a density matrix that need not be symmetric, as
+    /// needed for response and transition densities.
+    pub fn build_general(&self, D: &Array<f64, Ix2>) -> (Array<f64, Ix2>, Array<f64, Ix2>) {
+        match self {
+            // The direct build makes no use of the symmetry of D.
+            JKEngine::Direct(_) => self.build(D),
+            JKEngine::InMemory(I) => basis::JK_inmem_general(I, D),
+            JKEngine::ThreeIndex(df) => df.JK(D),
+        }
+    }
+}
+
+/// How fractional occupations are assigned from the orbital energies, with
+/// the Fermi level chosen to hold the right number of electrons.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Smearing {
+    /// n_i = 1 / (1 + exp((e_i - mu) / kT)), with kT in hartree.
+    FermiDirac(f64),
+    /// n_i = erfc((e_i - mu) / sigma) / 2, with the width sigma in hartree.
+    Gaussian(f64),
+}
+
+#[derive(Clone, Debug)]
+pub struct SCFOptions {
+    pub max_iterations: usize,
+    /// Convergence threshold on the change in energy between iterations.
+    pub thresh_e: f64,
+    /// Convergence threshold on the RMS change in the density matrix between
+    /// iterations.
+    pub thresh_d: f64,
+    pub jk: JKAlgorithm,
+    pub guess: Guess,
+    /// Number of Fock matrices kept for DIIS extrapolation, with the
+    /// orbital gradient X^T (FDS - SDF) X as the error; 0 turns DIIS off.
+    pub diis_size: usize,
+    /// Raise the virtual orbitals by this much (in hartree) before
+    /// diagonalizing the Fock matrix. The final orbitals are those of the
+    /// unshifted Fock matrix.
+    pub level_shift: f64,
+    /// Fraction of the previous density mixed into the new one at the first
+    /// iteration.
+    pub damping: f64,
+    /// The damping fraction decreases linearly to zero over this many
+    /// iterations.
+    pub damping_iterations: usize,
+    pub smearing: Option<Smearing>,
+    /// Let RHF and UHF hand over to the second-order optimizer in `soscf`
+    /// when the RMS of the DIIS error vector, X^T (FDS - SDF) X for every
+    /// spin, stalls. The same error is watched when DIIS is off. Not
+    /// available for Kohn-Sham.
+    pub second_order: Option<SOSCFOptions>,
+    /// Periodically save the SCF state so that it can be restarted.
+    pub checkpoint: Option<CheckpointOptions>,
+    /// Continue from a checkpoint, read with `checkpoint::read_restart`,
+    /// instead of starting from the guess. It must be for the same kind of
+    /// SCF as it is passed to, which `read_restart` checks.
+    pub restart: Option<Checkpoint>,
+    /// Diagonalize the Fock matrix within each irrep of this adaptation,
+    /// which must be for the basis and (symmetrized) geometry of the SCF.
+    pub symmetry: Option<SymmetryAdaptation>,
+    /// Doubly occupied orbitals per irrep, in place of the lowest orbitals
+    /// overall. Needs `symmetry`, and rules out smearing.
+    pub docc: Option<Vec<usize>>,
+    /// Singly occupied (alpha) orbitals per irrep for UHF, on top of `docc`.
+    pub socc: Option<Vec<usize>>,
+    /// Print the energy at every iteration.
+    pub verbose: bool,
+}
+
+impl SCFOptions {
+    /// The fraction of the previous density kept at an iteration.
+    pub fn damping_factor(&self, iteration: usize) -> f64 {
+        if iteration < self.damping_iterations {
+            self.damping * (1.0 - iteration as f64 / self.damping_iterations as f64)
+        } else {
+            0.0
+        }
+    }
+
+    /// The occupied orbitals per irrep for one spin, from `docc` and (for
+    /// alpha) `socc`, if either is given.
+    pub(crate) fn irrep_occupations(&self, alpha: bool) -> Option<Vec<usize>> {
+        let singly = if alpha { self.socc.as_ref() } else { None };
+        match (self.docc.as_ref(), singly) {
+            (None, None) => None,
+            (Some(docc), None) => Some(docc.clone()),
+            (None, Some(socc)) => Some(socc.clone()),
+            (Some(docc), Some(socc)) => Some(docc.iter().zip(socc).map(|(d, s)| d + s).collect()),
+        }
+    }
+}
+
+impl Default for SCFOptions {
+    fn default() -> SCFOptions {
+        SCFOptions {
+            max_iterations: 1024,
+            thresh_e: 1.0e-11,
+            thresh_d: 1.0e-8,
+            jk: JKAlgorithm::Direct,
+            guess: Guess::Core,
+            diis_size: 8,
+            level_shift: 0.0,
+            damping: 0.0,
+            damping_iterations: 0,
+            smearing: None,
+            second_order: None,
+            checkpoint: None,
+            restart: None,
+            symmetry: None,
+            docc: None,
+            socc: None,
+            verbose: false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RHFResult {
+    /// Total energy, including nuclear repulsion.
+    pub energy: f64,
+    pub e_nuc: f64,
+    pub C: Array<f64, Ix2>,
+    pub eps: Array<f64, Ix1>,
+    /// Density matrix for a single spin, D = C_occ C_occ^T.
+    pub D: Array<f64, Ix2>,
+    pub F: Array<f64, Ix2>,
+    pub nocc: usize,
+    pub iterations: usize,
+    pub converged: bool,
+    /// Why the last checkpoint could not be written, if one failed; the SCF
+    /// carries on regardless.
+    pub checkpoint_error: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct UHFResult {
+    /// Total energy, including nuclear repulsion.
+    pub energy: f64,
+    pub e_nuc: f64,
+    pub C_alpha: Array<f64, Ix2>,
+    pub C_beta: Array<f64, Ix2>,
+    pub eps_alpha: Array<f64, Ix1>,
+    pub eps_beta: Array<f64, Ix1>,
+    pub D_alpha: Array<f64, Ix2>,
+    pub D_beta: Array<f64, Ix2>,
+    pub F_alpha: Array<f64, Ix2>,
+    pub F_beta: Array<f64, Ix2>,
+    pub nalpha: usize,
+    pub nbeta: usize,
+    pub iterations: usize,
+    pub converged: bool,
+    /// Why the last checkpoint could not be written, if one failed; the SCF
+    /// carries on regardless.
+    pub checkpoint_error: Option<String>,
+}
+
+pub fn nuclear_repulsion(atomcoords: &[[f64; 3]], atomnos: &[u64]) -> f64 {
+    let mut e_nuc = 0.0;
+    for i in 0..atomcoords.len() {
+        for j in 0..i {
+            let r = (0..3)
+                .map(|k| (atomcoords[i][k] - atomcoords[j][k]).powi(2))
+                .sum::<f64>()
+                .sqrt();
+            e_nuc += (atomnos[i] * atomnos[j]) as f64 / r;
+        }
+    }
+    e_nuc
+}
+
+/// Electric dipole moment about the origin in atomic units, given the total
+/// (alpha + beta) density matrix.
+pub fn dipole_moment(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    D_total: &Array<f64, Ix2>,
+) -> [f64; 3] {
+    let integrals = basis::dipole(basis_set, &[0.0, 0.0, 0.0]);
+    let mut moment = [0.0; 3];
+    for k in 0..3 {
+        let nuclear: f64 = atomcoords
+            .iter()
+            .zip(atomnos.iter())
+            .map(|(coords, &atomno)| atomno as f64 * coords[k])
+            .sum();
+        moment[k] = nuclear - (&integrals[k] * D_total).sum();
+    }
+    moment
+}
+
+pub fn nelectrons(atomnos: &[u64], charge: i64) -> usize {
+    (atomnos.iter().sum::<u64>() as i64 - charge) as usize
+}
+
+/// Form S^{-1/2}, used to move between the AO and an orthonormal basis.
+pub fn symmetric_orthogonalization(S: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+    let (overlap_eigvals, overlap_eigvecs) = S.eigh(UPLO::Upper).unwrap();
+    let overlap_eigvals_inv_sqrt = Array::from_diag(&overlap_eigvals.mapv(|x| 1.0 / x.sqrt()));
+    overlap_eigvecs
+        .dot(&overlap_eigvals_inv_sqrt)
+        .dot(&overlap_eigvecs.t())
+}
+
+/// Solve FC = SCE given the orthogonalizer X, returning the orbital energies
+/// and AO coefficients.
+pub fn diagonalize(F: &Array<f64, Ix2>, X: &Array<f64, Ix2>) -> (Array<f64, Ix1>, Array<f64, Ix2>) {
+    let F_prime = X.t().dot(F).dot(X);
+    let (eps, C_prime) = F_prime.eigh(UPLO::Upper).unwrap();
+    (eps, X.dot(&C_prime))
+}
+
+/// Solve FC = SCE like `diagonalize`, but within each irrep if `options`
+/// has a symmetry adaptation, putting the orbitals that `occupations` (per
+/// irrep) asks for first.
+pub(crate) fn diagonalize_symmetric(
+    F: &Array<f64, Ix2>,
+    X: &Array<f64, Ix2>,
+    occupations: Option<&[usize]>,
+    options: &SCFOptions,
+) -> (Array<f64, Ix1>, Array<f64, Ix2>) {
+    match (&options.symmetry, occupations) {
+        (None, None) => diagonalize(F, X),
+        (None, Some(_)) => panic!("occupations per irrep need a symmetry adaptation"),
+        (Some(adaptation), None) => {
+            let (eps, C, _) = adaptation.diagonalize(F);
+            (eps, C)
+        }
+        (Some(adaptation), Some(occupations)) => {
+            assert!(
+                options.smearing.is_none(),
+                "smearing cannot be combined with occupations per irrep"
+            );
+            let (eps, C, irreps) = adaptation.diagonalize(F);
+            symmetry::occupy(&eps, &C, &irreps, occupations)
+        }
+    }
+}
+
+pub fn build_density(C: &Array<f64, Ix2>, nocc: usize) -> Array<f64, Ix2> {
+    C.slice_axis(Axis(1), Slice::from(..nocc))
+        .dot(&C.slice_axis(Axis(1), Slice::from(..nocc)).t())
+}
+
+/// Density matrix from orbitals with the given (single-spin) occupations.
+pub fn build_density_fractional(
+    C: &Array<f64, Ix2>,
+    occupations: &Array<f64, Ix1>,
+) -> Array<f64, Ix2> {
+    (C * occupations).dot(&C.t())
+}
+
+/// Occupations of orbitals with energies `eps` holding `nocc` electrons (or
+/// pairs), smeared around a Fermi level found by bisection.
+pub fn smeared_occupations(
+    eps: &Array<f64, Ix1>,
+    nocc: usize,
+    smearing: &Smearing,
+) -> Array<f64, Ix1> {
+    let occupation = |e: f64, mu: f64| match *smearing {
+        Smearing::FermiDirac(kT) => 1.0 / (1.0 + ((e - mu) / kT).exp()),
+        Smearing::Gaussian(sigma) => 0.5 * rgsl::error::erfc((e - mu) / sigma),
+    };
+    let width = match *smearing {
+        Smearing::FermiDirac(width) | Smearing::Gaussian(width) => width,
+    };
+    let mut lower = eps[0] - 50.0 * width;
+    let mut upper = eps[eps.len() - 1] + 50.0 * width;
+    for _ in 0..200 {
+        let mu = 0.5 * (lower + upper);
+        let total: f64 = eps.iter().map(|&e| occupation(e, mu)).sum();
+        if total > nocc as f64 {
+            upper = mu;
+        } else {
+            lower = mu;
+        }
+    }
+    let mu = 0.5 * (lower + upper);
+    eps.mapv(|e| occupation(e, mu))
+}
+
+/// Raise the virtual orbitals of F by `shift`, using the projector
+/// S C_v C_v^T S onto the orbitals C past the first `nocc`. Unlike
+/// S - S D S, this holds for the non-idempotent densities of smearing and
+/// damping. Before the first diagonalization C is zero and nothing is
+/// shifted.
+pub fn level_shift(
+    F: &Array<f64, Ix2>,
+    S: &Array<f64, Ix2>,
+    C: &Array<f64, Ix2>,
+    nocc: usize,
+    shift: f64,
+) -> Array<f64, Ix2> {
+    if shift == 0.0 {
+        return F.clone();
+    }
+    let SC_vir = S.dot(&C.slice(ndarray::s![.., nocc..]));
+    F + &(shift * &SC_vir.dot(&SC_vir.t()))
+}
+
+/// The density for the next iteration from the orbitals of the current Fock
+/// matrix, with the smearing and damping in `options`.
+pub(crate) fn next_density(
+    C: &Array<f64, Ix2>,
+    eps: &Array<f64, Ix1>,
+    nocc: usize,
+    D_old: &Array<f64, Ix2>,
+    iteration: usize,
+    options: &SCFOptions,
+) -> Array<f64, Ix2> {
+    let D = match &options.smearing {
+        Some(smearing) => build_density_fractional(C, &smeared_occupations(eps, nocc, smearing)),
+        None => build_density(C, nocc),
+    };
+    let damping = options.damping_factor(iteration);
+    if damping > 0.0 {
+        (1.0 - damping) * D + damping * D_old
+    } else {
+        D
+    }
+}
+
+/// Add the Fock matrices (one per spin) and their errors to the DIIS
+/// subspace as a single vector, and return the extrapolated Fock matrices,
+/// or the Fock matrices themselves without DIIS.
+pub(crate) fn extrapolate_fock(
+    diis: &mut Option<DIIS>,
+    F: &[&Array<f64, Ix2>],
+    errors: &[Array<f64, Ix2>],
+) -> Vec<Array<f64, Ix2>> {
+    let diis = match diis {
+        Some(diis) => diis,
+        None => return F.iter().map(|&F| F.clone()).collect(),
+    };
+    let flatten = |matrices: &mut dyn Iterator<Item = &Array<f64, Ix2>>| {
+        Array::from(matrices.flat_map(|M| M.iter().cloned()).collect::<Vec<_>>())
+    };
+    diis.push(flatten(&mut F.iter().copied()), flatten(&mut errors.iter()));
+    let extrapolated = diis.extrapolate();
+    let dim = F[0].shape()[0];
+    extrapolated
+        .exact_chunks(dim * dim)
+        .into_iter()
+        .map(|chunk| chunk.to_owned().into_shape_with_order((dim, dim)).unwrap())
+        .collect()
+}
+
+fn calc_elec_energy(D: &Array<f64, Ix2>, H: &Array<f64, Ix2>, F: &Array<f64, Ix2>) -> f64 {
+    ((H + F) * D).sum()
+}
+
+fn rms(A: &Array<f64, Ix2>) -> f64 {
+    A.mapv(|x| x * x).mean().unwrap().sqrt()
+}
+
+/// RMS of the DIIS error vector, i.e. of the errors of all spins together.
+fn diis_error(errors: &[Array<f64, Ix2>]) -> f64 {
+    let sum: f64 = errors.iter().map(|E| E.mapv(|x| x * x).sum()).sum();
+    let len: usize = errors.iter().map(|E| E.len()).sum();
+    (sum / len as f64).sqrt()
+}
+
+pub fn core_hamiltonian(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+) -> Array<f64, Ix2> {
+    basis::T(basis_set) + basis::V(basis_set, atomcoords, atomnos)
+}
+
+/// Closed-shell restricted Hartree-Fock, starting from the guess in
+/// `options`.
+pub fn rhf(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    nocc: usize,
+    options: &SCFOptions,
+) -> RHFResult {
+    let S = basis::S(basis_set);
+    let X = symmetric_orthogonalization(&S);
+    let H = core_hamiltonian(basis_set, atomcoords, atomnos);
+    let e_nuc = nuclear_repulsion(atomcoords, atomnos);
+    let jk = JKEngine::new(basis_set, &options.jk);
+    let occupations = options.irrep_occupations(false);
+    if let Some(occupations) = &occupations {
+        assert_eq!(occupations.iter().sum::<usize>(), nocc);
+    }
+
+    let dim = H.shape()[0];
+    let mut eps: Array<f64, _> = Array::zeros(dim);
+    let mut C: Array<f64, _> = Array::zeros((dim, dim));
+    let mut F = H.clone();
+    let mut diis = (options.diis_size > 0).then(|| DIIS::new(options.diis_size));
+    let (mut D, mut e_elec_new, mut iteration) =
+        match checkpoint::restart_for(&options.restart, SCFKind::RHF)
+            .unwrap_or_else(|error| panic!("{}", error))
+        {
+            Some(restart) => {
+                if let (Some(diis), Some(saved)) = (&mut diis, &restart.diis) {
+                    *diis = saved.clone();
+                }
+                C = restart.orbitals[0].clone();
+                (
+                    restart.densities[0].clone(),
+                    restart.e_elec,
+                    restart.iteration,
+                )
+            }
+            None => {
+                let D = guess::initial_density(&options.guess, basis_set, atomnos, &S, &H, nocc);
+                let e_elec = calc_elec_energy(&D, &H, &H);
+                (D, e_elec, 0)
+            }
+        };
+    let mut converged = false;
+    let mut checkpoint_error = None;
+    let mut best_error = f64::INFINITY;
+    let mut iterations_since_best = 0;
+    let mut stalled = false;
+
+    while iteration < options.max_iterations {
+        let (J, K) = jk.build(&D);
+        F = &H + &(2.0 * &J) - &K;
+        let e_elec_old = e_elec_new;
+        e_elec_new = calc_elec_energy(&D, &H, &F);
+        let FDS = F.dot(&D).dot(&S);
+        let gradient = X.t().dot(&(&FDS - &FDS.t())).dot(&X);
+        let error = diis_error(std::slice::from_ref(&gradient));
+        let F_diis = extrapolate_fock(&mut diis, &[&F], &[gradient]).remove(0);
+        let (eps_new, C_new) = diagonalize_symmetric(
+            &level_shift(&F_diis, &S, &C, nocc, options.level_shift),
+            &X,
+            occupations.as_deref(),
+            options,
+        );
+        eps = eps_new;
+        C = C_new;
+        let D_old = D;
+        D = next_density(&C, &eps, nocc, &D_old, iteration, options);
+        let delta_e = e_elec_new - e_elec_old;
+        let rms_d = rms(&(&D - &D_old));
+        if options.verbose {
+            println!(
+                "{:4} {:20.12} {:20.12} {:20.12}",
+                iteration,
+                e_elec_new + e_nuc,
+                delta_e,
+                rms_d
+            );
+        }
+        iteration += 1;
+        let done = delta_e.abs() < options.thresh_e && rms_d < options.thresh_d;
+        if let Err(error) = checkpoint::save(&options.checkpoint, iteration, done, || Checkpoint {
+            kind: SCFKind::RHF,
+            fingerprint: checkpoint::fingerprint(basis_set, atomcoords, atomnos),
+            iteration,
+            e_elec: e_elec_new,
+            densities: vec![D.clone()],
+            orbitals: vec![C.clone()],
+            diis: diis.clone(),
+        }) {
+            checkpoint_error = Some(error.to_string());
+        }
+        if done {
+            converged = true;
+            break;
+        }
+        if let Some(second_order) = &options.second_order {
+            if error < best_error {
+                best_error = error;
+                iterations_since_best = 0;
+            } else {
+                iterations_since_best += 1;
+            }
+            if iterations_since_best >= second_order.stall_iterations {
+                stalled = true;
+                break;
+            }
+        }
+    }
+    if stalled {
+        if options.verbose {
+            println!("Switching to second-order SCF");
+        }
+        let mut result = soscf::rhf(basis_set, atomcoords, atomnos, &C, nocc, options);
+        result.iterations += iteration;
+        result.checkpoint_error = checkpoint_error;
+        return result;
+    }
+    if options.verbose && converged {
+        println!("Convergence achieved!");
+    }
+    if options.level_shift != 0.0 {
+        let (eps_new, C_new) = diagonalize_symmetric(&F, &X, occupations.as_deref(), options);
+        eps = eps_new;
+        C = C_new;
+    }
+
+    RHFResult {
+        energy: e_elec_new + e_nuc,
+        e_nuc,
+        C,
+        eps,
+        D,
+        F,
+        nocc,
+        iterations: iteration,
+        converged,
+        checkpoint_error,
+    }
+}
+
+/// Unrestricted Hartree-Fock, starting from the guess in `options` for both
+/// spins.
+pub fn uhf(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    nalpha: usize,
+    nbeta: usize,
+    options: &SCFOptions,
+) -> UHFResult {
+    let S = basis::S(basis_set);
+    let H = core_hamiltonian(basis_set, atomcoords, atomnos);
+    let initial_density =
+        |nocc| guess::initial_density(&options.guess, basis_set, atomnos, &S, &H, nocc);
+    let densities = match checkpoint::restart_for(&options.restart, SCFKind::UHF)
+        .unwrap_or_else(|error| panic!("{}", error))
+    {
+        Some(restart) => (restart.densities[0].clone(), restart.densities[1].clone()),
+        None => (initial_density(nalpha), initial_density(nbeta)),
+    };
+    uhf_from_densities(
+        basis_set, atomcoords, atomnos, nalpha, nbeta, densities, options,
+    )
+}
+
+/// Unrestricted Hartree-Fock starting from the given alpha and beta
+/// densities rather than the guess in `options`. A restart in `options`
+/// takes precedence over both.
+pub fn uhf_from_densities(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    nalpha: usize,
+    nbeta: usize,
+    densities: (Array<f64, Ix2>, Array<f64, Ix2>),
+    options: &SCFOptions,
+) -> UHFResult {
+    let S = basis::S(basis_set);
+    let X = symmetric_orthogonalization(&S);
+    let H = core_hamiltonian(basis_set, atomcoords, atomnos);
+    let e_nuc = nuclear_repulsion(atomcoords, atomnos);
+    let jk = JKEngine::new(basis_set, &options.jk);
+    let occupations_alpha = options.irrep_occupations(true);
+    let occupations_beta = options.irrep_occupations(false);
+    if let (Some(alpha), Some(beta)) = (&occupations_alpha, &occupations_beta) {
+        assert_eq!(alpha.iter().sum::<usize>(), nalpha);
+        assert_eq!(beta.iter().sum::<usize>(), nbeta);
+    }
+
+    let dim = H.shape()[0];
+    let (mut eps_alpha, mut C_alpha) = (Array::zeros(dim), Array::zeros((dim, dim)));
+    let (mut eps_beta, mut C_beta) = (Array::zeros(dim), Array::zeros((dim, dim)));
+    let (mut D_alpha, mut D_beta) = densities;
+    let mut F_alpha = H.clone();
+    let mut F_beta = H.clone();
+    let mut e_elec_new =
+        0.5 * (calc_elec_energy(&D_alpha, &H, &H) + calc_elec_energy(&D_beta, &H, &H));
+    let mut iteration = 0;
+    let mut diis = (options.diis_size > 0).then(|| DIIS::new(options.diis_size));
+    if let Some(restart) = checkpoint::restart_for(&options.restart, SCFKind::UHF)
+        .unwrap_or_else(|error| panic!("{}", error))
+    {
+        if let (Some(diis), Some(saved)) = (&mut diis, &restart.diis) {
+            *diis = saved.clone();
+        }
+        D_alpha = restart.densities[0].clone();
+        D_beta = restart.densities[1].clone();
+        C_alpha = restart.orbitals[0].clone();
+        C_beta = restart.orbitals[1].clone();
+        e_elec_new = restart.e_elec;
+        iteration = restart.iteration;
+    }
+    let mut converged = false;
+    let mut checkpoint_error = None;
+    let mut best_error = f64::INFINITY;
+    let mut iterations_since_best = 0;
+    let mut stalled = false;
+
+    while iteration < options.max_iterations {
+        let (J_alpha, K_alpha) = jk.build(&D_alpha);
+        let (J_beta, K_beta) = jk.build(&D_beta);
+        let J = &J_alpha + &J_beta;
+        F_alpha = &H + &J - &K_alpha;
+        F_beta = &H + &J - &K_beta;
+        let e_elec_old = e_elec_new;
+        e_elec_new = 0.5
+            * (calc_elec_energy(&D_alpha, &H, &F_alpha) + calc_elec_energy(&D_beta, &H, &F_beta));
+        let orbital_gradient = |F: &Array<f64, Ix2>, D: &Array<f64, Ix2>| {
+            let FDS = F.dot(D).dot(&S);
+            X.t().dot(&(&FDS - &FDS.t())).dot(&X)
+        };
+        let gradients = [
+            orbital_gradient(&F_alpha, &D_alpha),
+            orbital_gradient(&F_beta, &D_beta),
+        ];
+        let error = diis_error(&gradients);
+        let F_diis = extrapolate_fock(&mut diis, &[&F_alpha, &F_beta], &gradients);
+        let (eps_alpha_new, C_alpha_new) = diagonalize_symmetric(
+            &level_shift(&F_diis[0], &S, &C_alpha, nalpha, options.level_shift),
+            &X,
+            occupations_alpha.as_deref(),
+            options,
+        );
+        let (eps_beta_new, C_beta_new) = diagonalize_symmetric(
+            &level_shift(&F_diis[1], &S, &C_beta, nbeta, options.level_shift),
+            &X,
+            occupations_beta.as_deref(),
+            options,
+        );
+        eps_alpha = eps_alpha_new;
+        C_alpha = C_alpha_new;
+        eps_beta = eps_beta_new;
+        C_beta = C_beta_new;
+        let D_alpha_old = D_alpha;
+        let D_beta_old = D_beta;
+        D_alpha = next_density(
+            &C_alpha,
+            &eps_alpha,
+            nalpha,
+            &D_alpha_old,
+            iteration,
+            options,
+        );
+        D_beta = next_density(&C_beta, &eps_beta, nbeta, &D_beta_old, iteration, options);
+        let delta_e = e_elec_new - e_elec_old;
+        let rms_d = rms(&(&D_alpha - &D_alpha_old)).max(rms(&(&D_beta - &D_beta_old)));
+        if options.verbose {
+            println!(
+                "{:4} {:20.12} {:20.12} {:20.12}",
+                iteration,
+                e_elec_new + e_nuc,
+                delta_e,
+                rms_d
+            );
+        }
+        iteration += 1;
+        let done = delta_e.abs() < options.thresh_e && rms_d < options.thresh_d;
+        if let Err(error) = checkpoint::save(&options.checkpoint, iteration, done, || Checkpoint {
+            kind: SCFKind::UHF,
+            fingerprint: checkpoint::fingerprint(basis_set, atomcoords, atomnos),
+            iteration,
+            e_elec: e_elec_new,
+            densities: vec![D_alpha.clone(), D_beta.clone()],
+            orbitals: vec![C_alpha.clone(), C_beta.clone()],
+            diis: diis.clone(),
+        }) {
+            checkpoint_error = Some(error.to_string());
+        }
+        if done {
+            converged = true;
+            break;
+        }
+        if let Some(second_order) = &options.second_order {
+            if error < best_error {
+                best_error = error;
+                iterations_since_best = 0;
+            } else {
+                iterations_since_best += 1;
+            }
+            if iterations_since_best >= second_order.stall_iterations {
+                stalled = true;
+                break;
+            }
+        }
+    }
+    if stalled {
+        if options.verbose {
+            println!("Switching to second-order SCF");
+        }
+        let mut result = soscf::uhf(
+            basis_set,
+            atomcoords,
+            atomnos,
+            (&C_alpha, &C_beta),
+            nalpha,
+            nbeta,
+            options,
+        );
+        result.iterations += iteration;
+        result.checkpoint_error = checkpoint_error;
+        return result;
+    }
+    if options.verbose && converged {
+        println!("Convergence achieved!");
+    }
+    if options.level_shift != 0.0 {
+        let (eps_alpha_new, C_alpha_new) =
+            diagonalize_symmetric(&F_alpha, &X, occupations_alpha.as_deref(), options);
+        let (eps_beta_new, C_beta_new) =
+            diagonalize_symmetric(&F_beta, &X, occupations_beta.as_deref(), options);
+        eps_alpha = eps_alpha_new;
+        C_alpha = C_alpha_new;
+        eps_beta = eps_beta_new;
+        C_beta = C_beta_new;
+    }
+
+    UHFResult {
+        energy: e_elec_new + e_nuc,
+        e_nuc,
+        C_alpha,
+        C_beta,
+        eps_alpha,
+        eps_beta,
+        D_alpha,
+        D_beta,
+        F_alpha,
+        F_beta,
+        nalpha,
+        nbeta,
+        iterations: iteration,
+        converged,
+        checkpoint_error,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_nuclear_repulsion() {
+        let (atomcoords, atomnos) = testing::water();
+        let e_nuc = nuclear_repulsion(&atomcoords, &atomnos);
+        assert!((e_nuc - 8.00236706181045).abs() < 1.0e-10);
+    }
+
+    #[test]
+    fn test_rhf_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!(result.converged);
+        // Crawford programming project #3.
+        assert!((result.energy - -74.942079928192).abs() < 1.0e-6);
+    }
+
+    #[test]
+    fn test_rhf_convergence_aids() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let reference = rhf(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            5,
+            &SCFOptions {
+                jk: JKAlgorithm::InMemory,
+                ..Default::default()
+            },
+        );
+        // Level shifting and damping change the path but not the solution.
+        let options = SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            level_shift: 0.5,
+            damping: 0.7,
+            damping_iterations: 10,
+            ..Default::default()
+        };
+        let result = rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!(result.converged);
+        assert!((result.energy - reference.energy).abs() < 1.0e-9);
+        assert!((&result.eps - &reference.eps).fold(0.0f64, |acc, x| acc.max(x.abs())) < 1.0e-6);
+
+        // Smearing with a width far below the HOMO-LUMO gap keeps integer
+        // occupations, while a wide one spreads them out.
+        for &smearing in [Smearing::FermiDirac(1.0e-3), Smearing::Gaussian(1.0e-3)].iter() {
+            let options = SCFOptions {
+                jk: JKAlgorithm::InMemory,
+                smearing: Some(smearing),
+                ..Default::default()
+            };
+            let result = rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+            assert!((result.energy - reference.energy).abs() < 1.0e-9);
+        }
+        let options = SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            level_shift: 0.5,
+            smearing: Some(Smearing::FermiDirac(1.0e-3)),
+            ..Default::default()
+        };
+        let result = rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!((result.energy - reference.energy).abs() < 1.0e-9);
+        // The shift raises exactly the virtual orbitals.
+        let S = basis::S(&basis_set);
+        let shifted = level_shift(&reference.F, &S, &reference.C, 5, 0.5);
+        let F_mo = reference.C.t().dot(&shifted).dot(&reference.C);
+        for p in 0..reference.eps.len() {
+            let shift = if p < 5 { 0.0 } else { 0.5 };
+            assert!((F_mo[[p, p]] - reference.eps[p] - shift).abs() < 1.0e-8);
+        }
+
+        let occupations = smeared_occupations(&reference.eps, 5, &Smearing::FermiDirac(0.2));
+        assert!((occupations.sum() - 5.0).abs() < 1.0e-10);
+        assert!(occupations[4] < 0.99 && occupations[5] > 0.01);
+    }
+
+    #[test]
+    fn test_uhf_closed_shell_matches_rhf() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result_rhf = rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let result_uhf = uhf(&basis_set, &atomcoords, &atomnos, 5, 5, &options);
+        assert!(result_uhf.converged);
+        assert!((result_rhf.energy - result_uhf.energy).abs() < 1.0e-9);
+    }
+}
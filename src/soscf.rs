@@ -0,0 +1,573 @@
+#![allow(non_snake_case)]
+
+//! Second-order orbital optimization for restricted and unrestricted
+//! Hartree-Fock with the trust-region augmented Hessian (TRAH) method.
+//!
+//! The orbitals are rotated as C exp(K), where K is antisymmetric with
+//! K_ai = x_ai between virtual a and occupied i (for UHF, one such rotation
+//! for each spin, optimized together). Each step solves the
+//! augmented Hessian eigenvalue problem
+//!
+//! [ 0      alpha g^T ] [ 1 ]       [ 1 ]
+//! [ alpha g    H     ] [ v ] = mu  [ v ],   x = v / alpha,
+//!
+//! in a Davidson subspace, with alpha chosen to keep the step inside the
+//! trust region. The electronic Hessian is only used through its products
+//! with trial vectors, which need one J/K build each.
+//!
+//! R. Helmich-Paris, J. Chem. Phys. 154, 164104 (2021).
+
+use ndarray::{Array, Axis, Ix1, Ix2, Slice};
+use ndarray_linalg::*;
+
+use crate::basis;
+use crate::scf::{self, JKEngine, RHFResult, SCFOptions, UHFResult};
+
+#[derive(Clone, Debug)]
+pub struct SOSCFOptions {
+    /// When used from `scf::rhf` or `scf::uhf`, switch to the second-order
+    /// optimizer once the RMS of the DIIS error vector has gone this many
+    /// iterations without reaching a new minimum.
+    pub stall_iterations: usize,
+    pub max_iterations: usize,
+    /// Convergence threshold on the norm of the orbital gradient.
+    pub thresh_gradient: f64,
+    /// The initial trust radius, as the norm of the rotation x.
+    pub trust_radius: f64,
+    /// Maximum number of Hessian-vector products per step.
+    pub max_micro_iterations: usize,
+}
+
+impl Default for SOSCFOptions {
+    fn default() -> SOSCFOptions {
+        SOSCFOptions {
+            stall_iterations: 8,
+            max_iterations: 50,
+            thresh_gradient: 1.0e-6,
+            trust_radius: 0.5,
+            max_micro_iterations: 30,
+        }
+    }
+}
+
+/// exp(A) by scaling and squaring of its Taylor series.
+fn expm(A: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+    let norm = A.iter().map(|x| x.abs()).fold(0.0, f64::max) * A.shape()[0] as f64;
+    let squarings = if norm > 0.5 {
+        (norm / 0.5).log2().ceil() as i32
+    } else {
+        0
+    };
+    let A = A / 2f64.powi(squarings);
+    let mut result: Array<f64, _> = Array::eye(A.shape()[0]);
+    let mut term = result.clone();
+    for k in 1..=16 {
+        term = term.dot(&A) / k as f64;
+        result += &term;
+    }
+    for _ in 0..squarings {
+        result = result.dot(&result);
+    }
+    result
+}
+
+/// Rotate the orbitals by the virtual-occupied rotation `x`.
+pub(crate) fn rotate(C: &Array<f64, Ix2>, x: &Array<f64, Ix2>) -> Array<f64, Ix2> {
+    let (nvir, nocc) = x.dim();
+    let nmo = nocc + nvir;
+    let mut K: Array<f64, _> = Array::zeros((nmo, nmo));
+    for a in 0..nvir {
+        for i in 0..nocc {
+            K[[nocc + a, i]] = x[[a, i]];
+            K[[i, nocc + a]] = -x[[a, i]];
+        }
+    }
+    C.dot(&expm(&K))
+}
+
+/// The density, Fock matrix and electronic energy for orbitals C.
+fn evaluate(
+    C: &Array<f64, Ix2>,
+    nocc: usize,
+    H: &Array<f64, Ix2>,
+    jk: &JKEngine,
+) -> (Array<f64, Ix2>, Array<f64, Ix2>, f64) {
+    let D = scf::build_density(C, nocc);
+    let (J, K) = jk.build(&D);
+    let F = H + &(2.0 * &J) - &K;
+    let e_elec = ((H + &F) * &D).sum();
+    (D, F, e_elec)
+}
+
+/// Alpha and beta matrices.
+type SpinPair = [Array<f64, Ix2>; 2];
+
+/// The alpha and beta densities, Fock matrices and the electronic energy
+/// for unrestricted orbitals C with `nocc` electrons of each spin.
+fn evaluate_unrestricted(
+    C: &SpinPair,
+    nocc: [usize; 2],
+    H: &Array<f64, Ix2>,
+    jk: &JKEngine,
+) -> (SpinPair, SpinPair, f64) {
+    let D = [
+        scf::build_density(&C[0], nocc[0]),
+        scf::build_density(&C[1], nocc[1]),
+    ];
+    let (J_alpha, K_alpha) = jk.build(&D[0]);
+    let (J_beta, K_beta) = jk.build(&D[1]);
+    let J = &J_alpha + &J_beta;
+    let F = [H + &J - &K_alpha, H + &J - &K_beta];
+    let e_elec = 0.5 * (((H + &F[0]) * &D[0]).sum() + ((H + &F[1]) * &D[1]).sum());
+    (D, F, e_elec)
+}
+
+/// The orbital energies and orbitals that diagonalize F separately within
+/// the occupied and the virtual orbitals of C.
+fn canonicalize(
+    C: &Array<f64, Ix2>,
+    F: &Array<f64, Ix2>,
+    nocc: usize,
+) -> (Array<f64, Ix1>, Array<f64, Ix2>) {
+    let nmo = C.shape()[1];
+    let mut eps: Array<f64, _> = Array::zeros(nmo);
+    let mut C_canonical: Array<f64, _> = Array::zeros(C.dim());
+    for range in [0..nocc, nocc..nmo].iter() {
+        let block = C.slice_axis(Axis(1), Slice::from(range.clone()));
+        let (e, U) = block.t().dot(F).dot(&block).eigh(UPLO::Upper).unwrap();
+        eps.slice_mut(ndarray::s![range.clone()]).assign(&e);
+        C_canonical
+            .slice_axis_mut(Axis(1), Slice::from(range.clone()))
+            .assign(&block.dot(&U));
+    }
+    (eps, C_canonical)
+}
+
+/// Solve the augmented Hessian problem within the trust radius, returning
+/// the step and the energy change predicted by the quadratic model.
+fn trah_step<S>(
+    g: &Array<f64, Ix1>,
+    diagonal: &Array<f64, Ix1>,
+    trust_radius: f64,
+    options: &SOSCFOptions,
+    mut hvp: S,
+) -> (Array<f64, Ix1>, f64)
+where
+    S: FnMut(&Array<f64, Ix1>) -> Array<f64, Ix1>,
+{
+    let gnorm = g.dot(g).sqrt();
+    let thresh = 0.1 * gnorm * gnorm.min(1.0);
+    let mut basis = vec![g / gnorm];
+    let mut sigmas = vec![hvp(&basis[0])];
+    let (mut step, mut Hstep) = (Array::zeros(g.len()), Array::zeros(g.len()));
+
+    for _ in 0..options.max_micro_iterations {
+        let m = basis.len();
+        let Hs = Array::from_shape_fn((m, m), |(i, j)| {
+            0.5 * (basis[i].dot(&sigmas[j]) + basis[j].dot(&sigmas[i]))
+        });
+        // The subspace starts from g, so the projected gradient only has
+        // its first component.
+        let solve = |alpha: f64| {
+            let mut A: Array<f64, _> = Array::zeros((m + 1, m + 1));
+            A.slice_mut(ndarray::s![1.., 1..]).assign(&Hs);
+            A[[0, 1]] = alpha * gnorm;
+            A[[1, 0]] = alpha * gnorm;
+            let (mu, v) = A.eigh(UPLO::Upper).unwrap();
+            let v0 = v[[0, 0]];
+            let xs = v
+                .column(0)
+                .slice(ndarray::s![1..])
+                .mapv(|c| c / (alpha * v0));
+            (mu[0], v0, xs)
+        };
+        let norm = |xs: &Array<f64, Ix1>| xs.dot(xs).sqrt();
+        let mut alpha = 1.0;
+        let (mut mu, mut v0, mut xs) = solve(alpha);
+        if norm(&xs) > trust_radius {
+            // Larger alpha shortens the step; bracket and bisect on log(alpha).
+            let (mut lower, mut upper) = (1.0f64, 2.0f64);
+            while norm(&solve(upper).2) > trust_radius && upper < 1.0e12 {
+                lower = upper;
+                upper *= 2.0;
+            }
+            for _ in 0..50 {
+                alpha = (lower * upper).sqrt();
+                if norm(&solve(alpha).2) > trust_radius {
+                    lower = alpha;
+                } else {
+                    upper = alpha;
+                }
+            }
+            alpha = upper;
+            let solution = solve(alpha);
+            mu = solution.0;
+            v0 = solution.1;
+            xs = solution.2;
+        }
+        step = Array::zeros(g.len());
+        Hstep = Array::zeros(g.len());
+        for k in 0..m {
+            step.scaled_add(xs[k], &basis[k]);
+            Hstep.scaled_add(xs[k], &sigmas[k]);
+        }
+        // The residual of the second row of the eigenvalue equation, in
+        // terms of x = v / (alpha v0).
+        let residual = (alpha * v0) * (g + &Hstep - &(mu * &step));
+        if residual.dot(&residual).sqrt() < thresh {
+            break;
+        }
+        let mut t: Array<f64, _> = Array::from_shape_fn(g.len(), |k| {
+            let denominator = diagonal[k] - mu;
+            residual[k]
+                / if denominator.abs() < 1.0e-4 {
+                    1.0e-4f64.copysign(denominator)
+                } else {
+                    denominator
+                }
+        });
+        for _ in 0..2 {
+            for b in basis.iter() {
+                let overlap = b.dot(&t);
+                t.scaled_add(-overlap, b);
+            }
+        }
+        let tnorm = t.dot(&t).sqrt();
+        if tnorm < 1.0e-10 {
+            break;
+        }
+        t /= tnorm;
+        sigmas.push(hvp(&t));
+        basis.push(t);
+    }
+
+    let predicted = g.dot(&step) + 0.5 * step.dot(&Hstep);
+    (step, predicted)
+}
+
+/// Closed-shell restricted Hartree-Fock by second-order orbital optimization,
+/// starting from the orbitals `C`. The convergence and J/K settings are
+/// taken from `options`, and those of the optimizer from
+/// `options.second_order` or its defaults.
+pub fn rhf(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    C: &Array<f64, Ix2>,
+    nocc: usize,
+    options: &SCFOptions,
+) -> RHFResult {
+    let so = options.second_order.clone().unwrap_or_default();
+    let H = scf::core_hamiltonian(basis_set, atomcoords, atomnos);
+    let e_nuc = scf::nuclear_repulsion(atomcoords, atomnos);
+    let jk = JKEngine::new(basis_set, &options.jk);
+    let nmo = C.shape()[1];
+    let nvir = nmo - nocc;
+
+    let mut C = C.clone();
+    let (mut D, mut F, mut e_elec) = evaluate(&C, nocc, &H, &jk);
+    let mut trust_radius = so.trust_radius;
+    let mut delta_e = f64::INFINITY;
+    let mut iteration = 0;
+    let mut converged = false;
+
+    while iteration < so.max_iterations {
+        let C_occ = C.slice_axis(Axis(1), Slice::from(..nocc)).to_owned();
+        let C_vir = C.slice_axis(Axis(1), Slice::from(nocc..)).to_owned();
+        let F_mo = C.t().dot(&F).dot(&C);
+        let F_oo = F_mo.slice(ndarray::s![..nocc, ..nocc]).to_owned();
+        let F_vv = F_mo.slice(ndarray::s![nocc.., nocc..]).to_owned();
+        let g = (4.0 * &F_mo.slice(ndarray::s![nocc.., ..nocc]))
+            .into_shape_with_order(nvir * nocc)
+            .unwrap();
+        let gnorm = g.dot(&g).sqrt();
+        if options.verbose {
+            println!(
+                "{:4} {:20.12} {:20.12} {:20.12}",
+                iteration,
+                e_elec + e_nuc,
+                delta_e,
+                gnorm
+            );
+        }
+        if gnorm < so.thresh_gradient {
+            converged = true;
+            break;
+        }
+
+        let diagonal = Array::from_shape_fn(nvir * nocc, |k| {
+            4.0 * (F_vv[[k / nocc, k / nocc]] - F_oo[[k % nocc, k % nocc]])
+        });
+        let hvp = |x: &Array<f64, Ix1>| {
+            let x = x.clone().into_shape_with_order((nvir, nocc)).unwrap();
+            let Dx = C_vir.dot(&x).dot(&C_occ.t());
+            let (J, K) = jk.build(&(&Dx + &Dx.t()));
+            let G = 2.0 * &J - &K;
+            let Hx = 4.0 * (F_vv.dot(&x) - x.dot(&F_oo) + C_vir.t().dot(&G).dot(&C_occ));
+            Hx.into_shape_with_order(nvir * nocc).unwrap()
+        };
+        let (step, predicted) = trah_step(&g, &diagonal, trust_radius, &so, hvp);
+
+        let C_trial = rotate(&C, &step.into_shape_with_order((nvir, nocc)).unwrap());
+        let (D_trial, F_trial, e_trial) = evaluate(&C_trial, nocc, &H, &jk);
+        let ratio = (e_trial - e_elec) / predicted;
+        iteration += 1;
+        if e_trial > e_elec && predicted.abs() > 1.0e-12 {
+            // Reject the step.
+            trust_radius *= 0.5;
+            continue;
+        }
+        if ratio < 0.25 {
+            trust_radius *= 0.7;
+        } else if ratio > 0.75 {
+            trust_radius *= 1.2;
+        }
+        delta_e = e_trial - e_elec;
+        C = C_trial;
+        D = D_trial;
+        F = F_trial;
+        e_elec = e_trial;
+    }
+    if options.verbose && converged {
+        println!("Convergence achieved!");
+    }
+
+    let (eps, C) = canonicalize(&C, &F, nocc);
+
+    RHFResult {
+        energy: e_elec + e_nuc,
+        e_nuc,
+        C,
+        eps,
+        D,
+        F,
+        nocc,
+        iterations: iteration,
+        converged,
+        checkpoint_error: None,
+    }
+}
+
+/// Unrestricted Hartree-Fock by second-order orbital optimization, starting
+/// from the alpha and beta orbitals in `C`, with options as for `rhf`.
+///
+/// With D^s = C_o^s C_o^s^T for each spin s, the gradient is
+/// g^s_ai = 2 F^s_ai, and the Hessian acts on the rotations x^s as
+///
+/// (H x)^s = 2 (F^s_vv x^s - x^s F^s_oo + C_v^s^T (J[dD] - K[dD^s]) C_o^s),
+///
+/// where dD^s = C_v^s x^s C_o^s^T + transpose and dD = dD^a + dD^b.
+pub fn uhf(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    C: (&Array<f64, Ix2>, &Array<f64, Ix2>),
+    nalpha: usize,
+    nbeta: usize,
+    options: &SCFOptions,
+) -> UHFResult {
+    let so = options.second_order.clone().unwrap_or_default();
+    let H = scf::core_hamiltonian(basis_set, atomcoords, atomnos);
+    let e_nuc = scf::nuclear_repulsion(atomcoords, atomnos);
+    let jk = JKEngine::new(basis_set, &options.jk);
+    let nocc = [nalpha, nbeta];
+    let nmo = C.0.shape()[1];
+    let nvir = [nmo - nalpha, nmo - nbeta];
+    let sizes = [nvir[0] * nocc[0], nvir[1] * nocc[1]];
+    // Split a vector over both spins into the (nvir, nocc) rotations.
+    let split = |x: &Array<f64, Ix1>| {
+        [
+            x.slice(ndarray::s![..sizes[0]])
+                .to_owned()
+                .into_shape_with_order((nvir[0], nocc[0]))
+                .unwrap(),
+            x.slice(ndarray::s![sizes[0]..])
+                .to_owned()
+                .into_shape_with_order((nvir[1], nocc[1]))
+                .unwrap(),
+        ]
+    };
+    let join = |x: [Array<f64, Ix2>; 2]| {
+        let [alpha, beta] = x;
+        let mut joined = alpha.into_shape_with_order(sizes[0]).unwrap().to_vec();
+        joined.extend(beta.into_shape_with_order(sizes[1]).unwrap());
+        Array::from(joined)
+    };
+
+    let mut C = [C.0.clone(), C.1.clone()];
+    let (mut D, mut F, mut e_elec) = evaluate_unrestricted(&C, nocc, &H, &jk);
+    let mut trust_radius = so.trust_radius;
+    let mut delta_e = f64::INFINITY;
+    let mut iteration = 0;
+    let mut converged = false;
+
+    while iteration < so.max_iterations {
+        let blocks: Vec<_> = (0..2)
+            .map(|s| {
+                let C_occ = C[s].slice_axis(Axis(1), Slice::from(..nocc[s])).to_owned();
+                let C_vir = C[s].slice_axis(Axis(1), Slice::from(nocc[s]..)).to_owned();
+                let F_mo = C[s].t().dot(&F[s]).dot(&C[s]);
+                let F_oo = F_mo.slice(ndarray::s![..nocc[s], ..nocc[s]]).to_owned();
+                let F_vv = F_mo.slice(ndarray::s![nocc[s].., nocc[s]..]).to_owned();
+                let F_vo = F_mo.slice(ndarray::s![nocc[s].., ..nocc[s]]).to_owned();
+                (C_occ, C_vir, F_oo, F_vv, F_vo)
+            })
+            .collect();
+        let g = join([2.0 * &blocks[0].4, 2.0 * &blocks[1].4]);
+        let gnorm = g.dot(&g).sqrt();
+        if options.verbose {
+            println!(
+                "{:4} {:20.12} {:20.12} {:20.12}",
+                iteration,
+                e_elec + e_nuc,
+                delta_e,
+                gnorm
+            );
+        }
+        if gnorm < so.thresh_gradient {
+            converged = true;
+            break;
+        }
+
+        let diagonal = join([0, 1].map(|s| {
+            let (_, _, F_oo, F_vv, _) = &blocks[s];
+            Array::from_shape_fn((nvir[s], nocc[s]), |(a, i)| {
+                2.0 * (F_vv[[a, a]] - F_oo[[i, i]])
+            })
+        }));
+        let hvp = |x: &Array<f64, Ix1>| {
+            let x = split(x);
+            let dD = [0, 1].map(|s| {
+                let (C_occ, C_vir, _, _, _) = &blocks[s];
+                let Dx = C_vir.dot(&x[s]).dot(&C_occ.t());
+                &Dx + &Dx.t()
+            });
+            let (J_alpha, K_alpha) = jk.build(&dD[0]);
+            let (J_beta, K_beta) = jk.build(&dD[1]);
+            let J = &J_alpha + &J_beta;
+            let K = [K_alpha, K_beta];
+            join([0, 1].map(|s| {
+                let (C_occ, C_vir, F_oo, F_vv, _) = &blocks[s];
+                let G = &J - &K[s];
+                2.0 * (F_vv.dot(&x[s]) - x[s].dot(F_oo) + C_vir.t().dot(&G).dot(C_occ))
+            }))
+        };
+        let (step, predicted) = trah_step(&g, &diagonal, trust_radius, &so, hvp);
+
+        let step = split(&step);
+        let C_trial = [rotate(&C[0], &step[0]), rotate(&C[1], &step[1])];
+        let (D_trial, F_trial, e_trial) = evaluate_unrestricted(&C_trial, nocc, &H, &jk);
+        let ratio = (e_trial - e_elec) / predicted;
+        iteration += 1;
+        if e_trial > e_elec && predicted.abs() > 1.0e-12 {
+            // Reject the step.
+            trust_radius *= 0.5;
+            continue;
+        }
+        if ratio < 0.25 {
+            trust_radius *= 0.7;
+        } else if ratio > 0.75 {
+            trust_radius *= 1.2;
+        }
+        delta_e = e_trial - e_elec;
+        C = C_trial;
+        D = D_trial;
+        F = F_trial;
+        e_elec = e_trial;
+    }
+    if options.verbose && converged {
+        println!("Convergence achieved!");
+    }
+
+    let (eps_alpha, C_alpha) = canonicalize(&C[0], &F[0], nalpha);
+    let (eps_beta, C_beta) = canonicalize(&C[1], &F[1], nbeta);
+    let [D_alpha, D_beta] = D;
+    let [F_alpha, F_beta] = F;
+
+    UHFResult {
+        energy: e_elec + e_nuc,
+        e_nuc,
+        C_alpha,
+        C_beta,
+        eps_alpha,
+        eps_beta,
+        D_alpha,
+        D_beta,
+        F_alpha,
+        F_beta,
+        nalpha,
+        nbeta,
+        iterations: iteration,
+        converged,
+        checkpoint_error: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_soscf_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let reference = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+
+        // From the core Hamiltonian guess, the second-order optimizer
+        // converges in a handful of steps.
+        let S = basis::S(&basis_set);
+        let H = scf::core_hamiltonian(&basis_set, &atomcoords, &atomnos);
+        let (_, C) = scf::diagonalize(&H, &scf::symmetric_orthogonalization(&S));
+        let result = rhf(&basis_set, &atomcoords, &atomnos, &C, 5, &options);
+        assert!(result.converged);
+        assert!(result.iterations < 10);
+        assert!((result.energy - reference.energy).abs() < 1.0e-9);
+        assert!((&result.eps - &reference.eps).fold(0.0f64, |acc, x| acc.max(x.abs())) < 1.0e-6);
+
+        // Switching over from the Roothaan iterations straight away gives
+        // the same result.
+        let options = SCFOptions {
+            second_order: Some(SOSCFOptions {
+                stall_iterations: 0,
+                ..Default::default()
+            }),
+            ..options
+        };
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        assert!(result.converged);
+        assert!((result.energy - reference.energy).abs() < 1.0e-9);
+    }
+
+    #[test]
+    fn test_soscf_uhf_water_cation() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let reference = scf::uhf(&basis_set, &atomcoords, &atomnos, 5, 4, &options);
+        assert!(reference.converged);
+
+        // Handing over from the Roothaan iterations straight away reaches
+        // the same doublet.
+        let options = SCFOptions {
+            second_order: Some(SOSCFOptions {
+                stall_iterations: 0,
+                ..Default::default()
+            }),
+            ..options
+        };
+        let result = scf::uhf(&basis_set, &atomcoords, &atomnos, 5, 4, &options);
+        assert!(result.converged);
+        assert!((result.energy - reference.energy).abs() < 1.0e-9);
+        let spin = (&result.D_alpha - &result.D_beta)
+            .dot(&basis::S(&basis_set))
+            .diag()
+            .sum();
+        assert!((spin - 1.0).abs() < 1.0e-10);
+    }
+}
@@ -0,0 +1,388 @@
+#![allow(non_snake_case)]
+
+//! Stability analysis of SCF solutions: the lowest eigenvalues of the
+//! orbital Hessian, found by Davidson's method, for rotations that keep the
+//! kind of wavefunction (internal) and for rotations that break the
+//! spin restriction or make the orbitals complex (external).
+//!
+//! For real orbitals the Hessian is 4 (A + B) and the real-to-complex one
+//! 4 (A - B), in terms of the response matrices of `response`, so the
+//! eigenvalues here are those of A + B and A - B. A negative eigenvalue
+//! means the solution is a saddle point, and following the eigenvector
+//! leads to a lower energy.
+//!
+//! R. Seeger and J. A. Pople, J. Chem. Phys. 66, 3045 (1977).
+
+use ndarray::{Array, Axis, Ix1, Ix2, Slice};
+
+use crate::basis;
+use crate::davidson::{self, DavidsonOptions, DavidsonResult};
+use crate::guess::Guess;
+use crate::response::{OrbitalHessian, Spin};
+use crate::scf::{self, JKAlgorithm, JKEngine, RHFResult, SCFOptions, UHFResult};
+use crate::soscf;
+
+#[derive(Clone, Debug)]
+pub struct StabilityOptions {
+    /// Number of eigenvalues to find for each Hessian.
+    pub nroots: usize,
+    pub davidson: DavidsonOptions,
+    /// Eigenvalues below this are treated as instabilities.
+    pub threshold: f64,
+    /// Length of the orbital rotation along the unstable eigenvector when
+    /// following an instability.
+    pub step: f64,
+}
+
+impl Default for StabilityOptions {
+    fn default() -> StabilityOptions {
+        StabilityOptions {
+            nroots: 3,
+            davidson: DavidsonOptions::default(),
+            threshold: -1.0e-5,
+            step: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StabilityResult {
+    /// Real rotations within RHF, or within UHF.
+    pub internal: DavidsonResult,
+    /// Real rotations from RHF to UHF, which are the triplet rotations; only
+    /// for an RHF reference.
+    pub rhf_to_uhf: Option<DavidsonResult>,
+    /// Imaginary rotations, making the orbitals complex.
+    pub real_to_complex: DavidsonResult,
+}
+
+impl StabilityResult {
+    pub fn is_stable(&self, threshold: f64) -> bool {
+        let lowest = |result: &DavidsonResult| result.eigenvalues[0];
+        lowest(&self.internal) >= threshold
+            && self
+                .rhf_to_uhf
+                .as_ref()
+                .is_none_or(|r| lowest(r) >= threshold)
+            && lowest(&self.real_to_complex) >= threshold
+    }
+}
+
+/// The eigenvector of the lowest eigenvalue, if that is an instability.
+fn unstable_direction(result: &DavidsonResult, threshold: f64) -> Option<Array<f64, Ix1>> {
+    if result.eigenvalues[0] < threshold {
+        Some(result.eigenvectors.column(0).to_owned())
+    } else {
+        None
+    }
+}
+
+/// Rotate orbitals along a direction with the `(nocc, nvirt)` layout of the
+/// response vectors.
+fn rotate_along(C: &Array<f64, Ix2>, nocc: usize, direction: &[f64], step: f64) -> Array<f64, Ix2> {
+    let nvirt = C.shape()[1] - nocc;
+    let x = Array::from_shape_vec((nocc, nvirt), direction.to_vec()).unwrap();
+    soscf::rotate(C, &(step * &x.t()))
+}
+
+/// The lowest eigenpairs of delta + the two-electron part `coupling` of one
+/// of the RHF Hessians.
+fn rhf_hessian_roots<G>(
+    hessian: &OrbitalHessian,
+    options: &StabilityOptions,
+    coupling: G,
+) -> DavidsonResult
+where
+    G: Fn(&Array<f64, Ix2>) -> Array<f64, Ix2>,
+{
+    let (nocc, nvirt) = (hessian.nocc(), hessian.nvirt());
+    let dim = nocc * nvirt;
+    let diagonal = hessian.delta.clone().into_shape_with_order(dim).unwrap();
+    davidson::davidson(&diagonal, options.nroots, &options.davidson, |v| {
+        let x = v.clone().into_shape_with_order((nocc, nvirt)).unwrap();
+        (&hessian.delta * &x + coupling(&x))
+            .into_shape_with_order(dim)
+            .unwrap()
+    })
+}
+
+pub fn rhf_stability(
+    basis_set: &basis::Basis,
+    scf: &RHFResult,
+    algorithm: &JKAlgorithm,
+    options: &StabilityOptions,
+) -> StabilityResult {
+    let hessian = OrbitalHessian::new(basis_set, scf, algorithm);
+    StabilityResult {
+        internal: rhf_hessian_roots(&hessian, options, |x| {
+            hessian.coupling_plus(x, Spin::Singlet)
+        }),
+        rhf_to_uhf: Some(rhf_hessian_roots(&hessian, options, |x| {
+            hessian.coupling_plus(x, Spin::Triplet)
+        })),
+        real_to_complex: rhf_hessian_roots(&hessian, options, |x| hessian.coupling_minus(x)),
+    }
+}
+
+pub fn uhf_stability(
+    basis_set: &basis::Basis,
+    scf: &UHFResult,
+    algorithm: &JKAlgorithm,
+    options: &StabilityOptions,
+) -> StabilityResult {
+    let jk = JKEngine::new(basis_set, algorithm);
+    let spins = [
+        (&scf.C_alpha, &scf.eps_alpha, scf.nalpha),
+        (&scf.C_beta, &scf.eps_beta, scf.nbeta),
+    ];
+    let blocks: Vec<_> = spins
+        .iter()
+        .map(|&(C, eps, nocc)| {
+            let nmo = C.shape()[1];
+            let delta = Array::from_shape_fn((nocc, nmo - nocc), |(i, a)| eps[nocc + a] - eps[i]);
+            let C_occ = C.slice_axis(Axis(1), Slice::from(..nocc)).to_owned();
+            let C_virt = C.slice_axis(Axis(1), Slice::from(nocc..)).to_owned();
+            (C_occ, C_virt, delta)
+        })
+        .collect();
+    let sizes: Vec<usize> = blocks.iter().map(|b| b.2.len()).collect();
+    let diagonal = Array::from_iter(blocks.iter().flat_map(|b| b.2.iter().cloned()));
+
+    // Split a trial vector into the AO densities C_occ x C_virt^T of each
+    // spin.
+    let densities = |v: &Array<f64, Ix1>| -> Vec<Array<f64, Ix2>> {
+        let (va, vb) = v.view().split_at(Axis(0), sizes[0]);
+        [va, vb]
+            .iter()
+            .zip(blocks.iter())
+            .map(|(v, (C_occ, C_virt, delta))| {
+                let x = v.to_owned().into_shape_with_order(delta.dim()).unwrap();
+                C_occ.dot(&x).dot(&C_virt.t())
+            })
+            .collect()
+    };
+    // Assemble (delta x + C_occ^T G C_virt) for both spins from the AO
+    // two-electron terms G of each.
+    let assemble = |v: &Array<f64, Ix1>, G: &[Array<f64, Ix2>]| {
+        let (va, vb) = v.view().split_at(Axis(0), sizes[0]);
+        let parts: Vec<Array<f64, Ix1>> = [va, vb]
+            .iter()
+            .zip(blocks.iter())
+            .zip(G.iter())
+            .map(|((v, (C_occ, C_virt, delta)), G)| {
+                let x = v.to_owned().into_shape_with_order(delta.dim()).unwrap();
+                (delta * &x + C_occ.t().dot(G).dot(C_virt))
+                    .into_shape_with_order(delta.len())
+                    .unwrap()
+            })
+            .collect();
+        ndarray::concatenate(Axis(0), &[parts[0].view(), parts[1].view()]).unwrap()
+    };
+
+    let internal = davidson::davidson(&diagonal, options.nroots, &options.davidson, |v| {
+        let d = densities(v);
+        let (J_alpha, K_alpha) = jk.build(&(&d[0] + &d[0].t()));
+        let (J_beta, K_beta) = jk.build(&(&d[1] + &d[1].t()));
+        let J = &J_alpha + &J_beta;
+        assemble(v, &[&J - &K_alpha, &J - &K_beta])
+    });
+    let real_to_complex = davidson::davidson(&diagonal, options.nroots, &options.davidson, |v| {
+        let d = densities(v);
+        let G: Vec<_> = d
+            .iter()
+            .map(|d| -jk.build_general(&(d - &d.t())).1)
+            .collect();
+        assemble(v, &G)
+    });
+    StabilityResult {
+        internal,
+        rhf_to_uhf: None,
+        real_to_complex,
+    }
+}
+
+/// The options for the SCF that follows an instability, which starts from
+/// the rotated orbitals rather than any restart, and must not overwrite the
+/// checkpoint of the SCF being followed.
+fn follow_up_options(scf_options: &SCFOptions) -> SCFOptions {
+    SCFOptions {
+        restart: None,
+        checkpoint: None,
+        ..scf_options.clone()
+    }
+}
+
+/// Follow an internal instability of an RHF solution by rotating the
+/// orbitals along it and restarting RHF, or `None` if it is stable.
+pub fn follow_rhf_instability(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    scf: &RHFResult,
+    stability: &StabilityResult,
+    scf_options: &SCFOptions,
+    options: &StabilityOptions,
+) -> Option<RHFResult> {
+    let direction = unstable_direction(&stability.internal, options.threshold)?;
+    let C = rotate_along(
+        &scf.C,
+        scf.nocc,
+        direction.as_slice().unwrap(),
+        options.step,
+    );
+    let scf_options = SCFOptions {
+        guess: Guess::Read {
+            basis: basis_set.clone(),
+            C,
+        },
+        ..follow_up_options(scf_options)
+    };
+    Some(scf::rhf(
+        basis_set,
+        atomcoords,
+        atomnos,
+        scf.nocc,
+        &scf_options,
+    ))
+}
+
+/// Follow an RHF to UHF instability by rotating the alpha and beta orbitals
+/// in opposite directions and starting UHF from them, or `None` if there is
+/// no such instability.
+pub fn follow_rhf_to_uhf(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    scf: &RHFResult,
+    stability: &StabilityResult,
+    scf_options: &SCFOptions,
+    options: &StabilityOptions,
+) -> Option<UHFResult> {
+    let direction = unstable_direction(stability.rhf_to_uhf.as_ref()?, options.threshold)?;
+    let direction = direction.as_slice().unwrap();
+    let nocc = scf.nocc;
+    let C_alpha = rotate_along(&scf.C, nocc, direction, options.step);
+    let C_beta = rotate_along(&scf.C, nocc, direction, -options.step);
+    let densities = (
+        scf::build_density(&C_alpha, nocc),
+        scf::build_density(&C_beta, nocc),
+    );
+    Some(scf::uhf_from_densities(
+        basis_set,
+        atomcoords,
+        atomnos,
+        nocc,
+        nocc,
+        densities,
+        &follow_up_options(scf_options),
+    ))
+}
+
+/// Follow an internal instability of a UHF solution by rotating the
+/// orbitals of each spin along it and restarting UHF, or `None` if it is
+/// stable.
+pub fn follow_uhf_instability(
+    basis_set: &basis::Basis,
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    scf: &UHFResult,
+    stability: &StabilityResult,
+    scf_options: &SCFOptions,
+    options: &StabilityOptions,
+) -> Option<UHFResult> {
+    let direction = unstable_direction(&stability.internal, options.threshold)?;
+    let nmo = scf.C_alpha.shape()[1];
+    let (direction_alpha, direction_beta) = direction
+        .as_slice()
+        .unwrap()
+        .split_at(scf.nalpha * (nmo - scf.nalpha));
+    let C_alpha = rotate_along(&scf.C_alpha, scf.nalpha, direction_alpha, options.step);
+    let C_beta = rotate_along(&scf.C_beta, scf.nbeta, direction_beta, options.step);
+    let densities = (
+        scf::build_density(&C_alpha, scf.nalpha),
+        scf::build_density(&C_beta, scf.nbeta),
+    );
+    Some(scf::uhf_from_densities(
+        basis_set,
+        atomcoords,
+        atomnos,
+        scf.nalpha,
+        scf.nbeta,
+        densities,
+        &follow_up_options(scf_options),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn test_stability_water_sto3g() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let scf_options = SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let options = StabilityOptions::default();
+        let rhf = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &scf_options);
+        let stability = rhf_stability(&basis_set, &rhf, &JKAlgorithm::InMemory, &options);
+        assert!(stability.is_stable(options.threshold));
+        assert!(follow_rhf_instability(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            &rhf,
+            &stability,
+            &scf_options,
+            &options
+        )
+        .is_none());
+
+        // The UHF Hessian of a closed-shell solution holds both the singlet
+        // and the triplet RHF ones.
+        let uhf = scf::uhf(&basis_set, &atomcoords, &atomnos, 5, 5, &scf_options);
+        let stability_uhf = uhf_stability(&basis_set, &uhf, &JKAlgorithm::InMemory, &options);
+        let lowest = stability.internal.eigenvalues[0]
+            .min(stability.rhf_to_uhf.as_ref().unwrap().eigenvalues[0]);
+        assert!((stability_uhf.internal.eigenvalues[0] - lowest).abs() < 1.0e-6);
+        assert!(
+            (stability_uhf.real_to_complex.eigenvalues[0]
+                - stability.real_to_complex.eigenvalues[0])
+                .abs()
+                < 1.0e-6
+        );
+    }
+
+    #[test]
+    fn test_stretched_h2_breaks_spin_symmetry() {
+        let atomcoords = vec![[0.0, 0.0, 0.0], [0.0, 0.0, 4.0]];
+        let atomnos = vec![1, 1];
+        let basis_set = basis::Basis::from_json(&atomnos, &atomcoords, testing::STO3G_JSON);
+        let scf_options = SCFOptions {
+            jk: JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let options = StabilityOptions::default();
+        let rhf = scf::rhf(&basis_set, &atomcoords, &atomnos, 1, &scf_options);
+        let stability = rhf_stability(&basis_set, &rhf, &JKAlgorithm::InMemory, &options);
+        assert!(stability.internal.eigenvalues[0] > 0.0);
+        assert!(stability.rhf_to_uhf.as_ref().unwrap().eigenvalues[0] < 0.0);
+
+        let uhf = follow_rhf_to_uhf(
+            &basis_set,
+            &atomcoords,
+            &atomnos,
+            &rhf,
+            &stability,
+            &scf_options,
+            &options,
+        )
+        .unwrap();
+        assert!(uhf.converged);
+        assert!(uhf.energy < rhf.energy - 1.0e-3);
+        let stability_uhf = uhf_stability(&basis_set, &uhf, &JKAlgorithm::InMemory, &options);
+        assert!(stability_uhf.is_stable(options.threshold));
+    }
+}
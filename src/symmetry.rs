@@ -0,0 +1,884 @@
+#![allow(non_snake_case)]
+
+//! Molecular point-group symmetry, limited to D2h and its subgroups, whose
+//! operations are all diagonal in a suitable frame and whose irreps are all
+//! one-dimensional.
+//!
+//! `detect` finds the largest such group, moves the molecule into the
+//! standard orientation for it and symmetrizes the geometry. A basis built
+//! on that geometry can then be split into symmetry-adapted linear
+//! combinations (SALCs) of basis functions, which block-diagonalize the Fock
+//! matrix by irrep. Molecules with higher symmetry, such as NH3 (C3v) or
+//! benzene (D6h), get the largest abelian subgroup of their point group.
+
+use std::cmp::Reverse;
+
+use ndarray::{Array, Ix1, Ix2};
+
+use crate::basis::Basis;
+use crate::elements;
+use crate::scf;
+
+/// The operations of D2h in the standard orientation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Operation {
+    E,
+    C2z,
+    C2y,
+    C2x,
+    I,
+    SigmaXY,
+    SigmaXZ,
+    SigmaYZ,
+}
+
+impl Operation {
+    /// The factor the operation multiplies x, y and z by.
+    pub fn signs(self) -> [f64; 3] {
+        match self {
+            Operation::E => [1.0, 1.0, 1.0],
+            Operation::C2z => [-1.0, -1.0, 1.0],
+            Operation::C2y => [-1.0, 1.0, -1.0],
+            Operation::C2x => [1.0, -1.0, -1.0],
+            Operation::I => [-1.0, -1.0, -1.0],
+            Operation::SigmaXY => [1.0, 1.0, -1.0],
+            Operation::SigmaXZ => [1.0, -1.0, 1.0],
+            Operation::SigmaYZ => [-1.0, 1.0, 1.0],
+        }
+    }
+
+    pub fn apply(self, r: [f64; 3]) -> [f64; 3] {
+        let signs = self.signs();
+        [signs[0] * r[0], signs[1] * r[1], signs[2] * r[2]]
+    }
+
+    /// The character of x^i y^j z^k, whose parity in each coordinate fixes
+    /// the irrep it belongs to.
+    fn character(self, powers: [usize; 3]) -> f64 {
+        let signs = self.signs();
+        (0..3).map(|k| signs[k].powi(powers[k] as i32)).product()
+    }
+}
+
+/// D2h and its subgroups. Axis-dependent groups are in the standard
+/// orientation: the C2 axis of C2, C2v and C2h along z, and the mirror
+/// plane of Cs the xy plane.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PointGroup {
+    C1,
+    Ci,
+    C2,
+    Cs,
+    D2,
+    C2v,
+    C2h,
+    D2h,
+}
+
+impl PointGroup {
+    pub fn name(self) -> &'static str {
+        match self {
+            PointGroup::C1 => "C1",
+            PointGroup::Ci => "Ci",
+            PointGroup::C2 => "C2",
+            PointGroup::Cs => "Cs",
+            PointGroup::D2 => "D2",
+            PointGroup::C2v => "C2v",
+            PointGroup::C2h => "C2h",
+            PointGroup::D2h => "D2h",
+        }
+    }
+
+    /// The operations in the order of Cotton's character tables.
+    pub fn operations(self) -> &'static [Operation] {
+        use Operation::*;
+        match self {
+            PointGroup::C1 => &[E],
+            PointGroup::Ci => &[E, I],
+            PointGroup::C2 => &[E, C2z],
+            PointGroup::Cs => &[E, SigmaXY],
+            PointGroup::D2 => &[E, C2z, C2y, C2x],
+            PointGroup::C2v => &[E, C2z, SigmaXZ, SigmaYZ],
+            PointGroup::C2h => &[E, C2z, I, SigmaXY],
+            PointGroup::D2h => &[E, C2z, C2y, C2x, I, SigmaXY, SigmaXZ, SigmaYZ],
+        }
+    }
+
+    /// The irreps in Cotton order, each with the parities in x, y and z of
+    /// a monomial that transforms like it.
+    fn irrep_table(self) -> &'static [(&'static str, [usize; 3])] {
+        match self {
+            PointGroup::C1 => &[("A", [0, 0, 0])],
+            PointGroup::Ci => &[("Ag", [0, 0, 0]), ("Au", [1, 1, 1])],
+            PointGroup::C2 => &[("A", [0, 0, 0]), ("B", [1, 0, 0])],
+            PointGroup::Cs => &[("A'", [0, 0, 0]), ("A\"", [0, 0, 1])],
+            PointGroup::D2 => &[
+                ("A", [0, 0, 0]),
+                ("B1", [0, 0, 1]),
+                ("B2", [0, 1, 0]),
+                ("B3", [1, 0, 0]),
+            ],
+            PointGroup::C2v => &[
+                ("A1", [0, 0, 0]),
+                ("A2", [1, 1, 0]),
+                ("B1", [1, 0, 0]),
+                ("B2", [0, 1, 0]),
+            ],
+            PointGroup::C2h => &[
+                ("Ag", [0, 0, 0]),
+                ("Bg", [1, 0, 1]),
+                ("Au", [0, 0, 1]),
+                ("Bu", [1, 0, 0]),
+            ],
+            PointGroup::D2h => &[
+                ("Ag", [0, 0, 0]),
+                ("B1g", [1, 1, 0]),
+                ("B2g", [1, 0, 1]),
+                ("B3g", [0, 1, 1]),
+                ("Au", [1, 1, 1]),
+                ("B1u", [0, 0, 1]),
+                ("B2u", [0, 1, 0]),
+                ("B3u", [1, 0, 0]),
+            ],
+        }
+    }
+
+    pub fn order(self) -> usize {
+        self.operations().len()
+    }
+
+    pub fn irreps(self) -> Vec<&'static str> {
+        self.irrep_table().iter().map(|(name, _)| *name).collect()
+    }
+
+    /// The characters of an irrep under each operation.
+    pub fn characters(self, irrep: usize) -> Vec<f64> {
+        let (_, parities) = self.irrep_table()[irrep];
+        self.operations()
+            .iter()
+            .map(|op| op.character(parities))
+            .collect()
+    }
+
+    /// The irrep that x^i y^j z^k belongs to.
+    pub fn irrep_of(self, powers: [usize; 3]) -> usize {
+        (0..self.order())
+            .find(|&irrep| {
+                self.operations()
+                    .iter()
+                    .zip(self.characters(irrep))
+                    .all(|(op, chi)| op.character(powers) == chi)
+            })
+            .unwrap()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SymmetryOptions {
+    /// How far (in bohr) an atom may be from the image of an equivalent atom
+    /// for an operation to count as a symmetry.
+    pub tolerance: f64,
+}
+
+impl Default for SymmetryOptions {
+    fn default() -> SymmetryOptions {
+        SymmetryOptions { tolerance: 1.0e-2 }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Symmetry {
+    pub point_group: PointGroup,
+    /// The geometry moved to the center of mass, rotated into the standard
+    /// orientation and made exactly symmetric.
+    pub atomcoords: Vec<[f64; 3]>,
+    /// For each operation, the atom that each atom is taken to.
+    pub atom_maps: Vec<Vec<usize>>,
+}
+
+fn dot(a: [f64; 3], b: [f64; 3]) -> f64 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+fn cross(a: [f64; 3], b: [f64; 3]) -> [f64; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalized(a: [f64; 3]) -> Option<[f64; 3]> {
+    let norm = dot(a, a).sqrt();
+    if norm > 1.0e-6 {
+        Some([a[0] / norm, a[1] / norm, a[2] / norm])
+    } else {
+        None
+    }
+}
+
+/// Some unit vector perpendicular to `d`.
+fn perpendicular(d: [f64; 3]) -> [f64; 3] {
+    let k = (0..3)
+        .min_by(|&a, &b| d[a].abs().partial_cmp(&d[b].abs()).unwrap())
+        .unwrap();
+    let mut axis = [0.0; 3];
+    axis[k] = 1.0;
+    normalized(cross(d, axis)).unwrap()
+}
+
+/// The atom each atom is taken to by `op`, if every atom lands within
+/// `tolerance` of an atom of the same element.
+fn map_atoms<F>(
+    atomcoords: &[[f64; 3]],
+    atomnos: &[u64],
+    op: F,
+    tolerance: f64,
+) -> Option<Vec<usize>>
+where
+    F: Fn([f64; 3]) -> [f64; 3],
+{
+    atomcoords
+        .iter()
+        .zip(atomnos)
+        .map(|(&r, z)| {
+            let image = op(r);
+            (0..atomcoords.len()).find(|&j| {
+                atomnos[j] == *z && (0..3).all(|k| (image[k] - atomcoords[j][k]).abs() < tolerance)
+            })
+        })
+        .collect()
+}
+
+/// Find the largest subgroup of D2h that the molecule has, and return the
+/// geometry in the standard orientation for it, symmetrized.
+///
+/// Candidate C2 axes and mirror plane normals are the directions of the
+/// atoms, of the midpoints of and differences between pairs of atoms, and
+/// of the normals of planes through pairs of atoms, all from the center of
+/// mass. When several orientations are possible, a planar molecule is put
+/// perpendicular to x, and otherwise z is the axis with the most atoms on
+/// it.
+pub fn detect(atomcoords: &[[f64; 3]], atomnos: &[u64], options: &SymmetryOptions) -> Symmetry {
+    let tolerance = options.tolerance;
+    let total_mass: f64 = atomnos.iter().map(|&z| elements::mass(z)).sum();
+    let mut center = [0.0; 3];
+    for (r, &z) in atomcoords.iter().zip(atomnos) {
+        for k in 0..3 {
+            center[k] += elements::mass(z) * r[k] / total_mass;
+        }
+    }
+    let coords: Vec<[f64; 3]> = atomcoords
+        .iter()
+        .map(|r| [r[0] - center[0], r[1] - center[1], r[2] - center[2]])
+        .collect();
+
+    let mut candidates = vec![[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for (i, &a) in coords.iter().enumerate() {
+        candidates.push(a);
+        for &b in &coords[..i] {
+            candidates.push([a[0] + b[0], a[1] + b[1], a[2] + b[2]]);
+            candidates.push([a[0] - b[0], a[1] - b[1], a[2] - b[2]]);
+            candidates.push(cross(a, b));
+        }
+    }
+    let mut directions: Vec<[f64; 3]> = Vec::new();
+    for d in candidates.into_iter().filter_map(normalized) {
+        if directions.iter().all(|&e| dot(d, e).abs() < 1.0 - 1.0e-6) {
+            directions.push(d);
+        }
+    }
+    // A linear molecule has no atom or plane off its axis to suggest the
+    // perpendicular directions.
+    if let Some(&axis) = directions.iter().find(|&&d| {
+        coords
+            .iter()
+            .all(|&r| dot(cross(r, d), cross(r, d)).sqrt() < tolerance)
+    }) {
+        let x = perpendicular(axis);
+        directions.push(x);
+        directions.push(cross(axis, x));
+    }
+
+    let is_symmetry =
+        |op: &dyn Fn([f64; 3]) -> [f64; 3]| map_atoms(&coords, atomnos, op, tolerance).is_some();
+    let rotation = |d: [f64; 3]| {
+        move |r: [f64; 3]| {
+            let p = 2.0 * dot(d, r);
+            [p * d[0] - r[0], p * d[1] - r[1], p * d[2] - r[2]]
+        }
+    };
+    let reflection = |d: [f64; 3]| {
+        move |r: [f64; 3]| {
+            let p = 2.0 * dot(d, r);
+            [r[0] - p * d[0], r[1] - p * d[1], r[2] - p * d[2]]
+        }
+    };
+    let c2_axes: Vec<[f64; 3]> = directions
+        .iter()
+        .cloned()
+        .filter(|&d| is_symmetry(&rotation(d)))
+        .collect();
+    let mirror_normals: Vec<[f64; 3]> = directions
+        .iter()
+        .cloned()
+        .filter(|&d| is_symmetry(&reflection(d)))
+        .collect();
+    let inversion = is_symmetry(&|r: [f64; 3]| [-r[0], -r[1], -r[2]]);
+
+    let perpendicular_to = |a: [f64; 3], b: [f64; 3]| dot(a, b).abs() < 1.0e-4;
+    let is_plane_normal = |d: [f64; 3]| coords.iter().all(|&r| dot(r, d).abs() < tolerance);
+    let atoms_on_axis = |d: [f64; 3]| {
+        coords
+            .iter()
+            .filter(|&&r| dot(cross(r, d), cross(r, d)).sqrt() < tolerance)
+            .count()
+    };
+
+    // The group and its axes as (x, z); y completes a right-handed frame.
+    let mut triple = None;
+    'search: for (i, &a) in c2_axes.iter().enumerate() {
+        for (j, &b) in c2_axes.iter().enumerate().skip(i + 1) {
+            if !perpendicular_to(a, b) {
+                continue;
+            }
+            for &c in &c2_axes[j + 1..] {
+                if perpendicular_to(a, c) && perpendicular_to(b, c) {
+                    triple = Some([a, b, c]);
+                    break 'search;
+                }
+            }
+        }
+    }
+    let (point_group, x, z) = if let Some(mut axes) = triple {
+        if let Some(k) = (0..3).find(|&k| is_plane_normal(axes[k])) {
+            axes.swap(0, k);
+        }
+        // Keep x as the plane normal if there is one, and take the first of
+        // the axes with the most atoms on them as z.
+        let start = if is_plane_normal(axes[0]) { 1 } else { 0 };
+        let k = (start..3)
+            .max_by_key(|&k| (atoms_on_axis(axes[k]), Reverse(k)))
+            .unwrap();
+        axes.swap(2, k);
+        let group = if inversion {
+            PointGroup::D2h
+        } else {
+            PointGroup::D2
+        };
+        (group, axes[0], axes[2])
+    } else if let Some((z, x)) = c2_axes.iter().find_map(|&z| {
+        let normals: Vec<[f64; 3]> = mirror_normals
+            .iter()
+            .cloned()
+            .filter(|&n| perpendicular_to(n, z))
+            .collect();
+        let x = normals
+            .iter()
+            .find(|&&n| is_plane_normal(n))
+            .or_else(|| normals.first())?;
+        Some((z, *x))
+    }) {
+        (PointGroup::C2v, x, z)
+    } else if let (Some(&z), true) = (c2_axes.first(), inversion) {
+        (PointGroup::C2h, perpendicular(z), z)
+    } else if let Some(&z) = c2_axes.first() {
+        (PointGroup::C2, perpendicular(z), z)
+    } else if let Some(&z) = mirror_normals.first() {
+        (PointGroup::Cs, perpendicular(z), z)
+    } else if inversion {
+        (PointGroup::Ci, [1.0, 0.0, 0.0], [0.0, 0.0, 1.0])
+    } else {
+        (PointGroup::C1, [1.0, 0.0, 0.0], [0.0, 0.0, 1.0])
+    };
+    let y = cross(z, x);
+    let oriented: Vec<[f64; 3]> = coords
+        .iter()
+        .map(|&r| [dot(x, r), dot(y, r), dot(z, r)])
+        .collect();
+
+    let atom_maps: Vec<Vec<usize>> = point_group
+        .operations()
+        .iter()
+        .map(|&op| {
+            map_atoms(&oriented, atomnos, |r| op.apply(r), tolerance)
+                .expect("symmetry operation lost in reorientation")
+        })
+        .collect();
+    // Average each atom with the images of the atoms that are taken to it.
+    let order = point_group.order() as f64;
+    let symmetrized = (0..oriented.len())
+        .map(|i| {
+            let mut r = [0.0; 3];
+            for (&op, map) in point_group.operations().iter().zip(&atom_maps) {
+                let image = op.apply(oriented[map[i]]);
+                for k in 0..3 {
+                    r[k] += image[k] / order;
+                }
+            }
+            r
+        })
+        .collect();
+    Symmetry {
+        point_group,
+        atomcoords: symmetrized,
+        atom_maps,
+    }
+}
+
+/// Where each operation takes each basis function: g phi_mu = sign phi_nu,
+/// since Cartesian functions only pick up the signs of their powers and
+/// move to the equivalent atom.
+#[derive(Clone, Debug)]
+pub struct FunctionSymmetry {
+    pub point_group: PointGroup,
+    /// For each operation, the image (nu, sign) of each function.
+    images: Vec<Vec<(usize, f64)>>,
+}
+
+impl FunctionSymmetry {
+    /// The basis must be built on the geometry in `symmetry`.
+    pub fn new(basis_set: &Basis, symmetry: &Symmetry) -> FunctionSymmetry {
+        let point_group = symmetry.point_group;
+        let nbasis = basis_set.nbasis();
+        let function_atoms = basis_set.function_atoms();
+        // Equivalent atoms are the same element, so the functions on them
+        // match one for one.
+        let atom_functions: Vec<Vec<usize>> = (0..basis_set.natoms())
+            .map(|atom| {
+                (0..nbasis)
+                    .filter(|&mu| function_atoms[mu] == atom)
+                    .collect()
+            })
+            .collect();
+        let images = point_group
+            .operations()
+            .iter()
+            .zip(&symmetry.atom_maps)
+            .map(|(&op, map)| {
+                (0..nbasis)
+                    .map(|mu| {
+                        let a = &basis_set.cgtos[mu];
+                        let atom = function_atoms[mu];
+                        let local = atom_functions[atom]
+                            .iter()
+                            .position(|&nu| nu == mu)
+                            .unwrap();
+                        let nu = atom_functions[map[atom]][local];
+                        let b = &basis_set.cgtos[nu];
+                        let image = op.apply(a.origin);
+                        assert!(
+                            b.powers == a.powers
+                                && (0..3).all(|k| (image[k] - b.origin[k]).abs() < 1.0e-8),
+                            "basis set is not on the symmetrized geometry"
+                        );
+                        (nu, op.character(a.powers))
+                    })
+                    .collect()
+            })
+            .collect();
+        FunctionSymmetry {
+            point_group,
+            images,
+        }
+    }
+
+    /// The images of one function under every operation.
+    pub fn images(&self, mu: usize) -> Vec<(usize, f64)> {
+        self.images.iter().map(|image| image[mu]).collect()
+    }
+
+    /// Whether point charges at `atomcoords` are unchanged by every
+    /// operation, so that their potential is totally symmetric.
+    pub fn is_invariant(&self, atomcoords: &[[f64; 3]], atomnos: &[u64]) -> bool {
+        self.point_group
+            .operations()
+            .iter()
+            .all(|&op| map_atoms(atomcoords, atomnos, |r| op.apply(r), 1.0e-8).is_some())
+    }
+
+    /// The distinct images (mu >= nu) of the pair (mu, nu) with their signs,
+    /// or `None` unless it is the first of them, which is the only one whose
+    /// integral needs computing.
+    pub(crate) fn pair_images(&self, mu: usize, nu: usize) -> Option<Vec<([usize; 2], f64)>> {
+        let mut images: Vec<([usize; 2], f64)> = Vec::with_capacity(self.images.len());
+        for image in &self.images {
+            let ((a, sa), (b, sb)) = (image[mu], image[nu]);
+            let pair = [a.max(b), a.min(b)];
+            if pair < [mu, nu] {
+                return None;
+            }
+            if images.iter().all(|(other, _)| *other != pair) {
+                images.push((pair, sa * sb));
+            }
+        }
+        Some(images)
+    }
+
+    /// Like `pair_images` for a quartet (mu nu|lambda sigma) in the
+    /// canonical order of `canonical_quartet`.
+    pub(crate) fn quartet_images(&self, quartet: [usize; 4]) -> Option<Vec<([usize; 4], f64)>> {
+        let mut images: Vec<([usize; 4], f64)> = Vec::with_capacity(self.images.len());
+        for image in &self.images {
+            let mut sign = 1.0;
+            let mut mapped = [0; 4];
+            for k in 0..4 {
+                let (nu, s) = image[quartet[k]];
+                mapped[k] = nu;
+                sign *= s;
+            }
+            let mapped = canonical_quartet(mapped);
+            if mapped < quartet {
+                return None;
+            }
+            if images.iter().all(|(other, _)| *other != mapped) {
+                images.push((mapped, sign));
+            }
+        }
+        Some(images)
+    }
+}
+
+/// The representative of (mu nu|lambda sigma) among the eight index
+/// permutations with the same value: mu >= nu, lambda >= sigma, and the pair
+/// (mu, nu) no smaller than (lambda, sigma).
+pub(crate) fn canonical_quartet(quartet: [usize; 4]) -> [usize; 4] {
+    let [a, b, c, d] = quartet;
+    let bra = [a.max(b), a.min(b)];
+    let ket = [c.max(d), c.min(d)];
+    if bra >= ket {
+        [bra[0], bra[1], ket[0], ket[1]]
+    } else {
+        [ket[0], ket[1], bra[0], bra[1]]
+    }
+}
+
+/// The basis functions combined into SALCs, grouped by irrep.
+#[derive(Clone, Debug)]
+pub struct SymmetryAdaptation {
+    pub point_group: PointGroup,
+    /// For each irrep, the SALCs as orthonormal columns of coefficients of
+    /// the basis functions.
+    pub salcs: Vec<Array<f64, Ix2>>,
+    /// For each irrep, the SALCs orthonormalized in the overlap metric,
+    /// X^T S X = 1.
+    orthogonalizers: Vec<Array<f64, Ix2>>,
+}
+
+impl SymmetryAdaptation {
+    /// Project every basis function onto each irrep. The basis must be built
+    /// on the geometry in `symmetry`.
+    pub fn new(basis_set: &Basis, symmetry: &Symmetry) -> SymmetryAdaptation {
+        let point_group = symmetry.point_group;
+        let nbasis = basis_set.nbasis();
+        let function_symmetry = FunctionSymmetry::new(basis_set, symmetry);
+        let S = crate::basis::S(basis_set);
+        let mut salcs = Vec::new();
+        let mut orthogonalizers = Vec::new();
+        for irrep in 0..point_group.order() {
+            let characters = point_group.characters(irrep);
+            let mut covered = vec![false; nbasis];
+            let mut columns: Vec<Array<f64, Ix1>> = Vec::new();
+            for mu in 0..nbasis {
+                if covered[mu] {
+                    continue;
+                }
+                let mut salc: Array<f64, _> = Array::zeros(nbasis);
+                for ((nu, sign), chi) in function_symmetry.images(mu).into_iter().zip(&characters) {
+                    salc[nu] += chi * sign;
+                    covered[nu] = true;
+                }
+                let norm = salc.dot(&salc).sqrt();
+                if norm > 1.0e-8 {
+                    columns.push(salc / norm);
+                }
+            }
+            let mut U: Array<f64, _> = Array::zeros((nbasis, columns.len()));
+            for (j, column) in columns.iter().enumerate() {
+                U.column_mut(j).assign(column);
+            }
+            let X = if columns.is_empty() {
+                U.clone()
+            } else {
+                U.dot(&scf::symmetric_orthogonalization(&U.t().dot(&S).dot(&U)))
+            };
+            salcs.push(U);
+            orthogonalizers.push(X);
+        }
+        assert_eq!(
+            salcs.iter().map(|U| U.shape()[1]).sum::<usize>(),
+            nbasis,
+            "basis set is not symmetric"
+        );
+        SymmetryAdaptation {
+            point_group,
+            salcs,
+            orthogonalizers,
+        }
+    }
+
+    /// The number of SALCs, and so of orbitals, in each irrep.
+    pub fn irrep_sizes(&self) -> Vec<usize> {
+        self.salcs.iter().map(|U| U.shape()[1]).collect()
+    }
+
+    /// Solve FC = SCE within each irrep, returning the orbitals in order of
+    /// energy together with the irrep of each.
+    pub fn diagonalize(
+        &self,
+        F: &Array<f64, Ix2>,
+    ) -> (Array<f64, Ix1>, Array<f64, Ix2>, Vec<usize>) {
+        let nbasis = F.shape()[0];
+        let mut orbitals: Vec<(f64, Array<f64, Ix1>, usize)> = Vec::with_capacity(nbasis);
+        for (irrep, X) in self.orthogonalizers.iter().enumerate() {
+            if X.shape()[1] == 0 {
+                continue;
+            }
+            let (eps, C) = scf::diagonalize(F, X);
+            for p in 0..eps.len() {
+                orbitals.push((eps[p], C.column(p).to_owned(), irrep));
+            }
+        }
+        orbitals.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        let eps = orbitals.iter().map(|o| o.0).collect();
+        let mut C: Array<f64, _> = Array::zeros((nbasis, nbasis));
+        for (p, orbital) in orbitals.iter().enumerate() {
+            C.column_mut(p).assign(&orbital.1);
+        }
+        let irreps = orbitals.iter().map(|o| o.2).collect();
+        (eps, C, irreps)
+    }
+
+    /// The irrep each orbital (column of `C`) has the largest component in.
+    pub fn orbital_irreps(&self, C: &Array<f64, Ix2>) -> Vec<usize> {
+        (0..C.shape()[1])
+            .map(|p| {
+                let weights: Vec<f64> = self
+                    .salcs
+                    .iter()
+                    .map(|U| U.t().dot(&C.column(p)).mapv(|c| c * c).sum())
+                    .collect();
+                (0..weights.len())
+                    .max_by(|&a, &b| weights[a].partial_cmp(&weights[b]).unwrap())
+                    .unwrap()
+            })
+            .collect()
+    }
+}
+
+/// Reorder orbitals sorted by energy so that the lowest `occupations[h]`
+/// of each irrep h come first, with both the occupied and the virtual
+/// orbitals still in order of energy.
+pub fn occupy(
+    eps: &Array<f64, Ix1>,
+    C: &Array<f64, Ix2>,
+    irreps: &[usize],
+    occupations: &[usize],
+) -> (Array<f64, Ix1>, Array<f64, Ix2>) {
+    let mut remaining = occupations.to_vec();
+    let mut occupied = Vec::new();
+    let mut virtual_ = Vec::new();
+    for (p, &irrep) in irreps.iter().enumerate() {
+        if remaining[irrep] > 0 {
+            remaining[irrep] -= 1;
+            occupied.push(p);
+        } else {
+            virtual_.push(p);
+        }
+    }
+    assert!(
+        remaining.iter().all(|&n| n == 0),
+        "more orbitals occupied in an irrep than it has"
+    );
+    let order: Vec<usize> = occupied.into_iter().chain(virtual_).collect();
+    let eps = order.iter().map(|&p| eps[p]).collect();
+    let C = C.select(ndarray::Axis(1), &order);
+    (eps, C)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::basis;
+    use crate::testing;
+
+    #[test]
+    fn test_detect_point_groups() {
+        let options = SymmetryOptions::default();
+        // Water starts out in the xy plane with its C2 axis along y, and
+        // slightly distorted.
+        let (mut atomcoords, atomnos) = testing::water();
+        atomcoords[1][0] += 1.0e-3;
+        let water = detect(&atomcoords, &atomnos, &options);
+        assert_eq!(water.point_group, PointGroup::C2v);
+        assert!(water.atomcoords.iter().all(|r| r[0] == 0.0));
+        assert_eq!(water.atomcoords[0][1], 0.0);
+        assert_eq!(water.atomcoords[1][1], -water.atomcoords[2][1]);
+        assert_eq!(water.atomcoords[1][2], water.atomcoords[2][2]);
+
+        let nitrogen = detect(&[[0.3, 0.2, 0.1], [1.0, 1.1, 1.2]], &[7, 7], &options);
+        assert_eq!(nitrogen.point_group, PointGroup::D2h);
+        assert!(nitrogen.atomcoords[0][2].abs() > 0.5);
+
+        let ethylene = [
+            [0.0, 0.0, 1.26],
+            [0.0, 0.0, -1.26],
+            [0.0, 1.74, 2.33],
+            [0.0, -1.74, 2.33],
+            [0.0, 1.74, -2.33],
+            [0.0, -1.74, -2.33],
+        ];
+        let ethylene = detect(&ethylene, &[6, 6, 1, 1, 1, 1], &options);
+        assert_eq!(ethylene.point_group, PointGroup::D2h);
+        assert!(ethylene.atomcoords.iter().all(|r| r[0] == 0.0));
+        assert!(ethylene.atomcoords[0][2].abs() > 1.0);
+
+        // Methane is Td, whose largest abelian subgroup is D2, and ammonia
+        // C3v, leaving only a mirror plane.
+        let methane = [
+            [0.0, 0.0, 0.0],
+            [1.2, 1.2, 1.2],
+            [-1.2, -1.2, 1.2],
+            [-1.2, 1.2, -1.2],
+            [1.2, -1.2, -1.2],
+        ];
+        let methane = detect(&methane, &[6, 1, 1, 1, 1], &options);
+        assert_eq!(methane.point_group, PointGroup::D2);
+        let ammonia = [
+            [0.0, 0.0, 0.2],
+            [1.77, 0.0, -0.5],
+            [-0.885, 1.533, -0.5],
+            [-0.885, -1.533, -0.5],
+        ];
+        let ammonia = detect(&ammonia, &[7, 1, 1, 1], &options);
+        assert_eq!(ammonia.point_group, PointGroup::Cs);
+        let hydrogen_peroxide = [
+            [0.0, 1.37, -0.1],
+            [0.0, -1.37, -0.1],
+            [1.8, 1.6, 0.8],
+            [-1.8, -1.6, 0.8],
+        ];
+        let hydrogen_peroxide = detect(&hydrogen_peroxide, &[8, 8, 1, 1], &options);
+        assert_eq!(hydrogen_peroxide.point_group, PointGroup::C2);
+    }
+
+    #[test]
+    fn test_symmetry_unique_integrals() {
+        let ethylene = [
+            [0.0, 0.0, 1.26],
+            [0.0, 0.0, -1.26],
+            [0.0, 1.74, 2.33],
+            [0.0, -1.74, 2.33],
+            [0.0, 1.74, -2.33],
+            [0.0, -1.74, -2.33],
+        ];
+        let atomnos = [6, 6, 1, 1, 1, 1];
+        let symmetry = detect(&ethylene, &atomnos, &SymmetryOptions::default());
+        let atomcoords = &symmetry.atomcoords;
+        let full = Basis::from_json(&atomnos, atomcoords, testing::STO3G_JSON);
+        let mut reduced = full.clone();
+        reduced.set_symmetry(&symmetry);
+        let close =
+            |A: &Array<f64, Ix2>, B: &Array<f64, Ix2>| (A - B).iter().all(|x| x.abs() < 1.0e-12);
+        assert!(close(&basis::S(&reduced), &basis::S(&full)));
+        assert!(close(&basis::T(&reduced), &basis::T(&full)));
+        assert!(close(
+            &basis::V(&reduced, atomcoords, &atomnos),
+            &basis::V(&full, atomcoords, &atomnos)
+        ));
+        // A probe charge off the symmetry elements must not use symmetry.
+        let probe = [[0.3, 0.5, 0.7]];
+        assert!(close(
+            &basis::V(&reduced, &probe, &[1]),
+            &basis::V(&full, &probe, &[1])
+        ));
+
+        // J and K are exact even for a density without the symmetry of the
+        // molecule, since every integral is reconstructed.
+        let nbasis = full.nbasis();
+        let D = Array::from_shape_fn((nbasis, nbasis), |(i, j)| {
+            (0.37 * i as f64 + 0.11 * j as f64 * j as f64).sin()
+        });
+        let (J_ref, K_ref) = basis::JK_inmem_general(&basis::build_I(&full), &D);
+        let mut J = Array::zeros((nbasis, nbasis));
+        let mut K = Array::zeros((nbasis, nbasis));
+        basis::JK_direct(&mut J, &mut K, &reduced, &D);
+        assert!(close(&J, &J_ref));
+        assert!(close(&K, &K_ref));
+
+        let function_symmetry = reduced.symmetry().unwrap();
+        let mut total = 0;
+        let mut unique = 0;
+        for mu in 0..nbasis {
+            for nu in 0..mu + 1 {
+                for lambda in 0..mu + 1 {
+                    let sigma_max = if lambda == mu { nu } else { lambda };
+                    for sigma in 0..sigma_max + 1 {
+                        total += 1;
+                        if function_symmetry
+                            .quartet_images([mu, nu, lambda, sigma])
+                            .is_some()
+                        {
+                            unique += 1;
+                        }
+                    }
+                }
+            }
+        }
+        // Many functions lie on symmetry elements, so D2h saves a factor of
+        // three here rather than eight.
+        assert_eq!(total, 5565);
+        assert_eq!(unique, 1849);
+    }
+
+    #[test]
+    fn test_symmetry_adapted_rhf_water() {
+        let (atomcoords, atomnos) = testing::water();
+        let symmetry = detect(&atomcoords, &atomnos, &SymmetryOptions::default());
+        let basis_set = Basis::from_json(&atomnos, &symmetry.atomcoords, testing::STO3G_JSON);
+        let adaptation = SymmetryAdaptation::new(&basis_set, &symmetry);
+        assert_eq!(adaptation.irrep_sizes(), [4, 0, 1, 2]);
+
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let reference = scf::rhf(&basis_set, &symmetry.atomcoords, &atomnos, 5, &options);
+        let symmetric_options = scf::SCFOptions {
+            symmetry: Some(adaptation.clone()),
+            ..options.clone()
+        };
+        let result = scf::rhf(
+            &basis_set,
+            &symmetry.atomcoords,
+            &atomnos,
+            5,
+            &symmetric_options,
+        );
+        assert!((result.energy - reference.energy).abs() < 1.0e-10);
+        let names = PointGroup::C2v.irreps();
+        let labels: Vec<&str> = adaptation
+            .orbital_irreps(&result.C)
+            .into_iter()
+            .map(|irrep| names[irrep])
+            .collect();
+        assert_eq!(labels, ["A1", "A1", "B2", "A1", "B1", "A1", "B2"]);
+
+        // Emptying the out-of-plane 1b1 in favour of the 2b2 gives an
+        // excited determinant.
+        let excited = scf::rhf(
+            &basis_set,
+            &symmetry.atomcoords,
+            &atomnos,
+            5,
+            &scf::SCFOptions {
+                docc: Some(vec![3, 0, 0, 2]),
+                ..symmetric_options
+            },
+        );
+        assert!(excited.converged);
+        assert!(excited.energy > result.energy + 0.5);
+        let excited_labels = adaptation.orbital_irreps(&excited.C);
+        assert_eq!(&excited_labels[..5], [0, 0, 3, 0, 3]);
+    }
+}
@@ -0,0 +1,331 @@
+//! Fixtures shared between unit tests that need a basis set, so that they
+//! don't depend on the `basis_set_exchange` Python package being installed.
+
+use crate::basis::Basis;
+
+pub(crate) const STO3G_JSON: &str = r#"{
+  "name": "STO-3G",
+  "description": "STO-3G Minimal Basis (3 functions/AO)",
+  "elements": {
+    "1": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["3.42525091", "0.62391373", "0.16885540"],
+          "coefficients": [["0.15432897", "0.53532814", "0.44463454"]]
+        }
+      ]
+    },
+    "6": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["71.6168370", "13.0450960", "3.5305122"],
+          "coefficients": [["0.15432897", "0.53532814", "0.44463454"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0, 1],
+          "exponents": ["2.9412494", "0.6834831", "0.2222899"],
+          "coefficients": [["-0.09996723", "0.39951283", "0.70011547"], ["0.15591627", "0.60768372", "0.39195739"]]
+        }
+      ]
+    },
+    "7": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["99.1061690", "18.0523120", "4.8856602"],
+          "coefficients": [["0.15432897", "0.53532814", "0.44463454"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0, 1],
+          "exponents": ["3.7804559", "0.8784966", "0.2857144"],
+          "coefficients": [["-0.09996723", "0.39951283", "0.70011547"], ["0.15591627", "0.60768372", "0.39195739"]]
+        }
+      ]
+    },
+    "8": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["130.7093200", "23.8088610", "6.4436083"],
+          "coefficients": [["0.15432897", "0.53532814", "0.44463454"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0, 1],
+          "exponents": ["5.0331513", "1.1695961", "0.3803890"],
+          "coefficients": [["-0.09996723", "0.39951283", "0.70011547"], ["0.15591627", "0.60768372", "0.39195739"]]
+        }
+      ]
+    }
+  }
+}"#;
+
+/// An even-tempered auxiliary basis of uncontracted functions for testing
+/// density fitting with STO-3G, not a published fitting basis.
+pub(crate) const FITTING_JSON: &str = r#"{
+  "name": "even-tempered-fit",
+  "description": "Even-tempered auxiliary basis for tests",
+  "elements": {
+    "1": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["0.2"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["0.6"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["1.8"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["5.4"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [1],
+          "exponents": ["0.4"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [1],
+          "exponents": ["1.2"],
+          "coefficients": [["1.0"]]
+        }
+      ]
+    },
+    "8": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["0.2"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["0.5"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["1.25"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["3.125"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["7.8"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["19.5"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["49.0"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["122.0"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["305.0"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["760.0"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [1],
+          "exponents": ["0.3"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [1],
+          "exponents": ["0.9"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [1],
+          "exponents": ["2.7"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [1],
+          "exponents": ["8.1"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [1],
+          "exponents": ["24.3"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [2],
+          "exponents": ["0.5"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [2],
+          "exponents": ["1.5"],
+          "coefficients": [["1.0"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [2],
+          "exponents": ["4.5"],
+          "coefficients": [["1.0"]]
+        }
+      ]
+    }
+  }
+}"#;
+
+/// 6-31G for hydrogen and oxygen, for tests that need a basis larger than
+/// STO-3G.
+pub(crate) const BASIS_631G_JSON: &str = r#"{
+  "name": "6-31G",
+  "description": "6-31G valence double-zeta",
+  "elements": {
+    "1": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["18.7311370", "2.8253937", "0.6401217"],
+          "coefficients": [["0.03349460", "0.23472695", "0.81375733"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["0.1612778"],
+          "coefficients": [["1.0000000"]]
+        }
+      ]
+    },
+    "8": {
+      "electron_shells": [
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0],
+          "exponents": ["5484.6717000", "825.2349500", "188.0469600", "52.9645000", "16.8975700", "5.7996353"],
+          "coefficients": [["0.0018311", "0.0139501", "0.0684451", "0.2327143", "0.4701930", "0.3585209"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0, 1],
+          "exponents": ["15.5396160", "3.5999336", "1.0137618"],
+          "coefficients": [["-0.1107775", "-0.1480263", "1.1307670"], ["0.0708743", "0.3397528", "0.7271586"]]
+        },
+        {
+          "function_type": "gto",
+          "region": "",
+          "angular_momentum": [0, 1],
+          "exponents": ["0.2700058"],
+          "coefficients": [["1.0000000"], ["1.0000000"]]
+        }
+      ]
+    }
+  }
+}"#;
+
+/// Water at the geometry used by the Crawford programming projects, which is
+/// also what `water_crawford.xyz` contains (in bohr).
+pub(crate) fn water() -> (Vec<[f64; 3]>, Vec<u64>) {
+    let atomcoords = vec![
+        [0.000000000000, -0.143225816552, 0.000000000000],
+        [1.638036840407, 1.136548822547, -0.000000000000],
+        [-1.638036840407, 1.136548822547, -0.000000000000],
+    ];
+    let atomnos = vec![8, 1, 1];
+    (atomcoords, atomnos)
+}
+
+pub(crate) fn water_sto3g() -> (Basis, Vec<[f64; 3]>, Vec<u64>) {
+    let (atomcoords, atomnos) = water();
+    let basis_set = Basis::from_json(&atomnos, &atomcoords, STO3G_JSON);
+    (basis_set, atomcoords, atomnos)
+}
@@ -0,0 +1,170 @@
+#![allow(non_snake_case)]
+
+//! Transformation of the two-electron integrals from the AO to the MO basis
+//! as four successive quarter transformations, each costing O(N^5).
+
+use ndarray::{Array, ArrayView2, Ix2, Ix4};
+
+use crate::basis;
+use crate::cholesky;
+use crate::df;
+use crate::scf::JKAlgorithm;
+
+/// Contract the first index of T with C and move the new index to the end,
+/// so that T(a, b, c, d) C(a, p) -> T'(b, c, d, p).
+fn quarter_transform(T: &Array<f64, Ix4>, C: &ArrayView2<f64>) -> Array<f64, Ix4> {
+    let shape = T.shape().to_vec();
+    let np = C.shape()[1];
+    let T2 = T
+        .view()
+        .into_shape_with_order((shape[0], shape[1] * shape[2] * shape[3]))
+        .unwrap();
+    C.t()
+        .dot(&T2)
+        .into_shape_with_order((np, shape[1], shape[2], shape[3]))
+        .unwrap()
+        .permuted_axes([1, 2, 3, 0])
+        .as_standard_layout()
+        .to_owned()
+}
+
+/// (pq|rs) = sum C1(mu, p) C2(nu, q) C3(lambda, r) C4(sigma, s) (mu nu|lambda sigma)
+/// from the full AO integrals.
+pub fn transform_I(
+    I: &Array<f64, Ix4>,
+    C1: &ArrayView2<f64>,
+    C2: &ArrayView2<f64>,
+    C3: &ArrayView2<f64>,
+    C4: &ArrayView2<f64>,
+) -> Array<f64, Ix4> {
+    let T = quarter_transform(I, C1);
+    let T = quarter_transform(&T, C2);
+    let T = quarter_transform(&T, C3);
+    quarter_transform(&T, C4)
+}
+
+/// The same transformation as `transform_I`, computing the AO integrals one
+/// (mu nu) pair at a time so that only the half-transformed integrals
+/// (mu nu|rs) are ever stored.
+pub fn transform_direct(
+    basis_set: &basis::Basis,
+    C1: &ArrayView2<f64>,
+    C2: &ArrayView2<f64>,
+    C3: &ArrayView2<f64>,
+    C4: &ArrayView2<f64>,
+) -> Array<f64, Ix4> {
+    let dim = basis_set.nbasis();
+    let (n1, n2, n3, n4) = (C1.shape()[1], C2.shape()[1], C3.shape()[1], C4.shape()[1]);
+    let mut half: Array<f64, _> = Array::zeros((n3, n4, dim, dim));
+    for mu in 0..dim {
+        for nu in 0..mu + 1 {
+            let rs = C3.t().dot(&basis::I_pair(basis_set, mu, nu)).dot(C4);
+            for r in 0..n3 {
+                for s in 0..n4 {
+                    half[[r, s, mu, nu]] = rs[[r, s]];
+                    half[[r, s, nu, mu]] = rs[[r, s]];
+                }
+            }
+        }
+    }
+    let mut mo: Array<f64, _> = Array::zeros((n1, n2, n3, n4));
+    for r in 0..n3 {
+        for s in 0..n4 {
+            let pq = C1.t().dot(&half.slice(ndarray::s![r, s, .., ..])).dot(C2);
+            mo.slice_mut(ndarray::s![.., .., r, s]).assign(&pq);
+        }
+    }
+    mo
+}
+
+/// Source of AO two-electron integrals for transformations, either
+/// recomputed for every transformation, computed once and kept in memory, or
+/// approximated from density-fitted or Cholesky three-index integrals.
+pub enum AOIntegrals<'a> {
+    Direct(&'a basis::Basis),
+    InMemory(Array<f64, Ix4>),
+    ThreeIndex(df::DFTensor),
+}
+
+impl<'a> AOIntegrals<'a> {
+    pub fn new(basis_set: &'a basis::Basis, algorithm: &JKAlgorithm) -> AOIntegrals<'a> {
+        match algorithm {
+            JKAlgorithm::Direct => AOIntegrals::Direct(basis_set),
+            JKAlgorithm::InMemory => AOIntegrals::InMemory(basis::build_I(basis_set)),
+            JKAlgorithm::DensityFitting(aux) => {
+                AOIntegrals::ThreeIndex(df::DFTensor::new(basis_set, aux))
+            }
+            JKAlgorithm::Cholesky(threshold) => {
+                AOIntegrals::ThreeIndex(cholesky::cholesky_eri(basis_set, *threshold))
+            }
+        }
+    }
+
+    pub fn transform(
+        &self,
+        C1: &ArrayView2<f64>,
+        C2: &ArrayView2<f64>,
+        C3: &ArrayView2<f64>,
+        C4: &ArrayView2<f64>,
+    ) -> Array<f64, Ix4> {
+        match self {
+            AOIntegrals::Direct(basis_set) => transform_direct(basis_set, C1, C2, C3, C4),
+            AOIntegrals::InMemory(I) => transform_I(I, C1, C2, C3, C4),
+            AOIntegrals::ThreeIndex(df) => df.transform_I(C1, C2, C3, C4),
+        }
+    }
+}
+
+/// Split the MO coefficients into occupied and virtual blocks.
+pub fn split_occupied(
+    C: &Array<f64, Ix2>,
+    nocc: usize,
+) -> (ArrayView2<'_, f64>, ArrayView2<'_, f64>) {
+    (
+        C.slice(ndarray::s![.., ..nocc]),
+        C.slice(ndarray::s![.., nocc..]),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scf;
+    use crate::testing;
+
+    #[test]
+    fn test_transform_direct_matches_in_memory() {
+        let (basis_set, atomcoords, atomnos) = testing::water_sto3g();
+        let options = scf::SCFOptions {
+            jk: scf::JKAlgorithm::InMemory,
+            ..Default::default()
+        };
+        let result = scf::rhf(&basis_set, &atomcoords, &atomnos, 5, &options);
+        let (C_occ, C_virt) = split_occupied(&result.C, 5);
+        let I = basis::build_I(&basis_set);
+        let ovov = transform_I(&I, &C_occ, &C_virt, &C_occ, &C_virt);
+        assert_eq!(ovov.shape(), &[5, 2, 5, 2]);
+        let ovov_direct = transform_direct(&basis_set, &C_occ, &C_virt, &C_occ, &C_virt);
+        for (a, b) in ovov.iter().zip(ovov_direct.iter()) {
+            assert!((a - b).abs() < 1.0e-12);
+        }
+        // check one element against the naive N^8 sum
+        let C = &result.C;
+        let (p, q, r, s) = (1, 5, 3, 6);
+        let mut naive = 0.0;
+        for mu in 0..7 {
+            for nu in 0..7 {
+                for lambda in 0..7 {
+                    for sigma in 0..7 {
+                        naive += C[[mu, p]]
+                            * C[[nu, q]]
+                            * C[[lambda, r]]
+                            * C[[sigma, s]]
+                            * I[[mu, nu, lambda, sigma]];
+                    }
+                }
+            }
+        }
+        assert!((ovov[[p, q - 5, r, s - 5]] - naive).abs() < 1.0e-12);
+    }
+}
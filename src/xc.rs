@@ -0,0 +1,462 @@
+#![allow(non_snake_case)]
+
+//! Exchange-correlation functionals for a spin-unpolarized density.
+//!
+//! Every functional is written as an energy per unit volume e(rho, sigma),
+//! with rho the total density and sigma = |grad rho|^2, together with its
+//! first derivatives, which is what `dft::XCIntegrator` needs for the
+//! energy and the potential matrix. Hybrid functionals are described by a
+//! weighted sum of these with a fraction of exact (Hartree-Fock) exchange,
+//! which the SCF takes from the ordinary K matrix.
+
+use std::f64::consts::PI;
+
+use ndarray::{Array, Ix1};
+
+/// A functional evaluated at every grid point.
+#[derive(Clone, Debug)]
+pub struct XCOutput {
+    /// Energy per unit volume, so that E_xc = sum_g w_g exc_g.
+    pub exc: Array<f64, Ix1>,
+    /// Derivative with respect to the density.
+    pub vrho: Array<f64, Ix1>,
+    /// Derivative with respect to sigma, for gradient-corrected functionals.
+    pub vsigma: Option<Array<f64, Ix1>>,
+}
+
+/// Densities below this are treated as zero when evaluating functionals.
+pub const DENSITY_THRESHOLD: f64 = 1.0e-14;
+
+/// The individual exchange and correlation functionals.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Component {
+    /// Slater (Dirac) exchange.
+    Slater,
+    /// Vosko-Wilk-Nusair correlation, parametrization V (the fit to the
+    /// Ceperley-Alder data).
+    VWN5,
+    /// Vosko-Wilk-Nusair correlation fitted to the RPA, as used in B3LYP.
+    VWNRPA,
+    /// Perdew-Wang 1992 correlation.
+    PW92,
+    /// Becke 1988 exchange, including the local part.
+    B88,
+    /// Lee-Yang-Parr correlation.
+    LYP,
+    /// Perdew-Burke-Ernzerhof exchange.
+    PBEExchange,
+    /// Perdew-Burke-Ernzerhof correlation.
+    PBECorrelation,
+}
+
+impl Component {
+    pub fn is_gga(&self) -> bool {
+        matches!(
+            self,
+            Component::B88 | Component::LYP | Component::PBEExchange | Component::PBECorrelation
+        )
+    }
+
+    /// The energy density and its derivatives with respect to rho and sigma
+    /// at a single point.
+    pub fn evaluate_point(&self, rho: f64, sigma: f64) -> (f64, f64, f64) {
+        if rho < DENSITY_THRESHOLD {
+            return (0.0, 0.0, 0.0);
+        }
+        match self {
+            Component::Slater => {
+                let (e, v) = slater(rho);
+                (e, v, 0.0)
+            }
+            Component::VWN5 => vwn(rho, &VWN5_PARAMETERS),
+            Component::VWNRPA => vwn(rho, &VWNRPA_PARAMETERS),
+            Component::PW92 => {
+                let rs = wigner_seitz_radius(rho);
+                let (eps, deps) = pw92_eps(rs);
+                (rho * eps, eps - rs / 3.0 * deps, 0.0)
+            }
+            Component::B88 => b88(rho, sigma),
+            Component::LYP => lyp(rho, sigma),
+            Component::PBEExchange => pbe_exchange(rho, sigma),
+            Component::PBECorrelation => pbe_correlation(rho, sigma),
+        }
+    }
+}
+
+fn wigner_seitz_radius(rho: f64) -> f64 {
+    (3.0 / (4.0 * PI * rho)).cbrt()
+}
+
+/// e = -(3/4) (3/pi)^(1/3) rho^(4/3)
+fn slater(rho: f64) -> (f64, f64) {
+    let c = (3.0 / PI).cbrt();
+    (-0.75 * c * rho.powf(4.0 / 3.0), -c * rho.cbrt())
+}
+
+/// Parameters A, b, c and x0 of the paramagnetic VWN interpolation.
+struct VWNParameters(f64, f64, f64, f64);
+
+const VWN5_PARAMETERS: VWNParameters = VWNParameters(0.0310907, 3.72744, 12.9352, -0.10498);
+const VWNRPA_PARAMETERS: VWNParameters = VWNParameters(0.0310907, 13.0720, 42.7198, -0.409286);
+
+/// S. H. Vosko, L. Wilk and M. Nusair, Can. J. Phys. 58, 1200 (1980), in
+/// terms of x = sqrt(rs).
+fn vwn(rho: f64, parameters: &VWNParameters) -> (f64, f64, f64) {
+    let VWNParameters(A, b, c, x0) = *parameters;
+    let x = wigner_seitz_radius(rho).sqrt();
+    let X = |x: f64| x * x + b * x + c;
+    let Q = (4.0 * c - b * b).sqrt();
+    let (Xx, Xx0) = (X(x), X(x0));
+    let atan = (Q / (2.0 * x + b)).atan();
+    let eps = A
+        * ((x * x / Xx).ln() + 2.0 * b / Q * atan
+            - b * x0 / Xx0 * (((x - x0).powi(2) / Xx).ln() + 2.0 * (b + 2.0 * x0) / Q * atan));
+    let deps_dx = A
+        * (2.0 / x
+            - (2.0 * x + b) / Xx
+            - b / Xx
+            - b * x0 / Xx0 * (2.0 / (x - x0) - (2.0 * x + b) / Xx - (b + 2.0 * x0) / Xx));
+    // rho d(eps)/d(rho) = -(rs/3) d(eps)/d(rs) = -(x/6) d(eps)/dx
+    (rho * eps, eps - x / 6.0 * deps_dx, 0.0)
+}
+
+/// The correlation energy per particle of the uniform electron gas and its
+/// derivative with respect to rs,
+/// J. P. Perdew and Y. Wang, Phys. Rev. B 45, 13244 (1992).
+fn pw92_eps(rs: f64) -> (f64, f64) {
+    let (A, alpha1) = (0.0310907, 0.21370);
+    let (beta1, beta2, beta3, beta4) = (7.5957, 3.5876, 1.6382, 0.49294);
+    let sqrt_rs = rs.sqrt();
+    let Q0 = -2.0 * A * (1.0 + alpha1 * rs);
+    let Q1 = 2.0 * A * (beta1 * sqrt_rs + beta2 * rs + beta3 * rs * sqrt_rs + beta4 * rs * rs);
+    let dQ1 = A * (beta1 / sqrt_rs + 2.0 * beta2 + 3.0 * beta3 * sqrt_rs + 4.0 * beta4 * rs);
+    let log = (1.0 + 1.0 / Q1).ln();
+    let eps = Q0 * log;
+    let deps = -2.0 * A * alpha1 * log - Q0 * dQ1 / (Q1 * Q1 + Q1);
+    (eps, deps)
+}
+
+/// A. D. Becke, Phys. Rev. A 38, 3098 (1988), for equal spin densities
+/// rho/2 with the reduced gradient x = |grad rho_s| / rho_s^(4/3).
+fn b88(rho: f64, sigma: f64) -> (f64, f64, f64) {
+    let beta = 0.0042;
+    let (e_lda, v_lda) = slater(rho);
+    let rho_s43 = (0.5 * rho).powf(4.0 / 3.0);
+    let x = 2f64.cbrt() * sigma.sqrt() / rho.powf(4.0 / 3.0);
+    let asinh = x.asinh();
+    let D = 1.0 + 6.0 * beta * x * asinh;
+    let dD = 6.0 * beta * (asinh + x / (1.0 + x * x).sqrt());
+    let g = x * x / D;
+    // g'(x) / x, which stays finite as x goes to zero
+    let dg_over_x = (2.0 * D - x * dD) / (D * D);
+    let e = e_lda - 2.0 * beta * rho_s43 * g;
+    let vrho = v_lda - 2.0 * beta * (4.0 / 3.0) * rho_s43 / rho * (g - x * x * dg_over_x);
+    let vsigma =
+        -2.0 * beta * rho_s43 * dg_over_x * 2f64.cbrt().powi(2) / rho.powf(8.0 / 3.0) / 2.0;
+    (e, vrho, vsigma)
+}
+
+/// C. Lee, W. Yang and R. G. Parr, Phys. Rev. B 37, 785 (1988), in the
+/// closed-shell form without the Laplacian of B. Miehlich, A. Savin,
+/// H. Stoll and H. Preuss, Chem. Phys. Lett. 157, 200 (1989),
+///
+/// e = -a rho / (1 + d rho^(-1/3))
+///     - a b omega [C_F rho^(14/3) - (1/24 + 7 delta / 72) rho^2 sigma]
+fn lyp(rho: f64, sigma: f64) -> (f64, f64, f64) {
+    let (a, b, c, d) = (0.04918, 0.132, 0.2533, 0.349);
+    let C_F = 0.3 * (3.0 * PI * PI).powf(2.0 / 3.0);
+    let u = rho.powf(-1.0 / 3.0);
+    let denom = 1.0 + d * u;
+    let omega = (-c * u).exp() / denom * rho.powf(-11.0 / 3.0);
+    let delta = c * u + d * u / denom;
+    let domega = omega * (delta - 11.0) / (3.0 * rho);
+    let ddelta = -(c + d / (denom * denom)) * u / (3.0 * rho);
+
+    let gradient_coef = -(1.0 / 24.0 + 7.0 * delta / 72.0);
+    let G = C_F * rho.powf(14.0 / 3.0) + gradient_coef * rho * rho * sigma;
+    let dG = 14.0 / 3.0 * C_F * rho.powf(11.0 / 3.0) + 2.0 * rho * sigma * gradient_coef
+        - 7.0 / 72.0 * ddelta * rho * rho * sigma;
+
+    let e = -a * rho / denom - a * b * omega * G;
+    let vrho =
+        -a * (1.0 / denom + d * u / (3.0 * denom * denom)) - a * b * (domega * G + omega * dG);
+    let vsigma = -a * b * omega * gradient_coef * rho * rho;
+    (e, vrho, vsigma)
+}
+
+/// J. P. Perdew, K. Burke and M. Ernzerhof, Phys. Rev. Lett. 77, 3865
+/// (1996), as the local exchange times the enhancement factor
+/// F(s) = 1 + kappa - kappa / (1 + mu s^2 / kappa).
+fn pbe_exchange(rho: f64, sigma: f64) -> (f64, f64, f64) {
+    let (kappa, mu) = (0.804, 0.2195149727645171);
+    let (e_lda, v_lda) = slater(rho);
+    // s^2 = sigma / (4 (3 pi^2)^(2/3) rho^(8/3))
+    let ds2_dsigma = 1.0 / (4.0 * (3.0 * PI * PI).powf(2.0 / 3.0) * rho.powf(8.0 / 3.0));
+    let s2 = sigma * ds2_dsigma;
+    let denom = 1.0 + mu * s2 / kappa;
+    let F = 1.0 + kappa - kappa / denom;
+    let dF = mu / (denom * denom);
+    let e = e_lda * F;
+    let vrho = v_lda * F - e_lda * dF * 8.0 / 3.0 * s2 / rho;
+    let vsigma = e_lda * dF * ds2_dsigma;
+    (e, vrho, vsigma)
+}
+
+/// The PW92 correlation plus the gradient correction H(rs, t) of PBE, for
+/// t = |grad rho| / (2 k_s rho).
+fn pbe_correlation(rho: f64, sigma: f64) -> (f64, f64, f64) {
+    let beta = 0.06672455060314922;
+    let gamma = (1.0 - 2f64.ln()) / (PI * PI);
+    let rs = wigner_seitz_radius(rho);
+    let (eps, deps_drs) = pw92_eps(rs);
+    let deps = -rs / (3.0 * rho) * deps_drs;
+
+    // t^2 = pi sigma / (16 k_F rho^2)
+    let k_F = (3.0 * PI * PI * rho).cbrt();
+    let dT_dsigma = PI / (16.0 * k_F * rho * rho);
+    let T = sigma * dT_dsigma;
+    let dT_drho = -7.0 / 3.0 * T / rho;
+
+    let exp = (-eps / gamma).exp();
+    let A = beta / gamma / (exp - 1.0);
+    let dA_deps = A * A * exp / beta;
+    let N = 1.0 + A * T;
+    let Dn = 1.0 + A * T + A * A * T * T;
+    let y = beta / gamma * T * N / Dn;
+    let dy_dT = beta / gamma * ((N + A * T) * Dn - T * N * (A + 2.0 * A * A * T)) / (Dn * Dn);
+    let dy_dA = beta / gamma * T * (T * Dn - N * (T + 2.0 * A * T * T)) / (Dn * Dn);
+    let H = gamma * (1.0 + y).ln();
+    let dH_dT = gamma / (1.0 + y) * dy_dT;
+    let dH_dA = gamma / (1.0 + y) * dy_dA;
+
+    let e = rho * (eps + H);
+    let vrho = eps + H + rho * (deps * (1.0 + dH_dA * dA_deps) + dH_dT * dT_drho);
+    let vsigma = rho * dH_dT * dT_dsigma;
+    (e, vrho, vsigma)
+}
+
+/// A (possibly hybrid) exchange-correlation functional as a weighted sum of
+/// components plus a fraction of exact exchange.
+#[derive(Clone, Debug)]
+pub struct Functional {
+    pub name: String,
+    pub components: Vec<(Component, f64)>,
+    /// Fraction of Hartree-Fock exchange, so that the Kohn-Sham matrix
+    /// contains -exact_exchange * K.
+    pub exact_exchange: f64,
+}
+
+impl Functional {
+    pub fn new(name: &str, components: Vec<(Component, f64)>, exact_exchange: f64) -> Functional {
+        Functional {
+            name: name.to_string(),
+            components,
+            exact_exchange,
+        }
+    }
+
+    /// Exact exchange only, which makes Kohn-Sham identical to Hartree-Fock.
+    pub fn hartree_fock() -> Functional {
+        Functional::new("HF", vec![], 1.0)
+    }
+
+    /// Slater exchange alone (Hartree-Fock-Slater).
+    pub fn slater() -> Functional {
+        Functional::new("Slater", vec![(Component::Slater, 1.0)], 0.0)
+    }
+
+    pub fn svwn5() -> Functional {
+        Functional::new(
+            "SVWN5",
+            vec![(Component::Slater, 1.0), (Component::VWN5, 1.0)],
+            0.0,
+        )
+    }
+
+    /// Slater exchange with Perdew-Wang correlation.
+    pub fn spw92() -> Functional {
+        Functional::new(
+            "SPW92",
+            vec![(Component::Slater, 1.0), (Component::PW92, 1.0)],
+            0.0,
+        )
+    }
+
+    pub fn blyp() -> Functional {
+        Functional::new(
+            "BLYP",
+            vec![(Component::B88, 1.0), (Component::LYP, 1.0)],
+            0.0,
+        )
+    }
+
+    pub fn pbe() -> Functional {
+        Functional::new(
+            "PBE",
+            vec![
+                (Component::PBEExchange, 1.0),
+                (Component::PBECorrelation, 1.0),
+            ],
+            0.0,
+        )
+    }
+
+    /// B3LYP with the RPA form of VWN, as defined in Gaussian,
+    ///
+    /// 0.08 Slater + 0.72 B88 + 0.20 HF + 0.19 VWN + 0.81 LYP
+    pub fn b3lyp() -> Functional {
+        Functional::new(
+            "B3LYP",
+            vec![
+                (Component::Slater, 0.08),
+                (Component::B88, 0.72),
+                (Component::VWNRPA, 0.19),
+                (Component::LYP, 0.81),
+            ],
+            0.20,
+        )
+    }
+
+    pub fn pbe0() -> Functional {
+        Functional::new(
+            "PBE0",
+            vec![
+                (Component::PBEExchange, 0.75),
+                (Component::PBECorrelation, 1.0),
+            ],
+            0.25,
+        )
+    }
+
+    /// Look up one of the functionals above by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<Functional> {
+        match name.to_lowercase().as_str() {
+            "hf" => Some(Functional::hartree_fock()),
+            "slater" | "hfs" => Some(Functional::slater()),
+            "svwn" | "svwn5" | "lda" => Some(Functional::svwn5()),
+            "spw92" => Some(Functional::spw92()),
+            "blyp" => Some(Functional::blyp()),
+            "pbe" => Some(Functional::pbe()),
+            "b3lyp" => Some(Functional::b3lyp()),
+            "pbe0" | "pbeh" => Some(Functional::pbe0()),
+            _ => None,
+        }
+    }
+
+    pub fn is_gga(&self) -> bool {
+        self.components.iter().any(|(c, _)| c.is_gga())
+    }
+
+    pub fn is_hybrid(&self) -> bool {
+        self.exact_exchange != 0.0
+    }
+
+    /// The energy density and its derivatives at every grid point.
+    pub fn evaluate(&self, rho: &Array<f64, Ix1>, sigma: &Array<f64, Ix1>) -> XCOutput {
+        let npoints = rho.len();
+        let mut exc = Array::zeros(npoints);
+        let mut vrho = Array::zeros(npoints);
+        let mut vsigma = Array::zeros(npoints);
+        for (component, weight) in self.components.iter() {
+            for g in 0..npoints {
+                let (e, vr, vs) = component.evaluate_point(rho[g], sigma[g]);
+                exc[g] += weight * e;
+                vrho[g] += weight * vr;
+                vsigma[g] += weight * vs;
+            }
+        }
+        XCOutput {
+            exc,
+            vrho,
+            vsigma: if self.is_gga() { Some(vsigma) } else { None },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COMPONENTS: [Component; 8] = [
+        Component::Slater,
+        Component::VWN5,
+        Component::VWNRPA,
+        Component::PW92,
+        Component::B88,
+        Component::LYP,
+        Component::PBEExchange,
+        Component::PBECorrelation,
+    ];
+
+    #[test]
+    fn test_uniform_gas_correlation() {
+        // Correlation energies per electron of the unpolarized uniform gas
+        // from quantum Monte Carlo (Ceperley-Alder, as fitted by PW92).
+        let reference = [
+            (1.0f64, -0.0598),
+            (2.0, -0.0448),
+            (5.0, -0.0282),
+            (10.0, -0.0186),
+        ];
+        for &(rs, eps_ref) in reference.iter() {
+            let rho = 3.0 / (4.0 * PI * rs.powi(3));
+            for &component in [Component::VWN5, Component::PW92].iter() {
+                let (e, _, _) = component.evaluate_point(rho, 0.0);
+                assert!((e / rho - eps_ref).abs() < 5.0e-4);
+            }
+            // PBE correlation reduces to PW92 without gradients.
+            let (e_pbe, _, _) = Component::PBECorrelation.evaluate_point(rho, 0.0);
+            let (e_pw92, _, _) = Component::PW92.evaluate_point(rho, 0.0);
+            assert_abs_diff_eq!(e_pbe, e_pw92, epsilon = 1.0e-15);
+        }
+    }
+
+    #[test]
+    fn test_exchange_limits() {
+        for &rho in [1.0e-3, 0.1, 10.0].iter() {
+            let (e_lda, v_lda, _) = Component::Slater.evaluate_point(rho, 0.0);
+            // Gradient-corrected exchange reduces to Slater exchange for a
+            // uniform density.
+            for &component in [Component::B88, Component::PBEExchange].iter() {
+                let (e, v, _) = component.evaluate_point(rho, 0.0);
+                assert_abs_diff_eq!(e, e_lda, epsilon = 1.0e-14);
+                assert_abs_diff_eq!(v, v_lda, epsilon = 1.0e-14);
+            }
+            // The PBE enhancement factor saturates at 1 + kappa, the
+            // Lieb-Oxford bound.
+            let (e, _, _) =
+                Component::PBEExchange.evaluate_point(rho, 1.0e20 * rho.powf(8.0 / 3.0));
+            assert_abs_diff_eq!(e / e_lda, 1.804, epsilon = 1.0e-6);
+            // PBE correlation vanishes for rapidly varying densities.
+            let (e, _, _) =
+                Component::PBECorrelation.evaluate_point(rho, 1.0e20 * rho.powf(7.0 / 3.0));
+            assert!(e.abs() < 1.0e-6 * rho);
+        }
+    }
+
+    #[test]
+    fn test_derivatives() {
+        let points = [
+            (1.0e-3, 1.0e-6),
+            (0.05, 0.01),
+            (0.3, 0.2),
+            (2.0, 5.0),
+            (40.0, 3.0e3),
+        ];
+        for &component in COMPONENTS.iter() {
+            for &(rho, sigma) in points.iter() {
+                let (_, vrho, vsigma) = component.evaluate_point(rho, sigma);
+                let h = 1.0e-6 * rho;
+                let (plus, _, _) = component.evaluate_point(rho + h, sigma);
+                let (minus, _, _) = component.evaluate_point(rho - h, sigma);
+                let numerical = (plus - minus) / (2.0 * h);
+                assert!((numerical - vrho).abs() < 1.0e-6 * (1.0 + vrho.abs()));
+                let h = 1.0e-6 * sigma;
+                let (plus, _, _) = component.evaluate_point(rho, sigma + h);
+                let (minus, _, _) = component.evaluate_point(rho, sigma - h);
+                let numerical = (plus - minus) / (2.0 * h);
+                assert!((numerical - vsigma).abs() < 1.0e-6 * (1.0 + vsigma.abs()));
+            }
+        }
+    }
+}